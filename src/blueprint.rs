@@ -0,0 +1,178 @@
+//! Copying parts of a layout and placing them again, e.g. to build a 4 bit adder from four copies
+//! of a full adder.
+//!
+//! A part of a layout is stored as a [`Simulator`] holding only its dominos and ports, and is
+//! exchanged through the clipboard or blueprint files in the layout format of [`crate::layout`].
+//! Blueprints are the parts saved by name in a [`Library`] directory.
+
+use std::path::{Path, PathBuf};
+
+use crate::{history::Command, layout, simulator::{Domino, Port, Simulator}};
+
+/// The dominos with the given ids and their ports.
+pub fn extract(simulator: &Simulator, ids: &[u32]) -> Simulator {
+    Simulator {
+        dominos: simulator.dominos.iter().filter(|d| ids.contains(&d.id)).cloned().collect(),
+        ports: simulator.ports.iter().filter(|p| ids.contains(&p.domino_id)).cloned().collect(),
+        ..Simulator::empty()
+    }
+}
+
+/// Creates a copy of `part` in `simulator` with fresh ids and standing upright, moved so that its
+/// center is at `at`, e.g. on top of a platform. The port names are prefixed with `port_prefix` and made unique by appending
+/// a number where they are taken.
+pub fn instantiate(simulator: &Simulator, part: &Simulator, at: cgmath::Point3<f32>, port_prefix: Option<&str>) -> Command {
+    let ids: Vec<u32> = part.dominos.iter().map(|d| d.id).collect();
+    let center = part.center_of(&ids).unwrap_or(at);
+    let offset = at - center;
+
+    let first_id = simulator.next_id();
+    let new_id = |id: u32| first_id + ids.iter().position(|i| *i == id).unwrap() as u32;
+    let dominos: Vec<Domino> = part.dominos.iter()
+        .map(|d| Domino { id: new_id(d.id), position: d.position + offset, fall_rotation: 0.0, angular_velocity: 0.0, ..d.clone() })
+        .collect();
+
+    let mut ports: Vec<Port> = vec![];
+    for port in part.ports.iter().filter(|p| ids.contains(&p.domino_id)) {
+        let name = match port_prefix {
+            Some(prefix) => format!("{}.{}", prefix, port.name),
+            None => port.name.clone(),
+        };
+        let taken = |name: &str| simulator.ports.iter().chain(ports.iter()).any(|p| p.name == name);
+        let name = if taken(&name) {
+            (2..).map(|n| format!("{}_{}", name, n)).find(|n| !taken(n)).unwrap()
+        } else {
+            name
+        };
+        ports.push(Port { name, domino_id: new_id(port.domino_id), direction: port.direction });
+    }
+
+    Command::Create { dominos, ports }
+}
+
+/// A directory of blueprint files, one layout file per blueprint named after the blueprint.
+pub struct Library {
+    pub dir: PathBuf,
+}
+
+impl Library {
+    /// The library in the data directory of the user, `None` if it can not be determined.
+    pub fn user() -> Option<Self> {
+        let data_dir = std::env::var_os("XDG_DATA_HOME").map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("share")))
+            .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+        Some(Library { dir: data_dir.join("domino-logic-simulator").join("blueprints") })
+    }
+
+    /// Names of all blueprints in the library, sorted alphabetically. A missing directory is an
+    /// empty library.
+    pub fn names(&self) -> Result<Vec<String>, layout::Error> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let mut names = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == layout::EXTENSION) {
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    names.push(stem.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    pub fn save(&self, name: &str, part: &Simulator) -> Result<(), layout::Error> {
+        let path = self.path(name)?;
+        std::fs::create_dir_all(&self.dir)?;
+        layout::save(part, &path)
+    }
+
+    pub fn load(&self, name: &str) -> Result<Simulator, layout::Error> {
+        layout::load(&self.path(name)?)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), layout::Error> {
+        std::fs::remove_file(self.path(name)?)?;
+        Ok(())
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, layout::Error> {
+        let name = name.trim();
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\', ':']) {
+            return Err(layout::Error::Invalid(format!("\"{}\" is not a valid blueprint name", name)));
+        }
+        Ok(self.dir.join(format!("{}.{}", name, layout::EXTENSION)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::PortDirection;
+
+    fn port(name: &str, domino_id: u32, direction: PortDirection) -> Port {
+        Port { name: name.to_string(), domino_id, direction }
+    }
+
+    /// A part of three dominos with an input and an output port, taken from the middle of a
+    /// layout whose other dominos are left out.
+    fn part() -> Simulator {
+        let mut layout = Simulator::empty();
+        for (id, z) in [(7, 0.0), (3, 0.1), (5, 0.2), (9, 0.3)] {
+            layout.dominos.push(Domino::new(id, cgmath::point3(1.0, 0.0, z), 0.0));
+        }
+        layout.dominos[1].fall_rotation = 80.0;
+        layout.dominos[1].angular_velocity = 20.0;
+        layout.ports = vec![port("in", 3, PortDirection::Input), port("out", 5, PortDirection::Output), port("other", 9, PortDirection::Output)];
+        extract(&layout, &[3, 5, 7])
+    }
+
+    fn create(command: Command) -> (Vec<Domino>, Vec<Port>) {
+        match command {
+            Command::Create { dominos, ports } => (dominos, ports),
+            other => panic!("expected a create command, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn extract_keeps_the_ports_of_the_dominos() {
+        let part = part();
+        assert_eq!(part.dominos.iter().map(|d| d.id).collect::<Vec<u32>>(), [7, 3, 5]);
+        assert_eq!(part.ports.iter().map(|p| p.name.as_str()).collect::<Vec<&str>>(), ["in", "out"]);
+    }
+
+    #[test]
+    fn instances_get_fresh_ids_and_stand_upright() {
+        let simulator = Simulator::new();
+        let (dominos, ports) = create(instantiate(&simulator, &part(), cgmath::point3(0.0, 0.0, 2.0), None));
+        let first_id = simulator.next_id();
+        assert_eq!(dominos.iter().map(|d| d.id).collect::<Vec<u32>>(), [first_id, first_id + 1, first_id + 2]);
+        assert!(dominos.iter().all(|d| d.fall_rotation == 0.0 && d.angular_velocity == 0.0));
+
+        // the center of the part is moved to the given point
+        assert_eq!(dominos[1].position, cgmath::point3(0.0, 0.0, 2.0));
+        assert!((dominos[0].position.z - 1.9).abs() < 1.0e-6);
+
+        // the ports follow the dominos to their new ids
+        let ports: Vec<(&str, u32)> = ports.iter().map(|p| (p.name.as_str(), p.domino_id)).collect();
+        assert_eq!(ports, [("in", first_id + 1), ("out", first_id + 2)]);
+    }
+
+    #[test]
+    fn port_names_are_prefixed_and_made_unique() {
+        let mut simulator = Simulator::empty();
+        for _ in 0..3 {
+            let command = instantiate(&simulator, &part(), cgmath::point3(0.0, 0.0, 0.0), Some("adder"));
+            command.apply(&mut simulator);
+        }
+        let names: Vec<&str> = simulator.ports.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["adder.in", "adder.out", "adder.in_2", "adder.out_2", "adder.in_3", "adder.out_3"]);
+
+        let (_, ports) = create(instantiate(&simulator, &part(), cgmath::point3(0.0, 0.0, 0.0), None));
+        assert_eq!(ports[0].name, "in");
+    }
+}
@@ -0,0 +1,432 @@
+//! Domino layouts for logic gates.
+//!
+//! Every gate is laid out in its own gate space, where signals travel from the inputs at `z = 0`
+//! towards the outputs at positive `z`. [`Gate::dominos`] are already transformed into world space
+//! with the `origin` and `rotation_y` the gate was created with.
+//!
+//! The gates are built from three primitives:
+//! * a *splitter*, where the last domino of a line knocks over two dominos standing next to each
+//!   other, each starting a new line,
+//! * a *merge*, where the last dominos of two lines standing next to each other both can knock over
+//!   the first domino of a new line and
+//! * a *junction*, a line that is fed from both ends. Next to the last domino of each input line
+//!   stands the first domino of an output line. A fall that enters from one end travels through
+//!   the junction and leaves through the output line at the other end. If falls enter from both
+//!   ends they run into each other in the middle of the junction and both stop.
+//!
+//! Junctions only work if the falls of both inputs arrive at about the same time (within the time
+//! a fall takes to travel through the junction), which is why gates that use them start all of
+//! their inputs together and balance the length of the lines leading to them.
+
+use crate::path::{self, Placement};
+use crate::simulator::{Domino, Port, PortDirection};
+
+/// Distance between two neighbouring dominos of a line.
+const SPACING: f32 = path::DEFAULT_SPACING;
+/// Corner radius of the lines within a gate.
+const CORNER_RADIUS: f32 = path::DEFAULT_CORNER_RADIUS;
+/// Sideways offset of two dominos standing next to each other in a splitter, merge or junction.
+const SIDE_OFFSET: f32 = 0.04;
+/// Half of the length of a junction.
+const JUNCTION_HALF_LENGTH: f32 = 0.4;
+/// Length of the straight piece of a line leaving a junction before it turns.
+const OUTPUT_APPROACH: f32 = 0.1;
+/// Length of the straight piece of a line entering a junction after its last turn. Longer than
+/// [`OUTPUT_APPROACH`], so the dominos in the turn can not reach the output line next to it.
+const INPUT_APPROACH: f32 = 0.4;
+/// Sideways distance from the center of a merge at which converging lines become parallel.
+const FUNNEL_OFFSET: f32 = 0.12;
+/// Length over which converging lines move from [`FUNNEL_OFFSET`] to [`SIDE_OFFSET`].
+const FUNNEL_LENGTH: f32 = 0.8;
+
+/// Constructor of a gate from its origin and rotation around the y axis.
+pub type GateFn = fn(cgmath::Point3<f32>, f32) -> Gate;
+
+/// All gates with their names.
+pub const GATES: [(&str, GateFn); 6] = [
+    ("and", and),
+    ("or", or),
+    ("xor", xor),
+    ("not", not),
+    ("fan_out", fan_out),
+    ("crossover", crossover),
+];
+
+/// Dominos forming a logic gate together with their named input and output ports.
+#[derive(Clone, Debug)]
+pub struct Gate {
+    pub dominos: Vec<Domino>,
+    pub ports: Vec<Port>,
+}
+
+impl Gate {
+    /// The port with the given name.
+    pub fn port(&self, name: &str) -> Option<&Port> {
+        self.ports.iter().find(|p| p.name == name)
+    }
+}
+
+/// Positions where lines have to end (`_in`) or start (`_out`) to connect to a junction.
+struct Junction {
+    left_in: cgmath::Point2<f32>,
+    left_out: cgmath::Point2<f32>,
+    right_in: cgmath::Point2<f32>,
+    right_out: cgmath::Point2<f32>,
+    /// Sideways distance from the center at which output lines should turn away.
+    output_turn: f32,
+    /// Sideways distance from the center at which input lines should turn towards the junction.
+    input_turn: f32,
+}
+
+struct Splitter {
+    input: cgmath::Point2<f32>,
+    left_out: cgmath::Point2<f32>,
+    right_out: cgmath::Point2<f32>,
+}
+
+struct Merge {
+    left_in: cgmath::Point2<f32>,
+    right_in: cgmath::Point2<f32>,
+    output: cgmath::Point2<f32>,
+}
+
+/// Exclusive or built into a [`Builder`]: a junction whose two outputs merge into one line.
+struct Xor {
+    left_in: cgmath::Point2<f32>,
+    right_in: cgmath::Point2<f32>,
+    output: cgmath::Point2<f32>,
+    /// Sideways distance from the center at which input lines should turn towards the xor.
+    input_turn: f32,
+    /// Length a fall travels from an input to the output.
+    length: f32,
+}
+
+/// Collects domino placements in gate space.
+#[derive(Default)]
+struct Builder {
+    placements: Vec<Placement>,
+    ports: Vec<(String, usize, PortDirection)>,
+}
+
+impl Builder {
+    /// Places a line of dominos along `points` and returns the indices of its first and last domino.
+    fn line(&mut self, points: &[cgmath::Point2<f32>]) -> (usize, usize) {
+        let first = self.placements.len();
+        self.placements.extend(path::along_polyline(points, SPACING, CORNER_RADIUS));
+        (first, self.placements.len() - 1)
+    }
+
+    fn port(&mut self, name: &str, index: usize, direction: PortDirection) {
+        self.ports.push((name.to_string(), index, direction));
+    }
+
+    /// A line fed from both ends centered at (`x`, `z`) that runs along the x axis.
+    fn junction(&mut self, x: f32, z: f32) -> Junction {
+        self.line(&[point(x - JUNCTION_HALF_LENGTH, z), point(x + JUNCTION_HALF_LENGTH, z)]);
+
+        let outer = JUNCTION_HALF_LENGTH + SPACING;
+        let output_turn = outer + OUTPUT_APPROACH + CORNER_RADIUS;
+        Junction {
+            left_in: point(x - outer, z - SIDE_OFFSET),
+            left_out: point(x - outer, z + SIDE_OFFSET),
+            right_in: point(x + outer, z - SIDE_OFFSET),
+            right_out: point(x + outer, z + SIDE_OFFSET),
+            output_turn,
+            input_turn: output_turn + CORNER_RADIUS + INPUT_APPROACH,
+        }
+    }
+
+    fn xor(&mut self, x: f32, z: f32) -> Xor {
+        let junction = self.junction(x, z);
+        let merge = merge(x, z + SIDE_OFFSET + CORNER_RADIUS + funnel_height(junction.output_turn));
+
+        let mut output_length = 0.0;
+        for (from, to, side) in [(junction.left_out, merge.left_in, -1.0), (junction.right_out, merge.right_in, 1.0)] {
+            let turn_x = x + side * junction.output_turn;
+            let mut points = vec![from, point(turn_x, from.y)];
+            points.extend(funnel(turn_x, to, side));
+            self.line(&points);
+            output_length = path::length(&points, CORNER_RADIUS);
+        }
+
+        Xor {
+            left_in: junction.left_in,
+            right_in: junction.right_in,
+            output: merge.output,
+            input_turn: junction.input_turn,
+            length: 2.0 * (JUNCTION_HALF_LENGTH + SPACING) + output_length + SPACING,
+        }
+    }
+
+    /// Transforms all placements from gate space into world space.
+    fn build(self, origin: cgmath::Point3<f32>, rotation_y: f32) -> Gate {
+        let (sin, cos) = rotation_y.to_radians().sin_cos();
+        let dominos = self.placements.iter().enumerate().map(|(i, p)| {
+            let (x, z) = (p.position.x, p.position.y);
+            let position = cgmath::point3(origin.x + x * cos + z * sin, origin.y, origin.z - x * sin + z * cos);
+            Domino::new(i as u32, position, p.rotation_y + rotation_y)
+        }).collect();
+        let ports = self.ports.into_iter().map(|(name, index, direction)| Port { name, domino_id: index as u32, direction }).collect();
+        Gate { dominos, ports }
+    }
+}
+
+fn splitter(x: f32, z: f32) -> Splitter {
+    Splitter {
+        input: point(x, z),
+        left_out: point(x - SIDE_OFFSET, z + SPACING),
+        right_out: point(x + SIDE_OFFSET, z + SPACING),
+    }
+}
+
+fn merge(x: f32, z: f32) -> Merge {
+    Merge {
+        left_in: point(x - SIDE_OFFSET, z),
+        right_in: point(x + SIDE_OFFSET, z),
+        output: point(x, z + SPACING),
+    }
+}
+
+fn point(x: f32, z: f32) -> cgmath::Point2<f32> {
+    cgmath::point2(x, z)
+}
+
+/// Rise a line needs to converge from a sideways distance of `offset` into a merge.
+fn funnel_height(offset: f32) -> f32 {
+    (offset - FUNNEL_OFFSET).max(0.0) + FUNNEL_LENGTH + CORNER_RADIUS
+}
+
+/// Leads a line running along the z axis at `x` into `end` of a merge, coming from `side` (`-1.0`
+/// for the left and `1.0` for the right input of the merge). Converging lines first
+/// approach each other at 45° and only slowly at the end, so that their falling dominos can not
+/// reach the line from the other side.
+fn funnel(x: f32, end: cgmath::Point2<f32>, side: f32) -> [cgmath::Point2<f32>; 4] {
+    let funnel_x = end.x + side * (FUNNEL_OFFSET - SIDE_OFFSET);
+    let funnel_z = end.y - FUNNEL_LENGTH - CORNER_RADIUS;
+    [
+        point(x, funnel_z - (x - funnel_x).abs()),
+        point(funnel_x, funnel_z),
+        point(end.x, end.y - CORNER_RADIUS),
+        end,
+    ]
+}
+
+/// Splits the line starting at input `in` into two lines ending at outputs `out_left` and
+/// `out_right`.
+pub fn fan_out(origin: cgmath::Point3<f32>, rotation_y: f32) -> Gate {
+    let mut b = Builder::default();
+    let split = splitter(0.0, 0.3);
+
+    let (input, _) = b.line(&[point(0.0, 0.0), split.input]);
+    b.port("in", input, PortDirection::Input);
+
+    for (name, start, side) in [("out_left", split.left_out, -1.0), ("out_right", split.right_out, 1.0)] {
+        let (_, output) = b.line(&[start, point(start.x, 0.5), point(side * 0.2, 0.7), point(side * 0.2, 1.0)]);
+        b.port(name, output, PortDirection::Output);
+    }
+    b.build(origin, rotation_y)
+}
+
+/// `out` falls if `a` or `b` (or both) fall.
+pub fn or(origin: cgmath::Point3<f32>, rotation_y: f32) -> Gate {
+    let mut b = Builder::default();
+    let input_x = 0.3;
+    let merge = merge(0.0, CORNER_RADIUS + funnel_height(input_x));
+
+    for (name, end, side) in [("a", merge.left_in, -1.0), ("b", merge.right_in, 1.0)] {
+        let mut points = vec![point(side * input_x, 0.0)];
+        points.extend(funnel(side * input_x, end, side));
+        let (input, _) = b.line(&points);
+        b.port(name, input, PortDirection::Input);
+    }
+
+    let (_, output) = b.line(&[merge.output, point(0.0, merge.output.y + 0.4)]);
+    b.port("out", output, PortDirection::Output);
+    b.build(origin, rotation_y)
+}
+
+/// `out` falls if exactly one of `a` and `b` falls. Both inputs have to be started together.
+pub fn xor(origin: cgmath::Point3<f32>, rotation_y: f32) -> Gate {
+    let mut b = Builder::default();
+    let xor = b.xor(0.0, 0.5);
+
+    for (name, end, side) in [("a", xor.left_in, -1.0), ("b", xor.right_in, 1.0)] {
+        let (input, _) = b.line(&[point(side * xor.input_turn, 0.0), point(side * xor.input_turn, end.y), end]);
+        b.port(name, input, PortDirection::Input);
+    }
+
+    let (_, output) = b.line(&[xor.output, point(0.0, xor.output.y + 0.4)]);
+    b.port("out", output, PortDirection::Output);
+    b.build(origin, rotation_y)
+}
+
+/// `out` falls if `clock` falls but `in` does not. A domino line can not start a fall by itself,
+/// so the inverted signal is taken from the `clock` line, which has to be started together with
+/// `in`.
+pub fn not(origin: cgmath::Point3<f32>, rotation_y: f32) -> Gate {
+    let mut b = Builder::default();
+    let junction = b.junction(0.0, 0.5);
+
+    for (name, end, side) in [("clock", junction.left_in, -1.0), ("in", junction.right_in, 1.0)] {
+        let x = side * junction.input_turn;
+        let (input, _) = b.line(&[point(x, 0.0), point(x, end.y), end]);
+        b.port(name, input, PortDirection::Input);
+    }
+
+    let start = junction.right_out;
+    let x = junction.output_turn;
+    let (_, output) = b.line(&[start, point(x, start.y), point(x, start.y + 0.6)]);
+    b.port("out", output, PortDirection::Output);
+    b.build(origin, rotation_y)
+}
+
+/// `out` falls if both `a` and `b` fall. Both inputs have to be started together.
+///
+/// Built as `a and not (a and not b)`: `a` is split, one branch is inhibited by `b` in a first
+/// junction and the result inhibits the other branch of `a` in a second junction.
+pub fn and(origin: cgmath::Point3<f32>, rotation_y: f32) -> Gate {
+    let mut b = Builder::default();
+    let first = b.junction(0.0, 1.0);
+    let second = b.junction(0.0, 1.0 + 2.0 * CORNER_RADIUS + first.input_turn);
+    let (input_x, output_x) = (first.input_turn, first.output_turn);
+    let split = splitter(-input_x, 0.3);
+
+    let (a, _) = b.line(&[point(-input_x, 0.0), split.input]);
+    b.port("a", a, PortDirection::Input);
+    let (input_b, _) = b.line(&[point(input_x, 0.0), point(input_x, first.right_in.y), first.right_in]);
+    b.port("b", input_b, PortDirection::Input);
+
+    let a_inhibited = [
+        split.right_out,
+        point(split.right_out.x, first.left_in.y),
+        first.left_in,
+    ];
+    let inhibitor = [
+        first.right_out,
+        point(output_x, first.right_out.y),
+        point(output_x, first.right_out.y + CORNER_RADIUS),
+        point(input_x, first.right_out.y + CORNER_RADIUS + (input_x - output_x)),
+        point(input_x, second.right_in.y),
+        second.right_in,
+    ];
+    let inhibitor_length = path::length(&a_inhibited, CORNER_RADIUS) + 2.0 * (JUNCTION_HALF_LENGTH + SPACING) + path::length(&inhibitor, CORNER_RADIUS);
+
+    // the second branch of `a` has to arrive at the second junction together with the output
+    // of the first junction, so it takes a detour to the left
+    let corner = point(split.left_out.x, second.left_in.y);
+    let turn_length = path::length(&[corner, second.left_in], CORNER_RADIUS);
+    let mut a_direct = path::detour(split.left_out, corner, inhibitor_length - turn_length, -1.0, CORNER_RADIUS);
+    a_direct.push(second.left_in);
+
+    b.line(&a_inhibited);
+    b.line(&inhibitor);
+    b.line(&a_direct);
+
+    let start = second.right_out;
+    let (_, output) = b.line(&[start, point(output_x, start.y), point(output_x, start.y + 0.6)]);
+    b.port("out", output, PortDirection::Output);
+    b.build(origin, rotation_y)
+}
+
+/// Two lines crossing each other: `out_a` (on the right) falls if `a` (on the left) falls and
+/// `out_b` (on the left) falls if `b` (on the right) falls. Both inputs have to be started
+/// together.
+///
+/// Built from three xors: `c = a xor b`, `out_b = a xor c` and `out_a = c xor b`.
+pub fn crossover(origin: cgmath::Point3<f32>, rotation_y: f32) -> Gate {
+    let mut b = Builder::default();
+    let center = b.xor(0.0, 1.0);
+    let split_c = splitter(0.0, center.output.y + 0.3);
+    let side_x = center.input_turn + 0.2;
+    let sides = [b.xor(-side_x, split_c.input.y + 0.8), b.xor(side_x, split_c.input.y + 0.8)];
+    let input_x = side_x + center.input_turn;
+
+    let c = [center.output, split_c.input];
+    b.line(&c);
+    let c_length = center.length + path::length(&c, CORNER_RADIUS) + SPACING;
+
+    for (name, side) in [("a", -1.0), ("b", 1.0)] {
+        let split = splitter(side * input_x, 0.3);
+        let (input, _) = b.line(&[point(side * input_x, 0.0), split.input]);
+        b.port(name, input, PortDirection::Input);
+
+        // the inner branch feeds the center xor, the outer one the xor on the same side
+        let (inner, outer) = if side < 0.0 { (split.right_out, split.left_out) } else { (split.left_out, split.right_out) };
+        let (center_in, side_in, c_in) = if side < 0.0 {
+            (center.left_in, sides[0].left_in, sides[0].right_in)
+        } else {
+            (center.right_in, sides[1].right_in, sides[1].left_in)
+        };
+        let inner_line = [inner, point(inner.x, center_in.y), center_in];
+        b.line(&inner_line);
+
+        let c_start = if side < 0.0 { split_c.left_out } else { split_c.right_out };
+        let c_line = [c_start, point(c_start.x, c_in.y), c_in];
+        b.line(&c_line);
+
+        // the outer branch has to arrive at its xor together with the output of the center xor
+        let c_arrival = path::length(&inner_line, CORNER_RADIUS) + c_length + path::length(&c_line, CORNER_RADIUS);
+        let corner = point(outer.x, side_in.y);
+        let turn_length = path::length(&[corner, side_in], CORNER_RADIUS);
+        let mut outer_line = path::detour(outer, corner, c_arrival - turn_length, side, CORNER_RADIUS);
+        outer_line.push(side_in);
+        b.line(&outer_line);
+    }
+
+    for (name, xor) in [("out_b", &sides[0]), ("out_a", &sides[1])] {
+        let (_, output) = b.line(&[xor.output, point(xor.output.x, xor.output.y + 0.4)]);
+        b.port(name, output, PortDirection::Output);
+    }
+    b.build(origin, rotation_y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::{truth_table::{TruthTable, DEFAULT_DT, DEFAULT_MAX_DURATION}, Simulator};
+
+    /// Simulates every input combination of `gate` and compares the fallen outputs with `f`,
+    /// which gets the inputs in the order of `inputs`.
+    fn assert_truth_table(gate: GateFn, inputs: &[&str], outputs: &[&str], f: impl Fn(&[bool]) -> Vec<bool>) {
+        let mut simulator = Simulator::empty();
+        simulator.add_gate(&gate(cgmath::point3(0.0, 0.0, 0.0), 0.0), "gate");
+        let table = simulator.truth_table(DEFAULT_DT, DEFAULT_MAX_DURATION).unwrap();
+
+        let names = |ports: &[&str]| -> Vec<String> { ports.iter().map(|p| format!("gate.{}", p)).collect() };
+        let (inputs, outputs) = (names(inputs), names(outputs));
+        let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+        let outputs: Vec<&str> = outputs.iter().map(String::as_str).collect();
+        let expected = TruthTable::from_fn(&inputs, &outputs, f);
+        assert_eq!(table.diff(&expected), Ok(vec![]), "\n{}", table);
+    }
+
+    #[test]
+    fn and_matches_its_truth_table() {
+        assert_truth_table(and, &["a", "b"], &["out"], |i| vec![i[0] && i[1]]);
+    }
+
+    #[test]
+    fn or_matches_its_truth_table() {
+        assert_truth_table(or, &["a", "b"], &["out"], |i| vec![i[0] || i[1]]);
+    }
+
+    #[test]
+    fn xor_matches_its_truth_table() {
+        assert_truth_table(xor, &["a", "b"], &["out"], |i| vec![i[0] ^ i[1]]);
+    }
+
+    #[test]
+    fn not_matches_its_truth_table() {
+        assert_truth_table(not, &["clock", "in"], &["out"], |i| vec![i[0] && !i[1]]);
+    }
+
+    #[test]
+    fn fan_out_matches_its_truth_table() {
+        assert_truth_table(fan_out, &["in"], &["out_left", "out_right"], |i| vec![i[0], i[0]]);
+    }
+
+    #[test]
+    fn crossover_matches_its_truth_table() {
+        assert_truth_table(crossover, &["a", "b"], &["out_a", "out_b"], |i| vec![i[0], i[1]]);
+    }
+}
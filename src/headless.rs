@@ -0,0 +1,354 @@
+//! Simulation of a layout without opening a window, e.g. to test circuits on machines without a
+//! display.
+//!
+//! ```text
+//! domino-logic-simulator --headless <layout file> [--trigger <input port>]... [--duration <s>] [--dt <s>]
+//! domino-logic-simulator --headless <layout file> --truth-table [--duration <s>] [--dt <s>]
+//! domino-logic-simulator --headless <layout file> --timing [--duration <s>] [--dt <s>]
+//! domino-logic-simulator --headless <layout file> --netlist <dot | json>
+//! domino-logic-simulator --headless <layout file> --synthesize <description file>
+//! ```
+//!
+//! `--dt` can not be longer than `--duration`, and `--duration` can be at most [`MAX_STEPS`] steps
+//! of `--dt`.
+//!
+//! All triggered input ports are pushed at the start of the simulation. After the simulation a
+//! JSON report is printed to stdout that lists every output port and the time its domino fell at
+//! (`null` if it did not fall):
+//!
+//! ```json
+//! {
+//!   "duration": 5.0,
+//!   "dt": 0.001,
+//!   "outputs": [
+//!     { "name": "and0.out", "domino_id": 12, "fell_at": 3.25 }
+//!   ]
+//! }
+//! ```
+//!
+//! With `--truth-table` every combination of the input ports is simulated instead, for at most
+//! `--duration` seconds each, and the
+//! [`TruthTable`](domino_logic_simulator::simulator::truth_table::TruthTable) of the layout is printed as JSON.
+//! `--timing` simulates them the same way and prints the
+//! [`TimingReport`](domino_logic_simulator::simulator::timing::TimingReport) with the gate delays and the critical
+//! path.
+//!
+//! `--netlist` does not simulate the layout, it prints the [`Netlist`](domino_logic_simulator::netlist::Netlist)
+//! extracted from it as Graphviz DOT or JSON.
+//!
+//! `--synthesize` compiles the circuit described in the given file (see [`domino_logic_simulator::synthesis`])
+//! into a layout, saves it to the layout file and prints the truth table it is expected to have
+//! as JSON, in the same format as `--truth-table`.
+
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use domino_logic_simulator::{layout, netlist, simulator::{PortDirection, Simulator}, synthesis::Circuit};
+
+/// Simulated time if no `--duration` is given, in seconds.
+const DEFAULT_DURATION: f32 = 10.0;
+/// Time step if no `--dt` is given, in seconds.
+const DEFAULT_DT: f32 = 0.001;
+/// Most steps of `--dt` that fit into `--duration`, e.g. a day in the default steps.
+const MAX_STEPS: f32 = 86_400_000.0;
+
+const USAGE: &str = "usage: domino-logic-simulator --headless <layout file> [--trigger <input port>]... [--truth-table | --timing | --netlist <dot | json> | --synthesize <description file>] [--duration <seconds>] [--dt <seconds>]";
+
+/// Format `--netlist` prints the netlist in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetlistFormat {
+    Dot,
+    Json,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub layout: PathBuf,
+    pub triggers: Vec<String>,
+    /// Print the truth table instead of simulating the triggered inputs.
+    pub truth_table: bool,
+    /// Print the timing report instead of simulating the triggered inputs.
+    pub timing: bool,
+    /// Print the netlist instead of simulating the layout.
+    pub netlist: Option<NetlistFormat>,
+    /// Compile the circuit described in this file into the layout file instead of loading it.
+    pub synthesize: Option<PathBuf>,
+    pub duration: f32,
+    pub dt: f32,
+}
+
+impl Options {
+    /// Parses the command line arguments following `--headless`.
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut layout = None;
+        let mut triggers = vec![];
+        let mut truth_table = false;
+        let mut timing = false;
+        let mut netlist = None;
+        let mut synthesize = None;
+        let mut duration = DEFAULT_DURATION;
+        let mut dt = DEFAULT_DT;
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+            match arg.as_str() {
+                "--trigger" => triggers.push(value("--trigger")?),
+                "--truth-table" => truth_table = true,
+                "--timing" => timing = true,
+                "--netlist" => netlist = Some(match value("--netlist")?.as_str() {
+                    "dot" => NetlistFormat::Dot,
+                    "json" => NetlistFormat::Json,
+                    other => return Err(format!("--netlist needs dot or json, got {}", other)),
+                }),
+                "--synthesize" => synthesize = Some(PathBuf::from(value("--synthesize")?)),
+                "--duration" => duration = parse_seconds("--duration", &value("--duration")?)?,
+                "--dt" => dt = parse_seconds("--dt", &value("--dt")?)?,
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ if layout.is_none() => layout = Some(PathBuf::from(arg)),
+                _ => return Err(format!("unexpected argument {}", arg)),
+            }
+        }
+
+        if truth_table && !triggers.is_empty() {
+            return Err("--trigger can not be combined with --truth-table".to_string());
+        }
+        if timing && !triggers.is_empty() {
+            return Err("--trigger can not be combined with --timing".to_string());
+        }
+        if truth_table && timing {
+            return Err("--truth-table can not be combined with --timing".to_string());
+        }
+        if netlist.is_some() && (truth_table || timing || !triggers.is_empty()) {
+            return Err("--netlist can not be combined with --trigger, --truth-table or --timing".to_string());
+        }
+        if synthesize.is_some() && (truth_table || timing || netlist.is_some() || !triggers.is_empty()) {
+            return Err("--synthesize can not be combined with --trigger, --truth-table, --timing or --netlist".to_string());
+        }
+        if dt > duration {
+            return Err(format!("--dt ({} s) can not be longer than --duration ({} s)", dt, duration));
+        }
+        if duration / dt > MAX_STEPS {
+            return Err(format!("--duration / --dt is limited to {} steps, got {}", MAX_STEPS, (duration / dt).round()));
+        }
+
+        Ok(Options {
+            layout: layout.ok_or("no layout file given")?,
+            triggers,
+            truth_table,
+            timing,
+            netlist,
+            synthesize,
+            duration,
+            dt,
+        })
+    }
+}
+
+fn parse_seconds(name: &str, value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(v) if v.is_finite() && v > 0.0 => Ok(v),
+        _ => Err(format!("{} needs a positive number of seconds, got {}", name, value)),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct OutputFall {
+    pub name: String,
+    pub domino_id: u32,
+    /// Simulated time at which the domino of the port fell, in seconds.
+    pub fell_at: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub duration: f32,
+    pub dt: f32,
+    pub outputs: Vec<OutputFall>,
+}
+
+/// Triggers the input ports `triggers`, simulates `duration` seconds in steps of `dt` and records
+/// when the dominos of the output ports fell.
+pub fn run(simulator: &mut Simulator, triggers: &[String], duration: f32, dt: f32) -> Result<Report, String> {
+    for name in triggers {
+        if !simulator.trigger(name) {
+            return Err(format!("the layout has no input port named \"{}\"", name));
+        }
+    }
+
+    let mut outputs: Vec<OutputFall> = simulator.ports.iter()
+        .filter(|p| p.direction == PortDirection::Output)
+        .map(|p| OutputFall { name: p.name.clone(), domino_id: p.domino_id, fell_at: None })
+        .collect();
+
+    let steps = (duration / dt).round() as usize;
+    for step in 1..=steps {
+        simulator.step(dt);
+        let time = step as f32 * dt;
+        for output in outputs.iter_mut().filter(|o| o.fell_at.is_none()) {
+            if simulator.dominos.iter().any(|d| d.id == output.domino_id && d.has_fallen()) {
+                output.fell_at = Some(time);
+            }
+        }
+    }
+
+    Ok(Report { duration, dt, outputs })
+}
+
+/// Runs the headless mode with the command line arguments following `--headless` and returns the
+/// exit code of the process.
+pub fn main(args: impl Iterator<Item = String>) -> i32 {
+    let options = match Options::parse(args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return 2;
+        }
+    };
+
+    if let Some(description) = &options.synthesize {
+        return synthesize(description, &options.layout);
+    }
+
+    let mut simulator = match layout::load(&options.layout) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("could not load {}: {}", options.layout.display(), e);
+            return 1;
+        }
+    };
+
+    if let Some(format) = options.netlist {
+        let netlist = netlist::extract(&simulator);
+        match format {
+            NetlistFormat::Dot => print!("{}", netlist.to_dot()),
+            NetlistFormat::Json => println!("{}", serde_json::to_string_pretty(&netlist).expect("netlists only contain serializable values")),
+        }
+        return 0;
+    }
+
+    if options.truth_table {
+        return match simulator.truth_table(options.dt, options.duration) {
+            Ok(table) => {
+                println!("{}", serde_json::to_string_pretty(&table).expect("truth tables only contain serializable values"));
+                0
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        };
+    }
+
+    if options.timing {
+        return match simulator.timing(options.dt, options.duration) {
+            Ok(report) => {
+                println!("{}", serde_json::to_string_pretty(&report).expect("timing reports only contain serializable values"));
+                0
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        };
+    }
+
+    match run(&mut simulator, &options.triggers, options.duration, options.dt) {
+        Ok(report) => {
+            println!("{}", serde_json::to_string_pretty(&report).expect("reports only contain serializable values"));
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+/// Compiles the circuit described in the file `description` into a layout saved to `layout_path`
+/// and prints its expected truth table. Returns the exit code of the process.
+fn synthesize(description: &std::path::Path, layout_path: &std::path::Path) -> i32 {
+    let circuit = match std::fs::read_to_string(description).map_err(|e| e.to_string()).and_then(|text| Circuit::parse(&text)) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("could not compile {}: {}", description.display(), e);
+            return 1;
+        }
+    };
+    if let Err(e) = layout::save(&circuit.layout(), layout_path) {
+        eprintln!("could not save {}: {}", layout_path.display(), e);
+        return 1;
+    }
+    println!("{}", serde_json::to_string_pretty(&circuit.truth_table()).expect("truth tables only contain serializable values"));
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use domino_logic_simulator::simulator::{Domino, Port};
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn parses_the_options() {
+        let options = parse(&["layout.json", "--trigger", "a", "--trigger", "b", "--duration", "2", "--dt", "0.01"]).unwrap();
+        assert_eq!(options.layout, PathBuf::from("layout.json"));
+        assert_eq!(options.triggers, ["a", "b"]);
+        assert_eq!((options.duration, options.dt), (2.0, 0.01));
+
+        let options = parse(&["layout.json", "--netlist", "dot"]).unwrap();
+        assert_eq!(options.netlist, Some(NetlistFormat::Dot));
+        assert_eq!((options.duration, options.dt), (DEFAULT_DURATION, DEFAULT_DT));
+    }
+
+    #[test]
+    fn conflicting_options_are_rejected() {
+        for (args, error) in [
+            (&["l", "--truth-table", "--trigger", "a"][..], "--trigger can not be combined with --truth-table"),
+            (&["l", "--timing", "--trigger", "a"], "--trigger can not be combined with --timing"),
+            (&["l", "--truth-table", "--timing"], "--truth-table can not be combined with --timing"),
+            (&["l", "--netlist", "json", "--timing"], "--netlist can not be combined"),
+            (&["l", "--synthesize", "c.txt", "--netlist", "json"], "--synthesize can not be combined"),
+        ] {
+            assert!(parse(args).unwrap_err().starts_with(error), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        assert_eq!(parse(&["l", "--trigger"]).unwrap_err(), "--trigger needs a value");
+        assert_eq!(parse(&["l", "--netlist", "svg"]).unwrap_err(), "--netlist needs dot or json, got svg");
+        assert!(parse(&["l", "--dt", "-1"]).unwrap_err().starts_with("--dt needs a positive number"));
+        assert!(parse(&["l", "--duration", "0.001", "--dt", "0.01"]).unwrap_err().starts_with("--dt (0.01 s) can not be longer"));
+        assert!(parse(&["l", "--duration", "1e9"]).unwrap_err().starts_with("--duration / --dt is limited"));
+        assert_eq!(parse(&["--trigger", "a"]).unwrap_err(), "no layout file given");
+        assert_eq!(parse(&["l", "m"]).unwrap_err(), "unexpected argument m");
+    }
+
+    #[test]
+    fn run_records_when_the_outputs_fell() {
+        let dominos = (0..3).map(|i| Domino::new(i, cgmath::point3(0.0, 0.0, i as f32 * 0.08), 0.0)).collect();
+        let mut simulator = Simulator {
+            dominos,
+            ports: vec![
+                Port { name: "in".to_string(), domino_id: 0, direction: PortDirection::Input },
+                Port { name: "out".to_string(), domino_id: 2, direction: PortDirection::Output },
+            ],
+            ..Simulator::empty()
+        };
+        let report = run(&mut simulator.clone(), &[], 2.0, 0.001).unwrap();
+        assert_eq!(report.outputs[0].fell_at, None);
+
+        let report = run(&mut simulator, &["in".to_string()], 2.0, 0.001).unwrap();
+        assert_eq!(report.outputs.len(), 1);
+        assert_eq!(report.outputs[0].name, "out");
+        let fell_at = report.outputs[0].fell_at.unwrap();
+        assert!(fell_at > 0.0 && fell_at < 2.0, "{}", fell_at);
+
+        assert!(run(&mut Simulator::empty(), &["in".to_string()], 1.0, 0.001).unwrap_err().contains("\"in\""));
+    }
+}
@@ -0,0 +1,455 @@
+//! Undo and redo of edits to a layout.
+//!
+//! Every edit is described by a [`Command`] that can be applied to a [`Simulator`] and inverted.
+//! Continuous edits, like dragging a slider, record one command per frame; these are coalesced
+//! into a single history entry as long as they share the same key and
+//! [`History::end_coalescing`] is not called in between.
+
+use crate::simulator::{component::Component, light::Light, terrain::Obstacle, Domino, Port, Simulator};
+
+/// Offset of duplicated dominos from the originals, in meters.
+pub const DUPLICATE_OFFSET: cgmath::Vector3<f32> = cgmath::Vector3 { x: 0.2, y: 0.0, z: 0.2 };
+
+/// Number of edits that can be undone.
+const MAX_ENTRIES: usize = 1000;
+
+#[derive(Clone, Debug)]
+pub enum Command {
+    /// Adds dominos and ports at the end of the layout.
+    Create { dominos: Vec<Domino>, ports: Vec<Port> },
+    /// Removes dominos and ports, each together with its index in the layout so that undoing
+    /// the deletion puts it back in place.
+    Delete { dominos: Vec<(usize, Domino)>, ports: Vec<(usize, Port)> },
+    /// Inserts dominos and ports at ascending indices, the inverse of [`Command::Delete`]. Indices
+    /// past the end append.
+    Insert { dominos: Vec<(usize, Domino)>, ports: Vec<(usize, Port)> },
+    /// Replaces the dominos with the ids of `after` (which are in the state `before`).
+    Modify { before: Vec<Domino>, after: Vec<Domino> },
+    /// Replaces all obstacles of the terrain (which are `before`) by `after`.
+    Terrain { before: Vec<Obstacle>, after: Vec<Obstacle> },
+    /// Replaces all components (which are `before`) by `after`.
+    Components { before: Vec<Box<dyn Component>>, after: Vec<Box<dyn Component>> },
+    /// Replaces all lights (which are `before`) by `after`.
+    Lights { before: Vec<Light>, after: Vec<Light> },
+    /// Several commands applied one after another as a single edit.
+    Group(Vec<Command>),
+}
+
+impl Command {
+    /// Deletes the dominos with the given ids together with their ports.
+    pub fn delete(simulator: &Simulator, ids: &[u32]) -> Self {
+        Command::Delete {
+            dominos: simulator.dominos.iter().cloned().enumerate().filter(|(_, d)| ids.contains(&d.id)).collect(),
+            ports: simulator.ports.iter().cloned().enumerate().filter(|(_, p)| ids.contains(&p.domino_id)).collect(),
+        }
+    }
+
+    /// Replaces the dominos, ports, obstacles and components of `simulator` by those of `layout`,
+    /// e.g. with a synthesized circuit. The lights are kept.
+    pub fn replace(simulator: &Simulator, layout: Simulator) -> Self {
+        Command::Group(vec![
+            Command::Delete {
+                dominos: simulator.dominos.iter().cloned().enumerate().collect(),
+                ports: simulator.ports.iter().cloned().enumerate().collect(),
+            },
+            Command::Create { dominos: layout.dominos, ports: layout.ports },
+            Command::Terrain { before: simulator.obstacles.clone(), after: layout.obstacles },
+            Command::Components { before: simulator.components.clone(), after: layout.components },
+        ])
+    }
+
+    /// Moves the dominos with the given ids by `offset` and rotates them by `rotation_y` degrees
+    /// around their common center.
+    pub fn transform(simulator: &Simulator, ids: &[u32], offset: cgmath::Vector3<f32>, rotation_y: f32) -> Self {
+        let before: Vec<Domino> = simulator.dominos.iter().filter(|d| ids.contains(&d.id)).cloned().collect();
+        let center = simulator.center_of(ids).unwrap_or(cgmath::point3(0.0, 0.0, 0.0));
+        let after = before.iter().map(|d| {
+            let mut d = d.clone();
+            d.rotate_around(center, rotation_y);
+            d.position += offset;
+            d
+        }).collect();
+        Command::Modify { before, after }
+    }
+
+    /// Copies the dominos with the given ids, standing upright, with fresh ids at [`DUPLICATE_OFFSET`].
+    /// Ports are not copied since their names must be unique.
+    pub fn duplicate(simulator: &Simulator, ids: &[u32]) -> Self {
+        let first_id = simulator.next_id();
+        let dominos = simulator.dominos.iter().filter(|d| ids.contains(&d.id)).zip(first_id..).map(|(d, id)| Domino {
+            id,
+            position: d.position + DUPLICATE_OFFSET,
+            fall_rotation: 0.0,
+            angular_velocity: 0.0,
+            ..d.clone()
+        }).collect();
+        Command::Create { dominos, ports: vec![] }
+    }
+
+    pub fn apply(&self, simulator: &mut Simulator) {
+        match self {
+            Command::Create { dominos, ports } => {
+                simulator.dominos.extend(dominos.iter().cloned());
+                simulator.ports.extend(ports.iter().cloned());
+            }
+            Command::Delete { dominos, ports } => {
+                simulator.dominos.retain(|d| !dominos.iter().any(|(_, deleted)| deleted.id == d.id));
+                simulator.ports.retain(|p| !ports.iter().any(|(_, deleted)| deleted.name == p.name));
+            }
+            Command::Insert { dominos, ports } => {
+                for (index, d) in dominos {
+                    simulator.dominos.insert((*index).min(simulator.dominos.len()), d.clone());
+                }
+                for (index, p) in ports {
+                    simulator.ports.insert((*index).min(simulator.ports.len()), p.clone());
+                }
+            }
+            Command::Modify { after, .. } => {
+                for modified in after {
+                    if let Some(d) = simulator.dominos.iter_mut().find(|d| d.id == modified.id) {
+                        *d = modified.clone();
+                    }
+                }
+            }
+            Command::Terrain { after, .. } => simulator.obstacles = after.clone(),
+            Command::Components { after, .. } => simulator.components = after.clone(),
+            Command::Lights { after, .. } => simulator.lights = after.clone(),
+            Command::Group(commands) => {
+                for command in commands {
+                    command.apply(simulator);
+                }
+            }
+        }
+    }
+
+    /// The command that reverts this one.
+    pub fn inverse(&self) -> Self {
+        match self {
+            Command::Create { dominos, ports } => Command::Delete {
+                // created dominos and ports are appended, so they are restored at the end
+                dominos: dominos.iter().map(|d| (usize::MAX, d.clone())).collect(),
+                ports: ports.iter().map(|p| (usize::MAX, p.clone())).collect(),
+            },
+            Command::Delete { dominos, ports } => Command::Insert { dominos: dominos.clone(), ports: ports.clone() },
+            Command::Insert { dominos, ports } => Command::Delete { dominos: dominos.clone(), ports: ports.clone() },
+            Command::Modify { before, after } => Command::Modify { before: after.clone(), after: before.clone() },
+            Command::Terrain { before, after } => Command::Terrain { before: after.clone(), after: before.clone() },
+            Command::Components { before, after } => Command::Components { before: after.clone(), after: before.clone() },
+            Command::Lights { before, after } => Command::Lights { before: after.clone(), after: before.clone() },
+            Command::Group(commands) => Command::Group(commands.iter().rev().map(|c| c.inverse()).collect()),
+        }
+    }
+
+    /// Extends this command by `next`, which was applied after it. Returns `false` if the two
+    /// commands can not be merged.
+    fn merge(&mut self, next: &Command) -> bool {
+        match (self, next) {
+            (Command::Modify { before, after }, Command::Modify { before: next_before, after: next_after }) => {
+                for d in next_before {
+                    if !before.iter().any(|b| b.id == d.id) {
+                        before.push(d.clone());
+                    }
+                }
+                for d in next_after {
+                    match after.iter_mut().find(|a| a.id == d.id) {
+                        Some(a) => *a = d.clone(),
+                        None => after.push(d.clone()),
+                    }
+                }
+                true
+            }
+            (Command::Terrain { after, .. }, Command::Terrain { after: next_after, .. }) => {
+                *after = next_after.clone();
+                true
+            }
+            (Command::Components { after, .. }, Command::Components { after: next_after, .. }) => {
+                *after = next_after.clone();
+                true
+            }
+            (Command::Lights { after, .. }, Command::Lights { after: next_after, .. }) => {
+                *after = next_after.clone();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+struct Entry {
+    command: Command,
+    /// Commands recorded with the same key are merged into this entry.
+    coalesce_key: Option<String>,
+}
+
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Entry>,
+    redo: Vec<Command>,
+    /// `false` once the last entry must not be extended anymore.
+    coalescing: bool,
+}
+
+impl History {
+    /// Applies `command` to `simulator` and records it.
+    pub fn execute(&mut self, simulator: &mut Simulator, command: Command) {
+        command.apply(simulator);
+        self.record(command);
+    }
+
+    /// Records a command that was already applied.
+    pub fn record(&mut self, command: Command) {
+        self.push(Entry { command, coalesce_key: None });
+    }
+
+    /// Records a command that was already applied and merges it into the last entry if that was
+    /// recorded with the same `key` since the last call to [`History::end_coalescing`].
+    pub fn record_coalesced(&mut self, key: &str, command: Command) {
+        if self.coalescing {
+            if let Some(last) = self.undo.last_mut() {
+                if last.coalesce_key.as_deref() == Some(key) && last.command.merge(&command) {
+                    self.redo.clear();
+                    return;
+                }
+            }
+        }
+        self.push(Entry { command, coalesce_key: Some(key.to_string()) });
+        self.coalescing = true;
+    }
+
+    /// Finishes the current continuous edit, the next command starts a new entry.
+    pub fn end_coalescing(&mut self) {
+        self.coalescing = false;
+    }
+
+    fn push(&mut self, entry: Entry) {
+        self.undo.push(entry);
+        if self.undo.len() > MAX_ENTRIES {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+        self.coalescing = false;
+    }
+
+    /// Reverts the last edit. Returns `false` if there is nothing to undo.
+    pub fn undo(&mut self, simulator: &mut Simulator) -> bool {
+        self.coalescing = false;
+        match self.undo.pop() {
+            Some(entry) => {
+                entry.command.inverse().apply(simulator);
+                self.redo.push(entry.command);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Applies the last undone edit again. Returns `false` if there is nothing to redo.
+    pub fn redo(&mut self, simulator: &mut Simulator) -> bool {
+        self.coalescing = false;
+        match self.redo.pop() {
+            Some(command) => {
+                command.apply(simulator);
+                self.undo.push(Entry { command, coalesce_key: None });
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Forgets all edits, e.g. after another layout was loaded.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.coalescing = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::InnerSpace;
+
+    use super::*;
+    use crate::simulator::{terrain::ObstacleKind, PortDirection};
+
+    fn ids(simulator: &Simulator) -> Vec<u32> {
+        simulator.dominos.iter().map(|d| d.id).collect()
+    }
+
+    /// Sets the intensity of the first light like a slider does, one coalesced command per frame.
+    fn drag_intensity(history: &mut History, simulator: &mut Simulator, intensity: f32) {
+        let before = simulator.lights.clone();
+        let mut after = before.clone();
+        after[0].intensity = intensity;
+        let command = Command::Lights { before, after };
+        command.apply(simulator);
+        history.record_coalesced("intensity", command);
+    }
+
+    fn move_domino(simulator: &Simulator, id: u32, x: f32) -> Command {
+        let before: Vec<Domino> = simulator.dominos.iter().filter(|d| d.id == id).cloned().collect();
+        let mut after = before.clone();
+        after[0].position.x = x;
+        Command::Modify { before, after }
+    }
+
+    #[test]
+    fn slider_drags_are_undone_at_once() {
+        let mut simulator = Simulator::new();
+        let mut history = History::default();
+        for intensity in [1.5, 2.0, 2.5] {
+            drag_intensity(&mut history, &mut simulator, intensity);
+        }
+        assert_eq!(simulator.lights[0].intensity, 2.5);
+
+        assert!(history.undo(&mut simulator));
+        assert_eq!(simulator.lights[0].intensity, 1.0);
+        assert!(!history.can_undo());
+        assert!(history.redo(&mut simulator));
+        assert_eq!(simulator.lights[0].intensity, 2.5);
+    }
+
+    #[test]
+    fn ending_a_drag_starts_a_new_entry() {
+        let mut simulator = Simulator::new();
+        let mut history = History::default();
+        drag_intensity(&mut history, &mut simulator, 1.5);
+        drag_intensity(&mut history, &mut simulator, 2.0);
+        history.end_coalescing();
+        drag_intensity(&mut history, &mut simulator, 3.0);
+
+        assert!(history.undo(&mut simulator));
+        assert_eq!(simulator.lights[0].intensity, 2.0);
+        assert!(history.undo(&mut simulator));
+        assert_eq!(simulator.lights[0].intensity, 1.0);
+        assert!(!history.undo(&mut simulator));
+    }
+
+    #[test]
+    fn created_dominos_can_be_undone_and_redone() {
+        let mut simulator = Simulator::new();
+        let mut history = History::default();
+        let command = Command::duplicate(&simulator, &[1, 2]);
+        history.execute(&mut simulator, command);
+        assert_eq!(ids(&simulator), [0, 1, 2, 3, 4, 5]);
+        // duplicates stand upright next to the originals
+        assert_eq!(simulator.dominos[4].position, simulator.dominos[1].position + DUPLICATE_OFFSET);
+        assert!(simulator.dominos[4..].iter().all(|d| d.fall_rotation == 0.0 && d.angular_velocity == 0.0));
+
+        assert!(history.undo(&mut simulator));
+        assert_eq!(ids(&simulator), [0, 1, 2, 3]);
+        assert!(history.redo(&mut simulator));
+        assert_eq!(ids(&simulator), [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn deleted_dominos_are_restored_in_place() {
+        let mut simulator = Simulator::new();
+        simulator.ports.push(Port { name: "in".to_string(), domino_id: 0, direction: PortDirection::Input });
+        simulator.ports.push(Port { name: "mid".to_string(), domino_id: 1, direction: PortDirection::Output });
+        simulator.ports.push(Port { name: "out".to_string(), domino_id: 3, direction: PortDirection::Output });
+        let original = simulator.dominos.clone();
+
+        let mut history = History::default();
+        let command = Command::delete(&simulator, &[1, 2]);
+        history.execute(&mut simulator, command);
+        assert_eq!(ids(&simulator), [0, 3]);
+        assert_eq!(simulator.ports.len(), 2);
+
+        assert!(history.undo(&mut simulator));
+        assert_eq!(ids(&simulator), [0, 1, 2, 3]);
+        assert_eq!(simulator.dominos[2].fall_rotation, original[2].fall_rotation);
+        let names: Vec<&str> = simulator.ports.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["in", "mid", "out"]);
+
+        assert!(history.redo(&mut simulator));
+        assert_eq!(ids(&simulator), [0, 3]);
+    }
+
+    #[test]
+    fn modified_dominos_can_be_undone_and_redone() {
+        let mut simulator = Simulator::new();
+        let mut history = History::default();
+        let command = Command::transform(&simulator, &[0, 2], cgmath::vec3(0.0, 0.0, 1.0), 180.0);
+        history.execute(&mut simulator, command);
+        // both dominos swap places around their common center and move along z
+        assert!((simulator.dominos[0].position - cgmath::point3(0.0, 0.0, 3.0)).magnitude() < 1.0e-5);
+        assert!((simulator.dominos[2].position - cgmath::point3(0.0, 0.0, 1.0)).magnitude() < 1.0e-5);
+        assert_eq!(simulator.dominos[2].rotation_y, 270.0);
+
+        assert!(history.undo(&mut simulator));
+        let original = Simulator::new();
+        assert_eq!(simulator.dominos[0].position, original.dominos[0].position);
+        assert_eq!(simulator.dominos[2].position, original.dominos[2].position);
+        assert_eq!(simulator.dominos[2].rotation_y, original.dominos[2].rotation_y);
+        assert!(history.redo(&mut simulator));
+        assert_eq!(simulator.dominos[2].rotation_y, 270.0);
+    }
+
+    #[test]
+    fn a_new_edit_clears_the_redo() {
+        let mut simulator = Simulator::new();
+        let mut history = History::default();
+        let command = move_domino(&simulator, 0, 1.0);
+        history.execute(&mut simulator, command);
+        assert!(history.undo(&mut simulator));
+        assert!(history.can_redo());
+
+        let command = move_domino(&simulator, 0, 2.0);
+        history.execute(&mut simulator, command);
+        assert!(!history.can_redo());
+        assert!(!history.redo(&mut simulator));
+        assert_eq!(simulator.dominos[0].position.x, 2.0);
+    }
+
+    #[test]
+    fn only_the_last_entries_are_kept() {
+        let mut simulator = Simulator::new();
+        let mut history = History::default();
+        for i in 0..MAX_ENTRIES + 10 {
+            let command = move_domino(&simulator, 0, i as f32);
+            history.execute(&mut simulator, command);
+        }
+        let mut undone = 0;
+        while history.undo(&mut simulator) {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_ENTRIES);
+        assert_eq!(simulator.dominos[0].position.x, 9.0);
+    }
+
+    #[test]
+    fn replacing_the_layout_can_be_undone() {
+        let mut simulator = Simulator::new();
+        simulator.ports.push(Port { name: "in".to_string(), domino_id: 0, direction: PortDirection::Input });
+        simulator.obstacles.push(Obstacle::new(0, ObstacleKind::Wall, cgmath::point3(1.0, 0.0, 0.0), 0.0));
+        let original = ids(&simulator);
+
+        let mut layout = Simulator::empty();
+        layout.add_gate(&crate::gates::or(cgmath::point3(0.0, 0.0, 0.0), 0.0), "or");
+        let replaced = ids(&layout);
+
+        let mut history = History::default();
+        let command = Command::replace(&simulator, layout);
+        history.execute(&mut simulator, command);
+        assert_eq!(ids(&simulator), replaced);
+        assert_eq!(simulator.ports.len(), 3);
+        assert!(simulator.obstacles.is_empty());
+        assert_eq!(simulator.lights.len(), Simulator::new().lights.len());
+
+        assert!(history.undo(&mut simulator));
+        assert_eq!(ids(&simulator), original);
+        assert_eq!(simulator.ports.len(), 1);
+        assert_eq!(simulator.obstacles.len(), 1);
+
+        assert!(history.redo(&mut simulator));
+        assert_eq!(ids(&simulator), replaced);
+    }
+}
@@ -0,0 +1,130 @@
+//! Which dominos of a layout knock over which, judged from their geometry alone.
+//!
+//! A standing domino reaches the dominos in front of it that its top can hit when it falls, and a
+//! domino that is hit tips towards the side away from the domino that hit it. Following this from
+//! the input ports (and from the starts of the chains that are not connected to one) gives the
+//! [`KnockGraph`] of the layout.
+
+use std::collections::VecDeque;
+
+use cgmath::InnerSpace;
+
+use crate::{path, simulator::{Domino, PortDirection, Simulator}};
+
+/// Direction a standing domino falls towards on the ground plane when it tips to `side` (`1.0`
+/// for its local +z axis, `-1.0` for -z).
+pub fn forward(d: &Domino, side: f32) -> cgmath::Vector2<f32> {
+    path::direction_of(d.rotation_y) * side
+}
+
+/// Gap between the faces of `a` and `b` along the direction `a` falls towards when it tips to
+/// `side`, or `None` if `b` is not in front of `a` or too far to the side to be hit by its top.
+pub fn gap_in_front(a: &Domino, side: f32, b: &Domino) -> Option<f32> {
+    let forward = forward(a, side);
+    let sideways = cgmath::vec2(forward.y, -forward.x);
+    let offset = cgmath::vec2(b.position.x - a.position.x, b.position.z - a.position.z);
+    let (along, across) = (offset.dot(forward), offset.dot(sideways));
+
+    // half size of the footprint of `b` along and across the fall direction of `a`
+    let (sin, cos) = (b.rotation_y - a.rotation_y).to_radians().sin_cos();
+    let (a_dim, b_dim) = (a.dimensions(), b.dimensions());
+    let b_along = 0.5 * (cos.abs() * b_dim.z + sin.abs() * b_dim.x);
+    let b_across = 0.5 * (sin.abs() * b_dim.z + cos.abs() * b_dim.x);
+
+    if along <= 0.0 || across.abs() >= 0.5 * a_dim.x + b_across {
+        return None;
+    }
+    Some(along - b_along - 0.5 * a_dim.z)
+}
+
+/// Largest gap between the faces of `d` and the next domino at which `d` still knocks it over,
+/// the largest spacing of a line from [`path::spacing_range`].
+pub fn max_gap(d: &Domino) -> f32 {
+    let dim = d.dimensions();
+    path::spacing_range(dim).end() - dim.z
+}
+
+/// `true` if `a` knocks over `b` when it tips to `side`.
+pub fn reaches(a: &Domino, side: f32, b: &Domino) -> bool {
+    a.id != b.id && gap_in_front(a, side, b).is_some_and(|gap| gap <= max_gap(a))
+}
+
+/// Side a standing domino `d` tips to when it is hit by a domino falling towards `direction`.
+pub fn side_away_from(d: &Domino, direction: cgmath::Vector2<f32>) -> f32 {
+    if forward(d, 1.0).dot(direction) < 0.0 { -1.0 } else { 1.0 }
+}
+
+/// A way a domino can fall during a simulation.
+#[derive(Clone, Debug)]
+pub struct Fall {
+    /// Index of the domino in [`Simulator::dominos`].
+    pub index: usize,
+    /// Side the domino tips to, see [`forward`].
+    pub side: f32,
+    /// Direction of the fall of the domino that knocked it over, its own fall direction for the
+    /// starts of chains.
+    pub arrival: cgmath::Vector2<f32>,
+    /// Indices of the dominos it knocks over.
+    pub knocks: Vec<usize>,
+}
+
+pub struct KnockGraph {
+    /// Every way a domino can fall, in the order the chains reach them. Dominos of junctions are
+    /// knocked over from both sides depending on the inputs and appear twice.
+    pub falls: Vec<Fall>,
+    /// Indices of the dominos starting the chains that are not reached from an input port. They
+    /// are pushed forward.
+    pub unconnected_starts: Vec<usize>,
+}
+
+impl KnockGraph {
+    pub fn new(simulator: &Simulator) -> Self {
+        let dominos = &simulator.dominos;
+        let slot = |side: f32| if side > 0.0 { 0 } else { 1 };
+        let mut reached = vec![[false; 2]; dominos.len()];
+        let mut falls = vec![];
+        let mut visit = |start: usize, reached: &mut Vec<[bool; 2]>| {
+            if reached[start][0] {
+                return;
+            }
+            reached[start][0] = true;
+            let mut queue = VecDeque::from([(start, 1.0, forward(&dominos[start], 1.0))]);
+            while let Some((a, side, arrival)) = queue.pop_front() {
+                let direction = forward(&dominos[a], side);
+                let knocks: Vec<usize> = (0..dominos.len()).filter(|b| reaches(&dominos[a], side, &dominos[*b])).collect();
+                for b in &knocks {
+                    let b_side = side_away_from(&dominos[*b], direction);
+                    if !reached[*b][slot(b_side)] {
+                        reached[*b][slot(b_side)] = true;
+                        queue.push_back((*b, b_side, direction));
+                    }
+                }
+                falls.push(Fall { index: a, side, arrival, knocks });
+            }
+        };
+
+        for p in simulator.ports.iter().filter(|p| p.direction == PortDirection::Input) {
+            if let Some(start) = dominos.iter().position(|d| d.id == p.domino_id) {
+                visit(start, &mut reached);
+            }
+        }
+        // the other chains start at the dominos no domino of them reaches when pushed forward
+        let mut unconnected_starts = vec![];
+        for start in 0..dominos.len() {
+            let is_reached = |d: usize| reached[d].iter().any(|r| *r);
+            if is_reached(start) || (0..dominos.len()).any(|a| !is_reached(a) && reaches(&dominos[a], 1.0, &dominos[start])) {
+                continue;
+            }
+            unconnected_starts.push(start);
+            visit(start, &mut reached);
+        }
+
+        KnockGraph { falls, unconnected_starts }
+    }
+
+    /// Indices of the dominos the domino with the given index knocks over when it tips to `side`,
+    /// empty if no chain makes it fall that way.
+    pub fn knocks(&self, index: usize, side: f32) -> &[usize] {
+        self.falls.iter().find(|f| f.index == index && f.side == side).map_or(&[], |f| f.knocks.as_slice())
+    }
+}
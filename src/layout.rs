@@ -0,0 +1,502 @@
+//! On-disk format of domino layouts.
+//!
+//! A layout is a JSON document holding the full [`Simulator`] state:
+//!
+//! ```json
+//! {
+//!   "version": 2,
+//!   "dominos": [
+//!     {
+//!       "id": 0,
+//!       "position": [0.0, 0.0, 0.0],
+//!       "rotation_y": 90.0,
+//!       "fall_rotation": 0.0,
+//!       "angular_velocity": 0.0,
+//!       "scale": [1.0, 1.0, 1.0],
+//!       "material": { "mass": 0.1, "friction": 0.0, "restitution": 0.0, "color": [1.0, 0.0, 0.0] }
+//!     }
+//!   ],
+//!   "ports": [
+//!     { "name": "and0.a", "domino_id": 0, "direction": "input" }
+//!   ],
+//!   "obstacles": [
+//!     { "id": 0, "kind": "stairs", "steps": 4, "position": [0.5, 0.0, 0.0], "rotation_y": 0.0, "size": [0.3, 0.2, 0.8] }
+//!   ],
+//!   "components": [
+//!     { "id": 0, "kind": "ball", "position": [0.0, 0.02, -0.5], "radius": 0.02, "mass": 0.05, "velocity": [0.0, 1.0] }
+//!   ],
+//!   "lights": [
+//!     { "kind": "point", "position": [0.0, 2.0, 0.0], "color": [1.0, 1.0, 1.0], "intensity": 1.0 },
+//!     { "kind": "directional", "direction": [-1.0, -2.0, -1.0], "color": [1.0, 0.9, 0.8], "intensity": 0.5 }
+//!   ]
+//! }
+//! ```
+//!
+//! * `version` is the version of the format, files with a newer version than [`VERSION`] are
+//!   rejected. Version 1 had no `material`, `obstacles`, `components` and `lights` and is still
+//!   read,
+//! * `position` is the center of the base area of a domino in meters (x, y, z),
+//! * `rotation_y`, `fall_rotation` and `angular_velocity` are in degrees (per second), see
+//!   [`Domino`],
+//! * `scale` is the scale of the domino along its width, height and depth,
+//! * `material` holds the mass in kg at a scale of 1, the friction coefficient, the restitution
+//!   and the RGB colour of the domino, see [`Material`],
+//! * `kind` of an obstacle is `ramp`, `platform`, `stairs` (with the number of `steps`) or `wall`,
+//!   its `position` is the center of its base area and its `size` is its width, height and
+//!   length in meters, see [`Obstacle`],
+//! * `kind` of a component is `ball`, `lever` or `bridge`, the other values are the setup of
+//!   the component, see [`component`],
+//! * `kind` of a light is `point` (with its `position`) or `directional` (with the `direction`
+//!   it shines in), the first light casts the shadows, see [`Light`],
+//! * `angular_velocity`, `material`, `ports`, `obstacles` and `components` may be omitted,
+//!   dominos without a material are made of the
+//!   [standard material](crate::simulator::material::STANDARD), layouts without `lights` get
+//!   the [default lights](light::default_lights).
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::simulator::{component::{self, Component}, light::{self, Light, LightKind}, material::Material, terrain::{Obstacle, ObstacleKind}, Domino, Port, PortDirection, Simulator};
+
+/// Version of the layout format written by [`to_string`]. Version 2 added materials, obstacles,
+/// components and lights.
+pub const VERSION: u32 = 2;
+
+/// File extension of layout files.
+pub const EXTENSION: &str = "json";
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    UnsupportedVersion(u32),
+    Invalid(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "could not access the file: {}", e),
+            Error::Parse(e) => write!(f, "the file is not a valid layout: {}", e),
+            Error::UnsupportedVersion(v) if *v > VERSION => {
+                write!(f, "layout version {} was written by a newer version of the simulator, the newest supported version is {}", v, VERSION)
+            }
+            Error::UnsupportedVersion(v) => write!(f, "layout version {} is not supported, the supported versions are 1 to {}", v, VERSION),
+            Error::Invalid(reason) => write!(f, "the layout is invalid: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Parse(e)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct LayoutFile {
+    version: u32,
+    dominos: Vec<DominoEntry>,
+    #[serde(default)]
+    ports: Vec<PortEntry>,
+    #[serde(default)]
+    obstacles: Vec<ObstacleEntry>,
+    #[serde(default)]
+    components: Vec<serde_json::Value>,
+    #[serde(default = "default_light_entries")]
+    lights: Vec<LightEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum LightEntry {
+    Point { position: [f32; 3], color: [f32; 3], intensity: f32 },
+    Directional { direction: [f32; 3], color: [f32; 3], intensity: f32 },
+}
+
+impl From<Light> for LightEntry {
+    fn from(l: Light) -> Self {
+        match l.kind {
+            LightKind::Point { position } => LightEntry::Point { position: position.into(), color: l.color, intensity: l.intensity },
+            LightKind::Directional { direction } => LightEntry::Directional { direction: direction.into(), color: l.color, intensity: l.intensity },
+        }
+    }
+}
+
+impl From<LightEntry> for Light {
+    fn from(entry: LightEntry) -> Self {
+        match entry {
+            LightEntry::Point { position, color, intensity } => Light { kind: LightKind::Point { position: position.into() }, color, intensity },
+            LightEntry::Directional { direction, color, intensity } => Light { kind: LightKind::Directional { direction: direction.into() }, color, intensity },
+        }
+    }
+}
+
+fn default_light_entries() -> Vec<LightEntry> {
+    light::default_lights().into_iter().map(LightEntry::from).collect()
+}
+
+#[derive(Serialize, Deserialize)]
+struct DominoEntry {
+    id: u32,
+    position: [f32; 3],
+    rotation_y: f32,
+    fall_rotation: f32,
+    #[serde(default)]
+    angular_velocity: f32,
+    scale: [f32; 3],
+    #[serde(default)]
+    material: MaterialEntry,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MaterialEntry {
+    mass: f32,
+    friction: f32,
+    restitution: f32,
+    color: [f32; 3],
+}
+
+impl Default for MaterialEntry {
+    fn default() -> Self {
+        Material::default().into()
+    }
+}
+
+impl From<Material> for MaterialEntry {
+    fn from(m: Material) -> Self {
+        MaterialEntry { mass: m.mass, friction: m.friction, restitution: m.restitution, color: m.color }
+    }
+}
+
+impl From<MaterialEntry> for Material {
+    fn from(m: MaterialEntry) -> Self {
+        Material { mass: m.mass, friction: m.friction, restitution: m.restitution, color: m.color }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct PortEntry {
+    name: String,
+    domino_id: u32,
+    direction: DirectionEntry,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum DirectionEntry {
+    Input,
+    Output,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ObstacleEntry {
+    id: u32,
+    #[serde(flatten)]
+    kind: KindEntry,
+    position: [f32; 3],
+    rotation_y: f32,
+    size: [f32; 3],
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum KindEntry {
+    Ramp,
+    Platform,
+    Stairs { steps: u32 },
+    Wall,
+}
+
+impl From<ObstacleKind> for KindEntry {
+    fn from(kind: ObstacleKind) -> Self {
+        match kind {
+            ObstacleKind::Ramp => KindEntry::Ramp,
+            ObstacleKind::Platform => KindEntry::Platform,
+            ObstacleKind::Stairs { steps } => KindEntry::Stairs { steps },
+            ObstacleKind::Wall => KindEntry::Wall,
+        }
+    }
+}
+
+impl From<KindEntry> for ObstacleKind {
+    fn from(kind: KindEntry) -> Self {
+        match kind {
+            KindEntry::Ramp => ObstacleKind::Ramp,
+            KindEntry::Platform => ObstacleKind::Platform,
+            KindEntry::Stairs { steps } => ObstacleKind::Stairs { steps },
+            KindEntry::Wall => ObstacleKind::Wall,
+        }
+    }
+}
+
+/// Only the version of a file, to reject newer files before parsing the rest.
+#[derive(Deserialize)]
+struct VersionOnly {
+    version: u32,
+}
+
+/// Serializes the full state of `simulator` into the layout format.
+pub fn to_string(simulator: &Simulator) -> String {
+    let file = LayoutFile {
+        version: VERSION,
+        dominos: simulator.dominos.iter().map(|d| DominoEntry {
+            id: d.id,
+            position: d.position.into(),
+            rotation_y: d.rotation_y,
+            fall_rotation: d.fall_rotation,
+            angular_velocity: d.angular_velocity,
+            scale: d.scale.into(),
+            material: d.material.into(),
+        }).collect(),
+        ports: simulator.ports.iter().map(|p| PortEntry {
+            name: p.name.clone(),
+            domino_id: p.domino_id,
+            direction: match p.direction {
+                PortDirection::Input => DirectionEntry::Input,
+                PortDirection::Output => DirectionEntry::Output,
+            },
+        }).collect(),
+        obstacles: simulator.obstacles.iter().map(|o| ObstacleEntry {
+            id: o.id,
+            kind: o.kind.into(),
+            position: o.position.into(),
+            rotation_y: o.rotation_y,
+            size: o.size.into(),
+        }).collect(),
+        components: simulator.components.iter().map(|c| c.to_json()).collect(),
+        lights: simulator.lights.iter().map(|l| (*l).into()).collect(),
+    };
+    serde_json::to_string_pretty(&file).expect("layouts only contain serializable values")
+}
+
+/// Parses and validates a layout.
+pub fn from_str(text: &str) -> Result<Simulator, Error> {
+    let version = serde_json::from_str::<VersionOnly>(text)?.version;
+    if version == 0 || version > VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    let file: LayoutFile = serde_json::from_str(text)?;
+
+    let mut dominos: Vec<Domino> = Vec::with_capacity(file.dominos.len());
+    for entry in file.dominos {
+        let angles = [entry.rotation_y, entry.fall_rotation, entry.angular_velocity];
+        if entry.position.iter().chain(entry.scale.iter()).chain(angles.iter()).any(|v| !v.is_finite()) {
+            return Err(Error::Invalid(format!("domino {} has a value that is not a finite number", entry.id)));
+        }
+        if entry.fall_rotation.abs() > 90.0 {
+            return Err(Error::Invalid(format!("the fall rotation of domino {} is not between -90 and 90 degrees", entry.id)));
+        }
+        if entry.scale.iter().any(|s| *s <= 0.0) {
+            return Err(Error::Invalid(format!("domino {} has a scale that is not positive", entry.id)));
+        }
+        let material: Material = entry.material.into();
+        if let Some(reason) = material.validate() {
+            return Err(Error::Invalid(format!("the material of domino {} is invalid: {}", entry.id, reason)));
+        }
+        if dominos.iter().any(|d| d.id == entry.id) {
+            return Err(Error::Invalid(format!("the id {} is used by more than one domino", entry.id)));
+        }
+
+        let mut domino = Domino::new(entry.id, entry.position.into(), entry.rotation_y);
+        domino.fall_rotation = entry.fall_rotation;
+        domino.angular_velocity = entry.angular_velocity;
+        domino.scale = entry.scale.into();
+        domino.material = material;
+        dominos.push(domino);
+    }
+
+    let mut ports: Vec<Port> = Vec::with_capacity(file.ports.len());
+    for entry in file.ports {
+        if !dominos.iter().any(|d| d.id == entry.domino_id) {
+            return Err(Error::Invalid(format!("port \"{}\" refers to domino {}, which does not exist", entry.name, entry.domino_id)));
+        }
+        if ports.iter().any(|p| p.name == entry.name) {
+            return Err(Error::Invalid(format!("the port name \"{}\" is used more than once", entry.name)));
+        }
+        ports.push(Port {
+            name: entry.name,
+            domino_id: entry.domino_id,
+            direction: match entry.direction {
+                DirectionEntry::Input => PortDirection::Input,
+                DirectionEntry::Output => PortDirection::Output,
+            },
+        });
+    }
+
+    let mut obstacles: Vec<Obstacle> = Vec::with_capacity(file.obstacles.len());
+    for entry in file.obstacles {
+        let obstacle = Obstacle {
+            id: entry.id,
+            kind: entry.kind.into(),
+            position: entry.position.into(),
+            rotation_y: entry.rotation_y,
+            size: entry.size.into(),
+        };
+        if let Some(reason) = obstacle.validate() {
+            return Err(Error::Invalid(format!("obstacle {} is invalid: {}", entry.id, reason)));
+        }
+        if obstacles.iter().any(|o| o.id == entry.id) {
+            return Err(Error::Invalid(format!("the id {} is used by more than one obstacle", entry.id)));
+        }
+        obstacles.push(obstacle);
+    }
+
+    let mut components: Vec<Box<dyn Component>> = Vec::with_capacity(file.components.len());
+    for value in file.components {
+        let c = component::from_json(value).map_err(Error::Invalid)?;
+        if components.iter().any(|other| other.id() == c.id()) {
+            return Err(Error::Invalid(format!("the id {} is used by more than one component", c.id())));
+        }
+        components.push(c);
+    }
+
+    if file.lights.len() > light::MAX_LIGHTS {
+        return Err(Error::Invalid(format!("there are more than {} lights", light::MAX_LIGHTS)));
+    }
+    let mut lights: Vec<Light> = Vec::with_capacity(file.lights.len());
+    for (index, entry) in file.lights.into_iter().enumerate() {
+        let l: Light = entry.into();
+        if let Some(reason) = l.validate() {
+            return Err(Error::Invalid(format!("light {} is invalid: {}", index, reason)));
+        }
+        lights.push(l);
+    }
+
+    Ok(Simulator { dominos, ports, obstacles, components, lights })
+}
+
+/// Writes the layout of `simulator` to the file at `path`.
+pub fn save(simulator: &Simulator, path: &std::path::Path) -> Result<(), Error> {
+    std::fs::write(path, to_string(simulator))?;
+    Ok(())
+}
+
+/// Reads and validates the layout in the file at `path`.
+pub fn load(path: &std::path::Path) -> Result<Simulator, Error> {
+    from_str(&std::fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_the_current_version() {
+        let text = to_string(&Simulator::new());
+        assert_eq!(serde_json::from_str::<VersionOnly>(&text).unwrap().version, VERSION);
+        let simulator = from_str(&text).unwrap();
+        assert_eq!(simulator.dominos.len(), Simulator::new().dominos.len());
+        assert_eq!(simulator.lights.len(), Simulator::new().lights.len());
+    }
+
+    #[test]
+    fn reads_version_1() {
+        let text = r#"{
+            "version": 1,
+            "dominos": [{ "id": 3, "position": [0.0, 0.0, 0.5], "rotation_y": 90.0, "fall_rotation": 0.0, "scale": [1.0, 1.0, 1.0] }],
+            "ports": [{ "name": "in", "domino_id": 3, "direction": "input" }]
+        }"#;
+        let simulator = from_str(text).unwrap();
+        assert_eq!(simulator.dominos[0].id, 3);
+        assert_eq!(simulator.dominos[0].material, Material::default());
+        assert_eq!(simulator.ports[0].name, "in");
+        assert!(simulator.obstacles.is_empty() && simulator.components.is_empty());
+        assert_eq!(simulator.lights.len(), light::default_lights().len());
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let text = format!(r#"{{ "version": {}, "dominos": [], "something_new": true }}"#, VERSION + 1);
+        match from_str(&text) {
+            Err(e @ Error::UnsupportedVersion(_)) => {
+                let message = e.to_string();
+                assert!(message.contains("newer version"), "{}", message);
+                assert!(message.contains(&VERSION.to_string()), "{}", message);
+            }
+            other => panic!("unexpected result {:?}", other.map(|s| s.dominos.len())),
+        }
+        assert!(matches!(from_str(r#"{ "version": 0, "dominos": [] }"#), Err(Error::UnsupportedVersion(0))));
+    }
+
+    #[test]
+    fn keeps_everything_when_written_and_read() {
+        let mut simulator = Simulator::empty();
+        let mut domino = Domino::new(7, cgmath::point3(0.5, 0.1, -0.25), 30.0);
+        domino.fall_rotation = -12.5;
+        domino.angular_velocity = 40.0;
+        domino.scale = cgmath::vec3(1.5, 0.75, 2.0);
+        domino.material = crate::simulator::material::CERAMIC;
+        simulator.dominos.push(domino);
+        simulator.dominos.push(Domino::new(8, cgmath::point3(0.5, 0.1, -0.15), 30.0));
+        simulator.ports.push(Port { name: "not.a".to_string(), domino_id: 7, direction: PortDirection::Input });
+        simulator.ports.push(Port { name: "not.out".to_string(), domino_id: 8, direction: PortDirection::Output });
+        let mut stairs = Obstacle::new(2, ObstacleKind::Stairs { steps: 3 }, cgmath::point3(0.5, 0.0, 0.0), 45.0);
+        stairs.size = cgmath::vec3(0.2, 0.1, 0.6);
+        simulator.obstacles.push(stairs);
+        simulator.obstacles.push(Obstacle::new(3, ObstacleKind::Ramp, cgmath::point3(-1.0, 0.0, 0.0), 0.0));
+        simulator.components.push(component::new("ball", 4, cgmath::point3(0.0, 0.0, -0.5)).unwrap());
+        simulator.components.push(component::new("bridge", 5, cgmath::point3(1.0, 0.0, 0.0)).unwrap());
+        let mut sun = Light::directional(cgmath::vec3(-1.0, -2.0, -1.0));
+        sun.color = [1.0, 0.9, 0.8];
+        sun.intensity = 0.5;
+        simulator.lights = vec![Light::point(cgmath::point3(0.0, 1.5, 0.0)), sun];
+
+        let read = from_str(&to_string(&simulator)).unwrap();
+        assert_eq!(read.dominos.len(), 2);
+        for (read, written) in read.dominos.iter().zip(&simulator.dominos) {
+            assert_eq!(read.id, written.id);
+            assert_eq!(read.position, written.position);
+            assert_eq!(read.rotation_y, written.rotation_y);
+            assert_eq!(read.fall_rotation, written.fall_rotation);
+            assert_eq!(read.angular_velocity, written.angular_velocity);
+            assert_eq!(read.scale, written.scale);
+            assert_eq!(read.material, written.material);
+        }
+        assert_eq!(read.ports.len(), 2);
+        for (read, written) in read.ports.iter().zip(&simulator.ports) {
+            assert_eq!((&read.name, read.domino_id, read.direction), (&written.name, written.domino_id, written.direction));
+        }
+        assert_eq!(read.obstacles, simulator.obstacles);
+        let setups = |s: &Simulator| s.components.iter().map(|c| c.to_json()).collect::<Vec<_>>();
+        assert_eq!(setups(&read), setups(&simulator));
+        assert_eq!(read.lights, simulator.lights);
+    }
+
+    /// The reason why a layout with the given domino entries is invalid.
+    fn invalid_dominos(dominos: &str) -> String {
+        let text = format!(r#"{{ "version": {}, "dominos": [{}] }}"#, VERSION, dominos);
+        match from_str(&text) {
+            Err(Error::Invalid(reason)) => reason,
+            other => panic!("unexpected result {:?}", other.map(|s| s.dominos.len())),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_dominos() {
+        let entry = |id: u32, position: &str, fall_rotation: &str, scale: &str| format!(
+            r#"{{ "id": {}, "position": {}, "rotation_y": 0.0, "fall_rotation": {}, "scale": {} }}"#,
+            id, position, fall_rotation, scale,
+        );
+        let valid = entry(1, "[0.0, 0.0, 0.0]", "0.0", "[1.0, 1.0, 1.0]");
+        let reason = invalid_dominos(&entry(1, "[1e39, 0.0, 0.0]", "0.0", "[1.0, 1.0, 1.0]"));
+        assert!(reason.contains("not a finite number"), "{}", reason);
+        let reason = invalid_dominos(&entry(1, "[0.0, 0.0, 0.0]", "-90.5", "[1.0, 1.0, 1.0]"));
+        assert!(reason.contains("between -90 and 90"), "{}", reason);
+        let reason = invalid_dominos(&entry(1, "[0.0, 0.0, 0.0]", "0.0", "[1.0, 0.0, 1.0]"));
+        assert!(reason.contains("scale that is not positive"), "{}", reason);
+        let reason = invalid_dominos(&format!("{}, {}", valid, valid));
+        assert!(reason.contains("more than one domino"), "{}", reason);
+        let fallen = entry(1, "[0.0, 0.0, 0.0]", "90.0", "[1.0, 1.0, 1.0]");
+        assert!(from_str(&format!(r#"{{ "version": {}, "dominos": [{}] }}"#, VERSION, fallen)).is_ok());
+    }
+}
@@ -0,0 +1,16 @@
+//! Simulation of logic circuits built from dominos.
+//!
+//! The library does not depend on any GUI crate, the 3d editor is part of the
+//! `domino-logic-simulator` binary and only built with the `gui` feature (enabled by default).
+//! The command line of the headless mode is part of the binary as well.
+
+pub mod blueprint;
+pub mod gates;
+pub mod history;
+pub mod knock;
+pub mod layout;
+pub mod netlist;
+pub mod path;
+pub mod simulator;
+pub mod synthesis;
+pub mod validation;
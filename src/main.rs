@@ -1,15 +1,27 @@
-pub mod main_window;
+mod headless;
+#[cfg(feature = "gui")]
+mod main_window;
+#[cfg(feature = "gui")]
+mod ui_3d;
 
+#[cfg(feature = "gui")]
 use std::{sync::Arc, sync::Mutex};
 
+#[cfg(feature = "gui")]
 use main_window::MainWindow;
 
-pub mod ui_3d;
-
-pub mod simulator;
+#[cfg(feature = "gui")]
+use domino_logic_simulator::{blueprint, gates, history, layout, netlist, path, simulator, synthesis, validation};
+#[cfg(feature = "gui")]
 use simulator::Simulator;
 
+#[cfg(feature = "gui")]
 fn main() -> eframe::Result<()> {
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(|a| a == "--headless").unwrap_or(false) {
+        std::process::exit(headless::main(args.skip(1)));
+    }
+
     let simulator = Simulator::new();
     
     // Log to stdout (if you run with `RUST_LOG=debug`).
@@ -20,3 +32,13 @@ fn main() -> eframe::Result<()> {
         Box::new(|cc| Box::new(MainWindow::new(cc, Arc::new(Mutex::new(simulator))))),
         )
 }
+
+/// Without the `gui` feature only the headless mode is available, `--headless` is optional.
+#[cfg(not(feature = "gui"))]
+fn main() {
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(|a| a == "--headless").unwrap_or(false) {
+        args.next();
+    }
+    std::process::exit(headless::main(args));
+}
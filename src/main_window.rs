@@ -1,24 +1,896 @@
-use std::{sync::Arc, sync::Mutex};
+use std::{path::PathBuf, sync::Arc, sync::Mutex, thread::JoinHandle};
 
-use crate::{ui_3d::UI3d, simulator::{Simulator, Domino}};
+use serde::{Deserialize, Serialize};
+
+use crate::{blueprint, gates, layout, netlist, synthesis::Circuit, validation::{self, Issue}, ui_3d::{UI3d, Placing, camera::{Camera, CameraEntry, Projection}, path_tool}, history::{Command, History}, simulator::{component, light::{self, Light, LightKind}, material::{self, Material}, terrain::{Obstacle, ObstacleKind}, timeline::Timeline, timing::TimingReport, truth_table, Simulator, Domino, PUSH_VELOCITY}};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FileAction {
+    Open,
+    SaveAs,
+    ExportNetlist,
+}
+
+/// Asks for the path of the layout file to open or save, or of the file to export to.
+struct FileDialog {
+    action: FileAction,
+    path: String,
+}
+
+/// Replay of the recorded timeline.
+struct Playback {
+    /// Simulated time that is shown, in seconds.
+    time: f32,
+    playing: bool,
+    /// The dominos before the replay started, their fall state is restored when it ends.
+    live_dominos: Vec<Domino>,
+}
+
+/// Number of files listed in File > Open recent.
+const RECENT_FILES: usize = 10;
+
+/// Which windows are shown, they can be closed and opened again from the Windows menu.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+struct Panels {
+    simulation: bool,
+    inspector: bool,
+    creator: bool,
+    blueprints: bool,
+    timing: bool,
+    validation: bool,
+    synthesis: bool,
+    terrain: bool,
+    components: bool,
+    lighting: bool,
+    snapping: bool,
+    path_tool: bool,
+}
+
+impl Default for Panels {
+    fn default() -> Self {
+        Panels {
+            simulation: true,
+            inspector: true,
+            creator: true,
+            blueprints: true,
+            timing: true,
+            validation: true,
+            synthesis: true,
+            terrain: true,
+            components: true,
+            lighting: true,
+            snapping: true,
+            path_tool: true,
+        }
+    }
+}
+
+impl Panels {
+    /// The title of each window with whether it is shown.
+    fn entries(&mut self) -> [(&'static str, &mut bool); 12] {
+        [
+            ("Simulation", &mut self.simulation),
+            ("Domino Inspector", &mut self.inspector),
+            ("Domino Creator", &mut self.creator),
+            ("Blueprints", &mut self.blueprints),
+            ("Timing", &mut self.timing),
+            ("Validation", &mut self.validation),
+            ("Synthesis", &mut self.synthesis),
+            ("Terrain", &mut self.terrain),
+            ("Components", &mut self.components),
+            ("Lighting", &mut self.lighting),
+            ("Snapping", &mut self.snapping),
+            ("Path Tool", &mut self.path_tool),
+        ]
+    }
+}
+
+/// The state of the editor that is stored with eframe and restored on the next launch.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    camera: Option<CameraEntry>,
+    panels: Panels,
+    /// The file the layout was last opened from or saved to, it is opened again.
+    file_path: Option<PathBuf>,
+    recent_files: Vec<PathBuf>,
+}
 
 pub struct MainWindow {
-    value: f32,
     ui_3d: Option<UI3d>,
     simulator: Arc<Mutex<Simulator>>,
+    running: bool,
+    /// Simulated time since the layout was loaded or reset, in seconds.
+    time: f32,
+    timeline: Timeline,
+    playback: Option<Playback>,
+    /// Factor the simulation and replay are sped up or slowed down with.
+    speed: f32,
+    /// Restart the replay at the beginning when it reached the end.
+    looping: bool,
+    gate_index: usize,
+    /// File the layout was last opened from or saved to.
+    file_path: Option<PathBuf>,
+    file_dialog: Option<FileDialog>,
+    /// Error shown to the user until it is dismissed.
+    error: Option<String>,
+    history: History,
+    /// Offset (x, z) the selected dominos are moved by in the inspector.
+    group_offset: cgmath::Vector2<f32>,
+    /// Angle in degrees the selected dominos are rotated by in the inspector.
+    group_rotation: f32,
+    /// Factor the selected dominos are scaled by in the inspector.
+    group_scale: f32,
+    /// Index of the material preset given to the selected dominos in the inspector.
+    group_material: usize,
+    /// The last copied dominos in the layout format, also put on the system clipboard.
+    clipboard: Option<String>,
+    blueprints: Option<blueprint::Library>,
+    blueprint_names: Vec<String>,
+    /// Name the selection is saved as blueprint with.
+    blueprint_name: String,
+    timing: Option<TimingReport>,
+    /// The timing analysis running in the background, it simulates every input combination.
+    timing_job: Option<JoinHandle<Result<TimingReport, String>>>,
+    highlight_critical_path: bool,
+    /// Result of the last layout validation.
+    issues: Option<Vec<Issue>>,
+    /// Boolean expressions, statements or a truth table the layout is built from.
+    synthesis_description: String,
+    /// Index into [`ObstacleKind::ALL`] of the kind of obstacle that is added next.
+    obstacle_kind: usize,
+    /// Index into [`component::KINDS`] of the component added next.
+    component_kind: usize,
+    /// Index of the light edited in the lighting panel.
+    selected_light: Option<usize>,
+    panels: Panels,
+    /// The files last opened or saved, the latest first.
+    recent_files: Vec<PathBuf>,
 }
 
 impl MainWindow {
     pub fn new(cc: &eframe::CreationContext<'_>, simulator: Arc<Mutex<Simulator>>) -> Self {
-        // if let Some(storage) = cc.storage {
-        //     return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
-        // }
-        let slf = Self {
+        let settings: Settings = cc.storage.and_then(|s| eframe::get_value(s, eframe::APP_KEY)).unwrap_or_default();
+        let mut main_window = Self {
             ui_3d: UI3d::new(cc, simulator.clone()),
-            value: 2.4,
-            simulator: simulator.clone(),
+            simulator,
+            running: false,
+            time: 0.0,
+            timeline: Timeline::default(),
+            playback: None,
+            speed: 1.0,
+            looping: false,
+            gate_index: 0,
+            file_path: None,
+            file_dialog: None,
+            error: None,
+            history: History::default(),
+            group_offset: cgmath::vec2(0.0, 0.0),
+            group_rotation: 90.0,
+            group_scale: 1.5,
+            group_material: 0,
+            clipboard: None,
+            blueprints: blueprint::Library::user(),
+            blueprint_names: vec![],
+            blueprint_name: String::new(),
+            timing: None,
+            timing_job: None,
+            highlight_critical_path: true,
+            issues: None,
+            synthesis_description: "out = (a & b) ^ c".to_string(),
+            obstacle_kind: 0,
+            component_kind: 0,
+            selected_light: None,
+            panels: settings.panels,
+            recent_files: settings.recent_files,
+        };
+        main_window.refresh_blueprints();
+        if let (Some(u), Some(camera)) = (&mut main_window.ui_3d, settings.camera) {
+            u.camera = camera.into();
+        }
+        // a file that was moved or deleted since is silently forgotten
+        if let Some(path) = settings.file_path.filter(|p| p.is_file()) {
+            main_window.open(path);
+        }
+        main_window
+    }
+
+    fn undo(&mut self) {
+        self.history.undo(&mut self.simulator.lock().unwrap());
+    }
+
+    fn redo(&mut self) {
+        self.history.redo(&mut self.simulator.lock().unwrap());
+    }
+
+    fn reset_timeline(&mut self) {
+        self.playback = None;
+        self.timeline.clear();
+        self.time = 0.0;
+    }
+
+    /// Pauses the simulation and replays the recorded timeline from the current time.
+    fn start_playback(&mut self) {
+        if self.playback.is_none() && !self.timeline.is_empty() {
+            self.running = false;
+            let live_dominos = self.simulator.lock().unwrap().dominos.clone();
+            self.playback = Some(Playback { time: self.time, playing: false, live_dominos });
+        }
+    }
+
+    /// Ends the replay and restores the fall state of the dominos from before it.
+    fn stop_playback(&mut self) {
+        if let Some(playback) = self.playback.take() {
+            let mut s = self.simulator.lock().unwrap();
+            for live in playback.live_dominos {
+                if let Some(d) = s.dominos.iter_mut().find(|d| d.id == live.id) {
+                    d.fall_rotation = live.fall_rotation;
+                    d.angular_velocity = live.angular_velocity;
+                }
+            }
+        }
+    }
+
+    /// Simulates `dt` seconds and records the result.
+    fn step_live(&mut self, dt: f32) {
+        let mut s = self.simulator.lock().unwrap();
+        if self.timeline.is_empty() {
+            self.timeline.record(self.time, &s);
+        }
+        s.step(dt);
+        self.time += dt;
+        self.timeline.record(self.time, &s);
+    }
+
+    /// Moves the replay by `frames` recorded frames.
+    fn step_playback(&mut self, frames: isize) {
+        self.start_playback();
+        if let Some(playback) = &mut self.playback {
+            let frame = self.timeline.frame_at(playback.time).saturating_add_signed(frames).min(self.timeline.len() - 1);
+            playback.time = self.timeline.time(frame);
+            playback.playing = false;
+        }
+    }
+
+    fn timeline_panel(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::bottom("timeline").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let playing = self.playback.as_ref().map_or(self.running, |p| p.playing);
+                if ui.button(if playing { "Pause" } else if self.playback.is_some() { "Play" } else { "Run" }).clicked() {
+                    match &mut self.playback {
+                        Some(playback) => playback.playing = !playback.playing,
+                        None => self.running = !self.running,
+                    }
+                }
+                if ui.add_enabled(self.timeline.len() > 1, egui::Button::new("⏴")).on_hover_text("Previous frame").clicked() {
+                    self.step_playback(-1);
+                }
+                if ui.add_enabled(!playing, egui::Button::new("⏵")).on_hover_text("Next frame").clicked() {
+                    match self.playback {
+                        Some(_) => self.step_playback(1),
+                        None => self.step_live(1.0 / 60.0),
+                    }
+                }
+
+                if !self.timeline.is_empty() {
+                    let (start, end) = (self.timeline.time(0), self.timeline.time(self.timeline.len() - 1));
+                    let mut time = self.playback.as_ref().map_or(self.time, |p| p.time);
+                    if ui.add(egui::Slider::new(&mut time, start..=end).suffix(" s").fixed_decimals(3)).changed() {
+                        self.start_playback();
+                        if let Some(playback) = &mut self.playback {
+                            playback.time = time;
+                        }
+                    }
+                }
+
+                ui.add(egui::Slider::new(&mut self.speed, 0.05..=2.0).logarithmic(true).text("speed"));
+                ui.checkbox(&mut self.looping, "Loop");
+
+                if self.playback.is_some() {
+                    if ui.button("Back to live").clicked() {
+                        self.stop_playback();
+                    }
+                } else if ui.button("Reset").on_hover_text("Stand all dominos up and clear the recording").clicked() {
+                    self.running = false;
+                    self.simulator.lock().unwrap().reset();
+                    self.reset_timeline();
+                }
+                if self.timeline.is_full() {
+                    ui.label("The recording is full.");
+                }
+            });
+        });
+    }
+
+    fn select_all(&mut self) {
+        if let Some(u) = &mut self.ui_3d {
+            u.select_all();
+        }
+    }
+
+    fn delete_selection(&mut self) {
+        if let Some(u) = &mut self.ui_3d {
+            let ids = u.selected_ids();
+            if ids.is_empty() {
+                return;
+            }
+            let mut s = self.simulator.lock().unwrap();
+            let command = Command::delete(&s, &ids);
+            self.history.execute(&mut s, command);
+            u.selection.clear();
+        }
+    }
+
+    /// Copies the selected dominos to the clipboard.
+    fn copy(&mut self, ctx: &egui::Context) {
+        let ids = self.ui_3d.as_ref().map(|u| u.selected_ids()).unwrap_or_default();
+        if ids.is_empty() {
+            return;
+        }
+        let text = layout::to_string(&blueprint::extract(&self.simulator.lock().unwrap(), &ids));
+        ctx.output_mut(|o| o.copied_text = text.clone());
+        self.clipboard = Some(text);
+    }
+
+    /// Places the dominos in the layout format `text` at the cursor.
+    fn paste(&mut self, text: &str) {
+        let part = match layout::from_str(text) {
+            Ok(part) => part,
+            Err(e) => {
+                self.error = Some(format!("The clipboard does not contain dominos: {}", e));
+                return;
+            }
+        };
+        if let Some(u) = &mut self.ui_3d {
+            let at = u.cursor_ground_point().unwrap_or(cgmath::point3(0.0, 0.0, 0.0));
+            u.place(&part, None, at);
+        }
+    }
+
+    fn refresh_blueprints(&mut self) {
+        if let Some(library) = &self.blueprints {
+            match library.names() {
+                Ok(names) => self.blueprint_names = names,
+                Err(e) => self.error = Some(format!("Could not read the blueprints in {}: {}", library.dir.display(), e)),
+            }
+        }
+    }
+
+    fn timing_window(&mut self, ctx: &egui::Context) {
+        if self.timing_job.as_ref().is_some_and(|job| job.is_finished()) {
+            match self.timing_job.take().unwrap().join() {
+                Ok(Ok(report)) => self.timing = Some(report),
+                Ok(Err(e)) => self.error = Some(format!("The timing analysis failed: {}", e)),
+                Err(_) => self.error = Some("The timing analysis panicked".to_string()),
+            }
+        }
+
+        egui::Window::new("Timing").open(&mut self.panels.timing).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.add_enabled(self.timing_job.is_none(), egui::Button::new("Analyse"))
+                    .on_hover_text("Simulates every combination of the input ports")
+                    .clicked()
+                {
+                    let simulator = self.simulator.lock().unwrap().clone();
+                    self.timing_job = Some(std::thread::spawn(move || {
+                        simulator.timing(truth_table::DEFAULT_DT, truth_table::DEFAULT_MAX_DURATION)
+                    }));
+                }
+                if self.timing_job.is_some() {
+                    ui.spinner();
+                    ctx.request_repaint();
+                }
+            });
+
+            let report = match &self.timing {
+                Some(report) => report,
+                None => {
+                    ui.label("Analyse the layout to see its gate delays and critical path.");
+                    return;
+                }
+            };
+
+            ui.separator();
+            match &report.critical_path {
+                Some(path) => {
+                    ui.label(format!("Critical path: {} → {}, {:.3} s over {} dominos", path.input, path.output, path.delay, path.dominos.len()));
+                    ui.checkbox(&mut self.highlight_critical_path, "Highlight critical path");
+                }
+                None => {
+                    ui.label("No output fell for any combination of the inputs.");
+                }
+            }
+
+            ui.separator();
+            ui.strong("Gate delays");
+            if report.gates.is_empty() {
+                ui.label("No signal passed through a gate.");
+            } else {
+                egui::Grid::new("gate_delays").striped(true).show(ui, |ui| {
+                    for header in ["Gate", "Input", "Output", "Delay"] {
+                        ui.strong(header);
+                    }
+                    ui.end_row();
+                    for gate in &report.gates {
+                        ui.label(&gate.gate);
+                        ui.label(&gate.input);
+                        ui.label(&gate.output);
+                        ui.label(format!("{:.3} s", gate.delay));
+                        ui.end_row();
+                    }
+                });
+            }
+
+            ui.separator();
+            let triggered: Vec<&str> = report.inputs.iter().zip(report.triggered.iter()).filter(|(_, t)| **t).map(|(name, _)| name.as_str()).collect();
+            ui.strong(format!("Fall starts with {} triggered", triggered.join(", ")));
+            egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                egui::Grid::new("fall_starts").striped(true).show(ui, |ui| {
+                    for header in ["Domino", "Began falling", "Hit by", "Fell"] {
+                        ui.strong(header);
+                    }
+                    ui.end_row();
+                    for d in &report.dominos {
+                        let selected = self.ui_3d.as_ref().is_some_and(|u| u.selection.contains(&d.id));
+                        if ui.selectable_label(selected, d.id.to_string()).clicked() {
+                            if let Some(u) = &mut self.ui_3d {
+                                u.selection = [d.id].into();
+                            }
+                        }
+                        ui.label(format!("{:.3} s", d.fall_start));
+                        ui.label(d.hit_by.map_or("triggered".to_string(), |id| id.to_string()));
+                        ui.label(if d.fell { "yes" } else { "no" });
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+
+        if let Some(u) = &mut self.ui_3d {
+            u.highlighted = match (&self.timing, self.highlight_critical_path) {
+                (Some(TimingReport { critical_path: Some(path), .. }), true) => path.dominos.iter().copied().collect(),
+                _ => Default::default(),
+            };
+        }
+    }
+
+    fn validation_window(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Validation").open(&mut self.panels.validation).show(ctx, |ui| {
+            if ui.button("Check layout").on_hover_text("Looks for gaps, overlaps and other mistakes that break the chains").clicked() {
+                self.issues = Some(validation::validate(&self.simulator.lock().unwrap()));
+            }
+            let issues = match &self.issues {
+                Some(issues) => issues,
+                None => return,
+            };
+            ui.separator();
+            if issues.is_empty() {
+                ui.label("No problems found.");
+                return;
+            }
+            ui.label(format!("{} problems, click one to select the domino:", issues.len()));
+            egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                for issue in issues {
+                    let selected = self.ui_3d.as_ref().is_some_and(|u| u.selection.len() == 1 && u.selection.contains(&issue.domino_id));
+                    if ui.selectable_label(selected, issue.to_string()).clicked() {
+                        if let Some(u) = &mut self.ui_3d {
+                            u.selection = [issue.domino_id].into();
+                        }
+                    }
+                }
+            });
+        });
+    }
+
+    fn synthesis_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.panels.synthesis;
+        egui::Window::new("Synthesis").open(&mut open).show(ctx, |ui| {
+            ui.label("Expressions like out = (a & b) ^ c, one per line, or a truth table:");
+            ui.add(egui::TextEdit::multiline(&mut self.synthesis_description).code_editor().desired_rows(6));
+            if ui.button("Build layout").on_hover_text("Replaces the layout with the compiled circuit").clicked() {
+                match Circuit::parse(&self.synthesis_description) {
+                    Ok(circuit) => self.build_layout(circuit.layout()),
+                    Err(e) => self.error = Some(format!("Could not compile the circuit: {}", e)),
+                }
+            }
+        });
+        self.panels.synthesis = open;
+    }
+
+    fn terrain_window(&mut self, ctx: &egui::Context) {
+        let u = match &mut self.ui_3d {
+            Some(u) => u,
+            None => return,
+        };
+        egui::Window::new("Terrain").open(&mut self.panels.terrain).show(ctx, |ui| {
+            let mut s = self.simulator.lock().unwrap();
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_source("obstacle kind").show_index(ui, &mut self.obstacle_kind, ObstacleKind::ALL.len(), |i| ObstacleKind::ALL[i].0.to_string());
+                if ui.button("Add").clicked() {
+                    let before = s.obstacles.clone();
+                    let obstacle = Obstacle::new(s.next_obstacle_id(), ObstacleKind::ALL[self.obstacle_kind].1, cgmath::point3(0.0, 0.0, 0.0), 0.0);
+                    u.selected_obstacle = Some(obstacle.id);
+                    let after = before.iter().cloned().chain([obstacle]).collect();
+                    self.history.execute(&mut s, Command::Terrain { before, after });
+                }
+            });
+            if s.obstacles.is_empty() {
+                ui.label("Ramps, platforms, stairs and walls dominos can stand on.");
+                return;
+            }
+            ui.horizontal_wrapped(|ui| {
+                for o in s.obstacles.iter() {
+                    let selected = u.selected_obstacle == Some(o.id);
+                    if ui.selectable_label(selected, format!("{} {}", o.kind.name(), o.id)).clicked() {
+                        u.selected_obstacle = if selected { None } else { Some(o.id) };
+                    }
+                }
+            });
+
+            let before = s.obstacles.clone();
+            let obstacle = match s.obstacles.iter_mut().find(|o| Some(o.id) == u.selected_obstacle) {
+                Some(o) => o,
+                None => return,
+            };
+            ui.separator();
+            let mut changed = false;
+            changed |= ui.add(egui::Slider::new(&mut obstacle.position.x, -10.0..=10.0).text("x-Position")).changed();
+            changed |= ui.add(egui::Slider::new(&mut obstacle.position.y, -10.0..=10.0).text("y-Position")).changed();
+            changed |= ui.add(egui::Slider::new(&mut obstacle.position.z, -10.0..=10.0).text("z-Position")).changed();
+            changed |= ui.add(egui::Slider::new(&mut obstacle.rotation_y, 0.0..=360.0).text("y-Rotation")).changed();
+            changed |= ui.add(egui::Slider::new(&mut obstacle.size.x, 0.01..=10.0).logarithmic(true).text("width (m)")).changed();
+            changed |= ui.add(egui::Slider::new(&mut obstacle.size.y, 0.01..=10.0).logarithmic(true).text("height (m)")).changed();
+            changed |= ui.add(egui::Slider::new(&mut obstacle.size.z, 0.01..=10.0).logarithmic(true).text("length (m)")).changed();
+            if let ObstacleKind::Stairs { steps } = &mut obstacle.kind {
+                changed |= ui.add(egui::Slider::new(steps, 1..=20).text("steps")).changed();
+            }
+            let id = obstacle.id;
+            if changed {
+                let after = s.obstacles.clone();
+                self.history.record_coalesced(&format!("obstacle {}", id), Command::Terrain { before, after });
+            }
+            if ui.button("Delete").clicked() {
+                let before = s.obstacles.clone();
+                let after = before.iter().filter(|o| o.id != id).cloned().collect();
+                self.history.execute(&mut s, Command::Terrain { before, after });
+                u.selected_obstacle = None;
+            }
+        });
+    }
+
+    fn lighting_window(&mut self, ctx: &egui::Context) {
+        let u = match &mut self.ui_3d {
+            Some(u) => u,
+            None => return,
+        };
+        egui::Window::new("Lighting").open(&mut self.panels.lighting).show(ctx, |ui| {
+            ui.checkbox(&mut u.shadows, "Shadows").on_hover_text("Cast by the first light, makes it easier to judge the spacing of the dominos");
+            let mut s = self.simulator.lock().unwrap();
+            ui.horizontal(|ui| {
+                ui.add_enabled_ui(s.lights.len() < light::MAX_LIGHTS, |ui| {
+                    let added = if ui.button("Add point").clicked() {
+                        Some(Light::point(cgmath::point3(0.0, 2.0, 0.0)))
+                    } else if ui.button("Add directional").clicked() {
+                        Some(Light::directional(cgmath::vec3(-1.0, -2.0, -1.0)))
+                    } else {
+                        None
+                    };
+                    if let Some(light) = added {
+                        let before = s.lights.clone();
+                        let after: Vec<Light> = before.iter().cloned().chain([light]).collect();
+                        self.selected_light = Some(after.len() - 1);
+                        self.history.execute(&mut s, Command::Lights { before, after });
+                    }
+                });
+            });
+            if s.lights.is_empty() {
+                ui.label("Without lights only the ambient light is left.");
+                return;
+            }
+            ui.horizontal_wrapped(|ui| {
+                for (i, l) in s.lights.iter().enumerate() {
+                    let kind = match l.kind {
+                        LightKind::Point { .. } => "point",
+                        LightKind::Directional { .. } => "directional",
+                    };
+                    let selected = self.selected_light == Some(i);
+                    if ui.selectable_label(selected, format!("{} {}", kind, i + 1)).clicked() {
+                        self.selected_light = if selected { None } else { Some(i) };
+                    }
+                }
+            });
+
+            let before = s.lights.clone();
+            let i = match self.selected_light {
+                Some(i) if i < s.lights.len() => i,
+                _ => return,
+            };
+            let light = &mut s.lights[i];
+            ui.separator();
+            let mut changed = false;
+            match &mut light.kind {
+                LightKind::Point { position } => {
+                    changed |= ui.add(egui::Slider::new(&mut position.x, -10.0..=10.0).text("x-Position")).changed();
+                    changed |= ui.add(egui::Slider::new(&mut position.y, 0.0..=10.0).text("y-Position")).changed();
+                    changed |= ui.add(egui::Slider::new(&mut position.z, -10.0..=10.0).text("z-Position")).changed();
+                }
+                LightKind::Directional { direction } => {
+                    changed |= ui.add(egui::Slider::new(&mut direction.x, -1.0..=1.0).text("x-Direction")).changed();
+                    changed |= ui.add(egui::Slider::new(&mut direction.y, -1.0..=1.0).text("y-Direction")).changed();
+                    changed |= ui.add(egui::Slider::new(&mut direction.z, -1.0..=1.0).text("z-Direction")).changed();
+                }
+            }
+            ui.horizontal(|ui| {
+                changed |= ui.color_edit_button_rgb(&mut light.color).changed();
+                ui.label("colour");
+            });
+            changed |= ui.add(egui::Slider::new(&mut light.intensity, 0.0..=5.0).text("intensity")).changed();
+            if changed {
+                if light.validate().is_some() {
+                    // e.g. a zero direction, keep the last valid light
+                    s.lights = before;
+                } else {
+                    let after = s.lights.clone();
+                    self.history.record_coalesced(&format!("light {}", i), Command::Lights { before, after });
+                }
+            }
+            if ui.button("Delete").clicked() {
+                let before = s.lights.clone();
+                let mut after = before.clone();
+                after.remove(i);
+                self.history.execute(&mut s, Command::Lights { before, after });
+                self.selected_light = None;
+            }
+        });
+    }
+
+    fn components_window(&mut self, ctx: &egui::Context) {
+        let u = match &mut self.ui_3d {
+            Some(u) => u,
+            None => return,
+        };
+        egui::Window::new("Components").open(&mut self.panels.components).show(ctx, |ui| {
+            let mut s = self.simulator.lock().unwrap();
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_source("component kind").show_index(ui, &mut self.component_kind, component::KINDS.len(), |i| component::KINDS[i].to_string());
+                if ui.button("Add").clicked() {
+                    let before = s.components.clone();
+                    let id = s.next_component_id();
+                    if let Some(c) = component::new(component::KINDS[self.component_kind], id, cgmath::point3(0.0, 0.0, 0.0)) {
+                        u.selected_component = Some(id);
+                        let after = before.iter().cloned().chain([c]).collect();
+                        self.history.execute(&mut s, Command::Components { before, after });
+                    }
+                }
+            });
+            if s.components.is_empty() {
+                ui.label("Balls, levers and bridges that interact with the dominos.");
+                return;
+            }
+            ui.horizontal_wrapped(|ui| {
+                for c in s.components.iter() {
+                    let selected = u.selected_component == Some(c.id());
+                    if ui.selectable_label(selected, format!("{} {}", c.kind(), c.id())).clicked() {
+                        u.selected_component = if selected { None } else { Some(c.id()) };
+                    }
+                }
+            });
+
+            let before = s.components.clone();
+            let c = match s.components.iter_mut().find(|c| Some(c.id()) == u.selected_component) {
+                Some(c) => c,
+                None => return,
+            };
+            ui.separator();
+            let mut changed = false;
+            for parameter in c.parameters() {
+                changed |= ui.add(egui::Slider::new(parameter.value, parameter.range).text(parameter.name)).changed();
+            }
+            let id = c.id();
+            if changed {
+                c.reset();
+                let after = s.components.clone();
+                self.history.record_coalesced(&format!("component {}", id), Command::Components { before, after });
+            }
+            if ui.button("Delete").clicked() {
+                let before = s.components.clone();
+                let after = before.iter().filter(|c| c.id() != id).cloned().collect();
+                self.history.execute(&mut s, Command::Components { before, after });
+                u.selected_component = None;
+            }
+        });
+    }
+
+    fn blueprint_window(&mut self, ctx: &egui::Context) {
+        let window = egui::Window::new("Blueprints").open(&mut self.panels.blueprints).show(ctx, |ui| {
+            let library = match &self.blueprints {
+                Some(library) => library,
+                None => {
+                    ui.label("The blueprint library is not available, no data directory was found.");
+                    return false;
+                }
+            };
+
+            let ids = self.ui_3d.as_ref().map(|u| u.selected_ids()).unwrap_or_default();
+            let mut changed = false;
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.blueprint_name);
+                let can_save = !ids.is_empty() && !self.blueprint_name.trim().is_empty();
+                if ui.add_enabled(can_save, egui::Button::new("Save selection")).clicked() {
+                    let part = blueprint::extract(&self.simulator.lock().unwrap(), &ids);
+                    match library.save(&self.blueprint_name, &part) {
+                        Ok(()) => changed = true,
+                        Err(e) => self.error = Some(format!("Could not save the blueprint: {}", e)),
+                    }
+                }
+            });
+            ui.separator();
+
+            if self.blueprint_names.is_empty() {
+                ui.label("Save a selection to reuse it as blueprint.");
+            }
+            for name in &self.blueprint_names {
+                ui.horizontal(|ui| {
+                    ui.label(name);
+                    if ui.button("Place").on_hover_text("Click on the ground to place the blueprint").clicked() {
+                        match library.load(name) {
+                            Ok(part) => {
+                                let s = self.simulator.lock().unwrap();
+                                let number = (0..).find(|n| !s.ports.iter().any(|p| p.name.starts_with(&format!("{}{}.", name, n)))).unwrap();
+                                if let Some(u) = &mut self.ui_3d {
+                                    u.placing = Some(Placing { part, port_prefix: Some(format!("{}{}", name, number)) });
+                                }
+                            }
+                            Err(e) => self.error = Some(format!("Could not load the blueprint {}: {}", name, e)),
+                        }
+                    }
+                    if ui.button("Delete").clicked() {
+                        match library.delete(name) {
+                            Ok(()) => changed = true,
+                            Err(e) => self.error = Some(format!("Could not delete the blueprint {}: {}", name, e)),
+                        }
+                    }
+                });
+            }
+            changed
+        });
+        if window.and_then(|w| w.inner).unwrap_or(false) {
+            self.refresh_blueprints();
+        }
+    }
+
+    fn show_file_dialog(&mut self, action: FileAction) {
+        let path = match (action, &self.file_path) {
+            (FileAction::ExportNetlist, Some(p)) => p.with_extension("dot").display().to_string(),
+            (_, Some(p)) => p.display().to_string(),
+            (_, None) => String::new(),
+        };
+        self.file_dialog = Some(FileDialog { action, path });
+    }
+
+    fn open(&mut self, path: PathBuf) {
+        match layout::load(&path) {
+            Ok(simulator) => self.replace_layout(simulator, Some(path)),
+            Err(e) => self.error = Some(format!("Could not open {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Replaces the whole layout, e.g. with one loaded from `file_path`. The edits of the previous
+    /// layout can not be undone anymore.
+    fn replace_layout(&mut self, simulator: Simulator, file_path: Option<PathBuf>) {
+        *self.simulator.lock().unwrap() = simulator;
+        self.forget_selection();
+        self.history.clear();
+        if let Some(path) = &file_path {
+            self.add_recent_file(path.clone());
+        }
+        self.file_path = file_path;
+    }
+
+    /// Replaces the contents of the layout by `layout` as an edit that can be undone.
+    fn build_layout(&mut self, layout: Simulator) {
+        {
+            let mut s = self.simulator.lock().unwrap();
+            let command = Command::replace(&s, layout);
+            self.history.execute(&mut s, command);
+        }
+        self.forget_selection();
+    }
+
+    /// Clears the selection and stops the simulation after the dominos were replaced.
+    fn forget_selection(&mut self) {
+        if let Some(u) = &mut self.ui_3d {
+            u.selection.clear();
+            u.selected_obstacle = None;
+            u.selected_component = None;
+        }
+        self.selected_light = None;
+        self.running = false;
+        self.reset_timeline();
+    }
+
+    /// Moves `path` to the top of the recently opened files.
+    fn add_recent_file(&mut self, path: PathBuf) {
+        self.recent_files.retain(|p| *p != path);
+        self.recent_files.insert(0, path);
+        self.recent_files.truncate(RECENT_FILES);
+    }
+
+    fn save(&mut self, mut path: PathBuf) {
+        if path.extension().is_none() {
+            path.set_extension(layout::EXTENSION);
+        }
+        let result = layout::save(&self.simulator.lock().unwrap(), &path);
+        match result {
+            Ok(()) => {
+                self.add_recent_file(path.clone());
+                self.file_path = Some(path);
+            }
+            Err(e) => self.error = Some(format!("Could not save {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Writes the netlist of the layout as JSON to `.json` files and as Graphviz DOT otherwise.
+    fn export_netlist(&mut self, mut path: PathBuf) {
+        if path.extension().is_none() {
+            path.set_extension("dot");
+        }
+        let netlist = netlist::extract(&self.simulator.lock().unwrap());
+        let contents = if path.extension().is_some_and(|e| e == "json") {
+            serde_json::to_string_pretty(&netlist).expect("netlists only contain serializable values")
+        } else {
+            netlist.to_dot()
         };
-        slf
+        if let Err(e) = std::fs::write(&path, contents) {
+            self.error = Some(format!("Could not export the netlist to {}: {}", path.display(), e));
+        }
+    }
+
+    fn file_windows(&mut self, ctx: &egui::Context) {
+        if let Some(dialog) = &mut self.file_dialog {
+            let (title, confirm_label) = match dialog.action {
+                FileAction::Open => ("Open layout", "Open"),
+                FileAction::SaveAs => ("Save layout as", "Save"),
+                FileAction::ExportNetlist => ("Export netlist (.dot or .json)", "Export"),
+            };
+            let mut confirmed = false;
+            let mut cancelled = false;
+            egui::Window::new(title).collapsible(false).show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Path:");
+                    let response = ui.text_edit_singleline(&mut dialog.path);
+                    confirmed |= response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                });
+                ui.horizontal(|ui| {
+                    confirmed |= ui.add_enabled(!dialog.path.trim().is_empty(), egui::Button::new(confirm_label)).clicked();
+                    cancelled = ui.button("Cancel").clicked();
+                });
+            });
+
+            if confirmed && !dialog.path.trim().is_empty() {
+                let (action, path) = (dialog.action, PathBuf::from(dialog.path.trim()));
+                self.file_dialog = None;
+                match action {
+                    FileAction::Open => self.open(path),
+                    FileAction::SaveAs => self.save(path),
+                    FileAction::ExportNetlist => self.export_netlist(path),
+                }
+            } else if cancelled {
+                self.file_dialog = None;
+            }
+        }
+
+        if let Some(error) = &self.error {
+            let mut dismissed = false;
+            egui::Window::new("Error").collapsible(false).show(ctx, |ui| {
+                ui.label(error);
+                dismissed = ui.button("Ok").clicked();
+            });
+            if dismissed {
+                self.error = None;
+            }
+        }
     }
 }
 
@@ -28,74 +900,398 @@ impl eframe::App for MainWindow {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         // let Self { value, ui_3d, simulator: _simulator} = self;
 
+        if !ctx.wants_keyboard_input() {
+            if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND | egui::Modifiers::SHIFT, egui::Key::Z)) {
+                self.redo();
+            } else if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::Z)) {
+                self.undo();
+            } else if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::A)) {
+                self.select_all();
+            } else if ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Delete)) {
+                self.delete_selection();
+            }
+            for event in ctx.input(|i| i.events.clone()) {
+                match event {
+                    egui::Event::Copy => self.copy(ctx),
+                    egui::Event::Paste(text) => self.paste(&text),
+                    _ => {}
+                }
+            }
+            if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+                if let Some(u) = &mut self.ui_3d {
+                    u.placing = None;
+                }
+            }
+        }
+        // a continuous edit like dragging a slider ends when the mouse button is released
+        if !ctx.input(|i| i.pointer.any_down()) {
+            self.history.end_coalescing();
+        }
+
         #[cfg(not(target_arch = "wasm32"))] // no File->Quit on web pages!
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             // The top panel is often a good place for a menu bar:
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
+                    if ui.button("Open…").clicked() {
+                        self.show_file_dialog(FileAction::Open);
+                        ui.close_menu();
+                    }
+                    ui.add_enabled_ui(!self.recent_files.is_empty(), |ui| {
+                        ui.menu_button("Open recent", |ui| {
+                            let mut opened = None;
+                            for path in self.recent_files.iter() {
+                                if ui.button(path.display().to_string()).clicked() {
+                                    opened = Some(path.clone());
+                                }
+                            }
+                            ui.separator();
+                            if ui.button("Clear list").clicked() {
+                                self.recent_files.clear();
+                                ui.close_menu();
+                            }
+                            if let Some(path) = opened {
+                                self.open(path);
+                                ui.close_menu();
+                            }
+                        });
+                    });
+                    if ui.button("Save").clicked() {
+                        match self.file_path.clone() {
+                            Some(path) => self.save(path),
+                            None => self.show_file_dialog(FileAction::SaveAs),
+                        }
+                        ui.close_menu();
+                    }
+                    if ui.button("Save As…").clicked() {
+                        self.show_file_dialog(FileAction::SaveAs);
+                        ui.close_menu();
+                    }
+                    if ui.button("Export netlist…").clicked() {
+                        self.show_file_dialog(FileAction::ExportNetlist);
+                        ui.close_menu();
+                    }
+                    ui.separator();
                     if ui.button("Quit").clicked() {
                         frame.close();
                     }
                 });
+                ui.menu_button("Edit", |ui| {
+                    if ui.add_enabled(self.history.can_undo(), egui::Button::new("Undo").shortcut_text("Ctrl+Z")).clicked() {
+                        self.undo();
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(self.history.can_redo(), egui::Button::new("Redo").shortcut_text("Ctrl+Shift+Z")).clicked() {
+                        self.redo();
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    let has_selection = self.ui_3d.as_ref().is_some_and(|u| !u.selected_ids().is_empty());
+                    if ui.add_enabled(has_selection, egui::Button::new("Copy").shortcut_text("Ctrl+C")).clicked() {
+                        self.copy(ctx);
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(self.clipboard.is_some(), egui::Button::new("Paste").shortcut_text("Ctrl+V")).clicked() {
+                        let text = self.clipboard.clone().unwrap();
+                        self.paste(&text);
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    if ui.add(egui::Button::new("Select all").shortcut_text("Ctrl+A")).clicked() {
+                        self.select_all();
+                        ui.close_menu();
+                    }
+                    if ui.button("Select none").clicked() {
+                        if let Some(u) = &mut self.ui_3d {
+                            u.selection.clear();
+                        }
+                        ui.close_menu();
+                    }
+                    if ui.add(egui::Button::new("Delete selection").shortcut_text("Del")).clicked() {
+                        self.delete_selection();
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("Windows", |ui| {
+                    for (title, open) in self.panels.entries() {
+                        ui.checkbox(open, title);
+                    }
+                });
+                if let Some(u) = &mut self.ui_3d {
+                    ui.menu_button("View", |ui| {
+                        let mut top_down = u.camera.projection == Projection::TopDown;
+                        if ui.checkbox(&mut top_down, "Top-down").on_hover_text("Orthographic view from above for laying out dominos").changed() {
+                            u.camera.projection = if top_down { Projection::TopDown } else { Projection::Perspective };
+                            ui.close_menu();
+                        }
+                        if ui.add_enabled(!u.selected_ids().is_empty(), egui::Button::new("Frame selection").shortcut_text("F")).clicked() {
+                            u.frame_selection();
+                            ui.close_menu();
+                        }
+                        let mut fov = cgmath::Deg::from(u.camera.fov).0;
+                        if ui.add(egui::Slider::new(&mut fov, 20.0..=120.0).text("field of view (°)")).changed() {
+                            u.camera.fov = cgmath::Deg(fov).into();
+                        }
+                        if ui.button("Reset camera").clicked() {
+                            u.camera = Camera::default();
+                            ui.close_menu();
+                        }
+                        ui.separator();
+                        ui.label("Drag to orbit, middle-drag to pan, scroll to zoom, WASD, Q and E to move.");
+                    });
+                }
             });
         });
 
-        egui::Window::new("Domino Inspector").show(ctx, |ui| {
+        self.file_windows(ctx);
+        self.timeline_panel(ctx);
+        self.blueprint_window(ctx);
+        self.timing_window(ctx);
+        self.validation_window(ctx);
+        self.synthesis_window(ctx);
+        self.terrain_window(ctx);
+        self.components_window(ctx);
+        self.lighting_window(ctx);
+
+        egui::Window::new("Domino Inspector").open(&mut self.panels.inspector).show(ctx, |ui| {
+            let ids = self.ui_3d.as_ref().map(|u| u.selected_ids()).unwrap_or_default();
             let mut s = self.simulator.lock().unwrap();
-            let domino_id = match &self.ui_3d {
-                Some(u) => u.selected_domino_id,
-                None => None
-            };
-            let domino: Option<&mut Domino> = s.dominos.iter_mut().find(|d| domino_id == Some(d.id));
 
-            if domino_id.is_none() || domino.is_none() {
-                ui.label("No domino is selected.");
-                ui.label("Selected a domino by clicking on it to see it in this inspector.");
-                return
-            }
+            match ids.len() {
+                0 => {
+                    ui.label("No domino is selected.");
+                    ui.label("Selected a domino by clicking on it to see it in this inspector. Shift-click or shift-drag a rectangle to select several dominos.");
+                    return
+                }
+                1 => {
+                    let domino = s.dominos.iter_mut().find(|d| d.id == ids[0]).unwrap();
+                    let before = domino.clone();
 
-            let domino = domino.unwrap();
-            
-            ui.label(domino.id.to_string());
+                    ui.label(domino.id.to_string());
+                    if let Some(time) = self.timeline.fall_time(domino.id) {
+                        ui.label(format!("Fell at {:.3} s", time));
+                    }
 
-            ui.add(egui::Slider::new(&mut domino.position.x, -10.0..=10.0).text("x-Position"));
-            ui.add(egui::Slider::new(&mut domino.position.y, -10.0..=10.0).text("y-Position"));
-            ui.add(egui::Slider::new(&mut domino.position.z, -10.0..=10.0).text("z-Position"));
+                    let mut changed = false;
+                    changed |= ui.add(egui::Slider::new(&mut domino.position.x, -10.0..=10.0).text("x-Position")).changed();
+                    changed |= ui.add(egui::Slider::new(&mut domino.position.y, -10.0..=10.0).text("y-Position")).changed();
+                    changed |= ui.add(egui::Slider::new(&mut domino.position.z, -10.0..=10.0).text("z-Position")).changed();
 
-            ui.add(egui::Slider::new(&mut domino.rotation_y, 0.0..=360.0).text("y-Rotation"));
-            ui.add(egui::Slider::new(&mut domino.fall_rotation, -90.0..=90.0).text("fall-rotation"));
+                    changed |= ui.add(egui::Slider::new(&mut domino.rotation_y, 0.0..=360.0).text("y-Rotation")).changed();
+                    changed |= ui.add(egui::Slider::new(&mut domino.fall_rotation, -90.0..=90.0).text("fall-rotation")).changed();
 
-            if ui.button("Delete domino").clicked() {
-                let idx = s.dominos.iter().position(|d| Some(d.id) == self.ui_3d.as_ref().unwrap().selected_domino_id);
-                if idx.is_some() {
-                    s.dominos.remove(idx.unwrap());
-                    self.ui_3d.as_mut().unwrap().selected_domino_id = None;
+                    changed |= ui.add(egui::Slider::new(&mut domino.scale.x, 0.1..=10.0).logarithmic(true).text("width scale")).changed();
+                    changed |= ui.add(egui::Slider::new(&mut domino.scale.y, 0.1..=10.0).logarithmic(true).text("height scale")).changed();
+                    changed |= ui.add(egui::Slider::new(&mut domino.scale.z, 0.1..=10.0).logarithmic(true).text("depth scale")).changed();
+                    ui.label(format!("{:.3} × {:.3} × {:.3} m, {:.3} kg", domino.dimensions().x, domino.dimensions().y, domino.dimensions().z, domino.mass()));
+                    ui.separator();
+                    changed |= material_editor(ui, &mut domino.material);
+
+                    if changed {
+                        let after = domino.clone();
+                        self.history.record_coalesced(&format!("inspector {}", after.id), Command::Modify { before: vec![before], after: vec![after] });
+                    }
+                }
+                n => {
+                    ui.label(format!("{} dominos are selected.", n));
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut self.group_offset.x).speed(0.01).prefix("x: ").suffix(" m"));
+                        ui.add(egui::DragValue::new(&mut self.group_offset.y).speed(0.01).prefix("z: ").suffix(" m"));
+                        if ui.button("Move").clicked() {
+                            let offset = cgmath::vec3(self.group_offset.x, 0.0, self.group_offset.y);
+                            let command = Command::transform(&s, &ids, offset, 0.0);
+                            self.history.execute(&mut s, command);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut self.group_rotation).speed(1.0).suffix("°"));
+                        if ui.button("Rotate").clicked() {
+                            let command = Command::transform(&s, &ids, cgmath::vec3(0.0, 0.0, 0.0), self.group_rotation);
+                            self.history.execute(&mut s, command);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut self.group_scale).speed(0.01).clamp_range(0.1..=10.0).prefix("×"));
+                        if ui.button("Scale").clicked() {
+                            let before: Vec<Domino> = s.dominos.iter().filter(|d| ids.contains(&d.id)).cloned().collect();
+                            let after = before.iter().map(|d| Domino { scale: d.scale * self.group_scale, ..d.clone() }).collect();
+                            self.history.execute(&mut s, Command::Modify { before, after });
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_id_source("group material").show_index(ui, &mut self.group_material, material::PRESETS.len(), |i| material::PRESETS[i].0.to_string());
+                        if ui.button("Apply material").clicked() {
+                            let before: Vec<Domino> = s.dominos.iter().filter(|d| ids.contains(&d.id)).cloned().collect();
+                            let after = before.iter().map(|d| Domino { material: material::PRESETS[self.group_material].1, ..d.clone() }).collect();
+                            self.history.execute(&mut s, Command::Modify { before, after });
+                        }
+                    });
                 }
             }
+
+            ui.horizontal(|ui| {
+                if ui.button("Duplicate").clicked() {
+                    let command = Command::duplicate(&s, &ids);
+                    if let (Command::Create { dominos, .. }, Some(u)) = (&command, &mut self.ui_3d) {
+                        u.selection = dominos.iter().map(|d| d.id).collect();
+                    }
+                    self.history.execute(&mut s, command);
+                }
+                if ui.button("Delete").clicked() {
+                    let command = Command::delete(&s, &ids);
+                    self.history.execute(&mut s, command);
+                    self.ui_3d.as_mut().unwrap().selection.clear();
+                }
+            });
         });
 
-        egui::Window::new("Domino Creator").show(ctx, |ui| {
+        egui::Window::new("Domino Creator").open(&mut self.panels.creator).show(ctx, |ui| {
             if ui.button("Create domino").clicked() {
                 let mut s = self.simulator.lock().unwrap();
-                let id = match s.dominos.last() {
-                    Some(d) => d.id+1,
-                    None => 0
-                };
-                s.dominos.push(Domino {
-                    fall_rotation: 0.0,
-                    id,
-                    rotation_y: 0.0,
-                    position: cgmath::point3(0.0, 0.0, 0.0),
-                    scale: cgmath::vec3(1.0, 1.0, 1.0),
+                let id = s.next_id();
+                let dominos = vec![Domino::new(id, cgmath::point3(0.0, 0.0, 0.0), 0.0)];
+                self.history.execute(&mut s, Command::Create { dominos, ports: vec![] });
+                self.ui_3d.as_mut().unwrap().selection = [id].into();
+            }
+
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_source("gate").show_index(ui, &mut self.gate_index, gates::GATES.len(), |i| gates::GATES[i].0.to_string());
+                if ui.button("Create gate").clicked() {
+                    let (name, gate) = gates::GATES[self.gate_index];
+                    let mut s = self.simulator.lock().unwrap();
+                    let number = (0..).find(|n| !s.ports.iter().any(|p| p.name.starts_with(&format!("{}{}.", name, n)))).unwrap();
+                    let ids = s.add_gate(&gate(cgmath::point3(0.0, 0.0, 0.0), 0.0), &format!("{}{}", name, number));
+                    self.history.record(Command::Create {
+                        dominos: s.dominos.iter().filter(|d| ids.contains(&d.id)).cloned().collect(),
+                        ports: s.ports.iter().filter(|p| ids.contains(&p.domino_id)).cloned().collect(),
+                    });
+                }
+            });
+        });
+
+        if let Some(u) = &mut self.ui_3d {
+            egui::Window::new("Snapping").open(&mut self.panels.snapping).show(ctx, |ui| {
+                ui.checkbox(&mut u.snapping.enabled, "Snap to grid");
+                ui.add_enabled_ui(u.snapping.enabled, |ui| {
+                    ui.add(egui::Slider::new(&mut u.snapping.grid_size, 0.01..=1.0).text("grid size (m)"));
+                    ui.add(egui::Slider::new(&mut u.snapping.angle_step, 1.0..=90.0).text("angle step (°)"));
                 });
-                self.ui_3d.as_mut().unwrap().selected_domino_id = Some(id);
+            });
+        }
+
+        if let Some(u) = &mut self.ui_3d {
+            let mut finish = false;
+            egui::Window::new("Path Tool").open(&mut self.panels.path_tool).show(ctx, |ui| {
+                ui.checkbox(&mut u.path_tool.active, "Draw line");
+                ui.add_enabled_ui(u.path_tool.active, |ui| {
+                    ui.label("Click on the ground to add points to the path.");
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut u.path_tool.shape, path_tool::Shape::Polyline, "Polyline");
+                        ui.radio_value(&mut u.path_tool.shape, path_tool::Shape::Spline, "Spline");
+                    });
+                    ui.add(egui::Slider::new(&mut u.path_tool.spacing, 0.01..=0.2).text("spacing (m)"));
+                    if let Some(error) = u.path_tool.spacing_error() {
+                        ui.colored_label(egui::Color32::RED, error);
+                    }
+                    ui.label(format!("{} points, {} dominos", u.path_tool.points().len(), u.path_tool.placements().len()));
+                    ui.horizontal(|ui| {
+                        let can_finish = u.path_tool.spacing_error().is_none() && u.path_tool.placements().len() >= 2;
+                        finish |= ui.add_enabled(can_finish, egui::Button::new("Finish").shortcut_text("Enter")).clicked();
+                        if ui.button("Remove last point").clicked() {
+                            u.path_tool.remove_last_point();
+                        }
+                        if ui.add(egui::Button::new("Clear").shortcut_text("Esc")).clicked() {
+                            u.path_tool.clear();
+                        }
+                    });
+                });
+            });
+
+            if u.path_tool.active && !ctx.wants_keyboard_input() {
+                finish |= ctx.input(|i| i.key_pressed(egui::Key::Enter));
+                if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+                    u.path_tool.clear();
+                }
+            }
+            if finish {
+                u.finish_path();
             }
+        }
+
+        egui::Window::new("Simulation").open(&mut self.panels.simulation).show(ctx, |ui| {
+            let ids = self.ui_3d.as_ref().map(|u| u.selected_ids()).unwrap_or_default();
+            ui.add_enabled_ui(!ids.is_empty(), |ui| {
+                ui.horizontal(|ui| {
+                    for (label, velocity) in [("Push selected forward", PUSH_VELOCITY), ("Push selected backward", -PUSH_VELOCITY)] {
+                        if ui.button(label).clicked() {
+                            let mut s = self.simulator.lock().unwrap();
+                            for id in &ids {
+                                s.push(*id, velocity);
+                            }
+                        }
+                    }
+                });
+            });
         });
 
-        match &mut self.ui_3d {
-            // Some(g) => (&mut g as &mut dyn eframe::App).update(ctx, frame),
-            Some(g) => g.update(ctx, frame),
-            None => {}
+        let dt = ctx.input(|i| i.stable_dt) * self.speed;
+        if let Some(playback) = &mut self.playback {
+            let (start, end) = (self.timeline.time(0), self.timeline.time(self.timeline.len() - 1));
+            if playback.playing {
+                playback.time += dt;
+                if playback.time > end {
+                    if self.looping {
+                        playback.time = start;
+                    } else {
+                        playback.time = end;
+                        playback.playing = false;
+                    }
+                }
+            }
+            self.timeline.apply(self.timeline.frame_at(playback.time), &mut self.simulator.lock().unwrap());
+        } else if self.running {
+            self.step_live(dt);
+        }
+
+        if let Some(g) = &mut self.ui_3d {
+            g.update(ctx, frame);
+            for command in g.take_edits() {
+                self.history.record(command);
+            }
         }
     }
+
+    /// Called by eframe from time to time and before shutting down.
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        let settings = Settings {
+            camera: self.ui_3d.as_ref().map(|u| CameraEntry::from(&u.camera)),
+            panels: self.panels.clone(),
+            file_path: self.file_path.clone(),
+            recent_files: self.recent_files.clone(),
+        };
+        eframe::set_value(storage, eframe::APP_KEY, &settings);
+    }
+}
+
+/// Edits `material` by choosing a preset or changing its values. Returns `true` if it changed.
+fn material_editor(ui: &mut egui::Ui, material: &mut Material) -> bool {
+    let mut changed = false;
+    egui::ComboBox::from_label("Material")
+        .selected_text(material.preset_name().unwrap_or("custom"))
+        .show_ui(ui, |ui| {
+            for (name, preset) in material::PRESETS {
+                if ui.selectable_label(*material == preset, name).clicked() {
+                    *material = preset;
+                    changed = true;
+                }
+            }
+        });
+    changed |= ui.add(egui::Slider::new(&mut material.mass, 0.01..=1.0).logarithmic(true).text("mass at scale 1 (kg)")).changed();
+    changed |= ui.add(egui::Slider::new(&mut material.friction, 0.0..=1.0).text("friction")).changed();
+    changed |= ui.add(egui::Slider::new(&mut material.restitution, 0.0..=1.0).text("restitution")).changed();
+    ui.horizontal(|ui| {
+        changed |= ui.color_edit_button_rgb(&mut material.color).changed();
+        ui.label("colour");
+    });
+    changed
 }
@@ -0,0 +1,652 @@
+//! Extraction of the circuit a layout implements, to review it as a circuit diagram or to diff two
+//! revisions of a layout at the logic level.
+//!
+//! The dominos are connected to the neighbours they knock over falling either way, leaving out
+//! the dominos further ahead, so that the dominos of a curve, which reach two dominos ahead, do
+//! not look like branches. Dominos with more than two neighbours are junctions, the chains between
+//! them are collapsed into wires, and the [`KnockGraph`] of the layout tells which ways the signal
+//! travels along each wire. The junctions are then matched against the junctions of the gates in
+//! [`gates::GATES`], taking the counter-clockwise order of the wires around each junction into
+//! account, so that e.g. the `clock` and `in` inputs of a `not` gate are told apart. The inputs
+//! and outputs of a matched gate may connect to ports or to other gates, and since the gates after
+//! it may knock back into its lines, its wires only have to carry the signal at least the ways
+//! they do in the gate alone. Junctions that are not part of a known gate become cells of the
+//! kind `junction`.
+//!
+//! Every wire that does not belong to a gate is a net, named after the first port of the layout
+//! along it or otherwise after the pin driving it. Gates whose pins are connected to ports named
+//! `<name>.<pin name>`, as added by [`Simulator::add_gate`], are named `<name>`. Domino ids do not
+//! appear in the exported formats, so that they only change when the circuit does.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use serde::Serialize;
+
+use crate::{gates, knock::{self, KnockGraph}, simulator::{PortDirection, Simulator}};
+
+/// Direction of the signal on a pin, seen from the cell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PinDirection {
+    Input,
+    Output,
+    /// The wire is knocked over from both ends, depending on the inputs.
+    Both,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Pin {
+    pub name: String,
+    pub direction: PinDirection,
+    pub net: String,
+}
+
+/// A gate, or a junction of wires that is not part of a known gate.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Cell {
+    pub name: String,
+    /// Name of the gate in [`gates::GATES`] or `junction`.
+    pub kind: String,
+    pub pins: Vec<Pin>,
+    #[serde(skip)]
+    pub dominos: Vec<u32>,
+}
+
+/// Something a net is connected to at one of its ends.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Endpoint {
+    Pin { cell: String, pin: String },
+    Port { name: String },
+}
+
+/// A wire between cells and ports.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Net {
+    pub name: String,
+    /// The end the signal enters the wire at, `None` for an open end.
+    pub source: Option<Endpoint>,
+    /// The end the signal leaves the wire at, `None` for an open end.
+    pub sink: Option<Endpoint>,
+    /// The wire is knocked over from both ends, depending on the inputs.
+    pub bidirectional: bool,
+    #[serde(skip)]
+    pub dominos: Vec<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct NetlistPort {
+    pub name: String,
+    /// Direction of the signal, seen from the layout.
+    pub direction: PinDirection,
+    /// The net the domino of the port is part of, `None` if it belongs to a cell.
+    pub net: Option<String>,
+}
+
+/// The circuit of a layout, with its cells, ports and nets ordered by name.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Netlist {
+    pub ports: Vec<NetlistPort>,
+    pub cells: Vec<Cell>,
+    pub nets: Vec<Net>,
+}
+
+/// Which way the signal travels along a wire, seen from one of its ends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Flow {
+    into_end: bool,
+    out_of_end: bool,
+}
+
+/// A chain of dominos between two junctions, or between a junction or a junction and the open end
+/// of a chain.
+struct Segment {
+    /// Positions in [`Collapsed::junctions`] of the junctions at both ends, `None` for open ends.
+    ends: [Option<usize>; 2],
+    /// Indices of the dominos from `ends[0]` to `ends[1]`, without the junctions.
+    dominos: Vec<usize>,
+    /// The signal travels from `ends[0]` to `ends[1]`.
+    forward: bool,
+    /// The signal travels from `ends[1]` to `ends[0]`.
+    backward: bool,
+}
+
+impl Flow {
+    /// `true` if the signal travels at least the ways of `other`. The gates after a gate in a
+    /// layout may knock back into its lines, which adds the other way.
+    fn covers(self, other: Flow) -> bool {
+        (self.into_end || !other.into_end) && (self.out_of_end || !other.out_of_end)
+    }
+}
+
+impl Segment {
+    fn flow(&self, end: usize) -> Flow {
+        if end == 0 {
+            Flow { into_end: self.backward, out_of_end: self.forward }
+        } else {
+            Flow { into_end: self.forward, out_of_end: self.backward }
+        }
+    }
+}
+
+/// The knock graph of a layout with its chains collapsed into segments.
+struct Collapsed {
+    /// Indices of the dominos with more than two neighbours.
+    junctions: Vec<usize>,
+    /// Segments at each junction as `(segment, end)` in counter-clockwise order.
+    incident: Vec<Vec<(usize, usize)>>,
+    segments: Vec<Segment>,
+}
+
+impl Collapsed {
+    fn new(simulator: &Simulator) -> Self {
+        let dominos = &simulator.dominos;
+        let graph = KnockGraph::new(simulator);
+        let n = dominos.len();
+
+        // the dominos each domino knocks over in the simulation, which decides the flow of the
+        // signal along the segments
+        let mut successors: Vec<Vec<usize>> = vec![vec![]; n];
+        for fall in &graph.falls {
+            successors[fall.index].extend(fall.knocks.iter().copied());
+        }
+
+        // the neighbours are taken from both ways every domino can fall, not only from the falls
+        // of the simulation, so that a gate has the same junctions in every layout, also where
+        // the gates after it knock back into its lines. The edges to dominos that are also
+        // knocked over by a domino in between, when it falls the way it is knocked over, are
+        // dropped.
+        let mut neighbours: Vec<Vec<usize>> = vec![vec![]; n];
+        for a in 0..n {
+            for side in [1.0, -1.0] {
+                let direction = knock::forward(&dominos[a], side);
+                let knocks: Vec<usize> = (0..n).filter(|b| knock::reaches(&dominos[a], side, &dominos[*b])).collect();
+                let through = |c: usize| knocks.iter().any(|b| *b != c && knock::reaches(&dominos[*b], knock::side_away_from(&dominos[*b], direction), &dominos[c]));
+                for c in knocks.iter().copied().filter(|c| !through(*c)) {
+                    neighbours[a].push(c);
+                    neighbours[c].push(a);
+                }
+            }
+        }
+        for n in neighbours.iter_mut() {
+            n.sort_unstable();
+            n.dedup();
+        }
+
+        let junctions: Vec<usize> = (0..n).filter(|a| neighbours[*a].len() > 2).collect();
+        let junction_of = |a: usize| junctions.iter().position(|j| *j == a);
+        let mut used = vec![false; n];
+        let mut segments = vec![];
+
+        // follows the chain from `previous` over `current` until a junction or an open end
+        let walk = |start: Option<usize>, mut previous: Option<usize>, mut current: usize, used: &mut Vec<bool>| {
+            let mut chain = vec![];
+            let end = loop {
+                if let Some(j) = junction_of(current) {
+                    break Some(j);
+                }
+                used[current] = true;
+                chain.push(current);
+                match neighbours[current].iter().copied().find(|next| Some(*next) != previous && !(used[*next] && junction_of(*next).is_none())) {
+                    Some(next) => {
+                        previous = Some(current);
+                        current = next;
+                    }
+                    None => break None,
+                }
+            };
+            let path: Vec<usize> = start.map(|j| junctions[j]).into_iter().chain(chain.iter().copied()).chain(end.map(|j| junctions[j])).collect();
+            let forward = path.len() > 1 && path.windows(2).all(|w| successors[w[0]].contains(&w[1]));
+            let backward = path.len() > 1 && path.windows(2).all(|w| successors[w[1]].contains(&w[0]));
+            Segment { ends: [start, end], dominos: chain, forward, backward }
+        };
+
+        for (j, junction) in junctions.iter().enumerate() {
+            for next in &neighbours[*junction] {
+                match junction_of(*next) {
+                    // junctions next to each other are connected by an empty segment, once
+                    Some(other) if j < other => segments.push(walk(Some(j), Some(*junction), *next, &mut used)),
+                    Some(_) => {}
+                    None if !used[*next] => segments.push(walk(Some(j), Some(*junction), *next, &mut used)),
+                    None => {}
+                }
+            }
+        }
+        // chains without junctions, starting at one of their ends if they have one
+        for start in (0..n).filter(|a| neighbours[*a].len() < 2).chain(0..n) {
+            if !used[start] && junction_of(start).is_none() && !neighbours[start].is_empty() {
+                segments.push(walk(None, None, start, &mut used));
+            }
+        }
+
+        let mut incident: Vec<Vec<(usize, usize)>> = vec![vec![]; junctions.len()];
+        for (s, segment) in segments.iter().enumerate() {
+            for end in 0..2 {
+                if let Some(j) = segment.ends[end] {
+                    incident[j].push((s, end));
+                }
+            }
+        }
+        for (j, incident) in incident.iter_mut().enumerate() {
+            let center = dominos[junctions[j]].position;
+            let angle = |(s, end): &(usize, usize)| {
+                let segment: &Segment = &segments[*s];
+                let first = if *end == 0 { segment.dominos.first() } else { segment.dominos.last() };
+                let towards = match first {
+                    Some(d) => dominos[*d].position,
+                    None => dominos[junctions[segment.ends[1 - end].unwrap()]].position,
+                };
+                // counter-clockwise seen from above, the z axis points towards the viewer
+                (towards.x - center.x).atan2(-(towards.z - center.z))
+            };
+            incident.sort_by(|a, b| angle(a).total_cmp(&angle(b)));
+        }
+
+        Collapsed { junctions, incident, segments }
+    }
+}
+
+/// The junctions of a gate, with the port at the open end of each segment leaving the gate.
+struct Pattern {
+    kind: &'static str,
+    collapsed: Collapsed,
+    ports: Vec<Option<(String, PortDirection)>>,
+}
+
+impl Pattern {
+    fn new(kind: &'static str, gate: &gates::Gate) -> Self {
+        let simulator = Simulator { dominos: gate.dominos.clone(), ports: gate.ports.clone(), ..Simulator::empty() };
+        let collapsed = Collapsed::new(&simulator);
+        let ports = collapsed.segments.iter().map(|s| {
+            s.dominos.iter().find_map(|d| {
+                let id = simulator.dominos[*d].id;
+                simulator.ports.iter().find(|p| p.domino_id == id).map(|p| (p.name.clone(), p.direction))
+            })
+        }).collect();
+        Pattern { kind, collapsed, ports }
+    }
+}
+
+/// Where a pattern was found in a layout.
+struct Match {
+    /// Layout junction of each pattern junction.
+    junctions: Vec<usize>,
+    /// Layout segment of each pattern segment.
+    segments: Vec<Option<usize>>,
+}
+
+/// Tries to find `pattern` with its first junction at the layout junction `l0`, with the segments
+/// around it rotated by `rotation`. Since the segments around every junction keep their order,
+/// this determines the whole match.
+fn match_at(pattern: &Pattern, layout: &Collapsed, l0: usize, rotation: usize, taken: &[bool]) -> Option<Match> {
+    let (ports, pattern) = (&pattern.ports, &pattern.collapsed);
+    let mut junctions: Vec<Option<(usize, usize)>> = vec![None; pattern.junctions.len()];
+    let mut segments: Vec<Option<usize>> = vec![None; pattern.segments.len()];
+    let mut used = vec![false; layout.junctions.len()];
+    junctions[0] = Some((l0, rotation));
+    used[l0] = true;
+    let mut queue = vec![0];
+
+    while let Some(p) = queue.pop() {
+        let (l, rotation) = junctions[p].unwrap();
+        let degree = pattern.incident[p].len();
+        if taken[l] || layout.incident[l].len() != degree {
+            return None;
+        }
+        for (i, (ps, pe)) in pattern.incident[p].iter().copied().enumerate() {
+            let (ls, le) = layout.incident[l][(i + rotation) % degree];
+            let (pattern_segment, layout_segment) = (&pattern.segments[ps], &layout.segments[ls]);
+            if !layout_segment.flow(le).covers(pattern_segment.flow(pe)) {
+                return None;
+            }
+            match segments[ps] {
+                Some(s) if s != ls => return None,
+                Some(_) => {}
+                None if segments.contains(&Some(ls)) => return None,
+                None => segments[ps] = Some(ls),
+            }
+
+            let pattern_other = match pattern_segment.ends[1 - pe] {
+                Some(o) => o,
+                // a segment leaving the gate through a port may continue in the layout
+                None if ports[ps].is_some() => continue,
+                None if layout_segment.ends[1 - le].is_none() => continue,
+                None => return None,
+            };
+            let layout_other = layout_segment.ends[1 - le]?;
+            if pattern_segment.dominos.len() != layout_segment.dominos.len() {
+                return None;
+            }
+            let pattern_index = pattern.incident[pattern_other].iter().position(|e| *e == (ps, 1 - pe))?;
+            let layout_index = layout.incident[layout_other].iter().position(|e| *e == (ls, 1 - le))?;
+            let other_degree = layout.incident[layout_other].len();
+            if pattern.incident[pattern_other].len() != other_degree {
+                return None;
+            }
+            let other_rotation = (layout_index + other_degree - pattern_index) % other_degree;
+            match junctions[pattern_other] {
+                Some(existing) if existing != (layout_other, other_rotation) => return None,
+                Some(_) => {}
+                None => {
+                    if used[layout_other] {
+                        return None;
+                    }
+                    used[layout_other] = true;
+                    junctions[pattern_other] = Some((layout_other, other_rotation));
+                    queue.push(pattern_other);
+                }
+            }
+        }
+    }
+
+    Some(Match { junctions: junctions.into_iter().map(|j| j.map(|(l, _)| l)).collect::<Option<_>>()?, segments })
+}
+
+/// A pin of a cell during the extraction, as `(name, direction, layout segment)`.
+type SegmentPin = (String, PinDirection, usize);
+
+fn pin_direction(flow: Flow) -> PinDirection {
+    match (flow.into_end, flow.out_of_end) {
+        (true, true) => PinDirection::Both,
+        (false, true) => PinDirection::Output,
+        _ => PinDirection::Input,
+    }
+}
+
+/// Extracts the netlist of the layout, see [`crate::netlist`].
+pub fn extract(simulator: &Simulator) -> Netlist {
+    let dominos = &simulator.dominos;
+    let layout = Collapsed::new(simulator);
+    let mut patterns: Vec<Pattern> = gates::GATES.iter()
+        .map(|(kind, gate)| Pattern::new(kind, &gate(cgmath::point3(0.0, 0.0, 0.0), 0.0)))
+        .filter(|p| !p.collapsed.junctions.is_empty())
+        .collect();
+    // the larger gates contain the junctions of the smaller ones
+    patterns.sort_by_key(|p| std::cmp::Reverse(p.collapsed.junctions.len()));
+
+    // the cell each junction and segment belongs to, and the kind and pins of each cell
+    let mut junction_cells: Vec<Option<usize>> = vec![None; layout.junctions.len()];
+    let mut segment_cells: Vec<Option<usize>> = vec![None; layout.segments.len()];
+    let mut cells: Vec<(&str, Vec<SegmentPin>)> = vec![];
+    for l0 in 0..layout.junctions.len() {
+        for pattern in &patterns {
+            if junction_cells[l0].is_some() {
+                break;
+            }
+            let taken: Vec<bool> = junction_cells.iter().map(|c| c.is_some()).collect();
+            let found = (0..layout.incident[l0].len()).find_map(|rotation| match_at(pattern, &layout, l0, rotation, &taken));
+            let found = match found {
+                Some(m) => m,
+                None => continue,
+            };
+            let cell = cells.len();
+            let mut pins = vec![];
+            for l in &found.junctions {
+                junction_cells[*l] = Some(cell);
+            }
+            for (ps, ls) in found.segments.iter().enumerate() {
+                let (ls, pattern_segment) = match ls {
+                    Some(ls) => (*ls, &pattern.collapsed.segments[ps]),
+                    None => continue,
+                };
+                let open_end = pattern_segment.ends.iter().position(|e| e.is_none());
+                match (open_end, &pattern.ports[ps]) {
+                    // the pins of gates keep the direction of their ports, even where the gate
+                    // knocks back into its inputs
+                    (Some(_), Some((name, PortDirection::Input))) => pins.push((name.clone(), PinDirection::Input, ls)),
+                    (Some(_), Some((name, PortDirection::Output))) => pins.push((name.clone(), PinDirection::Output, ls)),
+                    // the segments inside the gate and its dead ends belong to the cell
+                    _ => segment_cells[ls] = Some(cell),
+                }
+            }
+            pins.sort_by(|a, b| a.0.cmp(&b.0));
+            cells.push((pattern.kind, pins));
+        }
+    }
+    for (l, incident) in layout.incident.iter().enumerate() {
+        if junction_cells[l].is_none() {
+            junction_cells[l] = Some(cells.len());
+            let pins = incident.iter().enumerate().map(|(i, (s, end))| (format!("p{}", i), pin_direction(layout.segments[*s].flow(*end)), *s)).collect();
+            cells.push(("junction", pins));
+        }
+    }
+
+    // name the cells after the gates they were added as, if their ports tell
+    let port_at = |index: usize| simulator.ports.iter().find(|p| p.domino_id == dominos[index].id);
+    let mut names: Vec<String> = vec![];
+    for (kind, pins) in &cells {
+        let from_ports = pins.iter().find_map(|(pin, _, s)| {
+            layout.segments[*s].dominos.iter().filter_map(|d| port_at(*d)).find_map(|p| {
+                p.name.strip_suffix(&format!(".{}", pin)).map(|prefix| prefix.to_string())
+            })
+        });
+        let name = match from_ports {
+            Some(name) if !names.contains(&name) => name,
+            _ => (0..).map(|i| format!("{}{}", kind, i)).find(|n| !names.contains(n)).unwrap(),
+        };
+        names.push(name);
+    }
+
+    let mut nets = vec![];
+    let mut net_names: BTreeMap<usize, String> = BTreeMap::new();
+    for (s, segment) in layout.segments.iter().enumerate() {
+        if segment_cells[s].is_some() {
+            continue;
+        }
+        let endpoint = |end: usize| -> Option<Endpoint> {
+            match segment.ends[end] {
+                Some(j) => {
+                    let cell = junction_cells[j].unwrap();
+                    let pin = cells[cell].1.iter().find(|(_, _, ps)| *ps == s)?;
+                    Some(Endpoint::Pin { cell: names[cell].clone(), pin: pin.0.clone() })
+                }
+                None => {
+                    let last = if end == 0 { segment.dominos.first() } else { segment.dominos.last() };
+                    port_at(*last?).map(|p| Endpoint::Port { name: p.name.clone() })
+                }
+            }
+        };
+        // wires knocked over from both ends run from an input port or a driving pin, if any
+        let drives = |endpoint: &Option<Endpoint>| match endpoint {
+            Some(Endpoint::Port { name }) => simulator.port(name).is_some_and(|p| p.direction == PortDirection::Input),
+            Some(Endpoint::Pin { cell, pin }) => cells[names.iter().position(|n| n == cell).unwrap()].1.iter().any(|(p, d, _)| p == pin && *d == PinDirection::Output),
+            None => false,
+        };
+        let (first, second) = (endpoint(0), endpoint(1));
+        let reversed = if segment.forward != segment.backward { segment.backward } else { drives(&second) && !drives(&first) };
+        let (source, sink) = if reversed { (second, first) } else { (first, second) };
+        let name = segment.dominos.iter().find_map(|d| port_at(*d)).map(|p| p.name.clone())
+            .or_else(|| match &source {
+                Some(Endpoint::Pin { cell, pin }) => Some(format!("{}.{}", cell, pin)),
+                _ => None,
+            });
+        let name = match name {
+            Some(name) if !net_names.values().any(|n| *n == name) => name,
+            _ => (0..).map(|i| format!("n{}", i)).find(|n| !net_names.values().any(|name| name == n)).unwrap(),
+        };
+        net_names.insert(s, name.clone());
+        nets.push(Net {
+            name,
+            source,
+            sink,
+            bidirectional: segment.forward && segment.backward,
+            dominos: segment.dominos.iter().map(|d| dominos[*d].id).collect(),
+        });
+    }
+
+    let mut cells: Vec<Cell> = cells.iter().enumerate().map(|(c, (kind, pins))| {
+        let mut cell_dominos: Vec<u32> = layout.junctions.iter().enumerate().filter(|(j, _)| junction_cells[*j] == Some(c)).map(|(_, d)| dominos[*d].id).collect();
+        for (segment, _) in layout.segments.iter().zip(segment_cells.iter()).filter(|(_, cell)| **cell == Some(c)) {
+            cell_dominos.extend(segment.dominos.iter().map(|d| dominos[*d].id));
+        }
+        Cell {
+            name: names[c].clone(),
+            kind: kind.to_string(),
+            pins: pins.iter().map(|(name, direction, s)| Pin {
+                name: name.clone(),
+                direction: *direction,
+                net: net_names.get(s).cloned().unwrap_or_default(),
+            }).collect(),
+            dominos: cell_dominos,
+        }
+    }).collect();
+    cells.sort_by(|a, b| a.name.cmp(&b.name));
+    nets.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut ports: Vec<NetlistPort> = simulator.ports.iter().map(|p| NetlistPort {
+        name: p.name.clone(),
+        direction: match p.direction {
+            PortDirection::Input => PinDirection::Input,
+            PortDirection::Output => PinDirection::Output,
+        },
+        net: nets.iter().find(|n| n.dominos.contains(&p.domino_id)).map(|n| n.name.clone()),
+    }).collect();
+    ports.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Netlist { ports, cells, nets }
+}
+
+impl Netlist {
+    /// The netlist as Graphviz graph, with a box per cell, an ellipse per port and an edge per net.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph netlist {\n    rankdir=LR;\n");
+        let node = |endpoint: &Option<Endpoint>, open: String| match endpoint {
+            Some(Endpoint::Pin { cell, .. }) => format!("\"cell:{}\"", cell),
+            Some(Endpoint::Port { name }) => format!("\"port:{}\"", name),
+            None => format!("\"open:{}\"", open),
+        };
+        for port in &self.ports {
+            let _ = writeln!(dot, "    \"port:{}\" [shape=ellipse, label=\"{}\"];", port.name, port.name);
+        }
+        for cell in &self.cells {
+            let _ = writeln!(dot, "    \"cell:{}\" [shape=box, label=\"{}\\n{}\"];", cell.name, cell.name, cell.kind);
+        }
+        for net in &self.nets {
+            let pin_label = |endpoint: &Option<Endpoint>| match endpoint {
+                Some(Endpoint::Pin { pin, .. }) => pin.clone(),
+                _ => String::new(),
+            };
+            for (endpoint, side) in [(&net.source, "source"), (&net.sink, "sink")] {
+                if endpoint.is_none() {
+                    let _ = writeln!(dot, "    \"open:{}:{}\" [shape=point];", net.name, side);
+                }
+            }
+            let _ = writeln!(
+                dot,
+                "    {} -> {} [label=\"{}\", taillabel=\"{}\", headlabel=\"{}\"{}];",
+                node(&net.source, format!("{}:source", net.name)),
+                node(&net.sink, format!("{}:sink", net.name)),
+                net.name,
+                pin_label(&net.source),
+                pin_label(&net.sink),
+                if net.bidirectional { ", dir=both" } else { "" },
+            );
+        }
+        // ports in the middle of a net are attached to the source of the net
+        for port in &self.ports {
+            let net = match self.nets.iter().find(|n| Some(&n.name) == port.net.as_ref()) {
+                Some(net) => net,
+                None => continue,
+            };
+            let at_end = [&net.source, &net.sink].iter().any(|e| matches!(e, Some(Endpoint::Port { name }) if *name == port.name));
+            if !at_end {
+                let _ = writeln!(dot, "    {} -> \"port:{}\" [style=dashed, arrowhead=none];", node(&net.source, format!("{}:source", net.name)), port.name);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synthesis::Circuit;
+
+    fn netlist(description: &str) -> Netlist {
+        extract(&Circuit::parse(description).unwrap().layout())
+    }
+
+    fn kinds(netlist: &Netlist) -> Vec<&str> {
+        let mut kinds: Vec<&str> = netlist.cells.iter().map(|c| c.kind.as_str()).collect();
+        kinds.sort_unstable();
+        kinds
+    }
+
+    fn port(name: &str) -> Option<Endpoint> {
+        Some(Endpoint::Port { name: name.to_string() })
+    }
+
+    fn pin(cell: &str, pin: &str) -> Option<Endpoint> {
+        Some(Endpoint::Pin { cell: cell.to_string(), pin: pin.to_string() })
+    }
+
+    /// The source and sink of every net.
+    fn connections(netlist: &Netlist) -> Vec<(Option<Endpoint>, Option<Endpoint>)> {
+        netlist.nets.iter().map(|n| (n.source.clone(), n.sink.clone())).collect()
+    }
+
+    #[test]
+    fn every_gate_is_found_with_its_ports() {
+        for (name, gate) in gates::GATES {
+            let mut simulator = Simulator::empty();
+            let gate = gate(cgmath::point3(0.3, 0.0, -0.2), 30.0);
+            simulator.add_gate(&gate, name);
+            let netlist = extract(&simulator);
+            assert_eq!(kinds(&netlist), [name]);
+            let mut pins: Vec<&str> = netlist.cells[0].pins.iter().map(|p| p.name.as_str()).collect();
+            let mut ports: Vec<&str> = gate.ports.iter().map(|p| p.name.as_str()).collect();
+            pins.sort_unstable();
+            ports.sort_unstable();
+            assert_eq!(pins, ports, "{}", name);
+        }
+    }
+
+    #[test]
+    fn gates_feeding_gates_are_found() {
+        let netlist = netlist("out = (a ^ b) ^ c");
+        assert_eq!(kinds(&netlist), ["xor", "xor"]);
+        assert_eq!(connections(&netlist), [
+            (port("a"), pin("xor0", "a")),
+            (port("b"), pin("xor0", "b")),
+            (port("c"), pin("xor1", "b")),
+            (pin("xor1", "out"), port("out")),
+            (pin("xor0", "out"), pin("xor1", "a")),
+        ]);
+    }
+
+    #[test]
+    fn fan_outs_are_found() {
+        let netlist = netlist("out = a & b\nx = a");
+        assert_eq!(kinds(&netlist), ["and", "fan_out"]);
+        assert_eq!(connections(&netlist), [
+            (port("a"), pin("fan_out0", "in")),
+            (port("b"), pin("and0", "b")),
+            (pin("fan_out0", "out_right"), pin("and0", "a")),
+            (pin("and0", "out"), port("out")),
+            (pin("fan_out0", "out_left"), port("x")),
+        ]);
+    }
+
+    #[test]
+    fn crossovers_are_found() {
+        let netlist = netlist("x = a | b\ny = a & b");
+        assert_eq!(kinds(&netlist), ["and", "crossover", "fan_out", "fan_out", "or"]);
+        let crossover = netlist.cells.iter().find(|c| c.kind == "crossover").unwrap();
+        let net = |pin: &str| crossover.pins.iter().find(|p| p.name == pin).unwrap().net.as_str();
+        assert_eq!([net("a"), net("b")], ["fan_out0.out_right", "fan_out1.out_left"]);
+        assert!(connections(&netlist).contains(&(pin("crossover0", "out_a"), pin("and0", "a"))));
+        assert!(connections(&netlist).contains(&(pin("crossover0", "out_b"), pin("or0", "b"))));
+    }
+
+    #[test]
+    fn not_takes_the_clock() {
+        let netlist = netlist("out = !a");
+        assert_eq!(kinds(&netlist), ["not"]);
+        assert_eq!(connections(&netlist), [
+            (port("a"), pin("not0", "in")),
+            (port("clock"), pin("not0", "clock")),
+            (pin("not0", "out"), port("out")),
+        ]);
+    }
+}
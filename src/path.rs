@@ -0,0 +1,265 @@
+//! Placement of domino lines along paths on the ground plane.
+//!
+//! Paths are given as 2d points, where `x` is the world x axis and `y` is the world z axis.
+
+use std::ops::RangeInclusive;
+
+use cgmath::{InnerSpace, MetricSpace};
+
+/// Distance between two dominos of a line that reliably propagates a fall, in meters.
+pub const DEFAULT_SPACING: f32 = 0.08;
+/// Radius the corners of a polyline are rounded with, in meters.
+pub const DEFAULT_CORNER_RADIUS: f32 = 0.2;
+/// Length of the straight pieces the rounded corners are approximated with, in meters.
+const ARC_RESOLUTION: f32 = 0.005;
+/// Smallest spacing relative to the depth of a domino. Closer dominos lean on each other before
+/// they tipped far enough to fall.
+const MIN_SPACING_PER_DEPTH: f32 = 2.0;
+/// Largest spacing relative to the height of a domino, with a margin for the bend of curves.
+const MAX_SPACING_PER_HEIGHT: f32 = 0.9;
+
+/// A domino placement on the ground plane.
+#[derive(Clone, Copy, Debug)]
+pub struct Placement {
+    pub position: cgmath::Point2<f32>,
+    /// Rotation around the y axis in degrees, so that the domino falls along the path.
+    pub rotation_y: f32,
+}
+
+/// Rotation around the y axis (in degrees) of a domino falling towards `direction`.
+pub fn rotation_towards(direction: cgmath::Vector2<f32>) -> f32 {
+    direction.x.atan2(direction.y).to_degrees()
+}
+
+/// Direction a domino with the given rotation around the y axis falls towards.
+pub fn direction_of(rotation_y: f32) -> cgmath::Vector2<f32> {
+    let rotation = rotation_y.to_radians();
+    cgmath::vec2(rotation.sin(), rotation.cos())
+}
+
+/// Spacings at which a line of dominos with the given width, height and depth reliably propagates
+/// a fall.
+pub fn spacing_range(dimensions: cgmath::Vector3<f32>) -> RangeInclusive<f32> {
+    MIN_SPACING_PER_DEPTH * dimensions.z..=MAX_SPACING_PER_HEIGHT * dimensions.y
+}
+
+/// Checks that a line of dominos with the given dimensions and `spacing` propagates a fall.
+pub fn validate_spacing(spacing: f32, dimensions: cgmath::Vector3<f32>) -> Result<(), String> {
+    let range = spacing_range(dimensions);
+    if spacing < *range.start() {
+        Err(format!("a spacing of {:.3} m is too small, the dominos lean on each other before they fall (at least {:.3} m)", spacing, range.start()))
+    } else if spacing > *range.end() {
+        Err(format!("a spacing of {:.3} m is too large, a falling domino does not reach the next one (at most {:.3} m)", spacing, range.end()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Total length of a polyline after rounding its corners with `corner_radius`.
+pub fn length(points: &[cgmath::Point2<f32>], corner_radius: f32) -> f32 {
+    let dense = round_corners(points, corner_radius);
+    dense.windows(2).map(|w| w[0].distance(w[1])).sum()
+}
+
+/// Places dominos along the polyline `points` with its corners rounded with `corner_radius`.
+///
+/// The first and the last domino are placed exactly at the ends of the polyline, the spacing
+/// in between is adjusted slightly so that the dominos are distributed evenly. Every domino is
+/// rotated to fall along the path towards its end.
+pub fn along_polyline(points: &[cgmath::Point2<f32>], spacing: f32, corner_radius: f32) -> Vec<Placement> {
+    let dense = round_corners(points, corner_radius);
+    let total_length: f32 = dense.windows(2).map(|w| w[0].distance(w[1])).sum();
+    if dense.len() < 2 || total_length <= 0.0 {
+        return points.first().map(|p| vec![Placement { position: *p, rotation_y: 0.0 }]).unwrap_or_default();
+    }
+
+    let count = (total_length / spacing).round().max(1.0) as usize;
+    let step = total_length / count as f32;
+
+    let mut placements = Vec::with_capacity(count + 1);
+    let mut segment = 0;
+    let mut segment_start = 0.0;
+    for i in 0..=count {
+        let distance = (i as f32 * step).min(total_length);
+        // advance to the segment containing `distance`
+        while segment < dense.len() - 2 && segment_start + dense[segment].distance(dense[segment + 1]) < distance {
+            segment_start += dense[segment].distance(dense[segment + 1]);
+            segment += 1;
+        }
+        let (from, to) = (dense[segment], dense[segment + 1]);
+        let segment_length = from.distance(to);
+        let t = if segment_length > 0.0 { ((distance - segment_start) / segment_length).clamp(0.0, 1.0) } else { 0.0 };
+        placements.push(Placement {
+            position: from + (to - from) * t,
+            rotation_y: rotation_towards(to - from),
+        });
+    }
+    placements
+}
+
+/// Polyline from `from` to `to` along the z axis with a detour towards `side` (`-1.0` for -x,
+/// `1.0` for +x), so that it is `length` long after rounding its corners with `corner_radius`. A
+/// straight line is returned if `length` is too short for a detour.
+pub fn detour(from: cgmath::Point2<f32>, to: cgmath::Point2<f32>, length: f32, side: f32, corner_radius: f32) -> Vec<cgmath::Point2<f32>> {
+    let with_offset = |offset: f32| {
+        let x = from.x + side * offset;
+        vec![
+            from,
+            cgmath::point2(from.x, from.y + corner_radius),
+            cgmath::point2(x, from.y + corner_radius),
+            cgmath::point2(x, to.y - corner_radius),
+            cgmath::point2(from.x, to.y - corner_radius),
+            to,
+        ]
+    };
+
+    if to.y - from.y < 4.0 * corner_radius || self::length(&with_offset(0.0), corner_radius) >= length {
+        return vec![from, to];
+    }
+    let (mut low, mut high) = (0.0, length);
+    for _ in 0..24 {
+        let offset = 0.5 * (low + high);
+        if self::length(&with_offset(offset), corner_radius) < length {
+            low = offset;
+        } else {
+            high = offset;
+        }
+    }
+    with_offset(high)
+}
+
+/// Places dominos along a Catmull-Rom spline through `points`, see [`along_polyline`].
+pub fn along_spline(points: &[cgmath::Point2<f32>], spacing: f32) -> Vec<Placement> {
+    along_polyline(&interpolate_spline(points), spacing, 0.0)
+}
+
+/// Approximates the Catmull-Rom spline through `points` with short straight pieces.
+fn interpolate_spline(points: &[cgmath::Point2<f32>]) -> Vec<cgmath::Point2<f32>> {
+    let mut points: Vec<cgmath::Point2<f32>> = points.to_vec();
+    points.dedup_by(|a, b| a.distance2(*b) < f32::EPSILON);
+    if points.len() < 3 {
+        return points;
+    }
+
+    let mut dense = vec![points[0]];
+    for i in 0..points.len() - 1 {
+        // the ends are extended by mirroring their neighbours
+        let p0 = if i > 0 { points[i - 1] } else { points[0] + (points[0] - points[1]) };
+        let (p1, p2) = (points[i], points[i + 1]);
+        let p3 = if i + 2 < points.len() { points[i + 2] } else { p2 + (p2 - p1) };
+
+        let pieces = (p1.distance(p2) / ARC_RESOLUTION).ceil().max(1.0) as usize;
+        for piece in 1..=pieces {
+            let t = piece as f32 / pieces as f32;
+            let (t2, t3) = (t * t, t * t * t);
+            let weights = [-0.5 * t3 + t2 - 0.5 * t, 1.5 * t3 - 2.5 * t2 + 1.0, -1.5 * t3 + 2.0 * t2 + 0.5 * t, 0.5 * t3 - 0.5 * t2];
+            let point = [p0, p1, p2, p3].iter().zip(weights).fold(cgmath::vec2(0.0, 0.0), |sum, (p, w)| sum + cgmath::vec2(p.x, p.y) * w);
+            dense.push(cgmath::point2(point.x, point.y));
+        }
+    }
+    dense
+}
+
+/// Replaces every inner corner of the polyline with an arc of radius `radius` made up of short
+/// straight pieces. The radius is reduced where the adjacent segments are too short for it.
+fn round_corners(points: &[cgmath::Point2<f32>], radius: f32) -> Vec<cgmath::Point2<f32>> {
+    let mut points: Vec<cgmath::Point2<f32>> = points.to_vec();
+    points.dedup_by(|a, b| a.distance2(*b) < f32::EPSILON);
+    if points.len() < 3 || radius <= 0.0 {
+        return points;
+    }
+
+    let mut dense = vec![points[0]];
+    for i in 1..points.len() - 1 {
+        let (previous, corner, next) = (points[i - 1], points[i], points[i + 1]);
+        let incoming = (corner - previous).normalize();
+        let outgoing = (next - corner).normalize();
+        let turn = incoming.angle(outgoing).0;
+        if turn.abs() < 1e-4 {
+            dense.push(corner);
+            continue;
+        }
+
+        // distance from the corner at which the arc starts and ends
+        let max_tangent = 0.5 * corner.distance(previous).min(corner.distance(next));
+        let tangent = (radius * (turn.abs() * 0.5).tan()).min(max_tangent);
+        let radius = tangent / (turn.abs() * 0.5).tan();
+
+        let start = corner - incoming * tangent;
+        let normal = if turn > 0.0 { cgmath::vec2(-incoming.y, incoming.x) } else { cgmath::vec2(incoming.y, -incoming.x) };
+        let center = start + normal * radius;
+        let start_angle = (start - center).y.atan2((start - center).x);
+
+        let pieces = ((radius * turn.abs()) / ARC_RESOLUTION).ceil().max(1.0) as usize;
+        for piece in 0..=pieces {
+            let angle = start_angle + turn * piece as f32 / pieces as f32;
+            dense.push(center + cgmath::vec2(angle.cos(), angle.sin()) * radius);
+        }
+    }
+    dense.push(points[points.len() - 1]);
+    dense
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::DOMINO_SIZE;
+
+    fn distances(placements: &[Placement]) -> Vec<f32> {
+        placements.windows(2).map(|w| w[0].position.distance(w[1].position)).collect()
+    }
+
+    #[test]
+    fn straight_lines_are_spaced_evenly() {
+        let (start, end) = (cgmath::point2(0.0, 0.0), cgmath::point2(0.82, 0.0));
+        let placements = along_polyline(&[start, end], DEFAULT_SPACING, DEFAULT_CORNER_RADIUS);
+        assert_eq!(placements.len(), 11);
+        assert_eq!(placements[0].position, start);
+        assert!(placements[10].position.distance(end) < 1.0e-5);
+        assert!(distances(&placements).iter().all(|d| (d - 0.082).abs() < 1.0e-5));
+        // the dominos fall towards +x
+        assert!(placements.iter().all(|p| (p.rotation_y - 90.0).abs() < 1.0e-3));
+    }
+
+    #[test]
+    fn corners_are_rounded_with_the_radius() {
+        let points = [cgmath::point2(0.0, 0.0), cgmath::point2(0.0, 1.0), cgmath::point2(1.0, 1.0)];
+        let placements = along_polyline(&points, DEFAULT_SPACING, DEFAULT_CORNER_RADIUS);
+        let center = cgmath::point2(DEFAULT_CORNER_RADIUS, 1.0 - DEFAULT_CORNER_RADIUS);
+        let in_corner: Vec<&Placement> = placements.iter()
+            .filter(|p| p.position.x < center.x && p.position.y > center.y)
+            .collect();
+        assert!(!in_corner.is_empty());
+        // the dominos in the corner stand on the arc, the corner itself is cut off
+        for p in in_corner {
+            assert!((p.position.distance(center) - DEFAULT_CORNER_RADIUS).abs() < 1.0e-3, "{:?}", p.position);
+        }
+        assert!(distances(&placements).iter().all(|d| (d - DEFAULT_SPACING).abs() < 0.01));
+        let length = 2.0 - 2.0 * DEFAULT_CORNER_RADIUS + std::f32::consts::FRAC_PI_2 * DEFAULT_CORNER_RADIUS;
+        assert!((self::length(&points, DEFAULT_CORNER_RADIUS) - length).abs() < 1.0e-3);
+    }
+
+    #[test]
+    fn duplicate_points_are_ignored() {
+        let (a, b, c) = (cgmath::point2(0.0, 0.0), cgmath::point2(0.0, 1.0), cgmath::point2(1.0, 1.0));
+        let expected = along_polyline(&[a, b, c], DEFAULT_SPACING, DEFAULT_CORNER_RADIUS);
+        let placements = along_polyline(&[a, a, b, b, b, c, c], DEFAULT_SPACING, DEFAULT_CORNER_RADIUS);
+        assert_eq!(placements.len(), expected.len());
+        for (p, e) in placements.iter().zip(expected.iter()) {
+            assert_eq!((p.position, p.rotation_y), (e.position, e.rotation_y));
+        }
+
+        // a path without length places a single domino
+        assert_eq!(along_polyline(&[a, a], DEFAULT_SPACING, DEFAULT_CORNER_RADIUS).len(), 1);
+        assert_eq!(along_spline(&[a, a, b, b], DEFAULT_SPACING).iter().filter(|p| !p.position.x.is_finite()).count(), 0);
+    }
+
+    #[test]
+    fn spacing_must_fit_the_dominos() {
+        assert_eq!(validate_spacing(DEFAULT_SPACING, DOMINO_SIZE), Ok(()));
+        assert!(validate_spacing(DOMINO_SIZE.z, DOMINO_SIZE).unwrap_err().contains("too small"));
+        assert!(validate_spacing(DOMINO_SIZE.y, DOMINO_SIZE).unwrap_err().contains("too large"));
+        // larger dominos can stand further apart
+        assert_eq!(validate_spacing(DOMINO_SIZE.y, DOMINO_SIZE * 2.0), Ok(()));
+    }
+}
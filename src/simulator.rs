@@ -1,50 +1,264 @@
+
+use crate::gates::Gate;
+
+use self::{component::Component, light::Light, material::Material, terrain::Obstacle};
+
+pub mod component;
+pub mod light;
+pub mod material;
+pub mod obb;
+mod physics;
+pub mod terrain;
+pub mod timeline;
+pub mod timing;
+pub mod truth_table;
+
+/// Width, height and depth of a domino with a scale of 1, in meters.
+pub const DOMINO_SIZE: cgmath::Vector3<f32> = cgmath::Vector3 { x: 0.07, y: 0.14, z: 0.02 };
+/// Mass of a domino of the standard material with a scale of 1, in kg.
+pub const DOMINO_MASS: f32 = 0.1;
+/// Angular velocity (deg/s) a domino is given when it is pushed to start a chain.
+pub const PUSH_VELOCITY: f32 = 150.0;
+/// Fall rotation (deg) from which on a domino counts as fallen.
+pub const FALLEN_ROTATION: f32 = 45.0;
+
+#[derive(Clone, Debug)]
 pub struct Domino {
     pub position: cgmath::Point3<f32>,
     pub rotation_y: f32,
-    pub fall_rotation: f32, // -90-90 deg, around centerline of base area
+    pub fall_rotation: f32, // -90-90 deg, around the base edge the domino is tipping over
+    pub angular_velocity: f32, // deg/s, positive values tip the top towards the local +z axis
     pub scale: cgmath::Vector3<f32>, // width, heith, depth
+    pub material: Material,
     pub id: u32,
 }
 
+impl Domino {
+    pub fn new(id: u32, position: cgmath::Point3<f32>, rotation_y: f32) -> Self {
+        Domino {
+            position,
+            rotation_y,
+            fall_rotation: 0.0,
+            angular_velocity: 0.0,
+            scale: cgmath::vec3(1.0, 1.0, 1.0),
+            material: Material::default(),
+            id,
+        }
+    }
+
+    /// Width, height and depth of this domino in meters.
+    pub fn dimensions(&self) -> cgmath::Vector3<f32> {
+        cgmath::vec3(DOMINO_SIZE.x * self.scale.x, DOMINO_SIZE.y * self.scale.y, DOMINO_SIZE.z * self.scale.z)
+    }
+
+    /// Mass in kg, the mass of the material scaled with the volume of the domino.
+    pub fn mass(&self) -> f32 {
+        self.material.mass * self.scale.x * self.scale.y * self.scale.z
+    }
+
+    /// `1.0` if the domino is tipping towards its local +z axis, `-1.0` if it is tipping towards
+    /// -z and `0.0` if it is standing still on its base.
+    pub fn fall_direction(&self) -> f32 {
+        if self.fall_rotation != 0.0 {
+            self.fall_rotation.signum()
+        } else if self.angular_velocity != 0.0 {
+            self.angular_velocity.signum()
+        } else {
+            0.0
+        }
+    }
+
+    /// `true` once the domino tipped over far enough that it can not stand up again.
+    pub fn has_fallen(&self) -> bool {
+        self.fall_rotation.abs() >= FALLEN_ROTATION
+    }
+
+    /// Rotates the domino by `angle` degrees around the vertical axis through `center`.
+    pub fn rotate_around(&mut self, center: cgmath::Point3<f32>, angle: f32) {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let offset = self.position - center;
+        self.position.x = center.x + offset.x * cos + offset.z * sin;
+        self.position.z = center.z - offset.x * sin + offset.z * cos;
+        self.rotation_y = (self.rotation_y + angle).rem_euclid(360.0);
+    }
+
+    /// Rigid transformation from domino space (origin at the center of the base area) to world
+    /// space. The fall rotation pivots around the base edge in the direction of the fall.
+    pub fn transform(&self) -> cgmath::Matrix4<f32> {
+        let rotation_y = cgmath::Matrix3::from_angle_y(cgmath::Deg(self.rotation_y));
+        let pivot = cgmath::vec3(0.0, 0.0, self.fall_direction() * self.dimensions().z * 0.5);
+        let fall_rotation = cgmath::Matrix3::from_angle_x(cgmath::Deg(self.fall_rotation));
+
+        // the composition of translation * rotation_y * (pivot) * fall_rotation * (-pivot), built
+        // from 3x3 matrices as contact tests need it for every pair of neighbouring dominos
+        let rotation = rotation_y * fall_rotation;
+        let translation = cgmath::vec3(self.position.x, self.position.y, self.position.z) + rotation_y * pivot - rotation * pivot;
+        let mut transform = cgmath::Matrix4::from(rotation);
+        transform.w = translation.extend(1.0);
+        transform
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PortDirection {
+    Input,
+    Output,
+}
+
+/// A named domino where a signal enters (the first domino of a line) or leaves (the last domino
+/// of a line) a circuit.
+#[derive(Clone, Debug)]
+pub struct Port {
+    pub name: String,
+    pub domino_id: u32,
+    pub direction: PortDirection,
+}
+
+#[derive(Clone)]
 pub struct Simulator {
     pub dominos: Vec<Domino>,
+    pub ports: Vec<Port>,
+    /// Static obstacles on the ground plane, see [`terrain`].
+    pub obstacles: Vec<Obstacle>,
+    /// Balls, levers and bridges, see [`component`].
+    pub components: Vec<Box<dyn Component>>,
+    /// Lights the scene is drawn with, see [`light`].
+    pub lights: Vec<Light>,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Simulator {
     pub fn new() -> Self {
-        Simulator {
-            dominos: vec![
-                Domino {
-                    position: cgmath::Point3{x: 0.0, y: 0.0, z: 0.0},
-                    rotation_y: 0.0,
-                    fall_rotation: 0.0,
-                    scale: cgmath::Vector3{x: 1.0, y: 1.0, z: 1.0},
-                    id: 0,
-                },
-                Domino {
-                    position: cgmath::Point3{x: 1.0, y: 0.0, z: 1.0},
-                    rotation_y: 0.0,
-                    fall_rotation: 90.0,
-                    scale: cgmath::Vector3{x: 1.0, y: 1.0, z: 1.0},
-                    id: 1,
-                },
-
-                Domino {
-                    position: cgmath::Point3{x: 0.0, y: 0.0, z: 2.0},
-                    rotation_y: 90.0,
-                    fall_rotation: 45.0,
-                    scale: cgmath::Vector3{x: 1.0, y: 1.0, z: 1.0},
-                    id: 2,
-                },
-
-                Domino {
-                    position: cgmath::Point3{x: 0.3, y: 0.0, z: 2.0},
-                    rotation_y: 0.0,
-                    fall_rotation: 10.0,
-                    scale: cgmath::Vector3{x: 1.0, y: 1.0, z: 1.0},
-                    id: 3,
-                }
-            ]
+        let mut dominos = vec![
+            Domino::new(0, cgmath::Point3{x: 0.0, y: 0.0, z: 0.0}, 0.0),
+            Domino::new(1, cgmath::Point3{x: 1.0, y: 0.0, z: 1.0}, 0.0),
+            Domino::new(2, cgmath::Point3{x: 0.0, y: 0.0, z: 2.0}, 90.0),
+            Domino::new(3, cgmath::Point3{x: 0.3, y: 0.0, z: 2.0}, 0.0),
+        ];
+        dominos[1].fall_rotation = 90.0;
+        dominos[2].fall_rotation = 45.0;
+        dominos[3].fall_rotation = 10.0;
+
+        Simulator { dominos, ports: vec![], obstacles: vec![], components: vec![], lights: light::default_lights() }
+    }
+
+    /// A layout without dominos, ports, obstacles, components or lights.
+    pub fn empty() -> Self {
+        Simulator { dominos: vec![], ports: vec![], obstacles: vec![], components: vec![], lights: vec![] }
+    }
+
+    /// An id that is not used by any obstacle yet.
+    pub fn next_obstacle_id(&self) -> u32 {
+        self.obstacles.iter().map(|o| o.id + 1).max().unwrap_or(0)
+    }
+
+    /// An id that is not used by any component yet.
+    pub fn next_component_id(&self) -> u32 {
+        self.components.iter().map(|c| c.id() + 1).max().unwrap_or(0)
+    }
+
+    /// Height of the ground plane or of the highest obstacle above `(x, z)`, where a domino
+    /// placed there stands.
+    pub fn ground_height(&self, x: f32, z: f32) -> f32 {
+        terrain::ground_height(&self.obstacles, x, z)
+    }
+
+    /// An id that is not used by any domino yet.
+    pub fn next_id(&self) -> u32 {
+        self.dominos.iter().map(|d| d.id + 1).max().unwrap_or(0)
+    }
+
+    /// Adds all dominos of `gate` with fresh ids. The ports of the gate are added as
+    /// `<name>.<port name>`. Returns the ids the dominos of the gate were given.
+    pub fn add_gate(&mut self, gate: &Gate, name: &str) -> Vec<u32> {
+        let first_id = self.next_id();
+        let ids: Vec<u32> = (0..gate.dominos.len() as u32).map(|i| first_id + i).collect();
+
+        for (domino, id) in gate.dominos.iter().zip(ids.iter()) {
+            self.dominos.push(Domino { id: *id, ..domino.clone() });
+        }
+        for port in gate.ports.iter() {
+            let index = gate.dominos.iter().position(|d| d.id == port.domino_id).unwrap();
+            self.ports.push(Port {
+                name: format!("{}.{}", name, port.name),
+                domino_id: ids[index],
+                direction: port.direction,
+            });
+        }
+        ids
+    }
+
+    /// The port with the given name.
+    pub fn port(&self, name: &str) -> Option<&Port> {
+        self.ports.iter().find(|p| p.name == name)
+    }
+
+    /// Center of the base positions of the dominos with the given ids, `None` if none of them
+    /// exists.
+    pub fn center_of(&self, ids: &[u32]) -> Option<cgmath::Point3<f32>> {
+        let positions: Vec<cgmath::Point3<f32>> = self.dominos.iter().filter(|d| ids.contains(&d.id)).map(|d| d.position).collect();
+        if positions.is_empty() {
+            return None;
+        }
+        Some(cgmath::EuclideanSpace::centroid(&positions))
+    }
+
+    /// Advances the simulation by `dt` seconds. Large steps are split into smaller substeps so
+    /// that fast falling dominos can not tunnel through their neighbours.
+    pub fn step(&mut self, dt: f32) {
+        self.step_with_hits(dt);
+    }
+
+    /// Like [`Simulator::step`], returns the hits between dominos during the step. Steps that are
+    /// not a finite, positive number of seconds are ignored, very long ones are split into at most
+    /// [`physics::MAX_SUBSTEPS`] substeps.
+    pub fn step_with_hits(&mut self, dt: f32) -> Vec<physics::Hit> {
+        let mut hits = vec![];
+        if !dt.is_finite() || dt <= 0.0 {
+            return hits;
+        }
+        let solids: Vec<terrain::Solid> = self.obstacles.iter().flat_map(|o| o.solids()).collect();
+        let substeps = ((dt / physics::MAX_SUBSTEP).ceil() as usize).clamp(1, physics::MAX_SUBSTEPS);
+        let substep = dt / substeps as f32;
+        for _ in 0..substeps {
+            hits.extend(physics::substep(&mut self.dominos, &solids, substep));
+            component::substep(&mut self.components, &mut self.dominos, &solids, substep);
         }
+        hits
+    }
+
+    /// Gives the domino with the given id an initial angular velocity (deg/s), e.g. to start a
+    /// chain. Returns `false` if there is no such domino.
+    pub fn push(&mut self, id: u32, angular_velocity: f32) -> bool {
+        match self.dominos.iter_mut().find(|d| d.id == id) {
+            Some(d) => {
+                d.angular_velocity += angular_velocity;
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Pushes the domino of the input port with the given name forward. Returns `false` if there
+    /// is no such input port.
+    pub fn trigger(&mut self, port_name: &str) -> bool {
+        match self.port(port_name) {
+            Some(p) if p.direction == PortDirection::Input => {
+                let id = p.domino_id;
+                self.push(id, PUSH_VELOCITY)
+            }
+            _ => false
+        }
+    }
+
+    /// `true` if no domino and no component is moving noticeably anymore.
+    pub fn is_at_rest(&self) -> bool {
+        self.dominos.iter().all(|d| d.angular_velocity.abs() < physics::REST_VELOCITY)
+            && self.components.iter().all(|c| !c.is_moving())
     }
 }
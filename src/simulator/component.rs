@@ -0,0 +1,273 @@
+//! Mechanical components besides dominos: rolling balls, pivoting levers and toppling bridges.
+//!
+//! Every [`Component`] integrates its own motion under gravity and keeps out of the terrain.
+//! Contacts with dominos and with other components are found between their [`Collider`]s, a
+//! sphere or a box, and resolved with impulses along the contact normal. Each component describes
+//! how it moves at a point and how much it resists an impulse there, so that the same contact
+//! code works for all of them.
+
+use std::{fmt, ops::RangeInclusive};
+
+use cgmath::InnerSpace;
+
+use super::{obb::Obb, physics, terrain::Solid, Domino};
+
+pub mod ball;
+pub mod bridge;
+pub mod lever;
+
+/// Fraction of the approaching velocity that is kept when a component collides with something.
+pub const RESTITUTION: f32 = 0.3;
+/// Approaching velocities (m/s) below this do not bounce, so that resting contacts stay at rest.
+const BOUNCE_VELOCITY: f32 = 0.1;
+
+/// The names of the kinds of components, as used in layout files and offered in the editor.
+pub const KINDS: [&str; 3] = ["ball", "lever", "bridge"];
+
+/// The shape other bodies collide with.
+#[derive(Clone, Copy, Debug)]
+pub enum Collider {
+    Sphere { center: cgmath::Point3<f32>, radius: f32 },
+    Box(Obb),
+}
+
+/// Where two colliders touch.
+#[derive(Clone, Copy, Debug)]
+pub struct Contact {
+    pub point: cgmath::Point3<f32>,
+    /// Unit normal pointing from the first collider into the second one.
+    pub normal: cgmath::Vector3<f32>,
+    /// How far the colliders overlap along the normal, in meters.
+    pub depth: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartShape {
+    /// The box from (-0.5, 0, -0.5) to (0.5, 1, 0.5).
+    Box,
+    /// The sphere with a radius of 1 around the origin.
+    Sphere,
+}
+
+/// A mesh a component is drawn with.
+#[derive(Clone, Copy, Debug)]
+pub struct Part {
+    pub shape: PartShape,
+    /// Transformation from the space of the shape to world space.
+    pub transform: cgmath::Matrix4<f32>,
+    pub color: [f32; 3],
+}
+
+/// A value of the setup of a component that can be edited.
+pub struct Parameter<'a> {
+    pub name: &'static str,
+    pub value: &'a mut f32,
+    pub range: RangeInclusive<f32>,
+}
+
+pub trait Component: fmt::Debug + Send {
+    fn id(&self) -> u32;
+
+    /// One of [`KINDS`].
+    fn kind(&self) -> &'static str;
+
+    /// The editable values of the setup. The component must be [reset](Component::reset) after
+    /// they were changed.
+    fn parameters(&mut self) -> Vec<Parameter<'_>>;
+
+    /// Describes why the setup is invalid, `None` if it is valid.
+    fn validate(&self) -> Option<String>;
+
+    /// Puts the component back into its initial state.
+    fn reset(&mut self);
+
+    /// Moves the component under gravity for `dt` seconds and keeps it out of the `solids` of
+    /// the terrain.
+    fn integrate(&mut self, dt: f32, solids: &[Solid]);
+
+    fn collider(&self) -> Collider;
+
+    /// Velocity of the point of the component at `point`.
+    fn velocity_at(&self, point: cgmath::Point3<f32>) -> cgmath::Vector3<f32>;
+
+    /// Inverse of the mass with which the component resists an impulse along the unit vector
+    /// `direction` at `point`, 0 if it can not move that way.
+    fn inverse_mass_at(&self, point: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>) -> f32;
+
+    fn apply_impulse(&mut self, point: cgmath::Point3<f32>, impulse: cgmath::Vector3<f32>);
+
+    /// Moves the point of the component at `point` by about `offset`, to resolve an overlap.
+    fn displace(&mut self, point: cgmath::Point3<f32>, offset: cgmath::Vector3<f32>);
+
+    fn is_moving(&self) -> bool;
+
+    /// The values that change during a simulation, to record and replay them.
+    fn state(&self) -> Vec<f32>;
+
+    fn set_state(&mut self, state: &[f32]);
+
+    /// The meshes the component is drawn with.
+    fn parts(&self) -> Vec<Part>;
+
+    /// The setup in the layout format, see [`from_json`].
+    fn to_json(&self) -> serde_json::Value;
+
+    fn clone_box(&self) -> Box<dyn Component>;
+}
+
+impl Clone for Box<dyn Component> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// A new component of the given kind with its base at `position`, `None` for unknown kinds.
+pub fn new(kind: &str, id: u32, position: cgmath::Point3<f32>) -> Option<Box<dyn Component>> {
+    match kind {
+        "ball" => Some(Box::new(ball::Ball::new(id, position))),
+        "lever" => Some(Box::new(lever::Lever::new(id, position))),
+        "bridge" => Some(Box::new(bridge::Bridge::new(id, position))),
+        _ => None,
+    }
+}
+
+/// Reads a component from the layout format, an object with its `kind` and its setup.
+pub fn from_json(value: serde_json::Value) -> Result<Box<dyn Component>, String> {
+    let kind = value.get("kind").and_then(|k| k.as_str()).ok_or("the kind of a component is missing")?.to_string();
+    let component: Box<dyn Component> = match kind.as_str() {
+        "ball" => Box::new(ball::Ball::from_setup(serde_json::from_value(value).map_err(|e| e.to_string())?)),
+        "lever" => Box::new(lever::Lever::from_setup(serde_json::from_value(value).map_err(|e| e.to_string())?)),
+        "bridge" => Box::new(bridge::Bridge::from_setup(serde_json::from_value(value).map_err(|e| e.to_string())?)),
+        _ => return Err(format!("unknown component kind \"{}\"", kind)),
+    };
+    match component.validate() {
+        Some(reason) => Err(format!("{} {} is invalid: {}", kind, component.id(), reason)),
+        None => Ok(component),
+    }
+}
+
+/// Adds the `kind` to the serialized setup of a component.
+fn with_kind(setup: impl serde::Serialize, kind: &str) -> serde_json::Value {
+    let mut value = serde_json::to_value(setup).expect("setups only contain serializable values");
+    if let Some(object) = value.as_object_mut() {
+        object.insert("kind".to_string(), kind.into());
+    }
+    value
+}
+
+/// `true` if all values are finite numbers.
+fn all_finite(values: &[f32]) -> bool {
+    values.iter().all(|v| v.is_finite())
+}
+
+impl Collider {
+    /// Center and radius of a sphere around the collider.
+    fn bounding_sphere(&self) -> (cgmath::Point3<f32>, f32) {
+        match self {
+            Collider::Sphere { center, radius } => (*center, *radius),
+            Collider::Box(obb) => (obb.center, obb.half_extents.magnitude()),
+        }
+    }
+}
+
+/// Where the colliders touch, `None` if they do not.
+pub fn collide(a: &Collider, b: &Collider) -> Option<Contact> {
+    let ((a_center, a_radius), (b_center, b_radius)) = (a.bounding_sphere(), b.bounding_sphere());
+    if (b_center - a_center).magnitude2() > (a_radius + b_radius) * (a_radius + b_radius) {
+        return None;
+    }
+    match (a, b) {
+        (Collider::Sphere { .. }, Collider::Sphere { .. }) => {
+            let delta = b_center - a_center;
+            let distance = delta.magnitude();
+            let normal = if distance > f32::EPSILON { delta / distance } else { cgmath::Vector3::unit_y() };
+            Some(Contact { point: a_center + normal * a_radius, normal, depth: a_radius + b_radius - distance })
+        }
+        (Collider::Sphere { center, radius }, Collider::Box(obb)) => sphere_box(*center, *radius, obb),
+        (Collider::Box(obb), Collider::Sphere { center, radius }) => {
+            sphere_box(*center, *radius, obb).map(|c| Contact { normal: -c.normal, ..c })
+        }
+        (Collider::Box(a), Collider::Box(b)) => {
+            let (normal, depth) = a.penetration(b)?;
+            // the corners of one box inside the other one, or the middle of the overlap
+            let inside: Vec<cgmath::Point3<f32>> = b.vertices().into_iter().filter(|v| a.contains(*v))
+                .chain(a.vertices().into_iter().filter(|v| b.contains(*v)))
+                .collect();
+            let point = if inside.is_empty() {
+                a.center + normal * (a.radius_along(normal) - depth * 0.5)
+            } else {
+                cgmath::EuclideanSpace::centroid(&inside)
+            };
+            Some(Contact { point, normal, depth })
+        }
+    }
+}
+
+/// Contact from a sphere to a box.
+fn sphere_box(center: cgmath::Point3<f32>, radius: f32, obb: &Obb) -> Option<Contact> {
+    let closest = obb.closest_point(center);
+    let delta = closest - center;
+    let distance = delta.magnitude();
+    if distance > f32::EPSILON {
+        return (distance < radius).then(|| Contact { point: closest, normal: delta / distance, depth: radius - distance });
+    }
+    // the center is inside the box, it leaves through the nearest face
+    let offset = center - obb.center;
+    let (axis, inset) = (0..3)
+        .map(|i| (i, obb.half_extents[i] - offset.dot(obb.axes[i]).abs()))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .expect("a box has three axes");
+    let outwards = obb.axes[axis] * offset.dot(obb.axes[axis]).signum();
+    Some(Contact { point: center + outwards * inset, normal: -outwards, depth: radius + inset })
+}
+
+/// Moves all components by `dt` seconds and resolves their contacts with the dominos and with
+/// each other.
+pub fn substep(components: &mut [Box<dyn Component>], dominos: &mut [Domino], solids: &[Solid], dt: f32) {
+    for c in components.iter_mut() {
+        c.integrate(dt, solids);
+    }
+
+    for c in components.iter_mut() {
+        for d in dominos.iter_mut() {
+            if let Some(contact) = collide(&c.collider(), &Collider::Box(d.obb())) {
+                physics::resolve_component_contact(d, c.as_mut(), &contact);
+            }
+        }
+    }
+
+    for a in 0..components.len() {
+        for b in a + 1..components.len() {
+            let (first, second) = components.split_at_mut(b);
+            let (a, b) = (first[a].as_mut(), second[0].as_mut());
+            if let Some(contact) = collide(&a.collider(), &b.collider()) {
+                resolve(a, b, &contact);
+            }
+        }
+    }
+}
+
+/// Exchanges the impulse of the contact between `a` and `b` and moves them apart.
+fn resolve(a: &mut dyn Component, b: &mut dyn Component, contact: &Contact) {
+    let (point, normal) = (contact.point, contact.normal);
+    let (a_inverse, b_inverse) = (a.inverse_mass_at(point, normal), b.inverse_mass_at(point, normal));
+    let inverse = a_inverse + b_inverse;
+    if inverse <= 0.0 {
+        return;
+    }
+
+    let relative = (b.velocity_at(point) - a.velocity_at(point)).dot(normal);
+    if relative < 0.0 {
+        let restitution = restitution_at(-relative);
+        let impulse = -(1.0 + restitution) * relative / inverse;
+        a.apply_impulse(point, -normal * impulse);
+        b.apply_impulse(point, normal * impulse);
+    }
+    a.displace(point, -normal * contact.depth * a_inverse / inverse);
+    b.displace(point, normal * contact.depth * b_inverse / inverse);
+}
+
+/// The restitution of a contact approaching with `velocity` (m/s).
+pub(super) fn restitution_at(velocity: f32) -> f32 {
+    if velocity > BOUNCE_VELOCITY { RESTITUTION } else { 0.0 }
+}
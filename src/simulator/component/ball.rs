@@ -0,0 +1,216 @@
+//! A ball rolling over the ground and the terrain.
+
+use cgmath::{EuclideanSpace, InnerSpace};
+use serde::{Deserialize, Serialize};
+
+use super::{all_finite, restitution_at, with_kind, Collider, Component, Parameter, Part, PartShape, RESTITUTION};
+use crate::simulator::{physics::GRAVITY, terrain::{self, Solid}};
+
+/// Deceleration of a rolling ball as a fraction of gravity.
+const ROLLING_RESISTANCE: f32 = 0.02;
+/// Speeds (m/s) below this are considered to be at rest.
+const REST_SPEED: f32 = 0.01;
+/// Distance (in meters) above the ground within which a ball still rolls on it.
+const GROUND_TOLERANCE: f32 = 0.0001;
+/// Horizontal distance (in meters) over which the slope of the ground is measured.
+const SLOPE_DISTANCE: f32 = 0.005;
+const COLOR: [f32; 3] = [0.6, 0.6, 0.65];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BallSetup {
+    pub id: u32,
+    /// Initial center in meters.
+    pub position: [f32; 3],
+    pub radius: f32,
+    /// Mass in kg.
+    pub mass: f32,
+    /// Initial horizontal velocity along x and z in m/s.
+    #[serde(default)]
+    pub velocity: [f32; 2],
+}
+
+#[derive(Clone, Debug)]
+pub struct Ball {
+    pub setup: BallSetup,
+    pub position: cgmath::Point3<f32>,
+    pub velocity: cgmath::Vector3<f32>,
+}
+
+impl Ball {
+    /// A ball lying on the ground at `position`.
+    pub fn new(id: u32, position: cgmath::Point3<f32>) -> Self {
+        let radius = 0.02;
+        Ball::from_setup(BallSetup {
+            id,
+            position: [position.x, position.y + radius, position.z],
+            radius,
+            mass: 0.05,
+            velocity: [0.0, 0.0],
+        })
+    }
+
+    pub fn from_setup(setup: BallSetup) -> Self {
+        let mut ball = Ball { setup, position: cgmath::Point3::origin(), velocity: cgmath::vec3(0.0, 0.0, 0.0) };
+        ball.reset();
+        ball
+    }
+
+    /// Unit normal of the surface of the terrain below `(x, z)`.
+    fn surface_normal(solids: &[Solid], x: f32, z: f32) -> cgmath::Vector3<f32> {
+        let d = SLOPE_DISTANCE;
+        let slope_x = (terrain::surface_height(solids, x + d, z) - terrain::surface_height(solids, x - d, z)) / (2.0 * d);
+        let slope_z = (terrain::surface_height(solids, x, z + d) - terrain::surface_height(solids, x, z - d)) / (2.0 * d);
+        cgmath::vec3(-slope_x, 1.0, -slope_z).normalize()
+    }
+}
+
+impl Component for Ball {
+    fn id(&self) -> u32 {
+        self.setup.id
+    }
+
+    fn kind(&self) -> &'static str {
+        "ball"
+    }
+
+    fn parameters(&mut self) -> Vec<Parameter<'_>> {
+        let [x, y, z] = &mut self.setup.position;
+        let [vx, vz] = &mut self.setup.velocity;
+        vec![
+            Parameter { name: "x", value: x, range: -5.0..=5.0 },
+            Parameter { name: "y", value: y, range: 0.0..=2.0 },
+            Parameter { name: "z", value: z, range: -5.0..=5.0 },
+            Parameter { name: "radius", value: &mut self.setup.radius, range: 0.005..=0.2 },
+            Parameter { name: "mass", value: &mut self.setup.mass, range: 0.01..=2.0 },
+            Parameter { name: "velocity x", value: vx, range: -5.0..=5.0 },
+            Parameter { name: "velocity z", value: vz, range: -5.0..=5.0 },
+        ]
+    }
+
+    fn validate(&self) -> Option<String> {
+        let s = &self.setup;
+        if !all_finite(&[s.position[0], s.position[1], s.position[2], s.radius, s.mass, s.velocity[0], s.velocity[1]]) {
+            Some("a value is not a finite number".to_string())
+        } else if s.radius <= 0.0 {
+            Some("the radius is not positive".to_string())
+        } else if s.mass <= 0.0 {
+            Some("the mass is not positive".to_string())
+        } else {
+            None
+        }
+    }
+
+    fn reset(&mut self) {
+        self.position = self.setup.position.into();
+        self.velocity = cgmath::vec3(self.setup.velocity[0], 0.0, self.setup.velocity[1]);
+    }
+
+    fn integrate(&mut self, dt: f32, solids: &[Solid]) {
+        let radius = self.setup.radius;
+        let ground = terrain::surface_height(solids, self.position.x, self.position.z);
+        if self.position.y - radius <= ground + GROUND_TOLERANCE {
+            // a ball rolling without slipping is accelerated by 5/7 of the downhill gravity
+            let normal = Ball::surface_normal(solids, self.position.x, self.position.z);
+            let gravity = cgmath::vec3(0.0, -GRAVITY, 0.0);
+            self.velocity += (gravity - normal * gravity.dot(normal)) * (5.0 / 7.0) * dt;
+            let into = self.velocity.dot(normal);
+            if into < 0.0 {
+                self.velocity -= normal * into;
+            }
+            let speed = self.velocity.magnitude();
+            let slowed = (speed - ROLLING_RESISTANCE * GRAVITY * dt).max(0.0);
+            self.velocity = if speed > 0.0 { self.velocity * (slowed / speed) } else { self.velocity };
+        } else {
+            self.velocity.y -= GRAVITY * dt;
+        }
+
+        let mut next = self.position + self.velocity * dt;
+        let horizontal = cgmath::vec3(self.velocity.x, 0.0, self.velocity.z);
+        if horizontal.magnitude2() > f32::EPSILON {
+            // terrain rising above the lower half of the ball ahead is a wall it bounces off
+            let ahead = next + horizontal.normalize() * radius;
+            if terrain::surface_height(solids, ahead.x, ahead.z) > next.y - radius * 0.5 {
+                self.velocity.x *= -RESTITUTION;
+                self.velocity.z *= -RESTITUTION;
+                next = self.position + cgmath::vec3(0.0, self.velocity.y * dt, 0.0);
+            }
+        }
+        self.position = next;
+
+        let ground = terrain::surface_height(solids, self.position.x, self.position.z);
+        if self.position.y - radius < ground {
+            self.position.y = ground + radius;
+            if self.velocity.y < 0.0 {
+                self.velocity.y *= -restitution_at(-self.velocity.y);
+            }
+        }
+    }
+
+    fn collider(&self) -> Collider {
+        Collider::Sphere { center: self.position, radius: self.setup.radius }
+    }
+
+    fn velocity_at(&self, _point: cgmath::Point3<f32>) -> cgmath::Vector3<f32> {
+        self.velocity
+    }
+
+    fn inverse_mass_at(&self, _point: cgmath::Point3<f32>, _direction: cgmath::Vector3<f32>) -> f32 {
+        1.0 / self.setup.mass
+    }
+
+    fn apply_impulse(&mut self, _point: cgmath::Point3<f32>, impulse: cgmath::Vector3<f32>) {
+        self.velocity += impulse / self.setup.mass;
+    }
+
+    fn displace(&mut self, _point: cgmath::Point3<f32>, offset: cgmath::Vector3<f32>) {
+        self.position += offset;
+    }
+
+    fn is_moving(&self) -> bool {
+        self.velocity.magnitude() > REST_SPEED
+    }
+
+    fn state(&self) -> Vec<f32> {
+        vec![self.position.x, self.position.y, self.position.z, self.velocity.x, self.velocity.y, self.velocity.z]
+    }
+
+    fn set_state(&mut self, state: &[f32]) {
+        if let [x, y, z, vx, vy, vz] = *state {
+            self.position = cgmath::point3(x, y, z);
+            self.velocity = cgmath::vec3(vx, vy, vz);
+        }
+    }
+
+    fn parts(&self) -> Vec<Part> {
+        let transform = cgmath::Matrix4::from_translation(self.position.to_vec()) * cgmath::Matrix4::from_scale(self.setup.radius);
+        vec![Part { shape: PartShape::Sphere, transform, color: COLOR }]
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        with_kind(&self.setup, self.kind())
+    }
+
+    fn clone_box(&self) -> Box<dyn Component> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::{terrain::{Obstacle, ObstacleKind}, Domino, Simulator};
+
+    #[test]
+    fn ball_rolling_down_a_ramp_knocks_over_a_domino() {
+        let ramp = Obstacle::new(0, ObstacleKind::Ramp, cgmath::point3(0.0, 0.0, 0.0), 0.0);
+        let top = ramp.size.y * 0.9;
+        let ball = Ball::new(0, cgmath::point3(0.0, top, 0.4));
+        let domino = Domino::new(0, cgmath::point3(0.0, 0.0, -0.7), 0.0);
+        let mut s = Simulator { dominos: vec![domino], obstacles: vec![ramp], components: vec![Box::new(ball)], ..Simulator::empty() };
+        for _ in 0..500 {
+            s.step(0.01);
+        }
+        assert!(s.dominos[0].fall_rotation < 0.0, "the domino did not fall away from the ball");
+        assert!(s.dominos[0].has_fallen());
+    }
+}
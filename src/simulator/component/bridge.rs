@@ -0,0 +1,272 @@
+//! A bridge plank standing on its end, hinged at its front edge. Once pushed it topples over
+//! towards its local +z axis and comes to rest on the terrain, e.g. to span a gap between two
+//! platforms that dominos can then run across.
+
+use cgmath::{InnerSpace, Transform};
+use serde::{Deserialize, Serialize};
+
+use super::{all_finite, restitution_at, with_kind, Collider, Component, Parameter, Part, PartShape};
+use crate::simulator::{obb::Obb, physics::{GRAVITY, REST_VELOCITY}, terrain::{self, Solid}};
+
+/// Distance (in meters) the top edge may sink into the terrain, so that a plank lying flat on the
+/// ground does not count as penetrating it.
+const TERRAIN_TOLERANCE: f32 = 0.0005;
+const COLOR: [f32; 3] = [0.55, 0.35, 0.2];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BridgeSetup {
+    pub id: u32,
+    /// Center of the base area of the standing plank in meters.
+    pub position: [f32; 3],
+    /// Rotation around the vertical axis in degrees, the plank topples towards the local +z axis.
+    pub rotation_y: f32,
+    /// Length (the height of the standing plank), width and thickness in meters.
+    pub length: f32,
+    pub width: f32,
+    pub thickness: f32,
+    /// Mass in kg.
+    pub mass: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Bridge {
+    pub setup: BridgeSetup,
+    /// Rotation around the hinge in degrees, from 0 (standing) to 90 (lying flat).
+    pub angle: f32,
+    /// Angular velocity in deg/s.
+    pub angular_velocity: f32,
+    /// `1.0` if the plank rests on something it is toppling onto, `-1.0` if it stands upright and
+    /// can not tip backwards, `0.0` while it moves freely.
+    blocked: f32,
+}
+
+impl Bridge {
+    pub fn new(id: u32, position: cgmath::Point3<f32>) -> Self {
+        Bridge::from_setup(BridgeSetup {
+            id,
+            position: position.into(),
+            rotation_y: 0.0,
+            length: 0.3,
+            width: 0.08,
+            thickness: 0.01,
+            mass: 0.15,
+        })
+    }
+
+    pub fn from_setup(setup: BridgeSetup) -> Self {
+        let mut bridge = Bridge { setup, angle: 0.0, angular_velocity: 0.0, blocked: -1.0 };
+        bridge.reset();
+        bridge
+    }
+
+    /// The center of the hinge edge.
+    fn hinge(&self) -> cgmath::Point3<f32> {
+        self.rotation().transform_point(cgmath::point3(0.0, 0.0, self.setup.thickness * 0.5))
+    }
+
+    /// The axis of the hinge, positive rotations topple the plank towards the local +z axis.
+    fn axis(&self) -> cgmath::Vector3<f32> {
+        self.rotation().transform_vector(cgmath::Vector3::unit_x())
+    }
+
+    /// Rigid transformation of the standing plank.
+    fn rotation(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(cgmath::Vector3::from(self.setup.position))
+            * cgmath::Matrix4::from_angle_y(cgmath::Deg(self.setup.rotation_y))
+    }
+
+    /// Rigid transformation from plank space (origin at the center of its base area) to world
+    /// space, toppled around the hinge.
+    fn transform(&self) -> cgmath::Matrix4<f32> {
+        let hinge = cgmath::vec3(0.0, 0.0, self.setup.thickness * 0.5);
+        self.rotation()
+            * cgmath::Matrix4::from_translation(hinge)
+            * cgmath::Matrix4::from_angle_x(cgmath::Deg(self.angle))
+            * cgmath::Matrix4::from_translation(-hinge)
+    }
+
+    /// Moment of inertia of the plank around the hinge.
+    fn inertia(&self) -> f32 {
+        let s = &self.setup;
+        s.mass * (s.length * s.length + s.thickness * s.thickness) / 3.0
+    }
+
+    /// Lever of an impulse along `along` at `point` around the hinge.
+    fn lever(&self, point: cgmath::Point3<f32>, along: cgmath::Vector3<f32>) -> f32 {
+        (point - self.hinge()).cross(along).dot(self.axis())
+    }
+
+    /// `true` if the top edge of the plank is inside the terrain.
+    fn top_in_terrain(&self, solids: &[Solid]) -> bool {
+        let transform = self.transform();
+        let s = &self.setup;
+        [-0.5, 0.5].iter().any(|x| {
+            let corner = transform.transform_point(cgmath::point3(x * s.width, s.length, s.thickness * 0.5));
+            corner.y < terrain::surface_height(solids, corner.x, corner.z) - TERRAIN_TOLERANCE
+        })
+    }
+}
+
+impl Component for Bridge {
+    fn id(&self) -> u32 {
+        self.setup.id
+    }
+
+    fn kind(&self) -> &'static str {
+        "bridge"
+    }
+
+    fn parameters(&mut self) -> Vec<Parameter<'_>> {
+        let [x, y, z] = &mut self.setup.position;
+        vec![
+            Parameter { name: "x", value: x, range: -5.0..=5.0 },
+            Parameter { name: "y", value: y, range: 0.0..=2.0 },
+            Parameter { name: "z", value: z, range: -5.0..=5.0 },
+            Parameter { name: "rotation", value: &mut self.setup.rotation_y, range: 0.0..=360.0 },
+            Parameter { name: "length", value: &mut self.setup.length, range: 0.05..=2.0 },
+            Parameter { name: "width", value: &mut self.setup.width, range: 0.01..=0.5 },
+            Parameter { name: "thickness", value: &mut self.setup.thickness, range: 0.002..=0.1 },
+            Parameter { name: "mass", value: &mut self.setup.mass, range: 0.01..=2.0 },
+        ]
+    }
+
+    fn validate(&self) -> Option<String> {
+        let s = &self.setup;
+        let values = [s.position[0], s.position[1], s.position[2], s.rotation_y, s.length, s.width, s.thickness, s.mass];
+        if !all_finite(&values) {
+            Some("a value is not a finite number".to_string())
+        } else if s.length <= 0.0 || s.width <= 0.0 || s.thickness <= 0.0 {
+            Some("the size is not positive".to_string())
+        } else if s.mass <= 0.0 {
+            Some("the mass is not positive".to_string())
+        } else {
+            None
+        }
+    }
+
+    fn reset(&mut self) {
+        self.angle = 0.0;
+        self.angular_velocity = 0.0;
+        self.blocked = -1.0;
+    }
+
+    fn integrate(&mut self, dt: f32, solids: &[Solid]) {
+        if self.angular_velocity * self.blocked < 0.0 {
+            self.blocked = 0.0;
+        }
+        if self.blocked != 0.0 && self.angular_velocity == 0.0 {
+            return;
+        }
+
+        // gravity pulls the center of mass, which starts half the thickness behind the hinge
+        let s = &self.setup;
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        let offset = 0.5 * (s.length * sin - s.thickness * cos);
+        let acceleration = GRAVITY * offset * s.mass / self.inertia();
+        self.angular_velocity += acceleration.to_degrees() * dt;
+
+        let previous = self.angle;
+        self.angle = (self.angle + self.angular_velocity * dt).clamp(0.0, 90.0);
+        if self.angle <= 0.0 {
+            // back on its base
+            self.angular_velocity = 0.0;
+            self.blocked = -1.0;
+        } else if self.angle >= 90.0 || self.top_in_terrain(solids) {
+            if self.top_in_terrain(solids) {
+                self.angle = previous;
+            }
+            let top_speed = self.angular_velocity.to_radians().abs() * s.length;
+            self.angular_velocity *= -restitution_at(top_speed);
+            if self.angular_velocity == 0.0 {
+                self.blocked = 1.0;
+            }
+        }
+    }
+
+    fn collider(&self) -> Collider {
+        let s = &self.setup;
+        Collider::Box(Obb::new(self.transform(), cgmath::vec3(s.width, s.length, s.thickness)))
+    }
+
+    fn velocity_at(&self, point: cgmath::Point3<f32>) -> cgmath::Vector3<f32> {
+        self.axis().cross(point - self.hinge()) * self.angular_velocity.to_radians()
+    }
+
+    fn inverse_mass_at(&self, point: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>) -> f32 {
+        let lever = self.lever(point, direction);
+        if lever * self.blocked > 0.0 {
+            return 0.0;
+        }
+        lever * lever / self.inertia()
+    }
+
+    fn apply_impulse(&mut self, point: cgmath::Point3<f32>, impulse: cgmath::Vector3<f32>) {
+        self.angular_velocity += (self.lever(point, impulse) / self.inertia()).to_degrees();
+        if self.angular_velocity * self.blocked > 0.0 {
+            self.angular_velocity = 0.0;
+        }
+    }
+
+    fn displace(&mut self, point: cgmath::Point3<f32>, offset: cgmath::Vector3<f32>) {
+        let arm = point - self.hinge();
+        if arm.magnitude2() < f32::EPSILON {
+            return;
+        }
+        let rotation = (arm.cross(offset).dot(self.axis()) / arm.magnitude2()).to_degrees();
+        if rotation * self.blocked <= 0.0 {
+            self.angle = (self.angle + rotation).clamp(0.0, 90.0);
+        }
+    }
+
+    fn is_moving(&self) -> bool {
+        self.angular_velocity.abs() >= REST_VELOCITY
+    }
+
+    fn state(&self) -> Vec<f32> {
+        vec![self.angle, self.angular_velocity, self.blocked]
+    }
+
+    fn set_state(&mut self, state: &[f32]) {
+        if let [angle, angular_velocity, blocked] = *state {
+            self.angle = angle;
+            self.angular_velocity = angular_velocity;
+            self.blocked = blocked;
+        }
+    }
+
+    fn parts(&self) -> Vec<Part> {
+        let s = &self.setup;
+        let transform = self.transform() * cgmath::Matrix4::from_nonuniform_scale(s.width, s.length, s.thickness);
+        vec![Part { shape: PartShape::Box, transform, color: COLOR }]
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        with_kind(&self.setup, self.kind())
+    }
+
+    fn clone_box(&self) -> Box<dyn Component> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::{Domino, Simulator, PUSH_VELOCITY};
+
+    #[test]
+    fn bridge_topples_when_a_domino_hits_it() {
+        let bridge = Bridge::new(0, cgmath::point3(0.0, 0.0, 0.0));
+        let domino = Domino::new(0, cgmath::point3(0.0, 0.0, -0.08), 0.0);
+        let mut s = Simulator { dominos: vec![domino], components: vec![Box::new(bridge)], ..Simulator::empty() };
+        s.step(1.0);
+        assert_eq!(s.components[0].state()[0], 0.0, "the bridge toppled on its own");
+        s.push(0, PUSH_VELOCITY);
+        for _ in 0..300 {
+            s.step(0.01);
+        }
+        let angle = s.components[0].state()[0];
+        assert!(angle > 85.0, "the bridge only toppled by {} degrees", angle);
+        assert!(s.is_at_rest());
+    }
+}
@@ -0,0 +1,268 @@
+//! A seesaw: a plank pivoting on a fulcrum, one end goes up when the other one is pushed down.
+
+use cgmath::{EuclideanSpace, InnerSpace, Transform};
+use serde::{Deserialize, Serialize};
+
+use super::{all_finite, restitution_at, with_kind, Collider, Component, Parameter, Part, PartShape};
+use crate::simulator::{obb::Obb, physics::REST_VELOCITY, terrain::{self, Solid}};
+
+/// The plank can not tilt further than this (deg), e.g. on a fulcrum higher than half its length.
+const MAX_TILT: f32 = 80.0;
+const PLANK_COLOR: [f32; 3] = [0.75, 0.55, 0.3];
+const FULCRUM_COLOR: [f32; 3] = [0.4, 0.4, 0.4];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LeverSetup {
+    pub id: u32,
+    /// Center of the base of the fulcrum in meters.
+    pub position: [f32; 3],
+    /// Rotation around the vertical axis in degrees, the plank lies along the local z axis.
+    pub rotation_y: f32,
+    /// Length, width and thickness of the plank in meters.
+    pub length: f32,
+    pub width: f32,
+    pub thickness: f32,
+    /// Height of the fulcrum in meters.
+    pub height: f32,
+    /// Mass of the plank in kg.
+    pub mass: f32,
+    /// Initial tilt in degrees, positive values lower the +z end.
+    #[serde(default)]
+    pub tilt: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Lever {
+    pub setup: LeverSetup,
+    /// Tilt in degrees, positive values lower the +z end.
+    pub angle: f32,
+    /// Angular velocity in deg/s.
+    pub angular_velocity: f32,
+    /// `1.0` if the +z end rests on the terrain, `-1.0` if the -z end does, `0.0` otherwise.
+    blocked: f32,
+}
+
+impl Lever {
+    pub fn new(id: u32, position: cgmath::Point3<f32>) -> Self {
+        Lever::from_setup(LeverSetup {
+            id,
+            position: position.into(),
+            rotation_y: 0.0,
+            length: 0.4,
+            width: 0.06,
+            thickness: 0.01,
+            height: 0.03,
+            mass: 0.1,
+            tilt: 0.0,
+        })
+    }
+
+    pub fn from_setup(setup: LeverSetup) -> Self {
+        let mut lever = Lever { setup, angle: 0.0, angular_velocity: 0.0, blocked: 0.0 };
+        lever.reset();
+        lever
+    }
+
+    /// The point the plank pivots around, on top of the fulcrum.
+    fn pivot(&self) -> cgmath::Point3<f32> {
+        cgmath::Point3::from(self.setup.position) + cgmath::vec3(0.0, self.setup.height, 0.0)
+    }
+
+    /// The axis the plank pivots around, positive rotations lower the +z end.
+    fn axis(&self) -> cgmath::Vector3<f32> {
+        cgmath::Matrix4::from_angle_y(cgmath::Deg(self.setup.rotation_y)).transform_vector(cgmath::Vector3::unit_x())
+    }
+
+    /// Rigid transformation from plank space (origin at the center of its base area, on top of
+    /// the fulcrum) to world space.
+    fn transform(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(self.pivot().to_vec())
+            * cgmath::Matrix4::from_angle_y(cgmath::Deg(self.setup.rotation_y))
+            * cgmath::Matrix4::from_angle_x(cgmath::Deg(self.angle))
+    }
+
+    /// Moment of inertia of the plank around the pivot.
+    fn inertia(&self) -> f32 {
+        let s = &self.setup;
+        s.mass * (s.length * s.length + s.thickness * s.thickness) / 12.0
+    }
+
+    /// Lever of an impulse along `along` at `point` around the pivot.
+    fn lever(&self, point: cgmath::Point3<f32>, along: cgmath::Vector3<f32>) -> f32 {
+        (point - self.pivot()).cross(along).dot(self.axis())
+    }
+
+    /// `true` if the end of the plank towards `end` (+1 or -1 along the local z axis) is inside
+    /// the terrain.
+    fn end_in_terrain(&self, solids: &[Solid], end: f32) -> bool {
+        let transform = self.transform();
+        [-0.5, 0.5].iter().any(|x| {
+            let corner = transform.transform_point(cgmath::point3(x * self.setup.width, 0.0, end * self.setup.length * 0.5));
+            corner.y < terrain::surface_height(solids, corner.x, corner.z)
+        })
+    }
+}
+
+impl Component for Lever {
+    fn id(&self) -> u32 {
+        self.setup.id
+    }
+
+    fn kind(&self) -> &'static str {
+        "lever"
+    }
+
+    fn parameters(&mut self) -> Vec<Parameter<'_>> {
+        let [x, y, z] = &mut self.setup.position;
+        vec![
+            Parameter { name: "x", value: x, range: -5.0..=5.0 },
+            Parameter { name: "y", value: y, range: 0.0..=2.0 },
+            Parameter { name: "z", value: z, range: -5.0..=5.0 },
+            Parameter { name: "rotation", value: &mut self.setup.rotation_y, range: 0.0..=360.0 },
+            Parameter { name: "length", value: &mut self.setup.length, range: 0.05..=2.0 },
+            Parameter { name: "width", value: &mut self.setup.width, range: 0.01..=0.5 },
+            Parameter { name: "thickness", value: &mut self.setup.thickness, range: 0.002..=0.1 },
+            Parameter { name: "height", value: &mut self.setup.height, range: 0.005..=0.5 },
+            Parameter { name: "mass", value: &mut self.setup.mass, range: 0.01..=2.0 },
+            Parameter { name: "tilt", value: &mut self.setup.tilt, range: -MAX_TILT..=MAX_TILT },
+        ]
+    }
+
+    fn validate(&self) -> Option<String> {
+        let s = &self.setup;
+        let values = [s.position[0], s.position[1], s.position[2], s.rotation_y, s.length, s.width, s.thickness, s.height, s.mass, s.tilt];
+        if !all_finite(&values) {
+            Some("a value is not a finite number".to_string())
+        } else if s.length <= 0.0 || s.width <= 0.0 || s.thickness <= 0.0 || s.height <= 0.0 {
+            Some("the size is not positive".to_string())
+        } else if s.mass <= 0.0 {
+            Some("the mass is not positive".to_string())
+        } else if s.tilt.abs() > MAX_TILT {
+            Some(format!("the tilt is steeper than {} degrees", MAX_TILT))
+        } else {
+            None
+        }
+    }
+
+    fn reset(&mut self) {
+        self.angle = self.setup.tilt;
+        self.angular_velocity = 0.0;
+        self.blocked = 0.0;
+    }
+
+    fn integrate(&mut self, dt: f32, solids: &[Solid]) {
+        if self.angular_velocity * self.blocked < 0.0 {
+            self.blocked = 0.0;
+        }
+        if self.angular_velocity == 0.0 {
+            return;
+        }
+
+        // the plank is balanced on its fulcrum, only the bodies on it make it tilt
+        let previous = self.angle;
+        self.angle = (self.angle + self.angular_velocity * dt).clamp(-MAX_TILT, MAX_TILT);
+        let end = self.angular_velocity.signum();
+        if self.angle.abs() >= MAX_TILT || self.end_in_terrain(solids, end) {
+            // the end moving down comes to rest on the terrain
+            if self.end_in_terrain(solids, end) {
+                self.angle = previous;
+            }
+            let end_speed = self.angular_velocity.to_radians().abs() * self.setup.length * 0.5;
+            self.angular_velocity *= -restitution_at(end_speed);
+            if self.angular_velocity == 0.0 {
+                self.blocked = end;
+            }
+        }
+    }
+
+    fn collider(&self) -> Collider {
+        let s = &self.setup;
+        Collider::Box(Obb::new(self.transform(), cgmath::vec3(s.width, s.thickness, s.length)))
+    }
+
+    fn velocity_at(&self, point: cgmath::Point3<f32>) -> cgmath::Vector3<f32> {
+        self.axis().cross(point - self.pivot()) * self.angular_velocity.to_radians()
+    }
+
+    fn inverse_mass_at(&self, point: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>) -> f32 {
+        let lever = self.lever(point, direction);
+        if lever * self.blocked > 0.0 {
+            return 0.0;
+        }
+        lever * lever / self.inertia()
+    }
+
+    fn apply_impulse(&mut self, point: cgmath::Point3<f32>, impulse: cgmath::Vector3<f32>) {
+        self.angular_velocity += (self.lever(point, impulse) / self.inertia()).to_degrees();
+        if self.angular_velocity * self.blocked > 0.0 {
+            self.angular_velocity = 0.0;
+        }
+    }
+
+    fn displace(&mut self, point: cgmath::Point3<f32>, offset: cgmath::Vector3<f32>) {
+        let arm = point - self.pivot();
+        if arm.magnitude2() < f32::EPSILON {
+            return;
+        }
+        let rotation = (arm.cross(offset).dot(self.axis()) / arm.magnitude2()).to_degrees();
+        if rotation * self.blocked <= 0.0 {
+            self.angle = (self.angle + rotation).clamp(-MAX_TILT, MAX_TILT);
+        }
+    }
+
+    fn is_moving(&self) -> bool {
+        self.angular_velocity.abs() >= REST_VELOCITY
+    }
+
+    fn state(&self) -> Vec<f32> {
+        vec![self.angle, self.angular_velocity, self.blocked]
+    }
+
+    fn set_state(&mut self, state: &[f32]) {
+        if let [angle, angular_velocity, blocked] = *state {
+            self.angle = angle;
+            self.angular_velocity = angular_velocity;
+            self.blocked = blocked;
+        }
+    }
+
+    fn parts(&self) -> Vec<Part> {
+        let s = &self.setup;
+        let fulcrum = cgmath::Matrix4::from_translation(cgmath::Vector3::from(s.position))
+            * cgmath::Matrix4::from_angle_y(cgmath::Deg(s.rotation_y))
+            * cgmath::Matrix4::from_nonuniform_scale(s.width, s.height, s.height);
+        let plank = self.transform() * cgmath::Matrix4::from_nonuniform_scale(s.width, s.thickness, s.length);
+        vec![
+            Part { shape: PartShape::Box, transform: fulcrum, color: FULCRUM_COLOR },
+            Part { shape: PartShape::Box, transform: plank, color: PLANK_COLOR },
+        ]
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        with_kind(&self.setup, self.kind())
+    }
+
+    fn clone_box(&self) -> Box<dyn Component> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::{component::ball::{Ball, BallSetup}, Simulator};
+
+    #[test]
+    fn lever_tips_when_a_ball_drops_onto_one_end() {
+        let lever = Lever::new(0, cgmath::point3(0.0, 0.0, 0.0));
+        let ball = Ball::from_setup(BallSetup { id: 1, position: [0.0, 0.2, 0.15], radius: 0.02, mass: 0.05, velocity: [0.0, 0.0] });
+        let mut s = Simulator { components: vec![Box::new(lever), Box::new(ball)], ..Simulator::empty() };
+        s.step(0.1);
+        assert_eq!(s.components[0].state()[0], 0.0, "the lever moved before the ball hit it");
+        for _ in 0..200 {
+            s.step(0.01);
+        }
+        let angle = s.components[0].state()[0];
+        assert!(angle > 5.0, "the lever only tilted by {} degrees", angle);
+    }
+}
@@ -0,0 +1,59 @@
+//! Lights the scene is drawn with.
+//!
+//! Lights do not take part in the simulation, they are stored with the layout so that a scene
+//! looks the same when it is opened again. The first light casts the shadows.
+
+/// Number of lights the renderer supports.
+pub const MAX_LIGHTS: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    /// Shines in all directions from a position in meters.
+    Point { position: cgmath::Point3<f32> },
+    /// Shines from far away along a direction, like the sun.
+    Directional { direction: cgmath::Vector3<f32> },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Red, green and blue between 0 and 1.
+    pub color: [f32; 3],
+    /// Factor the colour is scaled with.
+    pub intensity: f32,
+}
+
+impl Light {
+    pub fn point(position: cgmath::Point3<f32>) -> Self {
+        Light { kind: LightKind::Point { position }, color: [1.0, 1.0, 1.0], intensity: 1.0 }
+    }
+
+    pub fn directional(direction: cgmath::Vector3<f32>) -> Self {
+        Light { kind: LightKind::Directional { direction }, color: [1.0, 1.0, 1.0], intensity: 1.0 }
+    }
+
+    /// Describes why the light is invalid, `None` if it is valid.
+    pub fn validate(&self) -> Option<String> {
+        let values: [f32; 3] = match self.kind {
+            LightKind::Point { position } => position.into(),
+            LightKind::Directional { direction } => direction.into(),
+        };
+        if values.iter().chain(self.color.iter()).chain([self.intensity].iter()).any(|v| !v.is_finite()) {
+            Some("a value is not a finite number".to_string())
+        } else if matches!(self.kind, LightKind::Directional { .. }) && values.iter().all(|v| *v == 0.0) {
+            Some("the direction is zero".to_string())
+        } else if self.color.iter().any(|c| !(0.0..=1.0).contains(c)) {
+            Some("a colour channel is not between 0 and 1".to_string())
+        } else if self.intensity < 0.0 {
+            Some("the intensity is negative".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+/// The lighting of new scenes and of layouts that were saved without lights, a single light
+/// above the origin.
+pub fn default_lights() -> Vec<Light> {
+    vec![Light::point(cgmath::point3(0.0, 2.0, 0.0))]
+}
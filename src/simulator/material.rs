@@ -0,0 +1,62 @@
+//! Materials dominos are made of.
+//!
+//! Real builds mix stone types, so every [`Domino`](super::Domino) has its own material. The
+//! physics takes the mass, the friction and the restitution from it and the renderer its colour.
+
+use super::DOMINO_MASS;
+
+/// Physical properties and colour of the stone a domino is made of.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Material {
+    /// Mass of a domino with a scale of 1, in kg.
+    pub mass: f32,
+    /// Coefficient of friction between the top edge of a falling domino and the face it slides
+    /// along.
+    pub friction: f32,
+    /// Fraction of the approaching velocity that is kept when the domino collides with another
+    /// one, between 0 and 1.
+    pub restitution: f32,
+    /// Red, green and blue between 0 and 1.
+    pub color: [f32; 3],
+}
+
+/// The default material, without friction and bounce. The gates of [`crate::gates`] are tuned
+/// for it.
+pub const STANDARD: Material = Material { mass: DOMINO_MASS, friction: 0.0, restitution: 0.0, color: [1.0, 0.0, 0.0] };
+pub const WOOD: Material = Material { mass: 0.09, friction: 0.4, restitution: 0.05, color: [0.75, 0.5, 0.25] };
+pub const PLASTIC: Material = Material { mass: 0.04, friction: 0.3, restitution: 0.2, color: [0.1, 0.5, 0.9] };
+pub const CERAMIC: Material = Material { mass: 0.15, friction: 0.6, restitution: 0.1, color: [0.9, 0.9, 0.85] };
+
+/// The material presets with their names, the first one is the default.
+pub const PRESETS: [(&str, Material); 4] = [("standard", STANDARD), ("wood", WOOD), ("plastic", PLASTIC), ("ceramic", CERAMIC)];
+
+impl Default for Material {
+    fn default() -> Self {
+        STANDARD
+    }
+}
+
+impl Material {
+    /// Name of the preset with exactly these properties, `None` for a custom material.
+    pub fn preset_name(&self) -> Option<&'static str> {
+        PRESETS.iter().find(|(_, m)| m == self).map(|(name, _)| *name)
+    }
+
+    /// Describes why the material is not physically valid, `None` if it is.
+    pub fn validate(&self) -> Option<String> {
+        let values = [self.mass, self.friction, self.restitution, self.color[0], self.color[1], self.color[2]];
+        if values.iter().any(|v| !v.is_finite()) {
+            Some("a material value is not a finite number".to_string())
+        } else if self.mass <= 0.0 {
+            Some("the mass is not positive".to_string())
+        } else if self.friction < 0.0 {
+            Some("the friction is negative".to_string())
+        } else if !(0.0..=1.0).contains(&self.restitution) {
+            Some("the restitution is not between 0 and 1".to_string())
+        } else if self.color.iter().any(|c| !(0.0..=1.0).contains(c)) {
+            Some("a colour channel is not between 0 and 1".to_string())
+        } else {
+            None
+        }
+    }
+}
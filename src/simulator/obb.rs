@@ -0,0 +1,104 @@
+//! Oriented bounding boxes of dominos and of the boxes of [components](super::component).
+
+use cgmath::{InnerSpace, Transform};
+
+use super::Domino;
+
+/// A box with arbitrary orientation, given by its center, its unit axes and its half size along
+/// each axis.
+#[derive(Clone, Copy, Debug)]
+pub struct Obb {
+    pub center: cgmath::Point3<f32>,
+    pub axes: [cgmath::Vector3<f32>; 3],
+    pub half_extents: cgmath::Vector3<f32>,
+}
+
+impl Domino {
+    /// The box the domino occupies in its current fall state, the same box it is drawn and picked
+    /// with.
+    pub fn obb(&self) -> Obb {
+        Obb::new(self.transform(), self.dimensions())
+    }
+}
+
+impl Obb {
+    /// The box of the given size (along x, y and z) with the center of its base area at the origin
+    /// of the rigid `transform`.
+    pub fn new(transform: cgmath::Matrix4<f32>, size: cgmath::Vector3<f32>) -> Self {
+        Obb {
+            center: transform.transform_point(cgmath::point3(0.0, size.y * 0.5, 0.0)),
+            axes: [
+                transform.transform_vector(cgmath::Vector3::unit_x()),
+                transform.transform_vector(cgmath::Vector3::unit_y()),
+                transform.transform_vector(cgmath::Vector3::unit_z()),
+            ],
+            half_extents: size * 0.5,
+        }
+    }
+
+    /// Half the length of the box projected onto `axis`.
+    pub fn radius_along(&self, axis: cgmath::Vector3<f32>) -> f32 {
+        (0..3).map(|i| self.half_extents[i] * self.axes[i].dot(axis).abs()).sum()
+    }
+
+    /// `true` if the boxes overlap by more than `tolerance` meters, so that boxes which only touch
+    /// do not count. Uses the separating axis test with the face normals of both boxes and the
+    /// cross products of their edges.
+    pub fn intersects(&self, other: &Obb, tolerance: f32) -> bool {
+        let delta = other.center - self.center;
+        self.separating_axes(other).iter().all(|axis| delta.dot(*axis).abs() + tolerance < self.radius_along(*axis) + other.radius_along(*axis))
+    }
+
+    /// The face normals of both boxes and the cross products of their edges.
+    fn separating_axes(&self, other: &Obb) -> Vec<cgmath::Vector3<f32>> {
+        let mut axes: Vec<cgmath::Vector3<f32>> = self.axes.iter().chain(other.axes.iter()).copied().collect();
+        for a in self.axes {
+            for b in other.axes {
+                let cross = a.cross(b);
+                // parallel edges do not give a new axis
+                if cross.magnitude2() > 1e-6 {
+                    axes.push(cross.normalize());
+                }
+            }
+        }
+        axes
+    }
+
+    /// The axis along which the boxes overlap the least, pointing from this box to `other`, and
+    /// the depth of the overlap along it. `None` if the boxes do not overlap.
+    pub fn penetration(&self, other: &Obb) -> Option<(cgmath::Vector3<f32>, f32)> {
+        let delta = other.center - self.center;
+        let mut least: Option<(cgmath::Vector3<f32>, f32)> = None;
+        for axis in self.separating_axes(other) {
+            let depth = self.radius_along(axis) + other.radius_along(axis) - delta.dot(axis).abs();
+            if depth <= 0.0 {
+                return None;
+            }
+            if least.is_none_or(|(_, d)| depth < d) {
+                least = Some((if delta.dot(axis) < 0.0 { -axis } else { axis }, depth));
+            }
+        }
+        least
+    }
+
+    /// The point of the box that is nearest to `p`, `p` itself if it is inside.
+    pub fn closest_point(&self, p: cgmath::Point3<f32>) -> cgmath::Point3<f32> {
+        let delta = p - self.center;
+        (0..3).fold(self.center, |closest, i| {
+            closest + self.axes[i] * delta.dot(self.axes[i]).clamp(-self.half_extents[i], self.half_extents[i])
+        })
+    }
+
+    pub fn contains(&self, p: cgmath::Point3<f32>) -> bool {
+        let delta = p - self.center;
+        (0..3).all(|i| delta.dot(self.axes[i]).abs() <= self.half_extents[i])
+    }
+
+    pub fn vertices(&self) -> [cgmath::Point3<f32>; 8] {
+        let corner = |i: usize| {
+            let sign = |bit: usize| if i >> bit & 1 == 1 { 1.0 } else { -1.0 };
+            self.center + (0..3).map(|a| self.axes[a] * self.half_extents[a] * sign(a)).sum::<cgmath::Vector3<f32>>()
+        };
+        [corner(0), corner(1), corner(2), corner(3), corner(4), corner(5), corner(6), corner(7)]
+    }
+}
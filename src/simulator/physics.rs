@@ -0,0 +1,518 @@
+//! Rigid body toppling physics for dominos.
+//!
+//! Every domino has a single degree of freedom: the rotation around the base edge it is tipping
+//! over (`Domino::fall_rotation`). Gravity is integrated per domino, while collisions between the
+//! top edge of a tipping domino and its neighbours exchange impulses, so that a falling domino
+//! knocks over the next one. The impulses depend on the [`Material`](super::material::Material)
+//! of both dominos: the restitution of the collision is the larger of their restitutions, and
+//! the top edge sliding along the face it hits is slowed by friction with the geometric mean of
+//! their friction coefficients.
+//!
+//! Contacts with [components](super::component) exchange impulses the same way, with the
+//! component moving out of the domino.
+//!
+//! Falling dominos also hit the [`Solid`]s of the terrain, which do not move: the domino is moved
+//! back out of the solid and bounces off with its own restitution. A domino can not fall further
+//! than lying flat at the height of its base, e.g. when it tips over the edge of a platform.
+
+use cgmath::{InnerSpace, Matrix, SquareMatrix, Transform};
+
+use super::{component::{self, Component}, terrain::Solid, Domino};
+
+/// Gravitational acceleration in m/s².
+pub const GRAVITY: f32 = 9.81;
+/// Longest time step (in seconds) that is integrated at once.
+pub const MAX_SUBSTEP: f32 = 0.001;
+/// Most substeps a single step is split into, longer steps get longer substeps.
+pub const MAX_SUBSTEPS: usize = 10_000;
+/// Angular velocities (deg/s) below this are considered to be at rest.
+pub const REST_VELOCITY: f32 = 2.0;
+/// Number of points along the top edge of a falling domino that are tested for collisions.
+const EDGE_SAMPLES: usize = 5;
+/// Number of rows of points on the face of a falling domino that are tested for collisions with
+/// the terrain, the last one is its top edge.
+const FACE_ROWS: usize = 4;
+/// Distance (in meters) behind the face of a falling domino at which it is tested for collisions
+/// with the terrain, so that a domino lying flat on a platform does not count as penetrating it.
+const TERRAIN_TOLERANCE: f32 = 0.0005;
+/// Number of bisection steps used to move a domino out of a neighbour it penetrates.
+const SEPARATION_STEPS: usize = 8;
+
+/// A falling domino hitting another one with an impulse.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hit {
+    /// Id of the falling domino.
+    pub from: u32,
+    /// Id of the domino that was hit.
+    pub to: u32,
+}
+
+struct Contact {
+    /// Contact point in world space.
+    point: cgmath::Point3<f32>,
+    /// Contact normal in world space, pointing from the falling domino into the hit one.
+    normal: cgmath::Vector3<f32>,
+}
+
+/// Integrates all dominos by `dt` seconds and resolves the collisions between them and with the
+/// `solids` of the terrain. Returns the hits between dominos that exchanged an impulse.
+pub fn substep(dominos: &mut [Domino], solids: &[Solid], dt: f32) -> Vec<Hit> {
+    let previous_rotations: Vec<f32> = dominos.iter().map(|d| d.fall_rotation).collect();
+
+    for d in dominos.iter_mut() {
+        integrate(d, dt);
+    }
+
+    // dominos leaning on a neighbour only get pushed by gravity and the dominos leaning on them,
+    // they come to rest once all collisions are resolved
+    let mut resting = vec![false; dominos.len()];
+    let mut hits = vec![];
+    let sweep = Sweep::new(dominos);
+    // only falling dominos move during the loop, their frames are updated once they are separated
+    let mut frames: Vec<Frame> = dominos.iter().map(Frame::new).collect();
+    for a in 0..dominos.len() {
+        if dominos[a].fall_rotation == 0.0 || (dominos[a].fall_rotation.abs() >= 90.0 && dominos[a].angular_velocity == 0.0) {
+            continue;
+        }
+        for b in sweep.neighbours(dominos, a) {
+            if a == b || !in_reach(&dominos[a], &dominos[b]) {
+                continue;
+            }
+            let contact = match find_contact(&dominos[a], &frames[a].transform, &dominos[b], &frames[b]) {
+                Some(c) => c,
+                None => continue,
+            };
+            if apply_impulse(dominos, a, b, &contact) {
+                hits.push(Hit { from: dominos[a].id, to: dominos[b].id });
+            }
+            let other = dominos[b].clone();
+            resting[a] |= separate(&mut dominos[a], previous_rotations[a], dt, |d| find_contact(d, &d.transform(), &other, &frames[b]).is_some());
+            frames[a] = Frame::new(&dominos[a]);
+        }
+    }
+
+    let spheres: Vec<_> = solids.iter().map(|s| s.bounding_sphere()).collect();
+    for (a, d) in dominos.iter_mut().enumerate() {
+        if d.fall_rotation == previous_rotations[a] {
+            continue;
+        }
+        for (solid, (center, radius)) in solids.iter().zip(spheres.iter()) {
+            let reach = d.dimensions().y + radius;
+            if (d.position - center).magnitude2() > reach * reach || !touches_solid(d, solid) {
+                continue;
+            }
+            let rotation = d.fall_rotation;
+            resting[a] |= separate(d, previous_rotations[a], dt, |d| touches_solid(d, solid));
+            if d.fall_rotation != rotation {
+                // the terrain does not move, the domino bounces off with its own restitution
+                d.angular_velocity *= -d.material.restitution;
+            }
+        }
+    }
+
+    for (d, resting) in dominos.iter_mut().zip(resting) {
+        if resting {
+            d.angular_velocity = 0.0;
+        }
+    }
+    hits
+}
+
+/// Moment of inertia of a domino around its base edge, per unit of mass.
+fn inertia(d: &Domino) -> f32 {
+    let dim = d.dimensions();
+    (dim.y * dim.y + dim.z * dim.z) / 3.0
+}
+
+fn integrate(d: &mut Domino, dt: f32) {
+    let direction = d.fall_direction();
+    if direction == 0.0 {
+        return;
+    }
+
+    let dim = d.dimensions();
+    // distance between the pivot edge and the center of mass and the angle the center of mass
+    // has to travel until it is above the pivot edge
+    let com_distance = 0.5 * (dim.y * dim.y + dim.z * dim.z).sqrt();
+    let balance_angle = (dim.z / dim.y).atan();
+
+    let theta = d.fall_rotation.to_radians();
+    let acceleration = direction * GRAVITY * com_distance / inertia(d) * (theta.abs() - balance_angle).sin();
+
+    let omega = d.angular_velocity.to_radians() + acceleration * dt;
+    let new_theta = theta + omega * dt;
+
+    if new_theta * direction < 0.0 {
+        // the domino rocked back onto its base
+        d.fall_rotation = 0.0;
+        d.angular_velocity = 0.0;
+    } else if new_theta.abs() >= std::f32::consts::FRAC_PI_2 {
+        // the domino lies flat on the ground
+        d.fall_rotation = 90.0 * direction;
+        d.angular_velocity = 0.0;
+    } else {
+        d.fall_rotation = new_theta.to_degrees();
+        d.angular_velocity = omega.to_degrees();
+    }
+}
+
+/// Broad phase of the collisions between dominos: the dominos sorted along the x axis, so that
+/// only the ones close to a falling domino are tested for contacts instead of all of them.
+struct Sweep {
+    /// Indices of the dominos ordered by their x coordinate.
+    order: Vec<usize>,
+    /// Largest diagonal of any domino.
+    max_size: f32,
+}
+
+impl Sweep {
+    fn new(dominos: &[Domino]) -> Self {
+        let mut order: Vec<usize> = (0..dominos.len()).collect();
+        order.sort_by(|&i, &j| dominos[i].position.x.total_cmp(&dominos[j].position.x));
+        let max_size = dominos.iter().map(|d| d.dimensions().magnitude()).fold(0.0, f32::max);
+        Sweep { order, max_size }
+    }
+
+    /// Indices of the dominos that might be [in reach](in_reach) of domino `a`, in ascending
+    /// order so that impulses are resolved in the same order as without the broad phase.
+    fn neighbours(&self, dominos: &[Domino], a: usize) -> Vec<usize> {
+        let x = dominos[a].position.x;
+        let reach = dominos[a].dimensions().y + self.max_size;
+        let start = self.order.partition_point(|&i| dominos[i].position.x < x - reach);
+        let end = self.order.partition_point(|&i| dominos[i].position.x <= x + reach);
+        let mut neighbours = self.order[start..end].to_vec();
+        neighbours.sort_unstable();
+        neighbours
+    }
+}
+
+/// Transform of a domino and its inverse, which every contact test with the domino needs.
+struct Frame {
+    transform: cgmath::Matrix4<f32>,
+    inverse: cgmath::Matrix4<f32>,
+}
+
+impl Frame {
+    fn new(d: &Domino) -> Self {
+        // the transform is rigid, its inverse rotates back by the transposed rotation
+        let transform = d.transform();
+        let rotation = cgmath::Matrix3::from_cols(transform.x.truncate(), transform.y.truncate(), transform.z.truncate()).transpose();
+        let mut inverse = cgmath::Matrix4::from(rotation);
+        inverse.w = (-(rotation * transform.w.truncate())).extend(1.0);
+        Frame { transform, inverse }
+    }
+}
+
+/// Cheap test whether the top of `a` can reach `b` at all.
+fn in_reach(a: &Domino, b: &Domino) -> bool {
+    let reach = a.dimensions().y + b.dimensions().magnitude();
+    (a.position - b.position).magnitude2() <= reach * reach
+}
+
+fn edge_points(d: &Domino, transform: &cgmath::Matrix4<f32>) -> impl Iterator<Item = cgmath::Point3<f32>> {
+    let dim = d.dimensions();
+    let transform = *transform;
+    let z = d.fall_direction() * dim.z * 0.5;
+    (0..EDGE_SAMPLES).map(move |i| {
+        let x = dim.x * (i as f32 / (EDGE_SAMPLES - 1) as f32 - 0.5);
+        transform.transform_point(cgmath::point3(x, dim.y, z))
+    })
+}
+
+/// Points just behind the face of `d` in the direction it falls towards, from the lower rows up to
+/// its top edge.
+fn face_points(d: &Domino) -> impl Iterator<Item = cgmath::Point3<f32>> {
+    let dim = d.dimensions();
+    let transform = d.transform();
+    let z = d.fall_direction() * (dim.z * 0.5 - TERRAIN_TOLERANCE);
+    (1..=FACE_ROWS).flat_map(move |row| {
+        let y = dim.y * row as f32 / FACE_ROWS as f32;
+        (0..EDGE_SAMPLES).map(move |i| {
+            let x = dim.x * (i as f32 / (EDGE_SAMPLES - 1) as f32 - 0.5);
+            transform.transform_point(cgmath::point3(x, y, z))
+        })
+    })
+}
+
+/// `true` if `d` penetrates the solid, either with its face or by an edge of the solid.
+fn touches_solid(d: &Domino, solid: &Solid) -> bool {
+    if face_points(d).any(|p| solid.contains(p)) {
+        return true;
+    }
+    match d.transform().invert() {
+        Some(inverse) => solid.edges().any(|(a, b)| segment_hits(d, &inverse, a, b)),
+        None => false,
+    }
+}
+
+/// `true` if the segment from `a` to `b` passes through the box of `d`.
+fn segment_hits(d: &Domino, inverse_transform: &cgmath::Matrix4<f32>, a: cgmath::Point3<f32>, b: cgmath::Point3<f32>) -> bool {
+    let dim = d.dimensions();
+    let (a, b) = (inverse_transform.transform_point(a), inverse_transform.transform_point(b));
+    let min = [-dim.x * 0.5, 0.0, -dim.z * 0.5];
+    let max = [dim.x * 0.5, dim.y, dim.z * 0.5];
+    let (mut t_min, mut t_max) = (0.0f32, 1.0f32);
+    for i in 0..3 {
+        let delta = b[i] - a[i];
+        if delta.abs() < f32::EPSILON {
+            if a[i] < min[i] || a[i] > max[i] {
+                return false;
+            }
+            continue;
+        }
+        let (t1, t2) = ((min[i] - a[i]) / delta, (max[i] - a[i]) / delta);
+        t_min = t_min.max(t1.min(t2));
+        t_max = t_max.min(t1.max(t2));
+        if t_max < t_min {
+            return false;
+        }
+    }
+    true
+}
+
+/// `true` if the point `local` in domino space lies inside `d`.
+fn contains(d: &Domino, local: cgmath::Point3<f32>) -> bool {
+    let dim = d.dimensions();
+    local.x.abs() <= dim.x * 0.5 && local.y >= 0.0 && local.y <= dim.y && local.z.abs() <= dim.z * 0.5
+}
+
+fn find_contact(a: &Domino, a_transform: &cgmath::Matrix4<f32>, b: &Domino, b_frame: &Frame) -> Option<Contact> {
+    // the top edge of `a` in the space of `b`
+    let local = edge_points(a, &(b_frame.inverse * a_transform)).find(|p| contains(b, *p))?;
+    let point = b_frame.transform.transform_point(local);
+
+    let a_direction = a_transform.transform_vector(cgmath::Vector3::unit_z()) * a.fall_direction();
+    let b_normal = b_frame.transform.transform_vector(cgmath::Vector3::unit_z());
+    let normal = if b_normal.dot(a_direction) < 0.0 { -b_normal } else { b_normal };
+
+    Some(Contact { point, normal })
+}
+
+/// World space pivot edge center and rotation axis of a domino that tips towards `direction`.
+fn pivot(d: &Domino, direction: f32) -> (cgmath::Point3<f32>, cgmath::Vector3<f32>) {
+    let transform = d.transform();
+    let pivot = transform.transform_point(cgmath::point3(0.0, 0.0, direction * d.dimensions().z * 0.5));
+    let axis = transform.transform_vector(cgmath::Vector3::unit_x());
+    (pivot, axis)
+}
+
+/// Lever of an impulse along `along` at `point` around the pivot of `d`.
+fn lever(d: &Domino, direction: f32, point: cgmath::Point3<f32>, along: cgmath::Vector3<f32>) -> f32 {
+    let (pivot, axis) = pivot(d, direction);
+    (point - pivot).cross(along).dot(axis)
+}
+
+/// Velocity of `point` when it rotates with `d` around its pivot.
+fn velocity_at(d: &Domino, direction: f32, point: cgmath::Point3<f32>) -> cgmath::Vector3<f32> {
+    let (pivot, axis) = pivot(d, direction);
+    axis.cross(point - pivot) * d.angular_velocity.to_radians()
+}
+
+/// Returns `false` if the dominos are not approaching each other and no impulse was applied.
+fn apply_impulse(dominos: &mut [Domino], a: usize, b: usize, contact: &Contact) -> bool {
+    let a_direction = dominos[a].fall_direction();
+    let mut b_direction = dominos[b].fall_direction();
+    if b_direction == 0.0 {
+        // a standing domino tips away from the domino hitting it
+        let b_z = dominos[b].transform().transform_vector(cgmath::Vector3::unit_z());
+        b_direction = if b_z.dot(contact.normal) < 0.0 { -1.0 } else { 1.0 };
+    }
+
+    let a_lever = lever(&dominos[a], a_direction, contact.point, contact.normal);
+    let b_lever = lever(&dominos[b], b_direction, contact.point, contact.normal);
+    let a_omega = dominos[a].angular_velocity.to_radians();
+    let b_omega = dominos[b].angular_velocity.to_radians();
+
+    let approaching_velocity = a_omega * a_lever - b_omega * b_lever;
+    if approaching_velocity <= 0.0 {
+        return false;
+    }
+
+    let a_inverse_inertia = 1.0 / (inertia(&dominos[a]) * dominos[a].mass());
+    let b_inverse_inertia = if is_lying_flat(&dominos[b], b_lever) {
+        0.0
+    } else {
+        1.0 / (inertia(&dominos[b]) * dominos[b].mass())
+    };
+    let restitution = dominos[a].material.restitution.max(dominos[b].material.restitution);
+    let impulse = (1.0 + restitution) * approaching_velocity
+        / (a_lever * a_lever * a_inverse_inertia + b_lever * b_lever * b_inverse_inertia);
+
+    dominos[a].angular_velocity = (a_omega - impulse * a_lever * a_inverse_inertia).to_degrees();
+    dominos[b].angular_velocity = (b_omega + impulse * b_lever * b_inverse_inertia).to_degrees();
+
+    // friction against the sliding of the contact point, at most enough to stop it
+    let sliding = velocity_at(&dominos[a], a_direction, contact.point) - velocity_at(&dominos[b], b_direction, contact.point);
+    let sliding = sliding - contact.normal * sliding.dot(contact.normal);
+    let friction = (dominos[a].material.friction * dominos[b].material.friction).sqrt();
+    if friction > 0.0 && sliding.magnitude2() > f32::EPSILON {
+        let tangent = sliding.normalize();
+        let a_lever = lever(&dominos[a], a_direction, contact.point, tangent);
+        let b_lever = lever(&dominos[b], b_direction, contact.point, tangent);
+        let resistance = a_lever * a_lever * a_inverse_inertia + b_lever * b_lever * b_inverse_inertia;
+        if resistance > 0.0 {
+            let friction_impulse = (friction * impulse).min(sliding.magnitude() / resistance);
+            dominos[a].angular_velocity -= (friction_impulse * a_lever * a_inverse_inertia).to_degrees();
+            dominos[b].angular_velocity += (friction_impulse * b_lever * b_inverse_inertia).to_degrees();
+        }
+    }
+    true
+}
+
+/// Exchanges the impulse of a contact from the component `c` to the domino `d` and moves the
+/// component out of the domino.
+pub(super) fn resolve_component_contact(d: &mut Domino, c: &mut dyn Component, contact: &component::Contact) {
+    let (point, normal) = (contact.point, contact.normal);
+    let mut direction = d.fall_direction();
+    if direction == 0.0 {
+        // a standing domino tips away from the component pushing it
+        let d_z = d.transform().transform_vector(cgmath::Vector3::unit_z());
+        direction = if d_z.dot(normal) < 0.0 { -1.0 } else { 1.0 };
+    }
+
+    let d_lever = lever(d, direction, point, normal);
+    let d_inverse_inertia = if is_lying_flat(d, d_lever) { 0.0 } else { 1.0 / (inertia(d) * d.mass()) };
+    let c_inverse = c.inverse_mass_at(point, normal);
+    let inverse = c_inverse + d_lever * d_lever * d_inverse_inertia;
+    if inverse <= 0.0 {
+        return;
+    }
+
+    let relative = d.angular_velocity.to_radians() * d_lever - c.velocity_at(point).dot(normal);
+    if relative < 0.0 {
+        let restitution = component::restitution_at(-relative).max(d.material.restitution);
+        let impulse = -(1.0 + restitution) * relative / inverse;
+        c.apply_impulse(point, -normal * impulse);
+        d.angular_velocity += (impulse * d_lever * d_inverse_inertia).to_degrees();
+    }
+    if c_inverse > 0.0 {
+        c.displace(point, -normal * contact.depth);
+    }
+}
+
+/// `true` if `d` lies on the ground and can not rotate any further towards `direction`. Only its
+/// sign matters, so the lever of an impulse can be passed to test whether it pushes `d` into the
+/// ground, no matter which way `d` fell.
+fn is_lying_flat(d: &Domino, direction: f32) -> bool {
+    d.fall_rotation.abs() >= 90.0 && d.fall_rotation * direction > 0.0
+}
+
+/// Rotates `d` back towards `previous_rotation` until it does not `touch` what it penetrates
+/// anymore. Returns `true` if `d` barely moved during the step, i.e. it rests on it.
+fn separate(d: &mut Domino, previous_rotation: f32, dt: f32, touches: impl Fn(&Domino) -> bool) -> bool {
+    let mut free = previous_rotation;
+    let mut blocked = d.fall_rotation;
+    d.fall_rotation = free;
+    if touches(d) {
+        // `d` was already touching before this step, nothing to separate from
+        d.fall_rotation = blocked;
+        return false;
+    }
+
+    for _ in 0..SEPARATION_STEPS {
+        let middle = 0.5 * (free + blocked);
+        d.fall_rotation = middle;
+        if touches(d) {
+            blocked = middle;
+        } else {
+            free = middle;
+        }
+    }
+    d.fall_rotation = free;
+
+    (free - previous_rotation).abs() < REST_VELOCITY * dt
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::simulator::{material::{self, Material}, Domino, Simulator, PUSH_VELOCITY};
+
+    /// Two dominos of `material` in a row along z, `gap` meters apart.
+    fn pair(material: Material, gap: f32) -> Simulator {
+        let dominos = [0.0, gap].iter().enumerate().map(|(id, z)| {
+            let mut d = Domino::new(id as u32, cgmath::point3(0.0, 0.0, *z), 0.0);
+            d.material = material;
+            d
+        }).collect();
+        Simulator { dominos, ..Simulator::empty() }
+    }
+
+    /// Pushes the first domino and returns the angular velocities of both right after the first
+    /// hit.
+    fn velocities_after_hit(material: Material) -> (f32, f32) {
+        let mut s = pair(material, 0.08);
+        s.push(0, PUSH_VELOCITY);
+        for _ in 0..1000 {
+            if !s.step_with_hits(0.001).is_empty() {
+                return (s.dominos[0].angular_velocity, s.dominos[1].angular_velocity);
+            }
+        }
+        panic!("the first domino did not hit the second one");
+    }
+
+    #[test]
+    fn pushed_domino_topples_its_neighbour() {
+        let mut s = pair(material::STANDARD, 0.08);
+        s.push(0, PUSH_VELOCITY);
+        for _ in 0..100 {
+            s.step(0.01);
+        }
+        assert!(s.dominos[0].has_fallen());
+        assert!(s.dominos[1].has_fallen());
+        assert!(s.is_at_rest());
+    }
+
+    #[test]
+    fn standing_dominos_stay_at_rest() {
+        let mut s = pair(material::STANDARD, 0.08);
+        s.step(1.0);
+        assert!(s.dominos.iter().all(|d| d.fall_rotation == 0.0));
+    }
+
+    #[test]
+    fn restitution_and_friction_change_the_hit() {
+        let (standard_from, standard_to) = velocities_after_hit(material::STANDARD);
+        // without restitution both move on together
+        assert!((standard_from - standard_to).abs() < 0.1);
+
+        // a bouncy stone keeps less of its own velocity and passes more on
+        let (bouncy_from, bouncy_to) = velocities_after_hit(Material { restitution: 0.8, ..material::STANDARD });
+        assert!(bouncy_from < standard_from * 0.5);
+        assert!(bouncy_to > standard_to * 1.5);
+
+        // friction at the top edge takes energy out of the hit
+        let (rough_from, rough_to) = velocities_after_hit(Material { friction: 1.0, ..material::STANDARD });
+        assert!(rough_from < standard_from);
+        assert!(rough_to < standard_to);
+    }
+
+    #[test]
+    fn lying_dominos_are_not_pushed_into_the_ground() {
+        // the second domino lies flat towards -z, right where the top of the first one lands
+        let mut s = pair(material::STANDARD, 0.2);
+        s.dominos[1].fall_rotation = -90.0;
+        s.push(0, PUSH_VELOCITY);
+        let mut hit = false;
+        for _ in 0..1000 {
+            hit |= !s.step_with_hits(0.001).is_empty();
+            assert_eq!(s.dominos[1].fall_rotation, -90.0);
+            assert_eq!(s.dominos[1].angular_velocity, 0.0);
+        }
+        assert!(hit);
+        assert!(s.dominos[0].has_fallen());
+    }
+
+    #[test]
+    fn invalid_steps_are_ignored() {
+        let mut s = pair(material::STANDARD, 0.08);
+        s.push(0, PUSH_VELOCITY);
+        for dt in [f32::NAN, f32::INFINITY, -0.1, 0.0] {
+            s.step(dt);
+        }
+        assert_eq!(s.dominos[0].fall_rotation, 0.0);
+        assert_eq!(s.dominos[0].angular_velocity, PUSH_VELOCITY);
+        // a huge step is split into a bounded number of substeps
+        s.step(1.0e9);
+        assert!(s.dominos.iter().all(|d| d.fall_rotation.is_finite()));
+    }
+}
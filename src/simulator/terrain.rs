@@ -0,0 +1,338 @@
+//! Static obstacles dominos stand on and collide with.
+//!
+//! The ground plane at a height of 0 is extended by [`Obstacle`]s like ramps, platforms, stairs
+//! and walls, e.g. to build elevated crossovers and circuits on several levels. Every obstacle is
+//! made of convex [`Solid`]s, unit boxes and wedges that are scaled, rotated around the vertical
+//! axis and moved into place. The solids never move, falling dominos come to rest on them.
+
+use cgmath::{EuclideanSpace, InnerSpace, SquareMatrix, Transform};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ObstacleKind {
+    /// Rises from the ground at its local -z end to its full height at the +z end.
+    Ramp,
+    Platform,
+    /// Steps of equal height and depth that rise towards the local +z axis.
+    Stairs { steps: u32 },
+    Wall,
+}
+
+impl ObstacleKind {
+    /// The kinds with their names, as offered in the editor.
+    pub const ALL: [(&'static str, ObstacleKind); 4] = [
+        ("ramp", ObstacleKind::Ramp),
+        ("platform", ObstacleKind::Platform),
+        ("stairs", ObstacleKind::Stairs { steps: 4 }),
+        ("wall", ObstacleKind::Wall),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ObstacleKind::Ramp => "ramp",
+            ObstacleKind::Platform => "platform",
+            ObstacleKind::Stairs { .. } => "stairs",
+            ObstacleKind::Wall => "wall",
+        }
+    }
+
+    /// Width, height and length in meters a new obstacle of this kind is created with.
+    pub fn default_size(&self) -> cgmath::Vector3<f32> {
+        match self {
+            ObstacleKind::Ramp => cgmath::vec3(0.3, 0.1, 1.0),
+            ObstacleKind::Platform => cgmath::vec3(0.5, 0.1, 0.5),
+            ObstacleKind::Stairs { .. } => cgmath::vec3(0.3, 0.2, 0.8),
+            ObstacleKind::Wall => cgmath::vec3(0.5, 0.3, 0.05),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Obstacle {
+    pub id: u32,
+    pub kind: ObstacleKind,
+    /// Center of the base area in meters.
+    pub position: cgmath::Point3<f32>,
+    /// Rotation around the vertical axis in degrees.
+    pub rotation_y: f32,
+    /// Width (local x), height and length (local z) in meters.
+    pub size: cgmath::Vector3<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    /// The box from (-0.5, 0, -0.5) to (0.5, 1, 0.5).
+    Box,
+    /// The part of the unit box below the plane rising from y = 0 at z = -0.5 to y = 1 at z = 0.5.
+    Wedge,
+}
+
+impl Shape {
+    /// The half-spaces `normal · p <= offset` whose intersection is the shape.
+    fn half_spaces(&self) -> &'static [([f32; 3], f32)] {
+        match self {
+            Shape::Box => &[
+                ([-1.0, 0.0, 0.0], 0.5), ([1.0, 0.0, 0.0], 0.5),
+                ([0.0, -1.0, 0.0], 0.0), ([0.0, 1.0, 0.0], 1.0),
+                ([0.0, 0.0, -1.0], 0.5), ([0.0, 0.0, 1.0], 0.5),
+            ],
+            Shape::Wedge => &[
+                ([-1.0, 0.0, 0.0], 0.5), ([1.0, 0.0, 0.0], 0.5),
+                ([0.0, -1.0, 0.0], 0.0), ([0.0, 1.0, -1.0], 0.5),
+                ([0.0, 0.0, 1.0], 0.5),
+            ],
+        }
+    }
+
+    /// Corners of the shape.
+    pub fn vertices(&self) -> Vec<cgmath::Point3<f32>> {
+        let mut vertices = vec![];
+        for x in [-0.5, 0.5] {
+            for z in [-0.5, 0.5] {
+                vertices.push(cgmath::point3(x, 0.0, z));
+                if *self == Shape::Box || z > 0.0 {
+                    vertices.push(cgmath::point3(x, 1.0, z));
+                }
+            }
+        }
+        vertices
+    }
+
+    /// Edges of the shape, the pairs of corners that lie on two common faces.
+    pub fn edges(&self) -> Vec<(cgmath::Point3<f32>, cgmath::Point3<f32>)> {
+        let faces_at = |p: cgmath::Point3<f32>| -> Vec<usize> {
+            self.half_spaces().iter().enumerate()
+                .filter(|(_, (normal, offset))| (cgmath::Vector3::from(*normal).dot(p.to_vec()) - offset).abs() < 1e-6)
+                .map(|(i, _)| i)
+                .collect()
+        };
+        let vertices = self.vertices();
+        let mut edges = vec![];
+        for (i, a) in vertices.iter().enumerate() {
+            for b in &vertices[i + 1..] {
+                let b_faces = faces_at(*b);
+                if faces_at(*a).iter().filter(|f| b_faces.contains(f)).count() >= 2 {
+                    edges.push((*a, *b));
+                }
+            }
+        }
+        edges
+    }
+
+    /// Height of the top of the shape above `(x, z)`, `None` outside of its footprint.
+    fn top_at(&self, x: f32, z: f32) -> Option<f32> {
+        if x.abs() > 0.5 || z.abs() > 0.5 {
+            return None;
+        }
+        match self {
+            Shape::Box => Some(1.0),
+            Shape::Wedge => Some(z + 0.5),
+        }
+    }
+}
+
+/// A scaled and placed [`Shape`].
+#[derive(Clone, Copy, Debug)]
+pub struct Solid {
+    pub shape: Shape,
+    /// Transformation from the space of the shape to world space.
+    pub transform: cgmath::Matrix4<f32>,
+    inverse: cgmath::Matrix4<f32>,
+}
+
+impl Solid {
+    fn new(shape: Shape, transform: cgmath::Matrix4<f32>) -> Self {
+        let inverse = transform.invert().unwrap_or(cgmath::Matrix4::identity());
+        Solid { shape, transform, inverse }
+    }
+
+    pub fn contains(&self, p: cgmath::Point3<f32>) -> bool {
+        let local = self.inverse.transform_point(p).to_vec();
+        self.shape.half_spaces().iter().all(|(normal, offset)| cgmath::Vector3::from(*normal).dot(local) <= *offset)
+    }
+
+    /// Corners of the solid in world space.
+    pub fn vertices(&self) -> impl Iterator<Item = cgmath::Point3<f32>> + '_ {
+        self.shape.vertices().into_iter().map(|v| self.transform.transform_point(v))
+    }
+
+    /// Edges of the solid in world space.
+    pub fn edges(&self) -> impl Iterator<Item = (cgmath::Point3<f32>, cgmath::Point3<f32>)> + '_ {
+        self.shape.edges().into_iter().map(|(a, b)| (self.transform.transform_point(a), self.transform.transform_point(b)))
+    }
+
+    /// Center and radius of a sphere around the solid.
+    pub fn bounding_sphere(&self) -> (cgmath::Point3<f32>, f32) {
+        let center = self.transform.transform_point(cgmath::point3(0.0, 0.5, 0.0));
+        let radius = self.vertices().map(|v| cgmath::MetricSpace::distance(v, center)).fold(0.0, f32::max);
+        (center, radius)
+    }
+
+    /// Height of the top of the solid above the point `(x, z)` on the ground plane.
+    pub fn top_at(&self, x: f32, z: f32) -> Option<f32> {
+        let local = self.inverse.transform_point(cgmath::point3(x, 0.0, z));
+        let top = self.shape.top_at(local.x, local.z)?;
+        Some(self.transform.transform_point(cgmath::point3(local.x, top, local.z)).y)
+    }
+
+    /// Distance along the ray to where it enters the solid, `None` if it misses it. The distance
+    /// is in multiples of `direction`.
+    pub fn ray_intersection(&self, origin: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>) -> Option<f32> {
+        // the parameter of the ray does not change when it is transformed into the space of the
+        // shape, so the half-spaces can clip it there
+        let origin = self.inverse.transform_point(origin).to_vec();
+        let direction = self.inverse.transform_vector(direction);
+        let (mut t_min, mut t_max) = (0.0f32, f32::MAX);
+        for (normal, offset) in self.shape.half_spaces() {
+            let normal = cgmath::Vector3::from(*normal);
+            let distance = offset - normal.dot(origin);
+            let approach = normal.dot(direction);
+            if approach.abs() < f32::EPSILON {
+                if distance < 0.0 {
+                    return None;
+                }
+            } else if approach > 0.0 {
+                t_max = t_max.min(distance / approach);
+            } else {
+                t_min = t_min.max(distance / approach);
+            }
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some(t_min)
+    }
+}
+
+impl Obstacle {
+    pub fn new(id: u32, kind: ObstacleKind, position: cgmath::Point3<f32>, rotation_y: f32) -> Self {
+        Obstacle { id, kind, position, rotation_y, size: kind.default_size() }
+    }
+
+    /// Rigid transformation from obstacle space (origin at the center of the base area) to world
+    /// space, without the size.
+    pub fn transform(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(self.position.to_vec()) * cgmath::Matrix4::from_angle_y(cgmath::Deg(self.rotation_y))
+    }
+
+    /// The solids the obstacle is made of.
+    pub fn solids(&self) -> Vec<Solid> {
+        let transform = self.transform();
+        let size = self.size;
+        match self.kind {
+            ObstacleKind::Platform | ObstacleKind::Wall => {
+                vec![Solid::new(Shape::Box, transform * cgmath::Matrix4::from_nonuniform_scale(size.x, size.y, size.z))]
+            }
+            ObstacleKind::Ramp => {
+                vec![Solid::new(Shape::Wedge, transform * cgmath::Matrix4::from_nonuniform_scale(size.x, size.y, size.z))]
+            }
+            ObstacleKind::Stairs { steps } => {
+                let steps = steps.max(1);
+                let depth = size.z / steps as f32;
+                (0..steps).map(|i| {
+                    let height = size.y * (i + 1) as f32 / steps as f32;
+                    let z = -0.5 * size.z + (i as f32 + 0.5) * depth;
+                    let step = cgmath::Matrix4::from_translation(cgmath::vec3(0.0, 0.0, z))
+                        * cgmath::Matrix4::from_nonuniform_scale(size.x, height, depth);
+                    Solid::new(Shape::Box, transform * step)
+                }).collect()
+            }
+        }
+    }
+
+    /// Describes why the obstacle is invalid, `None` if it is valid.
+    pub fn validate(&self) -> Option<String> {
+        let values = [self.position.x, self.position.y, self.position.z, self.rotation_y, self.size.x, self.size.y, self.size.z];
+        if values.iter().any(|v| !v.is_finite()) {
+            Some("a value is not a finite number".to_string())
+        } else if self.size.x <= 0.0 || self.size.y <= 0.0 || self.size.z <= 0.0 {
+            Some("the size is not positive".to_string())
+        } else if self.kind == (ObstacleKind::Stairs { steps: 0 }) {
+            Some("the stairs have no steps".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+/// Height of the highest surface of the ground plane and the `obstacles` above `(x, z)`.
+pub fn ground_height(obstacles: &[Obstacle], x: f32, z: f32) -> f32 {
+    surface_height(&obstacles.iter().flat_map(|o| o.solids()).collect::<Vec<_>>(), x, z)
+}
+
+/// Like [`ground_height`] for the solids of the obstacles.
+pub fn surface_height(solids: &[Solid], x: f32, z: f32) -> f32 {
+    solids.iter().filter_map(|s| s.top_at(x, z)).fold(0.0, f32::max)
+}
+
+/// The nearest point where the ray hits the ground plane or an obstacle.
+pub fn ray_ground_point(obstacles: &[Obstacle], origin: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>) -> Option<cgmath::Point3<f32>> {
+    let plane = if direction.y.abs() < f32::EPSILON { None } else { Some(-origin.y / direction.y).filter(|t| *t >= 0.0) };
+    obstacles.iter()
+        .flat_map(|o| o.solids())
+        .filter_map(|s| s.ray_intersection(origin, direction))
+        .chain(plane)
+        .min_by(f32::total_cmp)
+        .map(|t| origin + direction * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::{Domino, Simulator, PUSH_VELOCITY};
+
+    fn assert_height(obstacle: &Obstacle, x: f32, z: f32, expected: f32) {
+        let height = surface_height(&obstacle.solids(), x, z);
+        assert!((height - expected).abs() < 1e-5, "height at ({}, {}) is {}, not {}", x, z, height, expected);
+    }
+
+    #[test]
+    fn ramp_rises_towards_its_far_end() {
+        let ramp = Obstacle::new(0, ObstacleKind::Ramp, cgmath::point3(0.0, 0.0, 0.0), 0.0);
+        assert_height(&ramp, 0.0, -0.5, 0.0);
+        assert_height(&ramp, 0.0, 0.0, 0.05);
+        assert_height(&ramp, 0.1, 0.25, 0.075);
+        assert_height(&ramp, 0.0, 0.6, 0.0);
+        let turned = Obstacle::new(0, ObstacleKind::Ramp, cgmath::point3(1.0, 0.0, 0.0), 90.0);
+        assert_height(&turned, 1.25, 0.0, 0.075);
+        assert_height(&turned, 0.75, 0.0, 0.025);
+    }
+
+    #[test]
+    fn platform_is_flat() {
+        let platform = Obstacle::new(0, ObstacleKind::Platform, cgmath::point3(1.0, 0.2, 0.0), 0.0);
+        assert_height(&platform, 1.0, 0.0, 0.3);
+        assert_height(&platform, 1.2, -0.2, 0.3);
+        assert_height(&platform, 1.3, 0.0, 0.0);
+    }
+
+    #[test]
+    fn stairs_rise_step_by_step() {
+        let stairs = Obstacle::new(0, ObstacleKind::Stairs { steps: 4 }, cgmath::point3(0.0, 0.0, 0.0), 0.0);
+        assert_height(&stairs, 0.0, -0.35, 0.05);
+        assert_height(&stairs, 0.0, -0.15, 0.1);
+        assert_height(&stairs, 0.0, 0.05, 0.15);
+        assert_height(&stairs, 0.0, 0.35, 0.2);
+        assert_height(&stairs, 0.0, 0.45, 0.0);
+    }
+
+    #[test]
+    fn dominos_on_a_platform_stay_at_rest_and_topple_on_it() {
+        let platform = Obstacle::new(0, ObstacleKind::Platform, cgmath::point3(0.0, 0.0, 0.0), 0.0);
+        let top = platform.size.y;
+        let dominos = vec![
+            Domino::new(0, cgmath::point3(0.0, top, 0.0), 0.0),
+            Domino::new(1, cgmath::point3(0.0, top, 0.08), 0.0),
+        ];
+        let mut s = Simulator { dominos, obstacles: vec![platform], ..Simulator::empty() };
+        s.step(1.0);
+        assert!(s.is_at_rest());
+        assert!(s.dominos.iter().all(|d| d.fall_rotation == 0.0 && d.position.y == top));
+
+        s.push(0, PUSH_VELOCITY);
+        for _ in 0..100 {
+            s.step(0.01);
+        }
+        assert!(s.dominos.iter().all(|d| d.has_fallen()));
+        assert!(s.is_at_rest());
+    }
+}
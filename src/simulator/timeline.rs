@@ -0,0 +1,161 @@
+//! Recording of the fall rotations of all dominos and of the states of all components during a
+//! simulation, to replay it afterwards.
+
+use super::{Simulator, FALLEN_ROTATION};
+
+/// Number of frames a timeline holds, recording stops when it is full. An hour at 60 frames per
+/// second.
+pub const MAX_FRAMES: usize = 60 * 60 * 60;
+
+/// Resolution of the recorded fall rotations, in degrees.
+const ROTATION_STEP: f32 = 0.01;
+
+#[derive(Default)]
+pub struct Timeline {
+    /// Ids of the recorded dominos, the rotations of every frame are stored in this order.
+    ids: Vec<u32>,
+    /// Simulated time of each frame, in seconds.
+    times: Vec<f32>,
+    /// Fall rotations of all dominos of all frames in steps of [`ROTATION_STEP`].
+    rotations: Vec<i16>,
+    /// Ids of the recorded components.
+    component_ids: Vec<u32>,
+    /// The [states](super::component::Component::state) of all components of every frame, one
+    /// after another.
+    component_states: Vec<Vec<f32>>,
+}
+
+impl Timeline {
+    pub fn clear(&mut self) {
+        self.ids.clear();
+        self.times.clear();
+        self.rotations.clear();
+        self.component_ids.clear();
+        self.component_states.clear();
+    }
+
+    /// Number of recorded frames.
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= MAX_FRAMES
+    }
+
+    /// Simulated time of the frame with the given index.
+    pub fn time(&self, frame: usize) -> f32 {
+        self.times[frame]
+    }
+
+    /// Simulated time from the first to the last frame.
+    pub fn duration(&self) -> f32 {
+        match (self.times.first(), self.times.last()) {
+            (Some(first), Some(last)) => last - first,
+            _ => 0.0,
+        }
+    }
+
+    /// Index of the last frame at or before `time`.
+    pub fn frame_at(&self, time: f32) -> usize {
+        self.times.partition_point(|t| *t <= time).saturating_sub(1)
+    }
+
+    /// Adds a frame with the current fall rotations of the dominos and states of the components
+    /// of `simulator`. A timeline of other dominos or components is cleared first, e.g. after
+    /// dominos were added or deleted.
+    pub fn record(&mut self, time: f32, simulator: &Simulator) {
+        let same_dominos = self.ids.len() == simulator.dominos.len() && self.ids.iter().zip(simulator.dominos.iter()).all(|(id, d)| *id == d.id);
+        let same_components = self.component_ids.len() == simulator.components.len()
+            && self.component_ids.iter().zip(simulator.components.iter()).all(|(id, c)| *id == c.id());
+        if !same_dominos || !same_components {
+            self.clear();
+            self.ids = simulator.dominos.iter().map(|d| d.id).collect();
+            self.component_ids = simulator.components.iter().map(|c| c.id()).collect();
+        }
+        if self.is_full() {
+            return;
+        }
+        self.times.push(time);
+        self.rotations.extend(simulator.dominos.iter().map(|d| (d.fall_rotation / ROTATION_STEP).round() as i16));
+        self.component_states.push(simulator.components.iter().flat_map(|c| c.state()).collect());
+    }
+
+    fn rotation(&self, frame: usize, index: usize) -> f32 {
+        self.rotations[frame * self.ids.len() + index] as f32 * ROTATION_STEP
+    }
+
+    /// Sets the dominos and components of `simulator` to their state in the given frame. The
+    /// angular velocities of the dominos are estimated from the neighbouring frames, so that the
+    /// simulation can continue from there. Frames after the last one apply the last one, an empty
+    /// timeline leaves `simulator` as it is.
+    pub fn apply(&self, frame: usize, simulator: &mut Simulator) {
+        if self.is_empty() {
+            return;
+        }
+        let frame = frame.min(self.len() - 1);
+        let (previous, next) = (frame.saturating_sub(1), (frame + 1).min(self.len() - 1));
+        let dt = self.times[next] - self.times[previous];
+        for (index, id) in self.ids.iter().enumerate() {
+            if let Some(d) = simulator.dominos.iter_mut().find(|d| d.id == *id) {
+                d.fall_rotation = self.rotation(frame, index);
+                d.angular_velocity = if dt > 0.0 { (self.rotation(next, index) - self.rotation(previous, index)) / dt } else { 0.0 };
+            }
+        }
+
+        let mut states = self.component_states[frame].as_slice();
+        for id in self.component_ids.iter() {
+            if let Some(c) = simulator.components.iter_mut().find(|c| c.id() == *id) {
+                let (state, rest) = states.split_at(c.state().len().min(states.len()));
+                c.set_state(state);
+                states = rest;
+            }
+        }
+    }
+
+    /// Simulated time of the first frame in which the domino with the given id had fallen.
+    pub fn fall_time(&self, id: u32) -> Option<f32> {
+        let index = self.ids.iter().position(|i| *i == id)?;
+        (0..self.len()).find(|frame| self.rotation(*frame, index).abs() >= FALLEN_ROTATION).map(|frame| self.times[frame])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::Domino;
+
+    fn simulator() -> Simulator {
+        Simulator { dominos: vec![Domino::new(0, cgmath::point3(0.0, 0.0, 0.0), 0.0)], ..Simulator::empty() }
+    }
+
+    #[test]
+    fn applying_an_empty_timeline_changes_nothing() {
+        let mut s = simulator();
+        s.dominos[0].fall_rotation = 12.0;
+        let timeline = Timeline::default();
+        timeline.apply(0, &mut s);
+        timeline.apply(5, &mut s);
+        assert_eq!(s.dominos[0].fall_rotation, 12.0);
+    }
+
+    #[test]
+    fn apply_restores_rotations_and_velocities() {
+        let mut s = simulator();
+        let mut timeline = Timeline::default();
+        for (time, rotation) in [(0.0, 0.0), (0.1, 10.0), (0.2, 30.0)] {
+            s.dominos[0].fall_rotation = rotation;
+            timeline.record(time, &s);
+        }
+        timeline.apply(1, &mut s);
+        assert!((s.dominos[0].fall_rotation - 10.0).abs() < ROTATION_STEP);
+        assert!((s.dominos[0].angular_velocity - 150.0).abs() < 1.0);
+        // frames after the end apply the last one
+        timeline.apply(10, &mut s);
+        assert!((s.dominos[0].fall_rotation - 30.0).abs() < ROTATION_STEP);
+    }
+}
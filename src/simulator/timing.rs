@@ -0,0 +1,261 @@
+//! Timing analysis of circuits, the domino equivalent of static timing analysis.
+//!
+//! Every combination of the inputs is simulated like for a truth table, while the hits between
+//! the dominos tell when each domino began falling and which domino knocked it over. Following
+//! these causes back from a fallen output port gives the path the signal took from an input port,
+//! and the time between the ports of a gate on such a path is the propagation delay of the gate.
+
+use serde::Serialize;
+
+use super::{truth_table::{combinations, MAX_INPUTS}, PortDirection, Simulator};
+
+/// When a domino began falling during a simulation.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DominoTiming {
+    pub id: u32,
+    /// Simulated time of the first hit, 0 for dominos of triggered input ports, in seconds.
+    pub fall_start: f32,
+    /// The domino whose hit started the fall, `None` for dominos of triggered input ports.
+    pub hit_by: Option<u32>,
+    /// Whether the domino has fallen at the end of the simulation, a hit domino may also just
+    /// wobble.
+    pub fell: bool,
+}
+
+/// The longest propagation delay from an input port to an output port of a gate, the ports are
+/// named `<gate>.<port name>` as added by [`Simulator::add_gate`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GateDelay {
+    pub gate: String,
+    pub input: String,
+    pub output: String,
+    /// In seconds.
+    pub delay: f32,
+}
+
+/// The dominos a signal travelled along from an input port to an output port.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SignalPath {
+    pub input: String,
+    pub output: String,
+    /// Time from triggering the input until the output domino began falling, in seconds.
+    pub delay: f32,
+    /// Ids of the dominos from the input to the output domino.
+    pub dominos: Vec<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TimingReport {
+    pub inputs: Vec<String>,
+    /// The combination of triggered inputs that [`TimingReport::dominos`] were recorded for, the
+    /// one of the critical path if there is any.
+    pub triggered: Vec<bool>,
+    /// The dominos that were hit or triggered, ordered by their fall start.
+    pub dominos: Vec<DominoTiming>,
+    /// Ordered by gate and port names.
+    pub gates: Vec<GateDelay>,
+    /// The slowest path from any input to any output over all input combinations, `None` if no
+    /// output fell for any combination.
+    pub critical_path: Option<SignalPath>,
+}
+
+impl TimingReport {
+    /// Timing of the domino with the given id, `None` if it was neither hit nor triggered.
+    pub fn domino(&self, id: u32) -> Option<&DominoTiming> {
+        self.dominos.iter().find(|d| d.id == id)
+    }
+}
+
+/// Name of the gate a port belongs to, `None` for ports that are not part of a gate.
+fn gate_name(port_name: &str) -> Option<&str> {
+    port_name.rsplit_once('.').map(|(gate, _)| gate)
+}
+
+impl Simulator {
+    /// Analyses the timing of the layout by simulating every combination of its inputs with steps
+    /// of `dt` until the dominos came to rest, see [`crate::simulator::timing`].
+    pub fn timing(&self, dt: f32, max_duration: f32) -> Result<TimingReport, String> {
+        let inputs: Vec<String> = self.ports.iter().filter(|p| p.direction == PortDirection::Input).map(|p| p.name.clone()).collect();
+        let outputs: Vec<String> = self.ports.iter().filter(|p| p.direction == PortDirection::Output).map(|p| p.name.clone()).collect();
+        if inputs.is_empty() || outputs.is_empty() {
+            return Err("the layout needs at least one input and one output port".to_string());
+        }
+        if inputs.len() > MAX_INPUTS {
+            return Err(format!("the layout has {} input ports, timing analysis is limited to {}", inputs.len(), MAX_INPUTS));
+        }
+
+        let mut critical: Option<(SignalPath, Vec<bool>, Vec<DominoTiming>)> = None;
+        let mut last: Option<(Vec<bool>, Vec<DominoTiming>)> = None;
+        let mut gates: Vec<GateDelay> = vec![];
+        for combination in combinations(inputs.len()) {
+            let triggered: Vec<&String> = inputs.iter().zip(combination.iter()).filter(|(_, t)| **t).map(|(name, _)| name).collect();
+            let dominos = self.fall_timings(&triggered, dt, max_duration)?;
+
+            for output in &outputs {
+                let path = match self.signal_path(output, &dominos) {
+                    Some(p) => p,
+                    None => continue,
+                };
+                self.record_gate_delays(&path, &dominos, &mut gates);
+                if critical.as_ref().is_none_or(|(c, _, _)| path.delay > c.delay) {
+                    critical = Some((path, combination.clone(), dominos.clone()));
+                }
+            }
+            last = Some((combination, dominos));
+        }
+
+        gates.sort_by(|a, b| (&a.gate, &a.input, &a.output).cmp(&(&b.gate, &b.input, &b.output)));
+        let (critical_path, triggered, dominos) = match (critical, last) {
+            (Some((path, triggered, dominos)), _) => (Some(path), triggered, dominos),
+            (None, Some((triggered, dominos))) => (None, triggered, dominos),
+            (None, None) => unreachable!("there is at least one input combination"),
+        };
+        Ok(TimingReport { inputs, triggered, dominos, gates, critical_path })
+    }
+
+    /// Simulates a reset copy of the layout with the given inputs triggered and records when
+    /// each domino began falling.
+    fn fall_timings(&self, triggered: &[&String], dt: f32, max_duration: f32) -> Result<Vec<DominoTiming>, String> {
+        let mut simulator = self.clone();
+        simulator.reset();
+        let mut timings: Vec<DominoTiming> = vec![];
+        for name in triggered {
+            simulator.trigger(name);
+            let id = simulator.port(name).unwrap().domino_id;
+            timings.push(DominoTiming { id, fall_start: 0.0, hit_by: None, fell: false });
+        }
+
+        let steps = (max_duration / dt).ceil() as usize;
+        let mut at_rest = false;
+        for step in 1..=steps {
+            let time = step as f32 * dt;
+            for hit in simulator.step_with_hits(dt) {
+                if timings.iter().all(|t| t.id != hit.to) {
+                    timings.push(DominoTiming { id: hit.to, fall_start: time, hit_by: Some(hit.from), fell: false });
+                }
+            }
+            if simulator.is_at_rest() {
+                at_rest = true;
+                break;
+            }
+        }
+        if !at_rest {
+            let names: Vec<&str> = triggered.iter().map(|s| s.as_str()).collect();
+            return Err(format!("the dominos did not come to rest within {} s with the inputs [{}] triggered", max_duration, names.join(", ")));
+        }
+
+        for t in timings.iter_mut() {
+            t.fell = simulator.dominos.iter().any(|d| d.id == t.id && d.has_fallen());
+        }
+        Ok(timings)
+    }
+
+    /// Follows the hits back from the domino of the output port to the triggered input domino
+    /// that started its fall. `None` if the output did not fall.
+    fn signal_path(&self, output: &str, timings: &[DominoTiming]) -> Option<SignalPath> {
+        let output_id = self.port(output)?.domino_id;
+        let output_timing = timings.iter().find(|t| t.id == output_id && t.fell)?;
+
+        let mut dominos = vec![output_id];
+        let mut current = output_timing;
+        while let Some(from) = current.hit_by {
+            if dominos.contains(&from) {
+                return None;
+            }
+            dominos.push(from);
+            current = timings.iter().find(|t| t.id == from)?;
+        }
+        dominos.reverse();
+
+        let input = self.ports.iter().find(|p| p.direction == PortDirection::Input && p.domino_id == dominos[0])?;
+        Some(SignalPath { input: input.name.clone(), output: output.to_string(), delay: output_timing.fall_start, dominos })
+    }
+
+    /// Updates the delays of the gates whose input and output ports `path` passes, keeping the
+    /// longest delay per pair of ports.
+    fn record_gate_delays(&self, path: &SignalPath, timings: &[DominoTiming], gates: &mut Vec<GateDelay>) {
+        let fall_start = |id: u32| timings.iter().find(|t| t.id == id).map_or(0.0, |t| t.fall_start);
+        let mut entered: Option<(&str, f32)> = None;
+        for id in &path.dominos {
+            for port in self.ports.iter().filter(|p| p.domino_id == *id) {
+                match port.direction {
+                    PortDirection::Input => entered = Some((&port.name, fall_start(*id))),
+                    PortDirection::Output => {
+                        let (input, start) = match entered {
+                            Some(e) => e,
+                            None => continue,
+                        };
+                        let gate = match gate_name(&port.name).filter(|g| gate_name(input) == Some(g)) {
+                            Some(g) => g,
+                            None => continue,
+                        };
+                        let delay = fall_start(*id) - start;
+                        match gates.iter_mut().find(|g| g.input == input && g.output == port.name) {
+                            Some(g) => g.delay = g.delay.max(delay),
+                            None => gates.push(GateDelay { gate: gate.to_string(), input: input.to_string(), output: port.name.clone(), delay }),
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::{truth_table::{DEFAULT_DT, DEFAULT_MAX_DURATION}, Domino, Port};
+
+    /// A line of ten dominos split into two buffers in series, `first` and `second`, whose output
+    /// domino knocks over the input domino of the next one.
+    fn buffers_in_series() -> Simulator {
+        let dominos = (0..10).map(|i| Domino::new(i, cgmath::point3(0.0, 0.0, i as f32 * 0.08), 0.0)).collect();
+        let port = |name: &str, domino_id: u32, direction: PortDirection| Port { name: name.to_string(), domino_id, direction };
+        Simulator {
+            dominos,
+            ports: vec![
+                port("first.in", 0, PortDirection::Input),
+                port("first.out", 4, PortDirection::Output),
+                port("second.in", 5, PortDirection::Input),
+                port("second.out", 9, PortDirection::Output),
+            ],
+            ..Simulator::empty()
+        }
+    }
+
+    #[test]
+    fn critical_path_runs_through_both_gates() {
+        let report = buffers_in_series().timing(DEFAULT_DT, DEFAULT_MAX_DURATION).unwrap();
+        let path = report.critical_path.as_ref().unwrap();
+        assert_eq!(path.input, "first.in");
+        assert_eq!(path.output, "second.out");
+        assert_eq!(path.dominos, (0..10).collect::<Vec<u32>>());
+        assert_eq!(report.triggered, [true, false]);
+        assert_eq!(path.delay, report.domino(9).unwrap().fall_start);
+
+        // every domino was knocked over by the one before it
+        for id in 1..10 {
+            assert_eq!(report.domino(id).unwrap().hit_by, Some(id - 1));
+        }
+        assert!(report.dominos.windows(2).all(|w| w[0].fall_start <= w[1].fall_start));
+    }
+
+    #[test]
+    fn gate_delays_pair_the_ports_of_each_gate() {
+        let report = buffers_in_series().timing(DEFAULT_DT, DEFAULT_MAX_DURATION).unwrap();
+        let ports: Vec<(&str, &str, &str)> = report.gates.iter().map(|g| (g.gate.as_str(), g.input.as_str(), g.output.as_str())).collect();
+        assert_eq!(ports, [("first", "first.in", "first.out"), ("second", "second.in", "second.out")]);
+        assert!(report.gates.iter().all(|g| g.delay > 0.0));
+
+        // the delay of the first gate is the time its output began falling after the trigger
+        let critical = report.critical_path.as_ref().unwrap();
+        assert_eq!(report.gates[0].delay, report.domino(4).unwrap().fall_start);
+        assert!(report.gates[0].delay < critical.delay);
+    }
+
+    #[test]
+    fn fails_if_the_dominos_do_not_come_to_rest() {
+        let error = buffers_in_series().timing(DEFAULT_DT, 0.05).unwrap_err();
+        assert_eq!(error, "the dominos did not come to rest within 0.05 s with the inputs [second.in] triggered");
+    }
+}
@@ -0,0 +1,254 @@
+//! Verification of circuits by simulating every combination of their inputs.
+
+use std::{fmt, str::FromStr};
+
+use serde::Serialize;
+
+use super::{PortDirection, Simulator};
+
+/// Largest number of input ports a truth table is built for, it has 2^n rows.
+pub const MAX_INPUTS: usize = 16;
+
+/// Time step the combinations are simulated with, in seconds.
+pub const DEFAULT_DT: f32 = 0.001;
+/// Simulated time after which a combination is given up if the dominos did not come to rest, in
+/// seconds.
+pub const DEFAULT_MAX_DURATION: f32 = 30.0;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Row {
+    /// Whether each input port was triggered, in the order of [`TruthTable::inputs`].
+    pub inputs: Vec<bool>,
+    /// Whether the domino of each output port fell, in the order of [`TruthTable::outputs`].
+    pub outputs: Vec<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TruthTable {
+    /// Names of the input ports.
+    pub inputs: Vec<String>,
+    /// Names of the output ports.
+    pub outputs: Vec<String>,
+    /// One row per input combination, counting up with the first input as the most significant
+    /// bit.
+    pub rows: Vec<Row>,
+}
+
+/// A row of a truth table that does not match the expected one.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Mismatch {
+    pub inputs: Vec<bool>,
+    pub expected: Vec<bool>,
+    pub actual: Vec<bool>,
+}
+
+impl TruthTable {
+    /// The truth table of the boolean function `f`, e.g. the expected table of a gate.
+    pub fn from_fn(inputs: &[&str], outputs: &[&str], f: impl Fn(&[bool]) -> Vec<bool>) -> Self {
+        let rows = combinations(inputs.len()).map(|inputs| {
+            let outputs = f(&inputs);
+            Row { inputs, outputs }
+        }).collect();
+        TruthTable {
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            outputs: outputs.iter().map(|s| s.to_string()).collect(),
+            rows,
+        }
+    }
+
+    /// The rows whose outputs differ from `expected`. Columns are matched by the port names, so
+    /// they may be in a different order in both tables. Fails if the tables do not have the same
+    /// ports or `expected` misses an input combination.
+    pub fn diff(&self, expected: &TruthTable) -> Result<Vec<Mismatch>, String> {
+        let column_map = |names: &[String], expected_names: &[String], kind: &str| -> Result<Vec<usize>, String> {
+            if names.len() != expected_names.len() {
+                return Err(format!("the tables have {} and {} {} ports", names.len(), expected_names.len(), kind));
+            }
+            names.iter().map(|name| {
+                expected_names.iter().position(|n| n == name).ok_or(format!("the expected table has no {} port named \"{}\"", kind, name))
+            }).collect()
+        };
+        let input_map = column_map(&self.inputs, &expected.inputs, "input")?;
+        let output_map = column_map(&self.outputs, &expected.outputs, "output")?;
+
+        let mut mismatches = vec![];
+        for row in &self.rows {
+            let expected_row = expected.rows.iter()
+                .find(|r| input_map.iter().enumerate().all(|(i, j)| r.inputs[*j] == row.inputs[i]))
+                .ok_or(format!("the expected table has no row for the inputs {}", bits(&row.inputs)))?;
+            let expected_outputs: Vec<bool> = output_map.iter().map(|j| expected_row.outputs[*j]).collect();
+            if expected_outputs != row.outputs {
+                mismatches.push(Mismatch { inputs: row.inputs.clone(), expected: expected_outputs, actual: row.outputs.clone() });
+            }
+        }
+        Ok(mismatches)
+    }
+}
+
+impl fmt::Display for TruthTable {
+    /// Formats the table as text with one column per port and `0`/`1` values.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&String> = self.inputs.iter().chain(self.outputs.iter()).collect();
+        let header: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        writeln!(f, "{} | {}", header[..self.inputs.len()].join(" "), header[self.inputs.len()..].join(" "))?;
+        for row in &self.rows {
+            let cell = |(name, value): (&&String, &bool)| format!("{:>width$}", u8::from(*value), width = name.len());
+            let inputs: Vec<String> = names.iter().zip(row.inputs.iter()).map(cell).collect();
+            let outputs: Vec<String> = names[self.inputs.len()..].iter().zip(row.outputs.iter()).map(cell).collect();
+            writeln!(f, "{} | {}", inputs.join(" "), outputs.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TruthTable {
+    type Err = String;
+
+    /// Parses a table in the text format it is displayed in: a header with the input and the
+    /// output port names separated by `|`, followed by one row of `0`/`1` values per line.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut lines = s.lines().enumerate().map(|(i, line)| (i + 1, line.trim())).filter(|(_, line)| !line.is_empty());
+        let (header_number, header) = lines.next().ok_or("the table is empty")?;
+        let split = |number: usize, line: &str| -> Result<(Vec<String>, Vec<String>), String> {
+            let (left, right) = line.split_once('|').ok_or(format!("line {}: inputs and outputs have to be separated by |", number))?;
+            let words = |side: &str| side.split_whitespace().map(|w| w.to_string()).collect();
+            Ok((words(left), words(right)))
+        };
+        let (inputs, outputs) = split(header_number, header)?;
+
+        let mut rows = vec![];
+        for (number, line) in lines {
+            let (row_inputs, row_outputs) = split(number, line)?;
+            if row_inputs.len() != inputs.len() || row_outputs.len() != outputs.len() {
+                return Err(format!("line {}: expected {} input and {} output values", number, inputs.len(), outputs.len()));
+            }
+            let values = |words: Vec<String>| -> Result<Vec<bool>, String> {
+                words.iter().map(|w| match w.as_str() {
+                    "0" => Ok(false),
+                    "1" => Ok(true),
+                    _ => Err(format!("line {}: values have to be 0 or 1, got {}", number, w)),
+                }).collect()
+            };
+            rows.push(Row { inputs: values(row_inputs)?, outputs: values(row_outputs)? });
+        }
+        Ok(TruthTable { inputs, outputs, rows })
+    }
+}
+
+/// All combinations of `n` booleans, counting up with the first one as the most significant bit.
+pub(super) fn combinations(n: usize) -> impl Iterator<Item = Vec<bool>> {
+    (0..1usize << n).map(move |i| (0..n).map(|bit| i >> (n - 1 - bit) & 1 == 1).collect())
+}
+
+fn bits(values: &[bool]) -> String {
+    values.iter().map(|v| if *v { '1' } else { '0' }).collect()
+}
+
+impl Simulator {
+    /// Stands all dominos up and stops them, and puts the components back into their initial
+    /// state.
+    pub fn reset(&mut self) {
+        for d in self.dominos.iter_mut() {
+            d.fall_rotation = 0.0;
+            d.angular_velocity = 0.0;
+        }
+        for c in self.components.iter_mut() {
+            c.reset();
+        }
+    }
+
+    /// Simulates steps of `dt` seconds until all dominos came to rest. Returns `false` if they
+    /// were still moving after `max_duration` seconds.
+    pub fn run_until_at_rest(&mut self, dt: f32, max_duration: f32) -> bool {
+        let steps = (max_duration / dt).ceil() as usize;
+        for _ in 0..steps {
+            self.step(dt);
+            if self.is_at_rest() {
+                return true;
+            }
+        }
+        self.is_at_rest()
+    }
+
+    /// Builds the truth table of the layout from its input and output ports. For every
+    /// combination of the inputs a copy of the layout is reset, the inputs are triggered and
+    /// simulated with steps of `dt` until the dominos came to rest, and the fallen output dominos
+    /// are recorded.
+    pub fn truth_table(&self, dt: f32, max_duration: f32) -> Result<TruthTable, String> {
+        let ports_of = |direction: PortDirection| -> Vec<String> {
+            self.ports.iter().filter(|p| p.direction == direction).map(|p| p.name.clone()).collect()
+        };
+        let (inputs, outputs) = (ports_of(PortDirection::Input), ports_of(PortDirection::Output));
+        if inputs.is_empty() || outputs.is_empty() {
+            return Err("the layout needs at least one input and one output port".to_string());
+        }
+        if inputs.len() > MAX_INPUTS {
+            return Err(format!("the layout has {} input ports, truth tables are limited to {}", inputs.len(), MAX_INPUTS));
+        }
+
+        let mut rows = vec![];
+        for combination in combinations(inputs.len()) {
+            let mut simulator = self.clone();
+            simulator.reset();
+            for (name, _) in inputs.iter().zip(combination.iter()).filter(|(_, triggered)| **triggered) {
+                simulator.trigger(name);
+            }
+            if !simulator.run_until_at_rest(dt, max_duration) {
+                return Err(format!("the dominos did not come to rest within {} s for the inputs {}", max_duration, bits(&combination)));
+            }
+            let fell = |name: &String| {
+                let id = simulator.port(name).map(|p| p.domino_id);
+                simulator.dominos.iter().any(|d| Some(d.id) == id && d.has_fallen())
+            };
+            rows.push(Row { inputs: combination, outputs: outputs.iter().map(fell).collect() });
+        }
+        Ok(TruthTable { inputs, outputs, rows })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates;
+
+    fn and_gate() -> Simulator {
+        let mut simulator = Simulator::empty();
+        simulator.add_gate(&gates::and(cgmath::point3(0.0, 0.0, 0.0), 0.0), "and");
+        simulator
+    }
+
+    #[test]
+    fn and_gate_matches_its_table() {
+        let table = and_gate().truth_table(DEFAULT_DT, DEFAULT_MAX_DURATION).unwrap();
+        assert_eq!(table.inputs, ["and.a", "and.b"]);
+        assert_eq!(table.outputs, ["and.out"]);
+        let expected = TruthTable::from_fn(&["and.a", "and.b"], &["and.out"], |i| vec![i[0] && i[1]]);
+        assert_eq!(table.diff(&expected), Ok(vec![]));
+    }
+
+    #[test]
+    fn wrong_expectation_reports_the_mismatching_rows() {
+        let table = and_gate().truth_table(DEFAULT_DT, DEFAULT_MAX_DURATION).unwrap();
+        // expects an or, with the inputs in the other order
+        let expected = TruthTable::from_fn(&["and.b", "and.a"], &["and.out"], |i| vec![i[0] || i[1]]);
+        let mismatches = table.diff(&expected).unwrap();
+        assert_eq!(mismatches, [
+            Mismatch { inputs: vec![false, true], expected: vec![true], actual: vec![false] },
+            Mismatch { inputs: vec![true, false], expected: vec![true], actual: vec![false] },
+        ]);
+    }
+
+    #[test]
+    fn diff_fails_for_other_ports() {
+        let table = TruthTable::from_fn(&["a", "b"], &["out"], |i| vec![i[0] && i[1]]);
+        let expected = TruthTable::from_fn(&["a", "c"], &["out"], |i| vec![i[0] && i[1]]);
+        assert!(table.diff(&expected).unwrap_err().contains("\"b\""));
+    }
+
+    #[test]
+    fn parses_what_it_displays() {
+        let table = TruthTable::from_fn(&["a", "b"], &["out"], |i| vec![i[0] ^ i[1]]);
+        assert_eq!(table.to_string().parse::<TruthTable>(), Ok(table));
+        assert!("a b | out\n0 2 | 1".parse::<TruthTable>().unwrap_err().starts_with("line 2"));
+    }
+}
@@ -0,0 +1,281 @@
+//! Compilation of boolean functions into domino layouts.
+//!
+//! A circuit is described with one statement per line (or separated by `;`), `#` and `//` start a
+//! comment:
+//!
+//! ```text
+//! input a, b, c
+//! output sum, carry
+//! half = a ^ b
+//! sum = half ^ c
+//! carry = a & b | half & c
+//! ```
+//!
+//! The `input` and `output` declarations are optional, without them the inputs are the names the
+//! expressions use in the order they appear and the outputs are all assigned names. A description
+//! that is a single expression like `(a & b) ^ c` has the one output `out`. See
+//! [`expression`] for the syntax of the expressions.
+//!
+//! A domino line can not start a fall by itself, so negated inputs and outputs that are constant
+//! `1` take their falls from an additional input port named [`CLOCK`], which has to be triggered
+//! together with the other inputs. Its dominos stay standing while the clock is not triggered.
+//!
+//! A circuit can also be given as a complete [`TruthTable`] in its text format, it is compiled
+//! into the smallest of its algebraic normal form (an exclusive or of and terms), its disjunctive
+//! normal form and the negated disjunctive normal form of its zeros.
+
+pub mod expression;
+mod placement;
+
+use crate::simulator::{truth_table::{TruthTable, MAX_INPUTS}, Simulator};
+
+use self::expression::Expr;
+
+/// Name of the input port that feeds negations and constant outputs.
+pub const CLOCK: &str = "clock";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Circuit {
+    /// Names of the input ports, without the clock.
+    pub inputs: Vec<String>,
+    /// Names of the output ports with their expressions over the inputs.
+    pub outputs: Vec<(String, Expr)>,
+}
+
+impl Circuit {
+    /// Parses a description of the circuit, either statements as described in the module
+    /// documentation or a truth table.
+    pub fn parse(description: &str) -> Result<Circuit, String> {
+        if is_truth_table(description) {
+            return Circuit::from_truth_table(&description.parse()?);
+        }
+
+        let mut declared_inputs: Option<Vec<String>> = None;
+        let mut declared_outputs: Option<Vec<String>> = None;
+        let mut assignments: Vec<(String, Expr)> = vec![];
+        let mut inputs: Vec<String> = vec![];
+        let mut bare: Option<Expr> = None;
+        let statements = description.lines().enumerate().flat_map(|(i, line)| {
+            let line = line.split('#').next().unwrap().split("//").next().unwrap();
+            line.split(';').map(move |s| (i + 1, s.trim()))
+        });
+        for (number, statement) in statements.filter(|(_, s)| !s.is_empty()) {
+            let error = |message: String| format!("line {}: {}", number, message);
+            let declaration = ["input", "output"].into_iter().find_map(|keyword| {
+                statement.strip_prefix(keyword).filter(|rest| rest.starts_with(char::is_whitespace)).map(|rest| (keyword, rest))
+            });
+            if let Some((keyword, rest)) = declaration {
+                let names: Vec<String> = rest.split(',').map(|n| n.trim().to_string()).collect();
+                for name in &names {
+                    check_name(name).map_err(error)?;
+                }
+                let declared = if keyword == "input" { &mut declared_inputs } else { &mut declared_outputs };
+                if declared.is_some() {
+                    return Err(error(format!("the {}s are declared twice", keyword)));
+                }
+                *declared = Some(names);
+                continue;
+            }
+
+            let (name, expr) = match statement.split_once('=') {
+                Some((name, expr)) => (Some(name.trim()), expr),
+                None => (None, statement),
+            };
+            let expr = Expr::parse(expr).map_err(error)?;
+            // intermediate results are substituted, so the outputs only depend on inputs
+            let expr = expr.substitute(&|n| assignments.iter().find(|(a, _)| a == n).map(|(_, e)| e.clone()));
+            let mut used = vec![];
+            expr.collect_inputs(&mut used);
+            for input in used {
+                check_name(&input).map_err(error)?;
+                if declared_inputs.as_ref().is_some_and(|d| !d.contains(&input)) {
+                    return Err(error(format!("{} is neither an input nor assigned before", input)));
+                }
+                if !inputs.contains(&input) {
+                    inputs.push(input);
+                }
+            }
+            match name {
+                Some(name) => {
+                    check_name(name).map_err(error)?;
+                    if declared_inputs.as_ref().is_some_and(|d| d.iter().any(|i| i == name)) {
+                        return Err(error(format!("{} is an input and can not be assigned", name)));
+                    }
+                    if inputs.iter().any(|i| i == name) {
+                        return Err(error(format!("{} is used before it is assigned", name)));
+                    }
+                    if assignments.iter().any(|(a, _)| a == name) {
+                        return Err(error(format!("{} is assigned twice", name)));
+                    }
+                    assignments.push((name.to_string(), expr));
+                }
+                None if bare.is_none() && assignments.is_empty() => bare = Some(expr),
+                None => return Err(error("expected an assignment like name = expression".to_string())),
+            }
+        }
+
+        if let Some(expr) = bare {
+            if !assignments.is_empty() || declared_outputs.is_some() {
+                return Err("a single expression can not be combined with assignments or outputs".to_string());
+            }
+            assignments.push(("out".to_string(), expr));
+        }
+        if assignments.is_empty() {
+            return Err("the description does not assign any output".to_string());
+        }
+        let outputs = match declared_outputs {
+            Some(names) => names.into_iter().map(|name| {
+                match assignments.iter().find(|(a, _)| *a == name) {
+                    Some((_, expr)) => Ok((name, expr.clone())),
+                    None => Err(format!("the output {} is not assigned", name)),
+                }
+            }).collect::<Result<Vec<_>, String>>()?,
+            None => assignments,
+        };
+        let inputs = declared_inputs.unwrap_or(inputs);
+        if inputs.len() > MAX_INPUTS {
+            return Err(format!("the circuit has {} inputs, at most {} are supported", inputs.len(), MAX_INPUTS));
+        }
+        Ok(Circuit { inputs, outputs })
+    }
+
+    /// The circuit computing the outputs of `table`, which needs a row for every combination of
+    /// its inputs.
+    pub fn from_truth_table(table: &TruthTable) -> Result<Circuit, String> {
+        let n = table.inputs.len();
+        for name in table.inputs.iter().chain(table.outputs.iter()) {
+            check_name(name)?;
+        }
+        if n > MAX_INPUTS {
+            return Err(format!("the table has {} inputs, at most {} are supported", n, MAX_INPUTS));
+        }
+        if table.outputs.is_empty() {
+            return Err("the table has no outputs".to_string());
+        }
+
+        // the rows indexed by their inputs with the first input as the most significant bit
+        let mut rows: Vec<Option<&Vec<bool>>> = vec![None; 1 << n];
+        for row in &table.rows {
+            let index = row.inputs.iter().fold(0, |index, bit| index << 1 | usize::from(*bit));
+            rows[index] = Some(&row.outputs);
+        }
+        let rows = rows.into_iter().enumerate().map(|(index, row)| {
+            row.ok_or(format!("the table has no row for the inputs {:0width$b}", index, width = n))
+        }).collect::<Result<Vec<_>, String>>()?;
+
+        // the and of the inputs set in `index`, for a minterm with the others negated
+        let term = |index: usize, minterm: bool| -> Expr {
+            balanced((0..n).filter_map(|k| {
+                let input = Expr::Input(table.inputs[k].clone());
+                match index >> (n - 1 - k) & 1 == 1 {
+                    true => Some(input),
+                    false if minterm => Some(Expr::Not(Box::new(input))),
+                    false => None,
+                }
+            }).collect(), Expr::And, true)
+        };
+
+        let outputs = table.outputs.iter().enumerate().map(|(j, name)| {
+            let values: Vec<bool> = rows.iter().map(|row| row[j]).collect();
+            // algebraic normal form by the Möbius transform of the values
+            let mut coefficients = values.clone();
+            for bit in 0..n {
+                for index in 0..1 << n {
+                    if index & 1 << bit != 0 {
+                        coefficients[index] ^= coefficients[index ^ 1 << bit];
+                    }
+                }
+            }
+            let anf = balanced((0..1 << n).filter(|i| coefficients[*i]).map(|i| term(i, false)).collect(), Expr::Xor, false);
+            let minterms = |value: bool| balanced((0..1 << n).filter(|i| values[*i] == value).map(|i| term(i, true)).collect(), Expr::Or, false);
+            let sop = minterms(true);
+            let negated_sop = Expr::Not(Box::new(minterms(false)));
+            let expr = [anf, sop, negated_sop].into_iter().map(|e| e.normalize()).min_by_key(|e| e.size()).unwrap();
+            (name.clone(), expr)
+        }).collect();
+        Ok(Circuit { inputs: table.inputs.clone(), outputs })
+    }
+
+    /// `true` if the layout needs the [`CLOCK`] input.
+    pub fn needs_clock(&self) -> bool {
+        self.outputs.iter().any(|(_, e)| uses_clock(&e.normalize()))
+    }
+
+    /// The truth table the layout of the circuit is expected to have. If it needs the clock, it
+    /// is the last input, and without it negated inputs and constant `1` outputs do not fall.
+    pub fn truth_table(&self) -> TruthTable {
+        let mut inputs: Vec<&str> = self.inputs.iter().map(|s| s.as_str()).collect();
+        let clock = self.needs_clock();
+        if clock {
+            inputs.push(CLOCK);
+        }
+        let outputs: Vec<&str> = self.outputs.iter().map(|(name, _)| name.as_str()).collect();
+        let exprs: Vec<Expr> = self.outputs.iter().map(|(_, e)| clocked(&e.normalize())).collect();
+        TruthTable::from_fn(&inputs, &outputs, |values| {
+            let value = |name: &str| inputs.iter().position(|i| *i == name).is_some_and(|i| values[i]);
+            exprs.iter().map(|e| e.evaluate(&value)).collect()
+        })
+    }
+
+    /// Builds the domino layout of the circuit, with input ports at `z = 0` and output ports
+    /// at the far end.
+    pub fn layout(&self) -> Simulator {
+        let outputs: Vec<(String, Expr)> = self.outputs.iter().map(|(name, e)| (name.clone(), e.normalize())).collect();
+        let clock = if self.needs_clock() { Some(CLOCK) } else { None };
+        placement::place(&self.inputs, &outputs, clock)
+    }
+}
+
+/// `true` if `description` looks like a truth table: a header followed by rows of `0`, `1` and
+/// `|` only.
+fn is_truth_table(description: &str) -> bool {
+    let lines: Vec<&str> = description.lines().map(|l| l.trim()).filter(|l| !l.is_empty()).collect();
+    lines.len() >= 2 && lines[1..].iter().all(|l| l.chars().all(|c| c == '0' || c == '1' || c == '|' || c.is_whitespace()))
+}
+
+fn check_name(name: &str) -> Result<(), String> {
+    if !expression::is_name(name) {
+        Err(format!("\"{}\" is not a valid name", name))
+    } else if name == CLOCK {
+        Err(format!("{} is reserved for the clock input", CLOCK))
+    } else {
+        Ok(())
+    }
+}
+
+/// Combines `items` with `operator` as a balanced tree, so that the paths from the leaves to the
+/// root and with it the layout stay short. `empty` is the value of no items.
+fn balanced(mut items: Vec<Expr>, operator: fn(Box<Expr>, Box<Expr>) -> Expr, empty: bool) -> Expr {
+    match items.len() {
+        0 => Expr::Constant(empty),
+        1 => items.pop().unwrap(),
+        n => {
+            let right = items.split_off(n / 2);
+            operator(Box::new(balanced(items, operator, empty)), Box::new(balanced(right, operator, empty)))
+        }
+    }
+}
+
+/// `true` if the normalized expression `expr` has a negation or is constant `1`.
+fn uses_clock(expr: &Expr) -> bool {
+    match expr {
+        Expr::Constant(value) => *value,
+        Expr::Input(_) => false,
+        Expr::Not(_) => true,
+        Expr::And(a, b) | Expr::Or(a, b) | Expr::Xor(a, b) => uses_clock(a) || uses_clock(b),
+    }
+}
+
+/// The normalized expression `expr` with the negations and the constant `1` taken from the
+/// clock, like in the layout.
+fn clocked(expr: &Expr) -> Expr {
+    let clock = || Box::new(Expr::Input(CLOCK.to_string()));
+    match expr {
+        Expr::Constant(true) => *clock(),
+        Expr::Constant(false) | Expr::Input(_) => expr.clone(),
+        Expr::Not(_) => Expr::And(clock(), Box::new(expr.clone())),
+        Expr::And(a, b) => Expr::And(Box::new(clocked(a)), Box::new(clocked(b))),
+        Expr::Or(a, b) => Expr::Or(Box::new(clocked(a)), Box::new(clocked(b))),
+        Expr::Xor(a, b) => Expr::Xor(Box::new(clocked(a)), Box::new(clocked(b))),
+    }
+}
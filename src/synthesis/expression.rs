@@ -0,0 +1,361 @@
+//! Boolean expressions over the input ports of a circuit.
+//!
+//! Expressions are written with `&` (and), `^` (exclusive or), `|` (or), `!` or `~` (not),
+//! parentheses and the constants `0` and `1`. `!` binds strongest, followed by `&`, `^` and `|`,
+//! so `a & b ^ c | d` is `((a & b) ^ c) | d`.
+
+use std::fmt;
+
+/// Deepest nesting of negations and parentheses the parser accepts, so that it can not overflow
+/// the stack.
+pub const MAX_DEPTH: usize = 256;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    Constant(bool),
+    Input(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Xor(Box<Expr>, Box<Expr>),
+}
+
+/// `true` for the characters a name of an input may consist of, the first one also has to be
+/// a letter or `_`.
+pub fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+/// `true` if `name` can be used as the name of an input or output.
+pub fn is_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && name.chars().all(is_name_char)
+}
+
+impl Expr {
+    pub fn parse(s: &str) -> Result<Expr, String> {
+        let mut parser = Parser { chars: s.chars().collect(), position: 0, depth: 0 };
+        let expr = parser.or()?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => Ok(expr),
+            Some(c) => Err(parser.error(&format!("unexpected {}", c))),
+        }
+    }
+
+    /// The value of the expression with the inputs set by `input`.
+    pub fn evaluate(&self, input: &impl Fn(&str) -> bool) -> bool {
+        match self {
+            Expr::Constant(value) => *value,
+            Expr::Input(name) => input(name),
+            Expr::Not(e) => !e.evaluate(input),
+            Expr::And(a, b) => a.evaluate(input) && b.evaluate(input),
+            Expr::Or(a, b) => a.evaluate(input) || b.evaluate(input),
+            Expr::Xor(a, b) => a.evaluate(input) != b.evaluate(input),
+        }
+    }
+
+    /// Appends the names of the inputs the expression uses to `names`, in the order they first
+    /// appear and without duplicates.
+    pub fn collect_inputs(&self, names: &mut Vec<String>) {
+        match self {
+            Expr::Constant(_) => {}
+            Expr::Input(name) => {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+            Expr::Not(e) => e.collect_inputs(names),
+            Expr::And(a, b) | Expr::Or(a, b) | Expr::Xor(a, b) => {
+                a.collect_inputs(names);
+                b.collect_inputs(names);
+            }
+        }
+    }
+
+    /// Replaces the inputs for which `f` returns an expression.
+    pub fn substitute(&self, f: &impl Fn(&str) -> Option<Expr>) -> Expr {
+        match self {
+            Expr::Constant(value) => Expr::Constant(*value),
+            Expr::Input(name) => f(name).unwrap_or_else(|| self.clone()),
+            Expr::Not(e) => Expr::Not(Box::new(e.substitute(f))),
+            Expr::And(a, b) => Expr::And(Box::new(a.substitute(f)), Box::new(b.substitute(f))),
+            Expr::Or(a, b) => Expr::Or(Box::new(a.substitute(f)), Box::new(b.substitute(f))),
+            Expr::Xor(a, b) => Expr::Xor(Box::new(a.substitute(f)), Box::new(b.substitute(f))),
+        }
+    }
+
+    /// The equivalent expression without constants, unless it is a constant itself, in which
+    /// only inputs are negated. Negations are moved towards the inputs with De Morgan's laws.
+    pub fn normalize(&self) -> Expr {
+        self.normalize_negated(false)
+    }
+
+    fn normalize_negated(&self, negated: bool) -> Expr {
+        match self {
+            Expr::Constant(value) => Expr::Constant(*value != negated),
+            Expr::Input(_) if negated => Expr::Not(Box::new(self.clone())),
+            Expr::Input(_) => self.clone(),
+            Expr::Not(e) => e.normalize_negated(!negated),
+            // !(a & b) = !a | !b
+            Expr::And(a, b) if negated => or(a.normalize_negated(true), b.normalize_negated(true)),
+            Expr::And(a, b) => and(a.normalize(), b.normalize()),
+            Expr::Or(a, b) if negated => and(a.normalize_negated(true), b.normalize_negated(true)),
+            Expr::Or(a, b) => or(a.normalize(), b.normalize()),
+            Expr::Xor(a, b) => xor(a.normalize_negated(negated), b.normalize()),
+        }
+    }
+
+    /// Number of inputs in the expression, counting every use.
+    pub fn size(&self) -> usize {
+        match self {
+            Expr::Constant(_) | Expr::Input(_) => 1,
+            Expr::Not(e) => e.size(),
+            Expr::And(a, b) | Expr::Or(a, b) | Expr::Xor(a, b) => a.size() + b.size(),
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Or(..) => 0,
+            Expr::Xor(..) => 1,
+            Expr::And(..) => 2,
+            Expr::Not(_) => 3,
+            Expr::Constant(_) | Expr::Input(_) => 4,
+        }
+    }
+}
+
+/// `a & b` of normalized expressions, with the constants folded.
+fn and(a: Expr, b: Expr) -> Expr {
+    match (a, b) {
+        (Expr::Constant(false), _) | (_, Expr::Constant(false)) => Expr::Constant(false),
+        (Expr::Constant(true), e) | (e, Expr::Constant(true)) => e,
+        (a, b) => Expr::And(Box::new(a), Box::new(b)),
+    }
+}
+
+/// `a | b` of normalized expressions, with the constants folded.
+fn or(a: Expr, b: Expr) -> Expr {
+    match (a, b) {
+        (Expr::Constant(true), _) | (_, Expr::Constant(true)) => Expr::Constant(true),
+        (Expr::Constant(false), e) | (e, Expr::Constant(false)) => e,
+        (a, b) => Expr::Or(Box::new(a), Box::new(b)),
+    }
+}
+
+/// `a ^ b` of normalized expressions, with the constants folded.
+fn xor(a: Expr, b: Expr) -> Expr {
+    match (a, b) {
+        (Expr::Constant(false), e) | (e, Expr::Constant(false)) => e,
+        (Expr::Constant(true), e) | (e, Expr::Constant(true)) => e.normalize_negated(true),
+        (a, b) => Expr::Xor(Box::new(a), Box::new(b)),
+    }
+}
+
+impl fmt::Display for Expr {
+    /// Formats the expression with the fewest parentheses needed to parse it back into the same
+    /// expression.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operand = |e: &Expr, parenthesize: bool, f: &mut fmt::Formatter<'_>| {
+            if parenthesize { write!(f, "({})", e) } else { write!(f, "{}", e) }
+        };
+        // the operators are left associative, a right operand of the same precedence keeps its
+        // parentheses
+        let binary = |a: &Expr, operator: &str, b: &Expr, f: &mut fmt::Formatter<'_>| {
+            operand(a, a.precedence() < self.precedence(), f)?;
+            write!(f, " {} ", operator)?;
+            operand(b, b.precedence() <= self.precedence(), f)
+        };
+        match self {
+            Expr::Constant(value) => write!(f, "{}", u8::from(*value)),
+            Expr::Input(name) => write!(f, "{}", name),
+            Expr::Not(e) => {
+                write!(f, "!")?;
+                operand(e, e.precedence() < self.precedence(), f)
+            }
+            Expr::And(a, b) => binary(a, "&", b, f),
+            Expr::Or(a, b) => binary(a, "|", b, f),
+            Expr::Xor(a, b) => binary(a, "^", b, f),
+        }
+    }
+}
+
+/// Recursive descent parser with one function per precedence level.
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    /// Number of negations and parentheses around the current position.
+    depth: usize,
+}
+
+impl Parser {
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.position).is_some_and(|c| c.is_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    /// Consumes `c` if it is the next character after whitespace.
+    fn accept(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("{} at column {}", message, self.position + 1)
+    }
+
+    /// Parses a nested expression with `f`. Fails if it is nested deeper than [`MAX_DEPTH`].
+    fn nested(&mut self, f: fn(&mut Self) -> Result<Expr, String>) -> Result<Expr, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(&format!("expressions can be nested at most {} levels deep", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        let expr = f(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.xor()?;
+        while self.accept('|') {
+            expr = Expr::Or(Box::new(expr), Box::new(self.xor()?));
+        }
+        Ok(expr)
+    }
+
+    fn xor(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.accept('^') {
+            expr = Expr::Xor(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.accept('&') {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.accept('!') || self.accept('~') {
+            return Ok(Expr::Not(Box::new(self.nested(Self::unary)?)));
+        }
+        if self.accept('(') {
+            let expr = self.nested(Self::or)?;
+            if !self.accept(')') {
+                return Err(self.error("expected )"));
+            }
+            return Ok(expr);
+        }
+
+        self.skip_whitespace();
+        let start = self.position;
+        while self.peek().is_some_and(is_name_char) {
+            self.position += 1;
+        }
+        let word: String = self.chars[start..self.position].iter().collect();
+        match word.as_str() {
+            "" => match self.peek() {
+                Some(c) => Err(self.error(&format!("unexpected {}", c))),
+                None => Err(self.error("expected an input")),
+            },
+            "0" => Ok(Expr::Constant(false)),
+            "1" => Ok(Expr::Constant(true)),
+            _ if is_name(&word) => Ok(Expr::Input(word)),
+            _ => {
+                self.position = start;
+                Err(self.error(&format!("{} is not a valid input name", word)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(name: &str) -> Box<Expr> {
+        Box::new(Expr::Input(name.to_string()))
+    }
+
+    fn parse(s: &str) -> Expr {
+        Expr::parse(s).unwrap()
+    }
+
+    #[test]
+    fn operators_bind_by_precedence() {
+        let expected = Expr::Or(
+            Box::new(Expr::Xor(Box::new(Expr::And(input("a"), input("b"))), input("c"))),
+            input("d"),
+        );
+        assert_eq!(parse("a & b ^ c | d"), expected);
+        assert_eq!(parse("((a & b) ^ c) | d"), expected);
+        assert_eq!(parse("!a & ~b"), Expr::And(Box::new(Expr::Not(input("a"))), Box::new(Expr::Not(input("b")))));
+        assert_eq!(parse("a | b | c"), Expr::Or(Box::new(Expr::Or(input("a"), input("b"))), input("c")));
+    }
+
+    #[test]
+    fn errors_name_the_column() {
+        assert_eq!(Expr::parse("a & ").unwrap_err(), "expected an input at column 5");
+        assert_eq!(Expr::parse("a b").unwrap_err(), "unexpected b at column 3");
+        assert_eq!(Expr::parse("(a | b").unwrap_err(), "expected ) at column 7");
+        assert_eq!(Expr::parse("a & 2x").unwrap_err(), "2x is not a valid input name at column 5");
+        assert_eq!(Expr::parse("a & )").unwrap_err(), "unexpected ) at column 5");
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        assert!(Expr::parse(&format!("{}a", "!".repeat(MAX_DEPTH))).is_ok());
+        let negations = format!("{}a", "!".repeat(MAX_DEPTH + 1));
+        assert!(Expr::parse(&negations).unwrap_err().starts_with("expressions can be nested at most"));
+        let parentheses = format!("{}a{}", "(".repeat(100_000), ")".repeat(100_000));
+        assert!(Expr::parse(&parentheses).unwrap_err().starts_with("expressions can be nested at most"));
+    }
+
+    #[test]
+    fn normalize_moves_negations_to_the_inputs() {
+        assert_eq!(parse("!(a & b)").normalize(), parse("!a | !b"));
+        assert_eq!(parse("!(a | !b)").normalize(), parse("!a & b"));
+        assert_eq!(parse("!(a ^ b)").normalize(), parse("!a ^ b"));
+        assert_eq!(parse("!!a").normalize(), parse("a"));
+    }
+
+    #[test]
+    fn normalize_folds_constants() {
+        assert_eq!(parse("a & 1").normalize(), parse("a"));
+        assert_eq!(parse("a & 0 | b").normalize(), parse("b"));
+        assert_eq!(parse("!(a | 1)").normalize(), Expr::Constant(false));
+        assert_eq!(parse("1 ^ a").normalize(), parse("!a"));
+        assert_eq!(parse("!(0 ^ (a & b))").normalize(), parse("!a | !b"));
+        assert_eq!(parse("a ^ 0 ^ 1").normalize(), parse("!a"));
+    }
+
+    #[test]
+    fn display_parses_back_with_minimal_parentheses() {
+        for (s, displayed) in [
+            ("a & b ^ c | d", "a & b ^ c | d"),
+            ("((a & b) ^ c) | d", "a & b ^ c | d"),
+            ("a & (b | c)", "a & (b | c)"),
+            ("a | (b | c)", "a | (b | c)"),
+            ("!(a & b) ^ !!c", "!(a & b) ^ !!c"),
+            ("(a ^ 1) & 0", "(a ^ 1) & 0"),
+        ] {
+            let expr = parse(s);
+            assert_eq!(expr.to_string(), displayed);
+            assert_eq!(parse(&expr.to_string()), expr);
+        }
+    }
+}
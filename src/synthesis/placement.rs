@@ -0,0 +1,674 @@
+//! Placement of the gates of a circuit and routing of the lines between them.
+//!
+//! The layout is built in stages from the input ports at `z = 0` towards positive `z`, every
+//! stage places a row of gates next to each other and passes the lines that do not enter a gate
+//! straight through. Lines can not cross each other on the ground plane, so before the gates of
+//! a level of the circuit are placed, the lines of their inputs are split with `fan_out` gates
+//! and brought next to each other with `crossover` gates.
+//!
+//! Between two stages the lines move sideways to the inputs of the next gates. Gates built from
+//! junctions need their inputs to arrive at about the same time, so the line whose fall arrives
+//! earlier at a gate is lengthened with loops. For this the length of the path a fall travels
+//! from the input ports is tracked for every line. The actual speed of a fall varies a little
+//! with the shape of a line, so very long layouts can still miss the windows of their gates.
+
+use crate::{gates::{self, Gate}, knock::{self, KnockGraph}, path, simulator::{light, Domino, Port, PortDirection, Simulator}};
+
+use super::expression::Expr;
+
+const SPACING: f32 = path::DEFAULT_SPACING;
+const CORNER_RADIUS: f32 = path::DEFAULT_CORNER_RADIUS;
+/// Distance between the input ports.
+const INPUT_DISTANCE: f32 = 1.0;
+/// Free space between neighbouring gates and lines of a stage.
+const ELEMENT_DISTANCE: f32 = 0.6;
+/// Smallest distance between a loop of a line and the line next to it.
+const LOOP_CLEARANCE: f32 = 0.4;
+/// Smallest distance between the two lines leading to a gate while the earlier one is
+/// lengthened, so that there is room for its loops.
+const LOOP_WIDTH: f32 = 2.0;
+/// Height of a loop that lengthens a line.
+const LOOP_HEIGHT: f32 = 1.0;
+/// Differences of the arrival of two inputs of a gate that are not compensated, as length of
+/// the path a fall travels in meters.
+const ARRIVAL_TOLERANCE: f32 = 0.05;
+/// Length of the lines leading to the output ports after the last stage.
+const OUTPUT_LENGTH: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    And,
+    Or,
+    Xor,
+    Not,
+    FanOut,
+    Crossover,
+}
+
+impl Kind {
+    const ALL: [Kind; 6] = [Kind::And, Kind::Or, Kind::Xor, Kind::Not, Kind::FanOut, Kind::Crossover];
+
+    /// The gate and the names of its input and output ports from left to right.
+    fn gate(self) -> (gates::GateFn, &'static [&'static str], &'static [&'static str]) {
+        match self {
+            Kind::And => (gates::and, &["a", "b"], &["out"]),
+            Kind::Or => (gates::or, &["a", "b"], &["out"]),
+            Kind::Xor => (gates::xor, &["a", "b"], &["out"]),
+            Kind::Not => (gates::not, &["clock", "in"], &["out"]),
+            Kind::FanOut => (gates::fan_out, &["in"], &["out_left", "out_right"]),
+            // the output on the left carries the input on the right and the other way round
+            Kind::Crossover => (gates::crossover, &["a", "b"], &["out_b", "out_a"]),
+        }
+    }
+
+    /// Length of the path by which the fall on the left input should arrive after the one on
+    /// the right. The gates built from junctions only work if their inputs arrive within a
+    /// window, which is not centered for all of them, these are the middles of the windows
+    /// found by simulating the gates.
+    fn skew(self) -> f32 {
+        match self {
+            Kind::And => 1.1,
+            // the clock may arrive arbitrarily late, but not much earlier than the input
+            Kind::Not => 1.0,
+            Kind::Or | Kind::Xor | Kind::FanOut | Kind::Crossover => 0.0,
+        }
+    }
+}
+
+/// A gate at the origin with the positions of its ports.
+struct Template {
+    gate: Gate,
+    /// Positions of the input dominos from left to right.
+    inputs: Vec<cgmath::Point2<f32>>,
+    /// Positions of the output dominos from left to right.
+    outputs: Vec<cgmath::Point2<f32>>,
+    /// Smallest and largest x of the dominos and the approach of the lines.
+    x_range: (f32, f32),
+    /// Longest path a fall travels from an input to each output domino.
+    delays: Vec<f32>,
+    /// x of the lines leading to the inputs while the earlier one is lengthened, at least
+    /// [`LOOP_WIDTH`] apart if there are two.
+    approach: Vec<f32>,
+}
+
+impl Template {
+    fn new(kind: Kind) -> Self {
+        let (gate_fn, input_names, output_names) = kind.gate();
+        let gate = gate_fn(cgmath::point3(0.0, 0.0, 0.0), 0.0);
+        let index = |name: &&str| {
+            let id = gate.port(name).unwrap().domino_id;
+            gate.dominos.iter().position(|d| d.id == id).unwrap()
+        };
+        let inputs: Vec<usize> = input_names.iter().map(index).collect();
+        let outputs: Vec<usize> = output_names.iter().map(index).collect();
+
+        let simulator = Simulator { dominos: gate.dominos.clone(), ports: gate.ports.clone(), ..Simulator::empty() };
+        let mut delays = vec![0.0; outputs.len()];
+        for input in &inputs {
+            let lengths = travel_lengths(&simulator, *input);
+            for (delay, output) in delays.iter_mut().zip(outputs.iter()) {
+                if lengths[*output].is_finite() {
+                    *delay = lengths[*output].max(*delay);
+                }
+            }
+        }
+
+        let position = |i: &usize| cgmath::point2(gate.dominos[*i].position.x, gate.dominos[*i].position.z);
+        let inputs: Vec<cgmath::Point2<f32>> = inputs.iter().map(position).collect();
+        let approach = match inputs.as_slice() {
+            [a, b] if b.x - a.x < LOOP_WIDTH => {
+                let center = 0.5 * (a.x + b.x);
+                vec![center - 0.5 * LOOP_WIDTH, center + 0.5 * LOOP_WIDTH]
+            }
+            _ => inputs.iter().map(|p| p.x).collect(),
+        };
+        let x_range = gate.dominos.iter().map(|d| d.position.x).chain(approach.iter().copied())
+            .fold((f32::MAX, f32::MIN), |(min, max), x| (min.min(x), max.max(x)));
+        Template {
+            outputs: outputs.iter().map(position).collect(),
+            inputs,
+            gate,
+            x_range,
+            delays,
+            approach,
+        }
+    }
+
+    /// Highest z of the output dominos.
+    fn height(&self) -> f32 {
+        self.outputs.iter().map(|p| p.y).fold(0.0, f32::max)
+    }
+}
+
+/// Length of the shortest path a fall travels from the domino with index `start` to each
+/// domino, following the [`KnockGraph`] of the layout. Infinite for dominos it does not reach.
+fn travel_lengths(simulator: &Simulator, start: usize) -> Vec<f32> {
+    let dominos = &simulator.dominos;
+    let graph = KnockGraph::new(simulator);
+    let distance = |a: usize, b: usize| {
+        let (p, q) = (dominos[a].position, dominos[b].position);
+        ((p.x - q.x).powi(2) + (p.z - q.z).powi(2)).sqrt()
+    };
+
+    // a domino can be reached falling to either side, which decides which dominos it knocks
+    let sides = [1.0, -1.0];
+    let mut lengths = vec![[f32::INFINITY; 2]; dominos.len()];
+    let mut done = vec![[false; 2]; dominos.len()];
+    lengths[start][0] = 0.0;
+    loop {
+        let next = (0..dominos.len()).flat_map(|a| [(a, 0), (a, 1)])
+            .filter(|(a, s)| !done[*a][*s] && lengths[*a][*s].is_finite())
+            .min_by(|(a, s), (b, t)| lengths[*a][*s].total_cmp(&lengths[*b][*t]));
+        let (a, s) = match next {
+            Some(next) => next,
+            None => break,
+        };
+        done[a][s] = true;
+        let direction = knock::forward(&dominos[a], sides[s]);
+        for b in graph.knocks(a, sides[s]) {
+            let t = if knock::side_away_from(&dominos[*b], direction) > 0.0 { 0 } else { 1 };
+            lengths[*b][t] = lengths[*b][t].min(lengths[a][s] + distance(a, *b));
+        }
+    }
+    lengths.iter().map(|l| l[0].min(l[1])).collect()
+}
+
+/// A line that is not placed yet, it ends at the last of its points.
+struct Wire {
+    points: Vec<cgmath::Point2<f32>>,
+    /// Length of the path a fall travels from the input ports to the first point.
+    start: f32,
+    /// Name of the input port at the first point.
+    port: Option<String>,
+}
+
+impl Wire {
+    fn new(first: cgmath::Point2<f32>, start: f32, port: Option<String>) -> Self {
+        Wire { points: vec![first], start, port }
+    }
+
+    fn tip(&self) -> cgmath::Point2<f32> {
+        *self.points.last().unwrap()
+    }
+
+    /// Length of the path a fall travels from the input ports to the tip.
+    fn length(&self) -> f32 {
+        self.start + path::length(&self.points, CORNER_RADIUS)
+    }
+
+    /// Continues the line to `point`, a straight continuation along the z axis replaces the last
+    /// point.
+    fn extend(&mut self, point: cgmath::Point2<f32>) {
+        let n = self.points.len();
+        if self.points[n - 1] == point {
+            return;
+        }
+        if n >= 2 && self.points[n - 2].x == point.x && self.points[n - 1].x == point.x {
+            self.points[n - 1] = point;
+        } else {
+            self.points.push(point);
+        }
+    }
+}
+
+/// What happens to the next lines of a row in a stage.
+enum Step<T> {
+    /// The next line passes straight through.
+    Pass,
+    /// The next lines enter a gate, its outputs carry the given tags.
+    Gate(Kind, Vec<T>),
+}
+
+/// The lines of a row from left to right, together with what each of them carries.
+type Row<T> = Vec<(Wire, T)>;
+
+struct Placer {
+    templates: Vec<Template>,
+    dominos: Vec<Domino>,
+    ports: Vec<Port>,
+}
+
+impl Placer {
+    fn new() -> Self {
+        Placer { templates: Kind::ALL.iter().map(|k| Template::new(*k)).collect(), dominos: vec![], ports: vec![] }
+    }
+
+    fn template(&self, kind: Kind) -> &Template {
+        &self.templates[Kind::ALL.iter().position(|k| *k == kind).unwrap()]
+    }
+
+    fn add_domino(&mut self, position: cgmath::Point2<f32>, rotation_y: f32) -> u32 {
+        let id = self.dominos.len() as u32;
+        self.dominos.push(Domino::new(id, cgmath::point3(position.x, 0.0, position.y), rotation_y));
+        id
+    }
+
+    /// Places the dominos of `wire` and returns the id of its last domino.
+    fn place_wire(&mut self, wire: Wire) -> u32 {
+        let ids: Vec<u32> = path::along_polyline(&wire.points, SPACING, CORNER_RADIUS).iter()
+            .map(|p| self.add_domino(p.position, p.rotation_y))
+            .collect();
+        if let Some(name) = wire.port {
+            self.ports.push(Port { name, domino_id: ids[0], direction: PortDirection::Input });
+        }
+        *ids.last().unwrap()
+    }
+
+    fn place_gate(&mut self, kind: Kind, origin: cgmath::Point2<f32>) {
+        let dominos: Vec<(cgmath::Point2<f32>, f32)> = self.template(kind).gate.dominos.iter()
+            .map(|d| (cgmath::point2(origin.x + d.position.x, origin.y + d.position.z), d.rotation_y))
+            .collect();
+        for (position, rotation_y) in dominos {
+            self.add_domino(position, rotation_y);
+        }
+    }
+
+    /// Places the gates of a stage above the tips of `row`, which all end at the same z, and
+    /// routes the lines to them. Returns the row of lines leaving the stage, which again end at
+    /// the same z.
+    fn stage<T>(&mut self, row: Row<T>, steps: Vec<Step<T>>) -> Row<T> {
+        // every step is placed as close above its lines as the steps to its left allow
+        let mut lines = row.into_iter();
+        let mut placed: Vec<Placed<T>> = vec![];
+        let mut cursor = f32::MIN;
+        for step in steps {
+            let (approach, x_range) = match &step {
+                Step::Pass => (vec![0.0], (0.0, 0.0)),
+                Step::Gate(kind, _) => {
+                    let template = self.template(*kind);
+                    (template.approach.clone(), template.x_range)
+                }
+            };
+            let step_lines: Row<T> = lines.by_ref().take(approach.len()).collect();
+            let tips: f32 = step_lines.iter().map(|(w, _)| w.tip().x).sum();
+            let wanted = (tips - approach.iter().sum::<f32>()) / approach.len() as f32;
+            let origin = wanted.max(cursor - x_range.0);
+            cursor = origin + x_range.1 + ELEMENT_DISTANCE;
+            placed.push(Placed { step, lines: step_lines, origin });
+        }
+        let front = placed[0].lines[0].0.tip().y;
+
+        let approach: Vec<Vec<f32>> = placed.iter().map(|p| match &p.step {
+            Step::Pass => vec![p.origin],
+            Step::Gate(kind, _) => self.template(*kind).approach.iter().map(|x| p.origin + x).collect(),
+        }).collect();
+        let mut z = shift(&mut placed, &approach, front);
+
+        // the earlier input of a gate that needs its inputs together is lengthened with loops
+        // towards the other input, each loop adds at most `gain`
+        let room = LOOP_WIDTH - LOOP_CLEARANCE;
+        let gain = 2.0 * room - 4.0 * (2.0 - std::f32::consts::FRAC_PI_2) * CORNER_RADIUS;
+        let mut loops: Vec<Option<(usize, f32, usize)>> = vec![];
+        for p in &placed {
+            let skew = match &p.step {
+                Step::Gate(kind, _) => kind.skew(),
+                Step::Pass => 0.0,
+            };
+            let lengths: Vec<f32> = p.lines.iter().enumerate()
+                .map(|(i, (w, _))| w.length() + (z - w.tip().y) - if i == 0 { skew } else { 0.0 })
+                .collect();
+            loops.push(match lengths.as_slice() {
+                [a, b] if (a - b).abs() >= ARRIVAL_TOLERANCE => {
+                    let early = if a < b { 0 } else { 1 };
+                    let extra = (a - b).abs();
+                    Some((early, extra, (extra / gain).ceil() as usize))
+                }
+                _ => None,
+            });
+        }
+        let loop_count = loops.iter().flatten().map(|(_, _, count)| *count).max().unwrap_or(0);
+        if loop_count > 0 {
+            let height = loop_count as f32 * LOOP_HEIGHT;
+            for (p, l) in placed.iter_mut().zip(loops.iter()) {
+                let (early, extra, count) = match l {
+                    Some(l) => *l,
+                    None => continue,
+                };
+                let side = if early == 0 { 1.0 } else { -1.0 };
+                let wire = &mut p.lines[early].0;
+                let x = wire.tip().x;
+                wire.extend(cgmath::point2(x, z));
+                let piece = height / count as f32;
+                for j in 0..count {
+                    let from = cgmath::point2(x, z + j as f32 * piece);
+                    let to = cgmath::point2(x, z + (j + 1) as f32 * piece);
+                    for point in path::detour(from, to, piece + extra / count as f32, side, CORNER_RADIUS) {
+                        wire.extend(point);
+                    }
+                }
+            }
+            z += height;
+        }
+
+        let inputs: Vec<Vec<f32>> = placed.iter().map(|p| match &p.step {
+            Step::Pass => vec![p.origin],
+            Step::Gate(kind, _) => self.template(*kind).inputs.iter().map(|i| p.origin + i.x).collect(),
+        }).collect();
+        let z = shift(&mut placed, &inputs, z);
+
+        // the gates stand one spacing above the ends of the lines leading to them
+        let gate_z = z + SPACING;
+        let mut top = z;
+        let mut row = vec![];
+        for p in placed {
+            let (kind, tags) = match p.step {
+                Step::Pass => {
+                    row.extend(p.lines);
+                    continue;
+                }
+                Step::Gate(kind, tags) => (kind, tags),
+            };
+            let mut arrival: f32 = 0.0;
+            for (mut wire, _) in p.lines {
+                let x = wire.tip().x;
+                wire.extend(cgmath::point2(x, z));
+                arrival = arrival.max(wire.length() + SPACING);
+                self.place_wire(wire);
+            }
+            let origin = cgmath::point2(p.origin, gate_z);
+            self.place_gate(kind, origin);
+            let template = self.template(kind);
+            top = top.max(gate_z + template.height() + SPACING);
+            for ((output, delay), tag) in template.outputs.iter().zip(template.delays.iter()).zip(tags) {
+                let first = cgmath::point2(origin.x + output.x, origin.y + output.y + SPACING);
+                row.push((Wire::new(first, arrival + delay + SPACING, None), tag));
+            }
+        }
+        for (wire, _) in row.iter_mut() {
+            let x = wire.tip().x;
+            wire.extend(cgmath::point2(x, top));
+        }
+        row
+    }
+}
+
+/// A step of a stage with its position and the lines leading to it.
+struct Placed<T> {
+    step: Step<T>,
+    lines: Row<T>,
+    /// x of the origin of the gate, or of the line passing through.
+    origin: f32,
+}
+
+/// Moves the lines of `placed`, which end at `front`, sideways to the x given in `targets` for
+/// each step. The lines run straight along the z axis, then diagonally at 45° at most. Returns
+/// the z from which on all lines are straight again.
+fn shift<T>(placed: &mut [Placed<T>], targets: &[Vec<f32>], front: f32) -> f32 {
+    let distance = placed.iter().zip(targets.iter())
+        .flat_map(|(p, t)| p.lines.iter().zip(t.iter()).map(|((w, _), x)| (w.tip().x - x).abs()))
+        .fold(0.0, f32::max);
+    if distance == 0.0 {
+        return front;
+    }
+    let turn = front + CORNER_RADIUS;
+    for (p, t) in placed.iter_mut().zip(targets.iter()) {
+        for ((wire, _), x) in p.lines.iter_mut().zip(t.iter()) {
+            let from = wire.tip().x;
+            if from != *x {
+                wire.extend(cgmath::point2(from, turn));
+                wire.extend(cgmath::point2(*x, turn + distance));
+            }
+        }
+    }
+    turn + distance + CORNER_RADIUS
+}
+
+/// A node of the circuit, equal subexpressions of the outputs share one node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Node {
+    /// Index of an input port.
+    Input(usize),
+    /// A gate with the nodes of its inputs, `not` gates get the clock as first input.
+    Gate(Kind, [usize; 2]),
+}
+
+/// The nodes of the circuit in an order where the inputs of a gate come before it.
+struct Graph {
+    nodes: Vec<Node>,
+    clock: usize,
+}
+
+impl Graph {
+    fn add(&mut self, node: Node) -> usize {
+        match self.nodes.iter().position(|n| *n == node) {
+            Some(index) => index,
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    /// Adds the nodes of the normalized expression `expr` and returns the index of its root,
+    /// `None` for the constant `0`. The constant `1` is the clock.
+    fn add_expr(&mut self, expr: &Expr, inputs: &[String]) -> Option<usize> {
+        let input = |name: &String| inputs.iter().position(|i| i == name).unwrap();
+        let node = match expr {
+            Expr::Constant(false) => return None,
+            Expr::Constant(true) => Node::Input(self.clock),
+            Expr::Input(name) => Node::Input(input(name)),
+            Expr::Not(e) => match e.as_ref() {
+                Expr::Input(name) => {
+                    let (clock, x) = (self.add(Node::Input(self.clock)), self.add(Node::Input(input(name))));
+                    Node::Gate(Kind::Not, [clock, x])
+                }
+                _ => unreachable!("negations of normalized expressions are at inputs"),
+            },
+            Expr::And(a, b) | Expr::Or(a, b) | Expr::Xor(a, b) => {
+                let kind = match expr {
+                    Expr::And(..) => Kind::And,
+                    Expr::Or(..) => Kind::Or,
+                    _ => Kind::Xor,
+                };
+                let a = self.add_expr(a, inputs).expect("normalized expressions contain no constants");
+                let b = self.add_expr(b, inputs).expect("normalized expressions contain no constants");
+                Node::Gate(kind, [a.min(b), a.max(b)])
+            }
+        };
+        Some(self.add(node))
+    }
+
+    /// Number of gates between the input ports and each node on the longest path.
+    fn levels(&self) -> Vec<usize> {
+        let mut levels = vec![0; self.nodes.len()];
+        for (n, node) in self.nodes.iter().enumerate() {
+            if let Node::Gate(_, [a, b]) = node {
+                levels[n] = 1 + levels[*a].max(levels[*b]);
+            }
+        }
+        levels
+    }
+}
+
+/// Builds the layout of the normalized expressions `outputs` over `inputs`. If `clock` is given,
+/// it is added as last input and feeds the `not` gates and the outputs that are constant `1`. Its
+/// line starts left of the other inputs, where the `not` gates take it.
+///
+/// The gates are placed in one row per level of the circuit. Before each row the lines of the
+/// nodes the row needs are split with `fan_out` gates and brought next to the gates using them
+/// with `crossover` gates, the nodes needed by later rows or as outputs pass through.
+pub(super) fn place(inputs: &[String], outputs: &[(String, Expr)], clock: Option<&str>) -> Simulator {
+    let mut inputs = inputs.to_vec();
+    let mut graph = Graph { nodes: vec![], clock: inputs.len() };
+    if let Some(clock) = clock {
+        inputs.push(clock.to_string());
+    }
+    let roots: Vec<Option<usize>> = outputs.iter().map(|(_, e)| graph.add_expr(e, &inputs)).collect();
+    let levels = graph.levels();
+    let last_level = levels.iter().copied().max().unwrap_or(0);
+    // the last level each node is used on, `last_level + 1` for outputs
+    let mut last_use: Vec<Option<usize>> = vec![None; graph.nodes.len()];
+    for (n, node) in graph.nodes.iter().enumerate() {
+        if let Node::Gate(_, operands) = node {
+            for o in operands {
+                last_use[*o] = last_use[*o].max(Some(levels[n]));
+            }
+        }
+    }
+    for root in roots.iter().flatten() {
+        last_use[*root] = Some(last_level + 1);
+    }
+
+    let mut placer = Placer::new();
+    // the input ports, unused ones only get a short line
+    let mut row: Row<usize> = vec![];
+    let order: Vec<usize> = match clock {
+        Some(_) => std::iter::once(graph.clock).chain(0..graph.clock).collect(),
+        None => (0..inputs.len()).collect(),
+    };
+    for (slot, i) in order.into_iter().enumerate() {
+        let x = slot as f32 * INPUT_DISTANCE;
+        let mut wire = Wire::new(cgmath::point2(x, 0.0), 0.0, Some(inputs[i].clone()));
+        match graph.nodes.iter().position(|n| *n == Node::Input(i)).filter(|n| last_use[*n].is_some()) {
+            Some(node) => row.push((wire, node)),
+            None => {
+                wire.extend(cgmath::point2(x, OUTPUT_LENGTH));
+                placer.place_wire(wire);
+            }
+        }
+    }
+
+    for level in 1..=last_level {
+        // the gates of the level and the nodes passing through, ordered by where their lines
+        // are now so that few of them have to cross
+        let position = |node: usize, row: &Row<usize>| row.iter().position(|(_, n)| *n == node).unwrap() as f32;
+        let mut groups: Vec<(f32, Option<usize>, Vec<usize>)> = vec![];
+        for (n, node) in graph.nodes.iter().enumerate().filter(|(n, _)| levels[*n] == level) {
+            let operands = match node {
+                Node::Gate(Kind::Not, operands) => operands.to_vec(),
+                Node::Gate(_, [a, b]) if position(*a, &row) > position(*b, &row) => vec![*b, *a],
+                Node::Gate(_, [a, b]) => vec![*a, *b],
+                Node::Input(_) => unreachable!(),
+            };
+            let key = operands.iter().map(|o| position(*o, &row)).sum::<f32>() / operands.len() as f32;
+            groups.push((key, Some(n), operands));
+        }
+        for (_, node) in &row {
+            if last_use[*node] > Some(level) {
+                groups.push((position(*node, &row), None, vec![*node]));
+            }
+        }
+        groups.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let slots: Vec<usize> = groups.iter().flat_map(|(_, _, operands)| operands.iter().copied()).collect();
+
+        row = placer.arrange(row, &slots);
+        let steps = groups.into_iter().flat_map(|(_, gate, operands)| match gate {
+            Some(n) => match graph.nodes[n] {
+                Node::Gate(kind, _) => vec![Step::Gate(kind, vec![n])],
+                Node::Input(_) => unreachable!(),
+            },
+            None => operands.iter().map(|_| Step::Pass).collect(),
+        }).collect();
+        row = placer.stage(row, steps);
+    }
+
+    let slots: Vec<usize> = row.iter()
+        .flat_map(|(_, node)| std::iter::repeat_n(*node, roots.iter().filter(|r| **r == Some(*node)).count()))
+        .collect();
+    let row = placer.arrange(row, &slots);
+    let mut simulator = placer.outputs(row, outputs, &roots);
+    // the input ports in the order of `inputs`, followed by the output ports
+    simulator.ports.sort_by_key(|p| inputs.iter().position(|i| *i == p.name).unwrap_or(inputs.len()));
+    simulator
+}
+
+impl Placer {
+    /// Splits and reorders the lines of `row` so that they carry the nodes in `slots`, every line
+    /// has to carry a node that appears in `slots`.
+    fn arrange(&mut self, row: Row<usize>, slots: &[usize]) -> Row<usize> {
+        let copies = |node: usize| slots.iter().filter(|s| **s == node).count();
+        let mut row: Row<(usize, usize)> = row.into_iter().map(|(wire, node)| (wire, (node, copies(node)))).collect();
+        while row.iter().any(|(_, (_, count))| *count > 1) {
+            let steps = row.iter().map(|(_, (node, count))| match count {
+                1 => Step::Pass,
+                _ => Step::Gate(Kind::FanOut, vec![(*node, count - count / 2), (*node, count / 2)]),
+            }).collect();
+            row = self.stage(row, steps);
+        }
+
+        // the copies of a node are assigned to its slots from left to right, then sorted by
+        // swapping neighbours that are in the wrong order
+        let mut next_slot = vec![0; slots.iter().max().map_or(0, |n| n + 1)];
+        let mut row: Row<(usize, usize)> = row.into_iter().map(|(wire, (node, _))| {
+            let slot = (next_slot[node]..slots.len()).find(|s| slots[*s] == node).unwrap();
+            next_slot[node] = slot + 1;
+            (wire, (node, slot))
+        }).collect();
+        let mut parity = 0;
+        let mut sorted_rounds = 0;
+        while sorted_rounds < 2 {
+            let mut steps = vec![];
+            let mut swapped = false;
+            let mut i = 0;
+            while i < row.len() {
+                if i % 2 == parity && i + 1 < row.len() && row[i].1 .1 > row[i + 1].1 .1 {
+                    steps.push(Step::Gate(Kind::Crossover, vec![row[i + 1].1, row[i].1]));
+                    swapped = true;
+                    i += 2;
+                } else {
+                    steps.push(Step::Pass);
+                    i += 1;
+                }
+            }
+            if swapped {
+                row = self.stage(row, steps);
+                sorted_rounds = 0;
+            } else {
+                sorted_rounds += 1;
+            }
+            parity = 1 - parity;
+        }
+        row.into_iter().map(|(wire, (node, _))| (wire, node)).collect()
+    }
+
+    /// Ends the lines of the outputs in output ports and returns the layout, centered on the x
+    /// axis. `row` has a line for every output that is not constant `0`.
+    fn outputs(mut self, row: Row<usize>, outputs: &[(String, Expr)], roots: &[Option<usize>]) -> Simulator {
+        let top = row.first().map_or(0.0, |(w, _)| w.tip().y) + OUTPUT_LENGTH;
+        let mut right = self.dominos.iter().map(|d| d.position.x).fold(f32::MIN, f32::max);
+        let mut lines: Vec<Option<(Wire, usize)>> = row.into_iter().map(Some).collect();
+        for ((name, _), root) in outputs.iter().zip(roots) {
+            let id = match root {
+                Some(node) => {
+                    let line = lines.iter_mut().find(|l| l.as_ref().is_some_and(|(_, n)| n == node)).unwrap();
+                    let (mut wire, _) = line.take().unwrap();
+                    let x = wire.tip().x;
+                    wire.extend(cgmath::point2(x, top));
+                    self.place_wire(wire)
+                }
+                // the port of a constant 0 is a domino that is never knocked over
+                None => {
+                    right = if right == f32::MIN { 0.0 } else { right + ELEMENT_DISTANCE };
+                    self.add_domino(cgmath::point2(right, top), 0.0)
+                }
+            };
+            self.ports.push(Port { name: name.clone(), domino_id: id, direction: PortDirection::Output });
+        }
+
+        let (min, max) = self.dominos.iter().fold((f32::MAX, f32::MIN), |(min, max), d| (min.min(d.position.x), max.max(d.position.x)));
+        let center = 0.5 * (min + max);
+        for d in self.dominos.iter_mut() {
+            d.position.x -= center;
+        }
+        Simulator { dominos: self.dominos, ports: self.ports, lights: light::default_lights(), ..Simulator::empty() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{gates, simulator::truth_table::{DEFAULT_DT, DEFAULT_MAX_DURATION}, synthesis::Circuit};
+
+    #[test]
+    fn not_takes_the_clock_without_a_crossover() {
+        let circuit = Circuit::parse("out = !a").unwrap();
+        let layout = circuit.layout();
+        let names: Vec<&str> = layout.ports.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["a", "clock", "out"]);
+        // a single crossover has more dominos than the whole layout should have
+        let crossover = gates::crossover(cgmath::point3(0.0, 0.0, 0.0), 0.0).dominos.len();
+        assert!(layout.dominos.len() < crossover, "{} dominos", layout.dominos.len());
+
+        let table = layout.truth_table(DEFAULT_DT, DEFAULT_MAX_DURATION).unwrap();
+        assert_eq!(table.diff(&circuit.truth_table()), Ok(vec![]));
+    }
+}
@@ -1,31 +1,58 @@
-use std::{sync::Mutex as stdMutex, sync::Arc};
+use std::{collections::BTreeSet, sync::Mutex as stdMutex, sync::Arc};
 use cgmath::InnerSpace;
 use eframe::egui_glow::{self, *};
 use egui::{mutex::Mutex, Pos2};
 
 pub mod shaders;
 pub mod canvas;
+pub mod gizmo;
+pub mod path_tool;
+pub mod camera;
+pub mod picking;
 use canvas::*;
 
-use crate::simulator::Simulator;
+use crate::{blueprint, history::Command, path, simulator::{terrain, Simulator, DOMINO_SIZE}};
+
+/// A part of a layout that is placed where the ground is clicked next.
+pub struct Placing {
+    pub part: Simulator,
+    /// Prefix of the port names of the placed part.
+    pub port_prefix: Option<String>,
+}
 
 #[derive(Clone)]
 pub struct RenderMatrices {
     perspective: cgmath::Matrix4<f32>,
     view: cgmath::Matrix4<f32>,
-    rotation: Option<cgmath::Matrix4<f32>>,
-    translation: Option<cgmath::Matrix4<f32>>,
-    scale: Option<cgmath::Matrix4<f32>>,
 }
 
 pub struct UI3d {
     /// Behind an `Arc<Mutex<…>>` so we can pass it to [`egui::PaintCallback`] and paint later.
     pub canvas: Arc<Mutex<Canvas>>,
-    cam_pos: cgmath::Point3<f32>,
-    cam_angle: cgmath::Vector2<f32>,
+    pub camera: camera::Camera,
     simulator: Arc<stdMutex<Simulator>>,
-    fov: cgmath::Rad<f32>,
-    pub selected_domino_id: Option<u32>,
+    /// Ids of the selected dominos, may contain ids of dominos that were deleted since.
+    pub selection: BTreeSet<u32>,
+    /// Ids of the dominos drawn highlighted, e.g. the critical path of the timing analysis.
+    pub highlighted: BTreeSet<u32>,
+    /// Id of the obstacle of the terrain that is edited, drawn highlighted.
+    pub selected_obstacle: Option<u32>,
+    /// Id of the component that is edited, drawn highlighted.
+    pub selected_component: Option<u32>,
+    /// Whether the light casts shadows.
+    pub shadows: bool,
+    /// Screen position where the rectangle selecting dominos was started.
+    box_select_start: Option<Pos2>,
+    pub snapping: gizmo::Snapping,
+    pub path_tool: path_tool::PathTool,
+    pub placing: Option<Placing>,
+    /// Point on the ground plane or the terrain below the cursor while it is over the 3d view.
+    cursor_ground_point: Option<cgmath::Point3<f32>>,
+    /// Handle of the gizmo below the cursor.
+    hovered_handle: Option<gizmo::Handle>,
+    gizmo_drag: Option<gizmo::Drag>,
+    /// Edits made with the gizmo that were not yet taken by [`UI3d::take_edits`].
+    edits: Vec<Command>,
 }
 
 impl UI3d {
@@ -33,25 +60,93 @@ impl UI3d {
         let gl = cc.gl.as_ref()?;
         Some(Self {
             canvas: Arc::new(Mutex::new(Canvas::new(gl, simulator.clone())?)),
-            cam_pos: cgmath::Point3{x: 1.0f32, y: 2.0f32, z: 2.0f32},
-            cam_angle: cgmath::Vector2{x: 0f32, y: 0f32},
+            camera: camera::Camera::default(),
             simulator,
-            fov: cgmath::Rad(60f32 * ((2.0*std::f32::consts::PI) / 360.0)),
-            selected_domino_id: None,
+            selection: BTreeSet::new(),
+            highlighted: BTreeSet::new(),
+            selected_obstacle: None,
+            selected_component: None,
+            shadows: true,
+            box_select_start: None,
+            snapping: gizmo::Snapping::default(),
+            path_tool: path_tool::PathTool::default(),
+            placing: None,
+            cursor_ground_point: None,
+            hovered_handle: None,
+            gizmo_drag: None,
+            edits: vec![],
         })
     }
+
+    /// Ids of the selected dominos that exist.
+    pub fn selected_ids(&self) -> Vec<u32> {
+        let s = self.simulator.lock().unwrap();
+        self.selection.iter().copied().filter(|id| s.dominos.iter().any(|d| d.id == *id)).collect()
+    }
+
+    pub fn select_all(&mut self) {
+        self.selection = self.simulator.lock().unwrap().dominos.iter().map(|d| d.id).collect();
+    }
+
+    /// Point on the ground plane or the terrain below the cursor, or where it was when it last
+    /// left the 3d view.
+    pub fn cursor_ground_point(&self) -> Option<cgmath::Point3<f32>> {
+        self.cursor_ground_point
+    }
+
+    /// Adds a copy of `part` with its center at `at` and selects it.
+    pub fn place(&mut self, part: &Simulator, port_prefix: Option<&str>, at: cgmath::Point3<f32>) {
+        let mut s = self.simulator.lock().unwrap();
+        let command = blueprint::instantiate(&s, part, at, port_prefix);
+        command.apply(&mut s);
+        if let Command::Create { dominos, .. } = &command {
+            self.selection = dominos.iter().map(|d| d.id).collect();
+        }
+        self.edits.push(command);
+    }
+
+    /// Returns the finished edits since the last call, to record them in the history.
+    pub fn take_edits(&mut self) -> Vec<Command> {
+        std::mem::take(&mut self.edits)
+    }
+
+    /// Centers the camera on the selected dominos and moves it close enough to see all of them.
+    pub fn frame_selection(&mut self) {
+        let ids = self.selected_ids();
+        let s = self.simulator.lock().unwrap();
+        let center = match s.center_of(&ids) {
+            Some(c) => c,
+            None => return,
+        };
+        let radius = s.dominos.iter()
+            .filter(|d| ids.contains(&d.id))
+            .map(|d| (d.position - center).magnitude() + d.dimensions().y)
+            .fold(0.0, f32::max);
+        drop(s);
+        self.camera.frame(center, radius);
+    }
+
+    /// Lays the dominos along the path drawn with the path tool.
+    pub fn finish_path(&mut self) {
+        let command = self.path_tool.finish(&mut self.simulator.lock().unwrap());
+        self.edits.extend(command);
+    }
 }
 
 impl eframe::App for UI3d {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        let (keys_down, mods, screen_rect, pointer) = ctx.input(|i| (i.keys_down.to_owned(), i.modifiers, i.screen_rect, i.pointer.to_owned()));
+        let (keys_down, mods, screen_rect, pointer, scroll, dt) = ctx.input(|i| (i.keys_down.to_owned(), i.modifiers, i.screen_rect, i.pointer.to_owned(), i.scroll_delta.y, i.stable_dt));
+
+        if !ctx.wants_keyboard_input() {
+            self.move_camera(&keys_down, mods, dt);
+            if ctx.input(|i| i.key_pressed(egui::Key::F)) {
+                self.frame_selection();
+            }
+        }
 
-        // if pointer.any_click() {
-        //     self.get_clicked_ray_obb_intersection(pointer.interact_pos().unwrap(), screen_rect);
-        // }
         let frame = egui::Frame::none().inner_margin(egui::Margin::same(0.0)).outer_margin(egui::Margin::same(0.0));
         egui::CentralPanel::default().frame(frame).show(ctx, |ui| {
-            self.custom_painting(ui, screen_rect, keys_down, mods, pointer.interact_pos());
+            self.custom_painting(ui, screen_rect, mods, pointer.interact_pos(), pointer.press_origin(), scroll);
         });
         ctx.request_repaint();
     }
@@ -64,23 +159,77 @@ impl eframe::App for UI3d {
 }
 
 impl UI3d {
-    fn custom_painting(&mut self, ui: &mut egui::Ui, screen_rect: egui::Rect, keys_down: std::collections::HashSet<egui::Key>, mods: egui::Modifiers, mouse_pos: Option<Pos2>) {
+    fn custom_painting(&mut self, ui: &mut egui::Ui, screen_rect: egui::Rect, mods: egui::Modifiers, mouse_pos: Option<Pos2>, press_pos: Option<Pos2>, scroll: f32) {
         let (rect, response) =
             ui.allocate_exact_size(ui.available_size(), egui::Sense::click_and_drag());
 
-        if response.clicked() {
-            self.get_clicked_ray_obb_intersection(mouse_pos.unwrap_or_default(), screen_rect);
+        if let Some((origin, direction)) = response.hover_pos().and_then(|p| self.click_ray(p, screen_rect)) {
+            self.cursor_ground_point = terrain::ray_ground_point(&self.simulator.lock().unwrap().obstacles, origin, direction);
+        }
+
+        if self.placing.is_some() {
+            if response.clicked() {
+                if let (Some(placing), Some(at)) = (self.placing.take(), self.cursor_ground_point) {
+                    self.place(&placing.part, placing.port_prefix.as_deref(), at);
+                }
+            }
+            self.hovered_handle = None;
+            self.gizmo_drag = None;
+        } else if self.path_tool.active {
+            if response.clicked() {
+                self.add_path_point(mouse_pos.unwrap_or_default(), screen_rect);
+            }
+            self.hovered_handle = None;
+            self.gizmo_drag = None;
+        } else {
+            // clicking on the gizmo keeps the selection
+            if response.clicked() && self.hovered_handle.is_none() {
+                let hit = self.pick_domino(mouse_pos.unwrap_or_default(), screen_rect);
+                if mods.shift {
+                    if let Some(id) = hit {
+                        if !self.selection.remove(&id) {
+                            self.selection.insert(id);
+                        }
+                    }
+                } else {
+                    self.selection = hit.into_iter().collect();
+                }
+            }
+            self.update_gizmo(&response, screen_rect, mouse_pos, press_pos);
+            if response.drag_started_by(egui::PointerButton::Primary) && self.gizmo_drag.is_none() && mods.shift {
+                self.box_select_start = press_pos;
+            }
         }
 
-        let drag = response.drag_delta();
-        let render_mats = self.calc_mvp(keys_down, mods, drag, screen_rect);
-        let cam_pos = self.cam_pos.to_owned();
+        // dragging a handle of the gizmo or a selection rectangle must not move the camera
+        if self.gizmo_drag.is_none() && self.box_select_start.is_none() {
+            let drag = response.drag_delta();
+            if response.dragged_by(egui::PointerButton::Middle) {
+                self.camera.pan(cgmath::vec2(drag.x, drag.y), rect.height());
+            } else if response.dragged() {
+                self.camera.orbit(cgmath::vec2(drag.x, drag.y));
+            }
+        }
+        if response.hovered() && scroll != 0.0 {
+            self.camera.zoom(scroll);
+        }
+        let render_mats = self.render_matrices(screen_rect);
+        let cam_pos = self.camera.position();
 
         let canvas = self.canvas.clone();
-        let selected_domino_id = self.selected_domino_id.to_owned();
+        let selection = self.selection.clone();
+        let highlighted = self.highlighted.clone();
+        let selected_obstacles = self.selected_obstacle.into_iter().collect();
+        let selected_components = self.selected_component.into_iter().collect();
+        let shadows = self.shadows;
+        let gizmo = self.selection_center().filter(|_| !self.path_tool.active && self.placing.is_none()).map(|center| {
+            let highlighted = self.gizmo_drag.as_ref().map(|d| d.handle).or(self.hovered_handle);
+            (gizmo::model_matrix(center, cam_pos), highlighted)
+        });
 
+        let paint_mats = render_mats.clone();
         let cb = egui_glow::CallbackFn::new(move |_info, painter| {
-            canvas.lock().paint(painter.gl(), render_mats.clone(), cam_pos, selected_domino_id);
+            canvas.lock().paint(painter.gl(), paint_mats.clone(), cam_pos, &selection, &highlighted, &selected_obstacles, &selected_components, shadows, gizmo);
         });
 
         let callback = egui::PaintCallback {
@@ -88,118 +237,204 @@ impl UI3d {
             callback: Arc::new(cb),
         };
         ui.painter().add(callback);
+
+        if self.path_tool.active {
+            self.paint_path_preview(ui.painter(), &render_mats, rect);
+        }
+        if let (Some(placing), Some(at)) = (&self.placing, self.cursor_ground_point) {
+            paint_placement_preview(ui.painter(), &placing.part, at, &render_mats, rect);
+        }
+        if let Some(start) = self.box_select_start {
+            let selection_rect = egui::Rect::from_two_pos(start, mouse_pos.unwrap_or(start));
+            if response.dragged() {
+                ui.painter().rect(selection_rect, 0.0, egui::Color32::from_rgba_unmultiplied(80, 140, 255, 40), egui::Stroke::new(1.0, egui::Color32::LIGHT_BLUE));
+            } else {
+                self.select_in_rect(selection_rect, &render_mats, rect);
+                self.box_select_start = None;
+            }
+        }
     }
 
-    fn calc_mvp(&mut self, keys_down: std::collections::HashSet<egui::Key>, mods: egui::Modifiers, drag: egui::Vec2, screen_rect: egui::Rect) -> RenderMatrices {
-        self.cam_angle.x -= drag.x * 0.003f32;
-        self.cam_angle.y -= drag.y * 0.003f32;
+    /// Adds the dominos whose center is inside `selection_rect` on the screen to the selection.
+    fn select_in_rect(&mut self, selection_rect: egui::Rect, render_mats: &RenderMatrices, rect: egui::Rect) {
+        let s = self.simulator.lock().unwrap();
+        for d in s.dominos.iter() {
+            let center = d.transform() * cgmath::vec4(0.0, d.dimensions().y * 0.5, 0.0, 1.0);
+            if let Some(p) = to_screen(render_mats, rect, cgmath::point3(center.x, center.y, center.z)) {
+                if selection_rect.contains(p) {
+                    self.selection.insert(d.id);
+                }
+            }
+        }
+    }
 
-        let direction = cgmath::Vector3{
-            x: cgmath::Angle::cos(cgmath::Rad(self.cam_angle.y)) * cgmath::Angle::sin(cgmath::Rad(self.cam_angle.x)),
-            y: cgmath::Angle::sin(cgmath::Rad(self.cam_angle.y)),
-            z: cgmath::Angle::cos(cgmath::Rad(self.cam_angle.y)) * cgmath::Angle::cos(cgmath::Rad(self.cam_angle.x))
+    /// Adds the point on the ground or the terrain below the cursor to the path of the path tool.
+    fn add_path_point(&mut self, click_pos: Pos2, screen_rect: egui::Rect) {
+        let (origin, direction) = match self.click_ray(click_pos, screen_rect) {
+            Some(ray) => ray,
+            None => return,
         };
-        
-        let mut move_direction = direction;
-        move_direction.y = 0.0;
-
-        let up_vec = cgmath::Vector3{x: 0.0, y: 1.0, z: 0.0};
+        let point = terrain::ray_ground_point(&self.simulator.lock().unwrap().obstacles, origin, direction);
+        if let Some(mut point) = point {
+            if self.snapping.enabled && self.snapping.grid_size > 0.0 {
+                let grid_size = self.snapping.grid_size;
+                point.x = (point.x / grid_size).round() * grid_size;
+                point.z = (point.z / grid_size).round() * grid_size;
+            }
+            self.path_tool.add_point(cgmath::point2(point.x, point.z));
+        }
+    }
 
-        let right_vec = direction.cross(up_vec).normalize();
+    /// Draws the clicked points of the path tool and the dominos it would place on top of the scene.
+    fn paint_path_preview(&self, painter: &egui::Painter, render_mats: &RenderMatrices, rect: egui::Rect) {
+        let s = self.simulator.lock().unwrap();
+        let to_screen = |p: cgmath::Point2<f32>| to_screen(render_mats, rect, cgmath::point3(p.x, s.ground_height(p.x, p.y), p.y));
 
-        if keys_down.contains(&egui::Key::S) {
-            self.cam_pos -= move_direction * 0.02f32;
-        }
-        if keys_down.contains(&egui::Key::W) {
-            self.cam_pos += move_direction * 0.02f32;
-        }
-        if keys_down.contains(&egui::Key::D) {
-            self.cam_pos += right_vec * 0.02f32;
-        }
-        if keys_down.contains(&egui::Key::A) {
-            self.cam_pos -= right_vec * 0.02f32;
-        }
-        if mods.shift {
-            self.cam_pos += up_vec * 0.02f32;
+        let points = self.path_tool.points();
+        let line_stroke = egui::Stroke::new(1.5, egui::Color32::YELLOW);
+        for segment in points.windows(2) {
+            if let (Some(a), Some(b)) = (to_screen(segment[0]), to_screen(segment[1])) {
+                painter.line_segment([a, b], line_stroke);
+            }
         }
-        if mods.ctrl {
-            self.cam_pos -= up_vec * 0.02f32;
+        for p in points.iter().filter_map(|p| to_screen(*p)) {
+            painter.circle_filled(p, 4.0, egui::Color32::YELLOW);
         }
 
-        let proj_mat = cgmath::perspective(self.fov, screen_rect.aspect_ratio(), 0.1f32, 100f32);
-        let view_mat = cgmath::Matrix4::look_at_rh(self.cam_pos, self.cam_pos + direction, up_vec);
-        // let mat = proj_mat * view_mat;
-        RenderMatrices { perspective: proj_mat, view: view_mat, rotation: None, translation: None, scale: None }
+        let domino_color = if self.path_tool.spacing_error().is_some() { egui::Color32::RED } else { egui::Color32::WHITE };
+        for placement in self.path_tool.placements() {
+            // a line across the width of the domino
+            let across = path::direction_of(placement.rotation_y + 90.0) * DOMINO_SIZE.x * 0.5;
+            if let (Some(a), Some(b)) = (to_screen(placement.position - across), to_screen(placement.position + across)) {
+                painter.line_segment([a, b], egui::Stroke::new(3.0, domino_color));
+            }
+        }
     }
 
-    fn get_clicked_ray_obb_intersection(&mut self, click_pos: egui::Pos2, screen_size: egui::Rect) {
-        let ray_origin = self.cam_pos;
-
-        let relative_click = cgmath::point2((click_pos.x / screen_size.width()) - 0.5, (click_pos.y / screen_size.height()) - 0.5);
-        let click_angle = cgmath::point2(self.fov * relative_click.x * screen_size.aspect_ratio(), self.fov * relative_click.y);
+    /// Center of the selected dominos, where the gizmo is shown.
+    fn selection_center(&self) -> Option<cgmath::Point3<f32>> {
+        self.simulator.lock().unwrap().center_of(&self.selected_ids())
+    }
 
-        let ray_direction = cgmath::Vector3 {
-            x: cgmath::Angle::cos(cgmath::Rad(self.cam_angle.y)-click_angle.y) * cgmath::Angle::sin(cgmath::Rad(self.cam_angle.x)-click_angle.x),
-            y: cgmath::Angle::sin(cgmath::Rad(self.cam_angle.y)-click_angle.y),
-            z: cgmath::Angle::cos(cgmath::Rad(self.cam_angle.y)-click_angle.y) * cgmath::Angle::cos(cgmath::Rad(self.cam_angle.x)-click_angle.x)
+    /// Starts, continues and finishes dragging the handles of the gizmo.
+    fn update_gizmo(&mut self, response: &egui::Response, screen_rect: egui::Rect, mouse_pos: Option<Pos2>, press_pos: Option<Pos2>) {
+        let center = match self.selection_center() {
+            Some(c) => c,
+            None => {
+                self.hovered_handle = None;
+                self.gizmo_drag = None;
+                return;
+            }
         };
 
-        let aabb_min = cgmath::Point3{x: -0.035f32, y: 0.0, z: -0.01};
-        let aabb_max = cgmath::Point3{x: 0.035f32, y: 0.14, z: 0.01};
-
-        let (model_mats, _rot_mats) = self.canvas.lock().get_model_mats_list();
-
-        'mats_loop: for (id, model_mat) in model_mats {
-            
-            let mut t_min = 0.0f32;
-            let mut t_max = f32::MAX;
-
-            let obb_pos_world_space = cgmath::Point3 {x: model_mat.w.x, y: model_mat.w.y, z: model_mat.w.z};
-            let delta: cgmath::Vector3<f32> = obb_pos_world_space - ray_origin;
-
-            for i in 0..3 {
-                // let xaxis = cgmath::Vector3 {x: model_mat.x.x, y: model_mat.x.y, z: model_mat.x.z};
-                let axis = match i {
-                    0 => cgmath::Vector3 {x: model_mat.x.x, y: model_mat.x.y, z: model_mat.x.z},
-                    1 => cgmath::Vector3 {x: model_mat.y.x, y: model_mat.y.y, z: model_mat.y.z},
-                    2 => cgmath::Vector3 {x: model_mat.z.x, y: model_mat.z.y, z: model_mat.z.z},
-                    _ => {println!("range is wrong"); cgmath::Vector3 {x: model_mat.x.x, y: model_mat.x.y, z: model_mat.x.z}},
-                };
-                let e = axis.dot(delta);
-                let f = ray_direction.dot(axis);
-
-                let (aabb_min_t, aabb_max_t) = match i {
-                    0 => (aabb_min.x, aabb_max.x),
-                    1 => (aabb_min.y, aabb_max.y),
-                    2 => (aabb_min.z, aabb_max.z),
-                    _ => {println!("range is wrong"); (aabb_min.x, aabb_max.x)},
-                };
-                let mut t1 = (e+aabb_min_t) / f; // Intersection with the "left" plane
-                let mut t2 = (e+aabb_max_t) / f; // Intersection with the "right" plane
-                
-                if t1 > t2 { // if wrong order -> swap -> t1 represents nearest intersection
-                    let tmp = t1;
-                    t1 = t2;
-                    t2 = tmp;
+        if response.drag_started_by(egui::PointerButton::Primary) {
+            if let Some((origin, direction)) = press_pos.and_then(|p| self.click_ray(p, screen_rect)) {
+                if let Some(handle) = gizmo::pick(center, self.camera.position(), origin, direction) {
+                    let s = self.simulator.lock().unwrap();
+                    let dominos = s.dominos.iter().filter(|d| self.selection.contains(&d.id)).cloned().collect();
+                    self.gizmo_drag = gizmo::Drag::start(handle, dominos, center, origin, direction);
                 }
+            }
+        }
 
-                if t2 < t_max {
-                    t_max = t2;
+        if let Some(drag) = &self.gizmo_drag {
+            let mut guard = self.simulator.lock().unwrap();
+            let s = &mut *guard;
+            if let Some((origin, direction)) = mouse_pos.and_then(|p| self.click_ray(p, screen_rect)) {
+                drag.apply(&mut s.dominos, &s.obstacles, origin, direction, &self.snapping);
+            }
+            if !response.dragged() {
+                // dominos deleted during the drag are left out
+                let after: Vec<_> = s.dominos.iter().filter(|d| drag.before.iter().any(|b| b.id == d.id)).cloned().collect();
+                let before = drag.before.iter().filter(|b| after.iter().any(|d| d.id == b.id)).cloned().collect();
+                let moved = after.iter().any(|a| drag.before.iter().any(|b| b.id == a.id && (b.position != a.position || b.rotation_y != a.rotation_y)));
+                if moved {
+                    self.edits.push(Command::Modify { before, after });
                 }
+                drop(guard);
+                self.gizmo_drag = None;
+            }
+        }
 
-                if t1 > t_min {
-                    t_min = t1;
-                }
+        self.hovered_handle = match (&self.gizmo_drag, response.hover_pos()) {
+            (None, Some(hover_pos)) => self.click_ray(hover_pos, screen_rect)
+                .and_then(|(origin, direction)| gizmo::pick(center, self.camera.position(), origin, direction)),
+            _ => None,
+        };
+    }
 
-                if t_max < t_min {
-                    // println!("no hit");
-                    self.selected_domino_id = None;
-                    continue 'mats_loop;
-                }
-            }
-            // println!("intersection distance: {}, id: {}", t_min, id);
-            self.selected_domino_id = Some(id);
-            break;
+    /// Moves the pivot of the camera with WASD and down and up with Q and E, by the distance
+    /// covered in the `dt` seconds since the last frame. Keys held together with ctrl, alt or
+    /// command are shortcuts like ctrl+A and do not move the camera.
+    fn move_camera(&mut self, keys_down: &std::collections::HashSet<egui::Key>, mods: egui::Modifiers, dt: f32) {
+        if mods.ctrl || mods.alt || mods.command || mods.mac_cmd {
+            return;
+        }
+        let axis = |positive: egui::Key, negative: egui::Key| keys_down.contains(&positive) as i32 as f32 - keys_down.contains(&negative) as i32 as f32;
+        let forward = axis(egui::Key::W, egui::Key::S);
+        let right = axis(egui::Key::D, egui::Key::A);
+        let up = axis(egui::Key::E, egui::Key::Q);
+        self.camera.fly(forward, right, up, dt);
+    }
+
+    /// The matrices the scene is drawn with from the current camera.
+    fn render_matrices(&self, screen_rect: egui::Rect) -> RenderMatrices {
+        let (perspective, view) = self.camera.matrices(screen_rect.aspect_ratio());
+        RenderMatrices { perspective, view }
+    }
+
+    /// Origin and unit direction of the ray through the pixel at `click_pos`.
+    fn click_ray(&self, click_pos: egui::Pos2, screen_rect: egui::Rect) -> Option<(cgmath::Point3<f32>, cgmath::Vector3<f32>)> {
+        let render_mats = self.render_matrices(screen_rect);
+        picking::unproject(render_mats.perspective * render_mats.view, screen_rect, click_pos)
+    }
+
+    /// The id of the nearest domino below the cursor, dominos hidden behind the terrain can not be
+    /// picked.
+    fn pick_domino(&self, click_pos: egui::Pos2, screen_rect: egui::Rect) -> Option<u32> {
+        let (origin, direction) = self.click_ray(click_pos, screen_rect)?;
+        let s = self.simulator.lock().unwrap();
+
+        // the box of a domino with a scale of 1, the model matrices include the scale of the dominos
+        let min = cgmath::point3(-DOMINO_SIZE.x * 0.5, 0.0, -DOMINO_SIZE.z * 0.5);
+        let max = cgmath::point3(DOMINO_SIZE.x * 0.5, DOMINO_SIZE.y, DOMINO_SIZE.z * 0.5);
+        let model_mats = s.dominos.iter().map(|d| (d.id, d.transform() * cgmath::Matrix4::from_nonuniform_scale(d.scale.x, d.scale.y, d.scale.z)));
+        let (id, t) = picking::nearest_hit(origin, direction, model_mats, min, max)?;
+
+        let terrain = terrain::ray_ground_point(&s.obstacles, origin, direction).map(|p| picking::distance_along(origin, direction, p));
+        if terrain.is_some_and(|terrain| terrain < t) {
+            return None;
+        }
+        Some(id)
+    }
+}
+
+/// Draws the dominos of a part that is about to be placed at `at` on top of the scene.
+fn paint_placement_preview(painter: &egui::Painter, part: &Simulator, at: cgmath::Point3<f32>, render_mats: &RenderMatrices, rect: egui::Rect) {
+    let ids: Vec<u32> = part.dominos.iter().map(|d| d.id).collect();
+    let center = match part.center_of(&ids) {
+        Some(c) => c,
+        None => return,
+    };
+    let offset = at - center;
+    for d in part.dominos.iter() {
+        // a line across the width of the domino
+        let across = path::direction_of(d.rotation_y + 90.0) * d.dimensions().x * 0.5;
+        let position = d.position + offset;
+        let a = to_screen(render_mats, rect, cgmath::point3(position.x - across.x, position.y, position.z - across.y));
+        let b = to_screen(render_mats, rect, cgmath::point3(position.x + across.x, position.y, position.z + across.y));
+        if let (Some(a), Some(b)) = (a, b) {
+            painter.line_segment([a, b], egui::Stroke::new(3.0, egui::Color32::WHITE));
         }
     }
 }
+
+/// Projects a point in world space to the screen, `None` if it is behind the camera.
+fn to_screen(render_mats: &RenderMatrices, rect: egui::Rect, p: cgmath::Point3<f32>) -> Option<Pos2> {
+    let clip = render_mats.perspective * render_mats.view * cgmath::vec4(p.x, p.y, p.z, 1.0);
+    if clip.w <= 0.0 {
+        return None;
+    }
+    let (x, y) = (clip.x / clip.w, clip.y / clip.w);
+    Some(egui::pos2(rect.left() + (x + 1.0) * 0.5 * rect.width(), rect.top() + (1.0 - y) * 0.5 * rect.height()))
+}
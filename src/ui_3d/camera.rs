@@ -0,0 +1,253 @@
+//! The camera of the 3d view. It orbits around a pivot it looks at: dragging rotates it around
+//! the pivot, the keyboard and dragging with the middle mouse button move the pivot and the mouse
+//! wheel changes the distance to it.
+
+use cgmath::InnerSpace;
+use serde::{Deserialize, Serialize};
+
+/// Radians the camera is rotated by per dragged pixel.
+const ORBIT_SPEED: f32 = 0.003;
+/// Speed the pivot is moved with the keyboard, in m/s.
+const MOVE_SPEED: f32 = 1.2;
+/// Factor the distance changes with per scrolled point.
+const ZOOM_SPEED: f32 = 0.002;
+const MIN_DISTANCE: f32 = 0.05;
+const MAX_DISTANCE: f32 = 50.0;
+/// The elevation stays below straight up or down, where the horizontal direction is lost.
+const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+const NEAR: f32 = 0.1;
+const FAR: f32 = 100.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Projection {
+    Perspective,
+    /// Orthographic, looking straight down onto the ground for laying out dominos.
+    TopDown,
+}
+
+#[derive(Clone, Debug)]
+pub struct Camera {
+    /// The point the camera orbits around and looks at.
+    pub pivot: cgmath::Point3<f32>,
+    /// Distance from the pivot in meters, in the top-down view it sets the visible area.
+    pub distance: f32,
+    /// Rotation around the vertical axis (x) and elevation (y) of the view direction in radians.
+    pub angle: cgmath::Vector2<f32>,
+    /// Vertical field of view of the perspective projection.
+    pub fov: cgmath::Rad<f32>,
+    pub projection: Projection,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera::looking_at(cgmath::point3(1.0, 2.0, 2.0), cgmath::point3(0.0, 0.0, 0.0))
+    }
+}
+
+impl Camera {
+    /// A perspective camera at `position` orbiting around `pivot`.
+    pub fn looking_at(position: cgmath::Point3<f32>, pivot: cgmath::Point3<f32>) -> Self {
+        let offset = pivot - position;
+        let distance = offset.magnitude().clamp(MIN_DISTANCE, MAX_DISTANCE);
+        let direction = offset.normalize();
+        Camera {
+            pivot,
+            distance,
+            angle: cgmath::vec2(direction.x.atan2(direction.z), direction.y.asin().clamp(-MAX_PITCH, MAX_PITCH)),
+            fov: cgmath::Deg(60.0).into(),
+            projection: Projection::Perspective,
+        }
+    }
+
+    /// Unit vector the camera looks along.
+    pub fn direction(&self) -> cgmath::Vector3<f32> {
+        match self.projection {
+            Projection::Perspective => {
+                let (sin_yaw, cos_yaw) = self.angle.x.sin_cos();
+                let (sin_pitch, cos_pitch) = self.angle.y.sin_cos();
+                cgmath::vec3(cos_pitch * sin_yaw, sin_pitch, cos_pitch * cos_yaw)
+            }
+            Projection::TopDown => -cgmath::Vector3::unit_y(),
+        }
+    }
+
+    /// Unit vector pointing up on the screen.
+    pub fn up(&self) -> cgmath::Vector3<f32> {
+        match self.projection {
+            Projection::Perspective => self.right().cross(self.direction()),
+            // the horizontal view direction points up on the screen
+            Projection::TopDown => cgmath::vec3(self.angle.x.sin(), 0.0, self.angle.x.cos()),
+        }
+    }
+
+    /// Unit vector pointing right on the screen, it is always horizontal.
+    pub fn right(&self) -> cgmath::Vector3<f32> {
+        let (sin_yaw, cos_yaw) = self.angle.x.sin_cos();
+        cgmath::vec3(-cos_yaw, 0.0, sin_yaw)
+    }
+
+    pub fn position(&self) -> cgmath::Point3<f32> {
+        self.pivot - self.direction() * self.distance
+    }
+
+    /// Rotates the camera around the pivot by the dragged distance in pixels. The top-down view
+    /// only turns around the vertical axis.
+    pub fn orbit(&mut self, drag: cgmath::Vector2<f32>) {
+        self.angle.x -= drag.x * ORBIT_SPEED;
+        if self.projection == Projection::Perspective {
+            self.angle.y = (self.angle.y - drag.y * ORBIT_SPEED).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+    }
+
+    /// Moves the pivot so that the point below the cursor follows a drag by `drag` pixels in a
+    /// view `height` pixels high.
+    pub fn pan(&mut self, drag: cgmath::Vector2<f32>, height: f32) {
+        if height <= 0.0 {
+            return;
+        }
+        let meters_per_pixel = self.visible_height() / height;
+        self.pivot += (self.up() * drag.y - self.right() * drag.x) * meters_per_pixel;
+    }
+
+    /// Moves towards the pivot for positive `scroll` (in points) and away for negative.
+    pub fn zoom(&mut self, scroll: f32) {
+        self.distance = (self.distance * (-scroll * ZOOM_SPEED).exp()).clamp(MIN_DISTANCE, MAX_DISTANCE);
+    }
+
+    /// Moves the pivot over the ground, `forward`, `right` and `up` are between -1 and 1 and
+    /// `dt` is the time since the last frame in seconds.
+    pub fn fly(&mut self, forward: f32, right: f32, up: f32, dt: f32) {
+        let (sin_yaw, cos_yaw) = self.angle.x.sin_cos();
+        let forward_vec = cgmath::vec3(sin_yaw, 0.0, cos_yaw);
+        let movement = forward_vec * forward + self.right() * right + cgmath::Vector3::unit_y() * up;
+        self.pivot += movement * MOVE_SPEED * dt;
+    }
+
+    /// Centers the view on a sphere at `center` and moves away until it fits into the view.
+    pub fn frame(&mut self, center: cgmath::Point3<f32>, radius: f32) {
+        self.pivot = center;
+        self.distance = (radius / (self.fov.0 * 0.5).sin()).clamp(MIN_DISTANCE, MAX_DISTANCE);
+    }
+
+    /// Height in meters of the area visible at the distance of the pivot.
+    fn visible_height(&self) -> f32 {
+        2.0 * self.distance * (self.fov.0 * 0.5).tan()
+    }
+
+    /// The projection and view matrix for a view with the given aspect ratio.
+    pub fn matrices(&self, aspect_ratio: f32) -> (cgmath::Matrix4<f32>, cgmath::Matrix4<f32>) {
+        let projection = match self.projection {
+            Projection::Perspective => cgmath::perspective(self.fov, aspect_ratio, NEAR, FAR),
+            Projection::TopDown => {
+                // as large as the perspective view at the pivot, switching keeps the scale
+                let half_height = self.visible_height() * 0.5;
+                let half_width = half_height * aspect_ratio;
+                cgmath::ortho(-half_width, half_width, -half_height, half_height, NEAR, FAR + self.distance)
+            }
+        };
+        let position = self.position();
+        let view = cgmath::Matrix4::look_at_rh(position, position + self.direction(), self.up());
+        (projection, view)
+    }
+}
+
+/// A camera as it is stored between runs of the editor.
+#[derive(Serialize, Deserialize)]
+pub struct CameraEntry {
+    pivot: [f32; 3],
+    distance: f32,
+    /// Rotation around the vertical axis and elevation in degrees.
+    angle: [f32; 2],
+    /// Vertical field of view in degrees.
+    fov: f32,
+    projection: Projection,
+}
+
+impl From<&Camera> for CameraEntry {
+    fn from(camera: &Camera) -> Self {
+        CameraEntry {
+            pivot: camera.pivot.into(),
+            distance: camera.distance,
+            angle: [camera.angle.x.to_degrees(), camera.angle.y.to_degrees()],
+            fov: cgmath::Deg::from(camera.fov).0,
+            projection: camera.projection,
+        }
+    }
+}
+
+impl From<CameraEntry> for Camera {
+    /// Falls back to the default camera for values that are out of range, e.g. from a damaged
+    /// storage.
+    fn from(entry: CameraEntry) -> Self {
+        let values = [entry.pivot[0], entry.pivot[1], entry.pivot[2], entry.distance, entry.angle[0], entry.angle[1], entry.fov];
+        if values.iter().any(|v| !v.is_finite()) || !(1.0..180.0).contains(&entry.fov) {
+            return Camera::default();
+        }
+        Camera {
+            pivot: entry.pivot.into(),
+            distance: entry.distance.clamp(MIN_DISTANCE, MAX_DISTANCE),
+            angle: cgmath::vec2(entry.angle[0].to_radians(), entry.angle[1].to_radians().clamp(-MAX_PITCH, MAX_PITCH)),
+            fov: cgmath::Deg(entry.fov).into(),
+            projection: entry.projection,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: cgmath::Vector3<f32>, b: cgmath::Vector3<f32>) -> bool {
+        (a - b).magnitude() < 1.0e-5
+    }
+
+    #[test]
+    fn orbit_stays_below_straight_up_and_down() {
+        let mut camera = Camera::default();
+        camera.orbit(cgmath::vec2(0.0, -1.0e6));
+        assert_eq!(camera.angle.y, MAX_PITCH);
+        camera.orbit(cgmath::vec2(0.0, 1.0e6));
+        assert_eq!(camera.angle.y, -MAX_PITCH);
+        // the view still has a horizontal direction to turn around
+        assert!(camera.direction().y > -1.0);
+    }
+
+    #[test]
+    fn zoom_stays_within_the_distance_limits() {
+        let mut camera = Camera::default();
+        let distance = camera.distance;
+        camera.zoom(100.0);
+        assert!(camera.distance < distance);
+        camera.zoom(1.0e6);
+        assert_eq!(camera.distance, MIN_DISTANCE);
+        camera.zoom(-1.0e6);
+        assert_eq!(camera.distance, MAX_DISTANCE);
+    }
+
+    #[test]
+    fn frame_centers_the_selection_and_fits_it() {
+        let mut camera = Camera::default();
+        let center = cgmath::point3(3.0, 0.5, -2.0);
+        camera.frame(center, 1.0);
+        assert_eq!(camera.pivot, center);
+        assert!(close(camera.direction(), (center - camera.position()).normalize()));
+        // the sphere around the selection fits into the field of view
+        assert!(camera.distance * (camera.fov.0 * 0.5).sin() >= 1.0 - 1.0e-5);
+    }
+
+    #[test]
+    fn top_down_looks_straight_down() {
+        let mut camera = Camera { projection: Projection::TopDown, ..Camera::default() };
+        assert_eq!(camera.direction(), -cgmath::Vector3::unit_y());
+        assert_eq!(camera.position(), camera.pivot + cgmath::Vector3::unit_y() * camera.distance);
+
+        // orbiting only turns it around the vertical axis
+        let pitch = camera.angle.y;
+        camera.orbit(cgmath::vec2(100.0, 100.0));
+        assert_eq!(camera.angle.y, pitch);
+        assert_eq!(camera.direction(), -cgmath::Vector3::unit_y());
+        // right, up and the direction towards the camera form a right-handed frame
+        assert!(close(camera.right().cross(camera.up()), cgmath::Vector3::unit_y()));
+    }
+}
@@ -1,17 +1,19 @@
-use std::{sync::Arc, sync::Mutex};
+#![allow(clippy::identity_op, clippy::erasing_op)] // keeps the vertex attribute layouts aligned
+
+use std::{collections::BTreeSet, sync::Arc, sync::Mutex};
+use cgmath::{InnerSpace, Matrix, SquareMatrix};
 use eframe::egui_glow::*;
 use glow::*;
 
-use crate::{ui_3d::shaders, simulator::Simulator};
+use crate::{ui_3d::{shaders, gizmo}, simulator::{component::PartShape, light::{Light, LightKind, MAX_LIGHTS}, terrain::Shape, Simulator, DOMINO_SIZE}};
 
 use super::RenderMatrices;
 
 trait Renderable {
     unsafe fn destroy(&self, gl: &Context);
-    unsafe fn paint(&self, gl: &Context, render_mats: &RenderMatrices, cam_pos: cgmath::Point3<f32>, light_pos: cgmath::Point3<f32>, selected_id: Option<u32>);
-    unsafe fn fill_vbo(&self, gl: &Context, render_mats: &RenderMatrices, cam_pos: cgmath::Point3<f32>, light_pos: cgmath::Point3<f32>, selected_id: Option<u32>);
-    fn is_instanced(&self) -> bool;
-    unsafe fn fill_i_vbo(&mut self, _gl: &Context, _model_mats: &Vec<(u32, cgmath::Matrix4<f32>)>, _rot_mats: &Vec<cgmath::Matrix4<f32>>) {
+    unsafe fn paint(&self, gl: &Context, render_mats: &RenderMatrices, cam_pos: cgmath::Point3<f32>, lighting: &Lighting);
+    unsafe fn fill_vbo(&self, gl: &Context, render_mats: &RenderMatrices, cam_pos: cgmath::Point3<f32>, lighting: &Lighting);
+    unsafe fn fill_i_vbo(&mut self, _gl: &Context, _model_mats: &[(u32, cgmath::Matrix4<f32>)], _normal_mats: &[cgmath::Matrix4<f32>], _colors: &[[f32; 3]], _selection: &BTreeSet<u32>, _highlighted: &BTreeSet<u32>) {
         panic!("This struct is not an instanced RenderObject and thus has no instanced vertex object");
     }
 }
@@ -27,6 +29,8 @@ struct InstancedRenderObject {
     vao: NativeVertexArray,
     vbo: NativeBuffer,
     i_vbo: NativeBuffer,
+    /// Number of vertices of the mesh that is drawn for every instance.
+    vertex_count: i32,
     render_count: usize,
 }
 
@@ -38,21 +42,19 @@ impl Renderable for InstancedRenderObject {
         gl.delete_buffer(self.i_vbo);
     }
 
-    unsafe fn paint(&self, gl: &Context, render_mats: &RenderMatrices, cam_pos: cgmath::Point3<f32>, light_pos: cgmath::Point3<f32>, selected_id: Option<u32>) {
-        self.fill_vbo(gl, render_mats, cam_pos, light_pos, selected_id);
+    unsafe fn paint(&self, gl: &Context, render_mats: &RenderMatrices, cam_pos: cgmath::Point3<f32>, lighting: &Lighting) {
+        self.fill_vbo(gl, render_mats, cam_pos, lighting);
 
-        gl.draw_arrays_instanced(TRIANGLES, 0, 12*3, self.render_count.try_into().unwrap());
+        gl.draw_arrays_instanced(TRIANGLES, 0, self.vertex_count, self.render_count.try_into().unwrap());
         // gl.draw_arrays(TRIANGLES, 0, 12*3);
         gl.bind_vertex_array(None);
     }
 
-    unsafe fn fill_vbo(&self, gl: &Context, render_mats: &RenderMatrices, cam_pos: cgmath::Point3<f32>, light_pos: cgmath::Point3<f32>, selected_id: Option<u32>) {
+    unsafe fn fill_vbo(&self, gl: &Context, render_mats: &RenderMatrices, cam_pos: cgmath::Point3<f32>, lighting: &Lighting) {
         gl.use_program(Some(self.program));
         gl.bind_vertex_array(Some(self.vao));
 
-        let light_pos_location = gl.get_uniform_location(self.program, "lightPos");
-        let light_pos: [f32; 3] = [light_pos.x, light_pos.y, light_pos.z];
-        gl.uniform_3_f32_slice(light_pos_location.as_ref(), &light_pos);
+        upload_lighting(gl, self.program, lighting);
 
         let uniform_location = gl.get_uniform_location(self.program, "view_mat");
         let f32_mat: [[f32; 4]; 4] = render_mats.view.into();
@@ -65,37 +67,33 @@ impl Renderable for InstancedRenderObject {
         let cam_pos_location = gl.get_uniform_location(self.program, "camPos");
         let cam_pos: [f32; 3] = [cam_pos.x, cam_pos.y, cam_pos.z];
         gl.uniform_3_f32_slice(cam_pos_location.as_ref(), &cam_pos);
-
-        let id_location = gl.get_uniform_location(self.program, "selected_id");
-        let selected_id: f32 = if selected_id.is_some() { selected_id.unwrap() as f32 } else { -1.0 };
-        gl.uniform_1_f32(id_location.as_ref(), selected_id);
     }
 
-    unsafe fn fill_i_vbo(&mut self, gl: &Context, model_mats: &Vec<(u32, cgmath::Matrix4<f32>)>, rot_mats: &Vec<cgmath::Matrix4<f32>>) { 
+    unsafe fn fill_i_vbo(&mut self, gl: &Context, model_mats: &[(u32, cgmath::Matrix4<f32>)], normal_mats: &[cgmath::Matrix4<f32>], colors: &[[f32; 3]], selection: &BTreeSet<u32>, highlighted: &BTreeSet<u32>) {
         gl.use_program(Some(self.program));
         gl.bind_vertex_array(Some(self.vao));
 
         self.render_count = model_mats.len();
-        upload_vertex_attrib_model_and_rot(gl, model_mats, rot_mats, 3, &self.i_vbo);
+        upload_vertex_attrib_model_and_normal(gl, model_mats, normal_mats, colors, selection, highlighted, 3, &self.i_vbo);
     }
 
-    fn is_instanced(&self) -> bool { true }
 }
 
-unsafe fn upload_vertex_attrib_model_and_rot(gl: &Context, model_mats: &Vec<(u32, cgmath::Matrix4<f32>)>, rot_mats: &Vec<cgmath::Matrix4<f32>>, location: u32, vbo: &NativeBuffer) {
+#[allow(clippy::too_many_arguments)]
+unsafe fn upload_vertex_attrib_model_and_normal(gl: &Context, model_mats: &[(u32, cgmath::Matrix4<f32>)], normal_mats: &[cgmath::Matrix4<f32>], colors: &[[f32; 3]], selection: &BTreeSet<u32>, highlighted: &BTreeSet<u32>, location: u32, vbo: &NativeBuffer) {
     // let model_mats: Vec<cgmath::Matrix4<f32>> = model_mats.iter().map(|m| m.1).collect();
-    // let mats: Vec<&cgmath::Matrix4<f32>> = model_mats.iter().zip(rot_mats.iter()).map(|t| { vec![t.0, t.1]}).flatten().collect();
+    // let mats: Vec<&cgmath::Matrix4<f32>> = model_mats.iter().zip(normal_mats.iter()).map(|t| { vec![t.0, t.1]}).flatten().collect();
     // let values: Vec<f32> = mats.iter().map(|m| { vec![m.x, m.y, m.z, m.w] }).flatten().map(|v| { vec![v.x, v.y, v.z, v.w] }).flatten().collect();
 
-    let mats: Vec<(u32, cgmath::Matrix4<f32>, &cgmath::Matrix4<f32>)> = model_mats.iter().zip(rot_mats.iter()).map(|e| (e.0.0, e.0.1, e.1)).collect();
-    let values: Vec<f32> = mats.iter().map(|e| vec![e.1.x.x, e.1.x.y, e.1.x.z, e.1.x.w, e.1.y.x, e.1.y.y, e.1.y.z, e.1.y.w, e.1.z.x, e.1.z.y, e.1.z.z, e.1.z.w, e.1.w.x, e.1.w.y, e.1.w.z, e.1.w.w,
-                                            e.2.x.x, e.2.x.y, e.2.x.z, e.2.x.w, e.2.y.x, e.2.y.y, e.2.y.z, e.2.y.w, e.2.z.x, e.2.z.y, e.2.z.z, e.2.z.w, e.2.w.x, e.2.w.y, e.2.w.z, e.2.w.w, e.0 as f32]).flatten().collect();
+    let mats = model_mats.iter().zip(normal_mats.iter()).zip(colors.iter()).map(|e| (e.0.0.0, e.0.0.1, e.0.1, e.1));
+    let values: Vec<f32> = mats.flat_map(|e| vec![e.1.x.x, e.1.x.y, e.1.x.z, e.1.x.w, e.1.y.x, e.1.y.y, e.1.y.z, e.1.y.w, e.1.z.x, e.1.z.y, e.1.z.z, e.1.z.w, e.1.w.x, e.1.w.y, e.1.w.z, e.1.w.w,
+                                            e.2.x.x, e.2.x.y, e.2.x.z, e.2.x.w, e.2.y.x, e.2.y.y, e.2.y.z, e.2.y.w, e.2.z.x, e.2.z.y, e.2.z.z, e.2.z.w, e.2.w.x, e.2.w.y, e.2.w.z, e.2.w.w, e.0 as f32,
+                                            if selection.contains(&e.0) { 1.0 } else { 0.0 }, if highlighted.contains(&e.0) { 1.0 } else { 0.0 },
+                                            e.3[0], e.3[1], e.3[2]]).collect();
 
     let values_u8: &[u8] = core::slice::from_raw_parts(
         values.as_slice().as_ptr() as *const u8,
-        (values.len()+3) * core::mem::size_of::<f32>(), // TODO: figure out why "+3" is needed. If
-                                                        // left away, last elements of values are
-                                                        // not transferred to the shader
+        values.len() * core::mem::size_of::<f32>(),
     );
 
     gl.bind_buffer(ARRAY_BUFFER, Some(*vbo));
@@ -111,9 +109,13 @@ unsafe fn upload_vertex_attrib_model_and_rot(gl: &Context, model_mats: &Vec<(u32
     gl.enable_vertex_attrib_array(location+4+3);
 
     gl.enable_vertex_attrib_array(location+4+4);
+    gl.enable_vertex_attrib_array(location+4+5);
+    gl.enable_vertex_attrib_array(location+4+6);
+    gl.enable_vertex_attrib_array(location+4+7);
 
-    let stride: i32 = 16 * 4 * 2 + 4; // 16 f32s per matrix, 4 bytes per f32, 2 matrices and one
-                                      // u32 (4 bytes) as id
+    let stride: i32 = 16 * 4 * 2 + 4 + 4 + 4 + 3 * 4; // 16 f32s per matrix, 4 bytes per f32, 2 matrices,
+                                                      // the id and whether the domino is selected and
+                                                      // highlighted (4 bytes each) and the colour
 
     gl.vertex_attrib_pointer_f32(location+0, 4, FLOAT, false, stride, 0*4*4);
     gl.vertex_attrib_pointer_f32(location+1, 4, FLOAT, false, stride, 1*4*4);
@@ -125,7 +127,10 @@ unsafe fn upload_vertex_attrib_model_and_rot(gl: &Context, model_mats: &Vec<(u32
     gl.vertex_attrib_pointer_f32(location+4+2, 4, FLOAT, false, stride, 6*4*4);
     gl.vertex_attrib_pointer_f32(location+4+3, 4, FLOAT, false, stride, 7*4*4);
 
-    gl.vertex_attrib_pointer_f32(location+4+4, 4, FLOAT, false, stride, 8*4*4);
+    gl.vertex_attrib_pointer_f32(location+4+4, 1, FLOAT, false, stride, 8*4*4);
+    gl.vertex_attrib_pointer_f32(location+4+5, 1, FLOAT, false, stride, 8*4*4 + 4);
+    gl.vertex_attrib_pointer_f32(location+4+6, 1, FLOAT, false, stride, 8*4*4 + 8);
+    gl.vertex_attrib_pointer_f32(location+4+7, 3, FLOAT, false, stride, 8*4*4 + 12);
 
     gl.bind_buffer(ARRAY_BUFFER, None);
     gl.vertex_attrib_divisor(location+0, 1); // tell OpenGL this is an instanced vertex attribute    
@@ -139,8 +144,161 @@ unsafe fn upload_vertex_attrib_model_and_rot(gl: &Context, model_mats: &Vec<(u32
     gl.vertex_attrib_divisor(location+4+3, 1);
 
     gl.vertex_attrib_divisor(location+4+4, 1);
+    gl.vertex_attrib_divisor(location+4+5, 1);
+    gl.vertex_attrib_divisor(location+4+6, 1);
+    gl.vertex_attrib_divisor(location+4+7, 1);
+}
+
+/// The lights and the shadows of the first one, as drawn in a frame.
+pub struct Lighting {
+    /// At most [`MAX_LIGHTS`] lights.
+    pub lights: Vec<Light>,
+    /// Transformation from world space into the clip space of the first light, as the shadow map
+    /// was rendered with.
+    pub light_space: cgmath::Matrix4<f32>,
+    pub shadows: bool,
+}
+
+/// Sets the uniforms of the lights and of the shadow map of `program`. The shadow map is expected
+/// to be bound to texture unit [`SHADOW_MAP_UNIT`].
+unsafe fn upload_lighting(gl: &Context, program: Program, lighting: &Lighting) {
+    let lights = &lighting.lights[..lighting.lights.len().min(MAX_LIGHTS)];
+    let count_location = gl.get_uniform_location(program, "light_count");
+    gl.uniform_1_i32(count_location.as_ref(), lights.len() as i32);
+    if !lights.is_empty() {
+        let positions: Vec<f32> = lights.iter().flat_map(|l| match l.kind {
+            LightKind::Point { position } => [position.x, position.y, position.z, 1.0],
+            LightKind::Directional { direction } => [direction.x, direction.y, direction.z, 0.0],
+        }).collect();
+        let positions_location = gl.get_uniform_location(program, "light_positions");
+        gl.uniform_4_f32_slice(positions_location.as_ref(), &positions);
+        let colors: Vec<f32> = lights.iter().flat_map(|l| l.color.map(|c| c * l.intensity)).collect();
+        let colors_location = gl.get_uniform_location(program, "light_colors");
+        gl.uniform_3_f32_slice(colors_location.as_ref(), &colors);
+    }
+
+    let light_space_location = gl.get_uniform_location(program, "light_space_mat");
+    let f32_mat: [[f32; 4]; 4] = lighting.light_space.into();
+    gl.uniform_matrix_4_f32_slice(light_space_location.as_ref(), false, f32_mat.into_iter().flatten().collect::<Vec<f32>>().as_ref());
+
+    let shadow_map_location = gl.get_uniform_location(program, "shadow_map");
+    gl.uniform_1_i32(shadow_map_location.as_ref(), SHADOW_MAP_UNIT as i32);
+    let shadows_location = gl.get_uniform_location(program, "shadows");
+    gl.uniform_1_i32(shadows_location.as_ref(), lighting.shadows as i32);
+}
+
+/// Width and height of the shadow map in texels.
+const SHADOW_MAP_SIZE: i32 = 2048;
+/// Texture unit the shadow map is bound to while the scene is drawn, unit 0 is used by egui.
+const SHADOW_MAP_UNIT: u32 = 1;
+
+/// Depth of the scene as seen from the light, to find the fragments that are in its shadow.
+struct ShadowMap {
+    program: Program,
+    framebuffer: NativeFramebuffer,
+    texture: NativeTexture,
 }
 
+impl ShadowMap {
+    unsafe fn new(gl: &Context) -> Self {
+        let program = create_program(gl, shaders::shadow::VERTEX_SHADER, shaders::shadow::FRAGMENT_SHADER);
+
+        let texture = gl.create_texture().unwrap();
+        gl.bind_texture(TEXTURE_2D, Some(texture));
+        gl.tex_image_2d(TEXTURE_2D, 0, DEPTH_COMPONENT24 as i32, SHADOW_MAP_SIZE, SHADOW_MAP_SIZE, 0, DEPTH_COMPONENT, FLOAT, None);
+        gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MIN_FILTER, NEAREST as i32);
+        gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MAG_FILTER, NEAREST as i32);
+        gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_WRAP_S, CLAMP_TO_EDGE as i32);
+        gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_WRAP_T, CLAMP_TO_EDGE as i32);
+        gl.bind_texture(TEXTURE_2D, None);
+
+        let framebuffer = gl.create_framebuffer().unwrap();
+        let previous = current_framebuffer(gl);
+        gl.bind_framebuffer(FRAMEBUFFER, Some(framebuffer));
+        gl.framebuffer_texture_2d(FRAMEBUFFER, DEPTH_ATTACHMENT, TEXTURE_2D, Some(texture), 0);
+        // only the depth is rendered
+        gl.draw_buffer(NONE);
+        gl.read_buffer(NONE);
+        gl.bind_framebuffer(FRAMEBUFFER, previous);
+
+        ShadowMap { program, framebuffer, texture }
+    }
+
+    unsafe fn destroy(&self, gl: &Context) {
+        gl.delete_program(self.program);
+        gl.delete_framebuffer(self.framebuffer);
+        gl.delete_texture(self.texture);
+    }
+
+    /// Renders the depth of the `objects` with the instances they were last filled with, as seen
+    /// through `light_space`. Restores the framebuffer, viewport and scissor test egui paints with.
+    unsafe fn render(&self, gl: &Context, light_space: cgmath::Matrix4<f32>, objects: &[&InstancedRenderObject]) {
+        let previous = current_framebuffer(gl);
+        let mut viewport = [0i32; 4];
+        gl.get_parameter_i32_slice(VIEWPORT, &mut viewport);
+        let scissor = gl.is_enabled(SCISSOR_TEST);
+
+        gl.bind_framebuffer(FRAMEBUFFER, Some(self.framebuffer));
+        gl.viewport(0, 0, SHADOW_MAP_SIZE, SHADOW_MAP_SIZE);
+        gl.disable(SCISSOR_TEST);
+        gl.enable(DEPTH_TEST);
+        gl.depth_func(LESS);
+        gl.clear(DEPTH_BUFFER_BIT);
+        // pushes the depth away from the light on slanted faces, so that lit faces do not shadow
+        // themselves
+        gl.enable(POLYGON_OFFSET_FILL);
+        gl.polygon_offset(2.0, 4.0);
+
+        gl.use_program(Some(self.program));
+        let location = gl.get_uniform_location(self.program, "light_space_mat");
+        let f32_mat: [[f32; 4]; 4] = light_space.into();
+        gl.uniform_matrix_4_f32_slice(location.as_ref(), false, f32_mat.into_iter().flatten().collect::<Vec<f32>>().as_ref());
+        for obj in objects {
+            gl.bind_vertex_array(Some(obj.vao));
+            gl.draw_arrays_instanced(TRIANGLES, 0, obj.vertex_count, obj.render_count.try_into().unwrap());
+        }
+        gl.bind_vertex_array(None);
+
+        gl.disable(POLYGON_OFFSET_FILL);
+        gl.bind_framebuffer(FRAMEBUFFER, previous);
+        gl.viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+        if scissor {
+            gl.enable(SCISSOR_TEST);
+        }
+    }
+}
+
+/// The framebuffer that is currently drawn into, `None` for the default one.
+unsafe fn current_framebuffer(gl: &Context) -> Option<NativeFramebuffer> {
+    std::num::NonZeroU32::new(gl.get_parameter_i32(DRAW_FRAMEBUFFER_BINDING) as u32).map(NativeFramebuffer)
+}
+
+/// Transformation from world space into the clip space of `light` as it renders the shadow map.
+/// A point light looks down onto the scene, a directional light covers the area around the
+/// origin.
+fn light_space_matrix(light: &Light) -> cgmath::Matrix4<f32> {
+    match light.kind {
+        LightKind::Point { position } => {
+            let view = cgmath::Matrix4::look_to_rh(position, -cgmath::Vector3::unit_y(), -cgmath::Vector3::unit_z());
+            let projection = cgmath::perspective(cgmath::Deg(SHADOW_FOV), 1.0, 0.05, 20.0);
+            projection * view
+        }
+        LightKind::Directional { direction } => {
+            let direction = direction.normalize();
+            let up = if direction.y.abs() > 0.99 { -cgmath::Vector3::unit_z() } else { cgmath::Vector3::unit_y() };
+            let view = cgmath::Matrix4::look_to_rh(cgmath::point3(0.0, 0.0, 0.0) - direction * SHADOW_EXTENT * 2.0, direction, up);
+            let projection = cgmath::ortho(-SHADOW_EXTENT, SHADOW_EXTENT, -SHADOW_EXTENT, SHADOW_EXTENT, 0.05, SHADOW_EXTENT * 4.0);
+            projection * view
+        }
+    }
+}
+
+/// Opening angle of the shadow map of a point light in degrees, fragments outside of it are
+/// always lit.
+const SHADOW_FOV: f32 = 120.0;
+/// Half the width of the area around the origin a directional light casts shadows in, in meters.
+const SHADOW_EXTENT: f32 = 5.0;
+
 impl Renderable for RenderObject {
     unsafe fn destroy(&self, gl: &Context) { 
         gl.delete_program(self.program);
@@ -148,21 +306,19 @@ impl Renderable for RenderObject {
         gl.delete_buffer(self.vbo);
     }
 
-    unsafe fn paint(&self, gl: &Context, render_mats: &RenderMatrices, cam_pos: cgmath::Point3<f32>, light_pos: cgmath::Point3<f32>, selected_id: Option<u32>) {    
-        self.fill_vbo(gl, render_mats, cam_pos, light_pos, selected_id);
+    unsafe fn paint(&self, gl: &Context, render_mats: &RenderMatrices, cam_pos: cgmath::Point3<f32>, lighting: &Lighting) {    
+        self.fill_vbo(gl, render_mats, cam_pos, lighting);
 
         gl.draw_arrays(TRIANGLES, 0, 12*3);
         // gl.draw_arrays(TRIANGLES, 0, 12*3);
         gl.bind_vertex_array(None);
     }
 
-    unsafe fn fill_vbo(&self, gl: &Context, render_mats: &RenderMatrices, cam_pos: cgmath::Point3<f32>, light_pos: cgmath::Point3<f32>, selected_id: Option<u32>) {
+    unsafe fn fill_vbo(&self, gl: &Context, render_mats: &RenderMatrices, cam_pos: cgmath::Point3<f32>, lighting: &Lighting) {
         gl.use_program(Some(self.program));
         gl.bind_vertex_array(Some(self.vao));
 
-        let light_pos_location = gl.get_uniform_location(self.program, "lightPos");
-        let light_pos: [f32; 3] = [light_pos.x, light_pos.y, light_pos.z];
-        gl.uniform_3_f32_slice(light_pos_location.as_ref(), &light_pos);
+        upload_lighting(gl, self.program, lighting);
 
         let uniform_location = gl.get_uniform_location(self.program, "view_mat");
         let f32_mat: [[f32; 4]; 4] = render_mats.view.into();
@@ -175,19 +331,84 @@ impl Renderable for RenderObject {
         let cam_pos_location = gl.get_uniform_location(self.program, "camPos");
         let cam_pos: [f32; 3] = [cam_pos.x, cam_pos.y, cam_pos.z];
         gl.uniform_3_f32_slice(cam_pos_location.as_ref(), &cam_pos);
+    }
+}
+
+impl RenderObject {
+    /// Paints the cube of the light source at `position`.
+    unsafe fn paint_light(&self, gl: &Context, render_mats: &RenderMatrices, cam_pos: cgmath::Point3<f32>, lighting: &Lighting, position: cgmath::Point3<f32>, color: [f32; 3]) {
+        self.fill_vbo(gl, render_mats, cam_pos, lighting);
+
+        let position_location = gl.get_uniform_location(self.program, "lightPos");
+        gl.uniform_3_f32_slice(position_location.as_ref(), &[position.x, position.y, position.z]);
+        let color_location = gl.get_uniform_location(self.program, "lightColor");
+        gl.uniform_3_f32_slice(color_location.as_ref(), &color);
+
+        gl.draw_arrays(TRIANGLES, 0, 12*3);
+        gl.bind_vertex_array(None);
+    }
+}
+
+/// Handles of the gizmo, drawn on top of the scene.
+struct GizmoRenderObject {
+    program: Program,
+    vao: NativeVertexArray,
+    vbo: NativeBuffer,
+    vertex_count: i32,
+}
 
-        let id_location = gl.get_uniform_location(self.program, "selected_id");
-        let selected_id: f32 = if selected_id.is_some() { selected_id.unwrap() as f32 } else { -1.0 };
-        gl.uniform_1_f32(id_location.as_ref(), selected_id);
+impl GizmoRenderObject {
+    unsafe fn destroy(&self, gl: &Context) {
+        gl.delete_program(self.program);
+        gl.delete_vertex_array(self.vao);
+        gl.delete_buffer(self.vbo);
     }
 
-    fn is_instanced(&self) -> bool { false }
+    unsafe fn paint(&self, gl: &Context, render_mats: &RenderMatrices, model_mat: cgmath::Matrix4<f32>, highlighted: Option<gizmo::Handle>) {
+        gl.use_program(Some(self.program));
+        gl.bind_vertex_array(Some(self.vao));
+
+        for (name, mat) in [("view_mat", render_mats.view), ("perspective_mat", render_mats.perspective), ("model_mat", model_mat)] {
+            let location = gl.get_uniform_location(self.program, name);
+            let f32_mat: [[f32; 4]; 4] = mat.into();
+            gl.uniform_matrix_4_f32_slice(location.as_ref(), false, f32_mat.into_iter().flatten().collect::<Vec<f32>>().as_ref());
+        }
+
+        let highlighted_location = gl.get_uniform_location(self.program, "highlighted_handle");
+        gl.uniform_1_f32(highlighted_location.as_ref(), highlighted.map_or(-1.0, |h| h.index()));
+
+        // the gizmo must stay visible and grabbable inside of dominos and below the ground
+        gl.disable(glow::DEPTH_TEST);
+        gl.draw_arrays(TRIANGLES, 0, self.vertex_count);
+        gl.enable(glow::DEPTH_TEST);
+        gl.bind_vertex_array(None);
+    }
 }
 
+/// The transformation of the gizmo and its highlighted handle.
+pub type GizmoState = (cgmath::Matrix4<f32>, Option<gizmo::Handle>);
+
+/// Model matrices tagged with the domino id and the matching matrices that transform the normals.
+pub type ModelMatsList = (Vec<(u32, cgmath::Matrix4<f32>)>, Vec<cgmath::Matrix4<f32>>);
+
+/// Like [`ModelMatsList`] with the colour of every model.
+pub type PartMatsList = (Vec<(u32, cgmath::Matrix4<f32>)>, Vec<cgmath::Matrix4<f32>>, Vec<[f32; 3]>);
+
+/// Colour of the obstacles of the terrain.
+const TERRAIN_COLOR: [f32; 3] = [0.55, 0.55, 0.5];
+
 pub struct Canvas {
     domino_obj: InstancedRenderObject,
+    /// The boxes and wedges the obstacles are made of.
+    box_obj: InstancedRenderObject,
+    wedge_obj: InstancedRenderObject,
+    /// The boxes and balls the components are drawn with.
+    part_box_obj: InstancedRenderObject,
+    sphere_obj: InstancedRenderObject,
     light_obj: RenderObject,
     ground_obj: RenderObject,
+    gizmo_obj: GizmoRenderObject,
+    shadow_map: ShadowMap,
     simulator: Arc<Mutex<Simulator>>,
 }
 
@@ -196,12 +417,23 @@ impl Canvas {
     pub fn new(gl: &Context, simulator: Arc<Mutex<Simulator>>) -> Option<Self> {
         unsafe {
             // Create a vertex buffer and vertex array object
-            let (domino_obj, light_obj, ground_obj) = init_vertex_buffer(&gl);
+            let (domino_obj, light_obj, ground_obj, gizmo_obj) = init_vertex_buffer(gl);
+            let box_obj = create_instanced_object(gl, &shaders::dominos::get_vertices(cgmath::vec3(1.0, 1.0, 1.0)));
+            let wedge_obj = create_instanced_object(gl, &shaders::terrain::WEDGE_VERTICES);
+            let part_box_obj = create_instanced_object(gl, &shaders::dominos::get_vertices(cgmath::vec3(1.0, 1.0, 1.0)));
+            let sphere_obj = create_instanced_object(gl, &shaders::components::sphere_vertices());
+            let shadow_map = ShadowMap::new(gl);
 
             Some(Self {
                 domino_obj,
+                box_obj,
+                wedge_obj,
+                part_box_obj,
+                sphere_obj,
                 light_obj,
                 ground_obj,
+                gizmo_obj,
+                shadow_map,
                 simulator,
             })
         }
@@ -210,15 +442,45 @@ impl Canvas {
     pub fn destroy(&self, gl: &Context) {
         unsafe {
             self.domino_obj.destroy(gl);
+            self.box_obj.destroy(gl);
+            self.wedge_obj.destroy(gl);
+            self.part_box_obj.destroy(gl);
+            self.sphere_obj.destroy(gl);
             self.light_obj.destroy(gl);
             self.ground_obj.destroy(gl);
+            self.gizmo_obj.destroy(gl);
+            self.shadow_map.destroy(gl);
         }
     }
 
-    pub fn paint(&mut self, gl: &Context, render_mats: RenderMatrices, cam_pos: cgmath::Point3<f32>, selected_id: Option<u32>) {
-        let light_pos = cgmath::point3(0.0f32, 2.0f32, 0.0f32);
+    #[allow(clippy::too_many_arguments)]
+    pub fn paint(&mut self, gl: &Context, render_mats: RenderMatrices, cam_pos: cgmath::Point3<f32>, selection: &BTreeSet<u32>, highlighted: &BTreeSet<u32>, selected_obstacles: &BTreeSet<u32>, selected_components: &BTreeSet<u32>, shadows: bool, gizmo: Option<GizmoState>) {
+        let lights = self.simulator.lock().unwrap().lights.clone();
+        let light_space = lights.first().map_or(cgmath::Matrix4::identity(), light_space_matrix);
+        let lighting = Lighting { shadows: shadows && !lights.is_empty(), lights, light_space };
 
         unsafe {
+            let (model_mats, normal_mats) = self.get_model_mats_list();
+            let colors: Vec<[f32; 3]> = self.simulator.lock().unwrap().dominos.iter().map(|d| d.material.color).collect();
+            self.domino_obj.fill_i_vbo(gl, &model_mats, &normal_mats, &colors, selection, highlighted);
+            let solid_mats = [Shape::Box, Shape::Wedge].map(|shape| self.get_solid_mats_list(shape));
+            for (obj, (model_mats, normal_mats)) in [&mut self.box_obj, &mut self.wedge_obj].into_iter().zip(solid_mats) {
+                let colors = vec![TERRAIN_COLOR; model_mats.len()];
+                obj.fill_i_vbo(gl, &model_mats, &normal_mats, &colors, selected_obstacles, &BTreeSet::new());
+            }
+            let part_mats = [PartShape::Box, PartShape::Sphere].map(|shape| self.get_part_mats_list(shape));
+            for (obj, (model_mats, normal_mats, colors)) in [&mut self.part_box_obj, &mut self.sphere_obj].into_iter().zip(part_mats) {
+                obj.fill_i_vbo(gl, &model_mats, &normal_mats, &colors, selected_components, &BTreeSet::new());
+            }
+            let objects = [&self.domino_obj, &self.box_obj, &self.wedge_obj, &self.part_box_obj, &self.sphere_obj];
+
+            if lighting.shadows {
+                self.shadow_map.render(gl, lighting.light_space, &objects);
+            }
+            gl.active_texture(TEXTURE0 + SHADOW_MAP_UNIT);
+            gl.bind_texture(TEXTURE_2D, Some(self.shadow_map.texture));
+            gl.active_texture(TEXTURE0);
+
             gl.enable(glow::DEPTH_TEST);
             // gl.enable(glow::CULL_FACE); TODO: might improve performance
             gl.depth_func(glow::LESS);
@@ -226,31 +488,74 @@ impl Canvas {
 
             gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT); 
 
-            let (model_mats, rot_mats) = self.get_model_mats_list();
-
-            self.domino_obj.fill_i_vbo(gl, &model_mats, &rot_mats);
-            self.domino_obj.paint(gl, &render_mats, cam_pos, light_pos, selected_id);
-            self.light_obj.paint(gl, &render_mats, cam_pos, light_pos, selected_id);
-            self.ground_obj.paint(gl, &render_mats, cam_pos, light_pos, selected_id);
+            for obj in objects {
+                obj.paint(gl, &render_mats, cam_pos, &lighting);
+            }
+            for light in lighting.lights.iter() {
+                if let LightKind::Point { position } = light.kind {
+                    self.light_obj.paint_light(gl, &render_mats, cam_pos, &lighting, position, light.color);
+                }
+            }
+            self.ground_obj.paint(gl, &render_mats, cam_pos, &lighting);
+            if let Some((model_mat, highlighted)) = gizmo {
+                self.gizmo_obj.paint(gl, &render_mats, model_mat, highlighted);
+            }
+
+            gl.active_texture(TEXTURE0 + SHADOW_MAP_UNIT);
+            gl.bind_texture(TEXTURE_2D, None);
+            gl.active_texture(TEXTURE0);
         }
     }
 
-    pub fn get_model_mats_list(&self) -> (Vec<(u32, cgmath::Matrix4<f32>)>, Vec<cgmath::Matrix4<f32>>) {
+    pub fn get_model_mats_list(&self) -> ModelMatsList {
         let mut model_mats: Vec<(u32, cgmath::Matrix4<f32>)> = vec![];
-        let mut rot_mats: Vec<cgmath::Matrix4<f32>> = vec![];
+        let mut normal_mats: Vec<cgmath::Matrix4<f32>> = vec![];
         for d in self.simulator.lock().unwrap().dominos.iter() {
-            let translation = cgmath::Matrix4::from_translation(cgmath::Vector3{ x: d.position.x, y: d.position.y, z: d.position.z });
             let scale = cgmath::Matrix4::from_nonuniform_scale(d.scale.x, d.scale.y, d.scale.z);
-            let rotation_y: cgmath::Matrix4<f32> = cgmath::Matrix4::from_angle_y(cgmath::Deg(d.rotation_y));
-            let fall_rotation_axis = rotation_y * cgmath::Vector4::unit_x();
-            let fall_rotation = cgmath::Matrix4::from_axis_angle(cgmath::Vector3 {x: fall_rotation_axis.x, y: fall_rotation_axis.y, z: fall_rotation_axis.z}, cgmath::Deg(d.fall_rotation));
-            let rotation = fall_rotation * rotation_y;
+            let model_mat = d.transform() * scale;
+            model_mats.push((d.id, model_mat));
+            normal_mats.push(normal_matrix(model_mat));
+        }
+        (model_mats, normal_mats)
+    }
 
-            model_mats.push((d.id, translation * rotation * scale));
-            rot_mats.push(rotation);
+    /// Like [`Canvas::get_model_mats_list`] for the solids of the given shape the obstacles are
+    /// made of, tagged with the id of their obstacle.
+    pub fn get_solid_mats_list(&self, shape: Shape) -> ModelMatsList {
+        let mut model_mats: Vec<(u32, cgmath::Matrix4<f32>)> = vec![];
+        let mut normal_mats: Vec<cgmath::Matrix4<f32>> = vec![];
+        for o in self.simulator.lock().unwrap().obstacles.iter() {
+            for solid in o.solids().into_iter().filter(|s| s.shape == shape) {
+                model_mats.push((o.id, solid.transform));
+                normal_mats.push(normal_matrix(solid.transform));
+            }
         }
-        (model_mats, rot_mats)
+        (model_mats, normal_mats)
     }
+
+    /// Like [`Canvas::get_model_mats_list`] for the parts of the given shape the components are
+    /// drawn with, tagged with the id of their component, and their colours.
+    pub fn get_part_mats_list(&self, shape: PartShape) -> PartMatsList {
+        let mut model_mats: Vec<(u32, cgmath::Matrix4<f32>)> = vec![];
+        let mut normal_mats: Vec<cgmath::Matrix4<f32>> = vec![];
+        let mut colors: Vec<[f32; 3]> = vec![];
+        for c in self.simulator.lock().unwrap().components.iter() {
+            for part in c.parts().into_iter().filter(|p| p.shape == shape) {
+                model_mats.push((c.id(), part.transform));
+                normal_mats.push(normal_matrix(part.transform));
+                colors.push(part.color);
+            }
+        }
+        (model_mats, normal_mats, colors)
+    }
+}
+
+/// Matrix that transforms the normals of a mesh drawn with `model_mat`.
+fn normal_matrix(model_mat: cgmath::Matrix4<f32>) -> cgmath::Matrix4<f32> {
+    // normals are transformed with the inverse transpose of the model matrix, so that they stay
+    // perpendicular to the faces of meshes that are not scaled uniformly
+    let linear = cgmath::Matrix3::from_cols(model_mat.x.truncate(), model_mat.y.truncate(), model_mat.z.truncate());
+    linear.invert().map_or(cgmath::Matrix3::identity(), |m| m.transpose()).into()
 }
 
 unsafe fn create_program(
@@ -293,13 +598,13 @@ unsafe fn create_program(
     program
 }
 
-unsafe fn init_vertex_buffer(gl: &Context) -> (InstancedRenderObject, RenderObject, RenderObject) {
+unsafe fn init_vertex_buffer(gl: &Context) -> (InstancedRenderObject, RenderObject, RenderObject, GizmoRenderObject) {
     // We now construct a vertex array to describe the format of the input buffer 
-    let domino_program = create_program(&gl, shaders::dominos::VERTEX_SHADER, shaders::dominos::FRAGMENT_SHADER);
+    let domino_program = create_program(gl, shaders::dominos::VERTEX_SHADER, shaders::dominos::FRAGMENT_SHADER);
     let domino_vao = gl.create_vertex_array().unwrap();
     gl.bind_vertex_array(Some(domino_vao));
 
-    let domino_vertices = shaders::dominos::get_vertices(cgmath::Vector3{x: 0.07, y: 0.14, z: 0.02});
+    let domino_vertices = shaders::dominos::get_vertices(DOMINO_SIZE);
     let domino_vertices_u8: &[u8] = core::slice::from_raw_parts(
         domino_vertices.as_ptr() as *const u8,
         domino_vertices.len() * core::mem::size_of::<f32>(),
@@ -317,10 +622,10 @@ unsafe fn init_vertex_buffer(gl: &Context) -> (InstancedRenderObject, RenderObje
     let domino_i_vbo = gl.create_buffer().unwrap();
 
     gl.bind_vertex_array(None);
-    let dominos = InstancedRenderObject{vbo: domino_vbo, vao: domino_vao, i_vbo: domino_i_vbo, program: domino_program, render_count: 0};
+    let dominos = InstancedRenderObject{vbo: domino_vbo, vao: domino_vao, i_vbo: domino_i_vbo, program: domino_program, vertex_count: (domino_vertices.len() / 6) as i32, render_count: 0};
 
 
-    let light_program = create_program(&gl, shaders::light_source::VERTEX_SHADER, shaders::light_source::FRAGMENT_SHADER);
+    let light_program = create_program(gl, shaders::light_source::VERTEX_SHADER, shaders::light_source::FRAGMENT_SHADER);
     let light_vao = gl.create_vertex_array().unwrap();
     gl.bind_vertex_array(Some(light_vao));
 
@@ -343,7 +648,7 @@ unsafe fn init_vertex_buffer(gl: &Context) -> (InstancedRenderObject, RenderObje
     let light = RenderObject{vbo: light_vbo, vao: light_vao, program: light_program};
 
 
-    let ground_program = create_program(&gl, shaders::ground_plane::VERTEX_SHADER, shaders::ground_plane::FRAGMENT_SHADER);
+    let ground_program = create_program(gl, shaders::ground_plane::VERTEX_SHADER, shaders::ground_plane::FRAGMENT_SHADER);
     let ground_vao = gl.create_vertex_array().unwrap();
     gl.bind_vertex_array(Some(ground_vao));
 
@@ -365,5 +670,53 @@ unsafe fn init_vertex_buffer(gl: &Context) -> (InstancedRenderObject, RenderObje
     gl.bind_vertex_array(None);
     let ground = RenderObject{vbo: ground_vbo, vao: ground_vao, program: ground_program};
 
-    (dominos, light, ground)
+
+    let gizmo_program = create_program(gl, shaders::gizmo::VERTEX_SHADER, shaders::gizmo::FRAGMENT_SHADER);
+    let gizmo_vao = gl.create_vertex_array().unwrap();
+    gl.bind_vertex_array(Some(gizmo_vao));
+
+    let gizmo_vertices = gizmo::vertices();
+    let gizmo_vertices_u8: &[u8] = core::slice::from_raw_parts(
+        gizmo_vertices.as_ptr() as *const u8,
+        gizmo_vertices.len() * core::mem::size_of::<f32>(),
+    );
+
+    let gizmo_vbo = gl.create_buffer().unwrap();
+    gl.bind_buffer(ARRAY_BUFFER, Some(gizmo_vbo));
+    gl.buffer_data_u8_slice(ARRAY_BUFFER, gizmo_vertices_u8, STATIC_DRAW);
+    gl.enable_vertex_attrib_array(0); //vec3 gizmo vertices positions
+    gl.vertex_attrib_pointer_f32(0, 3, FLOAT, false, 4*4, 0);
+    gl.enable_vertex_attrib_array(1); //float handle index
+    gl.vertex_attrib_pointer_f32(1, 1, FLOAT, false, 4*4, 3*4);
+
+    gl.bind_vertex_array(None);
+    let gizmo = GizmoRenderObject{vbo: gizmo_vbo, vao: gizmo_vao, program: gizmo_program, vertex_count: (gizmo_vertices.len() / 4) as i32};
+
+    (dominos, light, ground, gizmo)
+}
+
+/// An instanced object drawn with the shaders of the dominos, with `vertices` (position and
+/// normal) as mesh.
+unsafe fn create_instanced_object(gl: &Context, vertices: &[f32]) -> InstancedRenderObject {
+    let program = create_program(gl, shaders::dominos::VERTEX_SHADER, shaders::dominos::FRAGMENT_SHADER);
+    let vao = gl.create_vertex_array().unwrap();
+    gl.bind_vertex_array(Some(vao));
+
+    let vertices_u8: &[u8] = core::slice::from_raw_parts(
+        vertices.as_ptr() as *const u8,
+        std::mem::size_of_val(vertices),
+    );
+
+    let vbo = gl.create_buffer().unwrap();
+    gl.bind_buffer(ARRAY_BUFFER, Some(vbo));
+    gl.buffer_data_u8_slice(ARRAY_BUFFER, vertices_u8, STATIC_DRAW);
+    gl.enable_vertex_attrib_array(0); //vec3 vertices positions
+    gl.vertex_attrib_pointer_f32(0, 3, FLOAT, false, 6*4, 0);
+    gl.enable_vertex_attrib_array(1); //vec3 vertices normal
+    gl.vertex_attrib_pointer_f32(1, 3, FLOAT, false, 6*4, 3*4);
+
+    let i_vbo = gl.create_buffer().unwrap();
+
+    gl.bind_vertex_array(None);
+    InstancedRenderObject{vbo, vao, i_vbo, program, vertex_count: (vertices.len() / 6) as i32, render_count: 0}
 }
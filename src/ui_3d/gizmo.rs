@@ -0,0 +1,216 @@
+//! Translate and rotate gizmo for moving the selected dominos on the ground plane.
+//!
+//! The whole selection is moved and rotated together around its common center, the center of the
+//! base positions of the selected dominos. The gizmo is defined in its own space with the origin
+//! at that center, scaled with the distance to the camera so that it keeps its size on the
+//! screen.
+
+use cgmath::{EuclideanSpace, InnerSpace};
+
+use super::picking::ray_box;
+use crate::simulator::{terrain::{self, Obstacle}, Domino};
+
+/// Distance from the center at which the translation arrows start.
+const ARROW_START: f32 = 0.2;
+const ARROW_LENGTH: f32 = 1.0;
+const ARROW_THICKNESS: f32 = 0.03;
+const ARROW_HEAD_LENGTH: f32 = 0.2;
+const ARROW_HEAD_THICKNESS: f32 = 0.07;
+/// Half of the edge length of the square in the center that moves freely on the ground plane.
+const GROUND_HANDLE_SIZE: f32 = 0.12;
+const RING_RADIUS: f32 = 1.25;
+/// Half of the width of the rotation ring.
+const RING_WIDTH: f32 = 0.03;
+/// Half of the width of the area around the rotation ring that grabs it.
+const RING_GRAB_WIDTH: f32 = 0.08;
+const RING_SEGMENTS: usize = 64;
+/// Size of the gizmo relative to its distance to the camera.
+const SCREEN_SIZE: f32 = 0.15;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Handle {
+    TranslateX,
+    TranslateZ,
+    TranslateGround,
+    Rotate,
+}
+
+impl Handle {
+    /// Index of the handle in the vertex data and shader.
+    pub fn index(self) -> f32 {
+        match self {
+            Handle::TranslateX => 0.0,
+            Handle::TranslateZ => 1.0,
+            Handle::TranslateGround => 2.0,
+            Handle::Rotate => 3.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Snapping {
+    pub enabled: bool,
+    /// Edge length of the grid positions snap to, in meters.
+    pub grid_size: f32,
+    /// Step rotations snap to, in degrees.
+    pub angle_step: f32,
+}
+
+impl Default for Snapping {
+    fn default() -> Self {
+        Snapping { enabled: false, grid_size: 0.05, angle_step: 15.0 }
+    }
+}
+
+/// A handle of the gizmo that is being dragged.
+pub struct Drag {
+    pub handle: Handle,
+    /// The dragged dominos as they were when the drag started.
+    pub before: Vec<Domino>,
+    /// Position of the gizmo when the drag started.
+    center: cgmath::Point3<f32>,
+    /// Point on the ground plane below the cursor when the drag started.
+    start: cgmath::Point3<f32>,
+}
+
+impl Drag {
+    pub fn start(handle: Handle, dominos: Vec<Domino>, center: cgmath::Point3<f32>, ray_origin: cgmath::Point3<f32>, ray_direction: cgmath::Vector3<f32>) -> Option<Self> {
+        let start = ground_point(ray_origin, ray_direction, center.y)?;
+        Some(Drag { handle, before: dominos, center, start })
+    }
+
+    /// Moves or rotates the dragged dominos in `dominos` according to the cursor ray. Snapping
+    /// applies to the center of the gizmo, and to the rotation of a single dragged domino.
+    pub fn apply(&self, dominos: &mut [Domino], obstacles: &[Obstacle], ray_origin: cgmath::Point3<f32>, ray_direction: cgmath::Vector3<f32>, snapping: &Snapping) {
+        let current = match ground_point(ray_origin, ray_direction, self.center.y) {
+            Some(p) => p,
+            None => return,
+        };
+        let snap = |value: f32, step: f32| if snapping.enabled && step > 0.0 { (value / step).round() * step } else { value };
+        let snap_offset = |center: f32, delta: f32| snap(center + delta, snapping.grid_size) - center;
+
+        let delta = current - self.start;
+        let center = self.center;
+        let (offset, angle) = match self.handle {
+            Handle::TranslateX => (cgmath::vec3(snap_offset(center.x, delta.x), 0.0, 0.0), 0.0),
+            Handle::TranslateZ => (cgmath::vec3(0.0, 0.0, snap_offset(center.z, delta.z)), 0.0),
+            Handle::TranslateGround => (cgmath::vec3(snap_offset(center.x, delta.x), 0.0, snap_offset(center.z, delta.z)), 0.0),
+            Handle::Rotate => {
+                let angle = |p: cgmath::Point3<f32>| (p.x - center.x).atan2(p.z - center.z).to_degrees();
+                let reference = if self.before.len() == 1 { self.before[0].rotation_y } else { 0.0 };
+                let rotation = snap(reference + angle(current) - angle(self.start), snapping.angle_step) - reference;
+                (cgmath::vec3(0.0, 0.0, 0.0), rotation)
+            }
+        };
+
+        for before in &self.before {
+            if let Some(d) = dominos.iter_mut().find(|d| d.id == before.id) {
+                *d = before.clone();
+                d.rotate_around(center, angle);
+                d.position += offset;
+                // the dominos keep their height above the terrain
+                d.position.y += terrain::ground_height(obstacles, d.position.x, d.position.z)
+                    - terrain::ground_height(obstacles, before.position.x, before.position.z);
+            }
+        }
+    }
+}
+
+/// Uniform scale of a gizmo at `center` seen from `cam_pos`.
+pub fn scale(center: cgmath::Point3<f32>, cam_pos: cgmath::Point3<f32>) -> f32 {
+    (center - cam_pos).magnitude() * SCREEN_SIZE
+}
+
+/// Transformation from gizmo space to world space.
+pub fn model_matrix(center: cgmath::Point3<f32>, cam_pos: cgmath::Point3<f32>) -> cgmath::Matrix4<f32> {
+    cgmath::Matrix4::from_translation(cgmath::vec3(center.x, center.y, center.z)) * cgmath::Matrix4::from_scale(scale(center, cam_pos))
+}
+
+/// Intersection of a ray with the horizontal plane at `height`.
+pub fn ground_point(ray_origin: cgmath::Point3<f32>, ray_direction: cgmath::Vector3<f32>, height: f32) -> Option<cgmath::Point3<f32>> {
+    if ray_direction.y.abs() < f32::EPSILON {
+        return None;
+    }
+    let t = (height - ray_origin.y) / ray_direction.y;
+    if t < 0.0 {
+        return None;
+    }
+    Some(ray_origin + ray_direction * t)
+}
+
+/// The handle of a gizmo at `center` hit by the ray, the nearest one if several are hit.
+pub fn pick(center: cgmath::Point3<f32>, cam_pos: cgmath::Point3<f32>, ray_origin: cgmath::Point3<f32>, ray_direction: cgmath::Vector3<f32>) -> Option<Handle> {
+    // transform the ray into gizmo space, the direction does not need to be scaled
+    let origin = cgmath::Point3::from_vec((ray_origin - center) / scale(center, cam_pos));
+    let direction = ray_direction;
+    let mut hits: Vec<(f32, Handle)> = vec![];
+
+    let (start, end, thickness) = (ARROW_START, ARROW_LENGTH + ARROW_HEAD_LENGTH, ARROW_HEAD_THICKNESS);
+    if let Some(t) = ray_box(origin, direction, cgmath::point3(start, -thickness, -thickness), cgmath::point3(end, thickness, thickness)) {
+        hits.push((t, Handle::TranslateX));
+    }
+    if let Some(t) = ray_box(origin, direction, cgmath::point3(-thickness, -thickness, start), cgmath::point3(thickness, thickness, end)) {
+        hits.push((t, Handle::TranslateZ));
+    }
+
+    if let Some(p) = ground_point(origin, direction, 0.0) {
+        let t = (p - origin).magnitude() / direction.magnitude();
+        if p.x.abs() <= GROUND_HANDLE_SIZE && p.z.abs() <= GROUND_HANDLE_SIZE {
+            hits.push((t, Handle::TranslateGround));
+        }
+        if (cgmath::vec2(p.x, p.z).magnitude() - RING_RADIUS).abs() <= RING_GRAB_WIDTH {
+            hits.push((t, Handle::Rotate));
+        }
+    }
+
+    hits.into_iter().min_by(|a, b| a.0.total_cmp(&b.0)).map(|(_, handle)| handle)
+}
+
+/// Triangles of the gizmo in gizmo space: the position followed by the handle index per vertex.
+pub fn vertices() -> Vec<f32> {
+    let mut vertices = vec![];
+    let shaft = (ARROW_START, ARROW_LENGTH, ARROW_THICKNESS);
+    let head = (ARROW_LENGTH, ARROW_LENGTH + ARROW_HEAD_LENGTH, ARROW_HEAD_THICKNESS);
+    for (start, end, thickness) in [shaft, head] {
+        push_box(&mut vertices, cgmath::point3(start, -thickness, -thickness), cgmath::point3(end, thickness, thickness), Handle::TranslateX);
+        push_box(&mut vertices, cgmath::point3(-thickness, -thickness, start), cgmath::point3(thickness, thickness, end), Handle::TranslateZ);
+    }
+
+    let s = GROUND_HANDLE_SIZE;
+    push_quad(&mut vertices, [cgmath::point3(-s, 0.0, -s), cgmath::point3(s, 0.0, -s), cgmath::point3(s, 0.0, s), cgmath::point3(-s, 0.0, s)], Handle::TranslateGround);
+
+    let (inner, outer) = (RING_RADIUS - RING_WIDTH, RING_RADIUS + RING_WIDTH);
+    for i in 0..RING_SEGMENTS {
+        let angle = |i: usize| 2.0 * std::f32::consts::PI * i as f32 / RING_SEGMENTS as f32;
+        let (sin0, cos0) = angle(i).sin_cos();
+        let (sin1, cos1) = angle(i + 1).sin_cos();
+        push_quad(&mut vertices, [
+            cgmath::point3(inner * cos0, 0.0, inner * sin0),
+            cgmath::point3(outer * cos0, 0.0, outer * sin0),
+            cgmath::point3(outer * cos1, 0.0, outer * sin1),
+            cgmath::point3(inner * cos1, 0.0, inner * sin1),
+        ], Handle::Rotate);
+    }
+    vertices
+}
+
+fn push_quad(vertices: &mut Vec<f32>, corners: [cgmath::Point3<f32>; 4], handle: Handle) {
+    for i in [0, 1, 2, 2, 3, 0] {
+        vertices.extend([corners[i].x, corners[i].y, corners[i].z, handle.index()]);
+    }
+}
+
+fn push_box(vertices: &mut Vec<f32>, min: cgmath::Point3<f32>, max: cgmath::Point3<f32>, handle: Handle) {
+    let corner = |x: bool, y: bool, z: bool| cgmath::point3(if x { max.x } else { min.x }, if y { max.y } else { min.y }, if z { max.z } else { min.z });
+    let faces = [
+        [corner(false, false, false), corner(true, false, false), corner(true, true, false), corner(false, true, false)],
+        [corner(false, false, true), corner(true, false, true), corner(true, true, true), corner(false, true, true)],
+        [corner(false, false, false), corner(false, true, false), corner(false, true, true), corner(false, false, true)],
+        [corner(true, false, false), corner(true, true, false), corner(true, true, true), corner(true, false, true)],
+        [corner(false, false, false), corner(true, false, false), corner(true, false, true), corner(false, false, true)],
+        [corner(false, true, false), corner(true, true, false), corner(true, true, true), corner(false, true, true)],
+    ];
+    for face in faces {
+        push_quad(vertices, face, handle);
+    }
+}
@@ -0,0 +1,87 @@
+//! Lays lines of dominos along a path the user clicks on the ground plane, standing on the
+//! terrain below the path.
+
+use crate::{history::Command, path, simulator::{Domino, Simulator, DOMINO_SIZE}};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    /// Straight segments between the points with rounded corners.
+    Polyline,
+    /// A smooth curve through the points.
+    Spline,
+}
+
+pub struct PathTool {
+    /// Clicks on the ground add points to the path instead of selecting dominos.
+    pub active: bool,
+    pub shape: Shape,
+    /// Distance between two dominos of the line, in meters.
+    pub spacing: f32,
+    points: Vec<cgmath::Point2<f32>>,
+}
+
+impl Default for PathTool {
+    fn default() -> Self {
+        PathTool { active: false, shape: Shape::Polyline, spacing: path::DEFAULT_SPACING, points: vec![] }
+    }
+}
+
+impl PathTool {
+    /// Points of the path on the ground plane, `y` is the world z axis.
+    pub fn points(&self) -> &[cgmath::Point2<f32>] {
+        &self.points
+    }
+
+    pub fn add_point(&mut self, point: cgmath::Point2<f32>) {
+        self.points.push(point);
+    }
+
+    /// Removes the last point, e.g. after a misclick.
+    pub fn remove_last_point(&mut self) {
+        self.points.pop();
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// Why the dominos of the line would not propagate a fall with the current spacing.
+    pub fn spacing_error(&self) -> Option<String> {
+        path::validate_spacing(self.spacing, DOMINO_SIZE).err()
+    }
+
+    /// Where the dominos of the line will be placed.
+    pub fn placements(&self) -> Vec<path::Placement> {
+        if self.points.len() < 2 {
+            return vec![];
+        }
+        match self.shape {
+            Shape::Polyline => path::along_polyline(&self.points, self.spacing, path::DEFAULT_CORNER_RADIUS),
+            Shape::Spline => path::along_spline(&self.points, self.spacing),
+        }
+    }
+
+    /// Adds the dominos of the line to `simulator` and starts a new path. Returns the applied
+    /// command, or `None` if the path is too short or the spacing is invalid.
+    pub fn finish(&mut self, simulator: &mut Simulator) -> Option<Command> {
+        if self.spacing_error().is_some() {
+            return None;
+        }
+        let placements = self.placements();
+        if placements.len() < 2 {
+            return None;
+        }
+
+        let first_id = simulator.next_id();
+        let dominos = placements.iter().zip(first_id..)
+            .map(|(p, id)| {
+                let height = simulator.ground_height(p.position.x, p.position.y);
+                Domino::new(id, cgmath::point3(p.position.x, height, p.position.y), p.rotation_y)
+            })
+            .collect();
+        let command = Command::Create { dominos, ports: vec![] };
+        command.apply(simulator);
+        self.points.clear();
+        Some(command)
+    }
+}
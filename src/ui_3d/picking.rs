@@ -0,0 +1,163 @@
+//! Finds what is below the cursor by casting a ray from the camera through the clicked pixel.
+//!
+//! The ray is unprojected through the inverse of the view-projection matrix the scene is drawn
+//! with, so it matches the picture for every field of view, aspect ratio and projection.
+
+use cgmath::{InnerSpace, SquareMatrix, Transform};
+
+/// Origin and unit direction of the ray through `pos` on the screen, where the scene is drawn into
+/// `rect` with `view_projection`. The ray starts on the near plane. `None` if the matrix can not
+/// be inverted, e.g. for an empty `rect`.
+pub fn unproject(view_projection: cgmath::Matrix4<f32>, rect: egui::Rect, pos: egui::Pos2) -> Option<(cgmath::Point3<f32>, cgmath::Vector3<f32>)> {
+    let inverse = view_projection.invert()?;
+    // normalized device coordinates, y points up
+    let x = (pos.x - rect.left()) / rect.width() * 2.0 - 1.0;
+    let y = 1.0 - (pos.y - rect.top()) / rect.height() * 2.0;
+    let near = inverse.transform_point(cgmath::point3(x, y, -1.0));
+    let far = inverse.transform_point(cgmath::point3(x, y, 1.0));
+    let direction = far - near;
+    if !direction.magnitude2().is_normal() {
+        return None;
+    }
+    Some((near, direction.normalize()))
+}
+
+/// Distance along the ray to the axis aligned box from `min` to `max`, measured in multiples of
+/// `direction`. `0.0` if the ray starts inside the box.
+pub fn ray_box(origin: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>, min: cgmath::Point3<f32>, max: cgmath::Point3<f32>) -> Option<f32> {
+    let mut t_min = 0.0f32;
+    let mut t_max = f32::MAX;
+    for axis in 0..3 {
+        if direction[axis].abs() < f32::EPSILON {
+            if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let mut t1 = (min[axis] - origin[axis]) / direction[axis];
+        let mut t2 = (max[axis] - origin[axis]) / direction[axis];
+        if t1 > t2 {
+            std::mem::swap(&mut t1, &mut t2);
+        }
+        t_min = t_min.max(t1);
+        t_max = t_max.min(t2);
+        if t_max < t_min {
+            return None;
+        }
+    }
+    Some(t_min)
+}
+
+/// Like [`ray_box`] for the box from `min` to `max` in model space, placed in the world by
+/// `model_mat`, which may rotate and scale it.
+pub fn ray_obb(origin: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>, model_mat: cgmath::Matrix4<f32>, min: cgmath::Point3<f32>, max: cgmath::Point3<f32>) -> Option<f32> {
+    // an affine transformation keeps the distance along the ray in multiples of the direction
+    let inverse = model_mat.invert()?;
+    ray_box(inverse.transform_point(origin), inverse.transform_vector(direction), min, max)
+}
+
+/// The id and distance of the nearest of the boxes from `min` to `max` placed by the model
+/// matrices that the ray hits.
+pub fn nearest_hit(origin: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>, model_mats: impl IntoIterator<Item = (u32, cgmath::Matrix4<f32>)>, min: cgmath::Point3<f32>, max: cgmath::Point3<f32>) -> Option<(u32, f32)> {
+    model_mats.into_iter()
+        .filter_map(|(id, model_mat)| ray_obb(origin, direction, model_mat, min, max).map(|t| (id, t)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// Distance from `origin` to `point` in multiples of `direction`.
+pub fn distance_along(origin: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>, point: cgmath::Point3<f32>) -> f32 {
+    (point - origin).magnitude() / direction.magnitude()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn rect() -> egui::Rect {
+        egui::Rect::from_min_size(egui::pos2(10.0, 20.0), egui::vec2(800.0, 600.0))
+    }
+
+    fn view_projection(eye: cgmath::Point3<f32>, target: cgmath::Point3<f32>) -> cgmath::Matrix4<f32> {
+        let projection = cgmath::perspective(cgmath::Deg(60.0), 800.0 / 600.0, 0.1, 100.0);
+        projection * cgmath::Matrix4::look_at_rh(eye, target, cgmath::Vector3::unit_y())
+    }
+
+    fn unit_box() -> (cgmath::Point3<f32>, cgmath::Point3<f32>) {
+        (cgmath::point3(-0.5, -0.5, -0.5), cgmath::point3(0.5, 0.5, 0.5))
+    }
+
+    #[test]
+    fn center_of_the_screen_looks_along_the_view_direction() {
+        let eye = cgmath::point3(1.0, 2.0, 2.0);
+        let target = cgmath::point3(0.0, 0.0, 0.0);
+        let (origin, direction) = unproject(view_projection(eye, target), rect(), rect().center()).unwrap();
+        assert!((direction - (target - eye).normalize()).magnitude() < EPSILON);
+        // the ray starts on the near plane in front of the camera
+        assert!((distance_along(eye, direction, origin) - 0.1).abs() < 0.01);
+    }
+
+    #[test]
+    fn ray_passes_through_the_projected_point() {
+        let eye = cgmath::point3(1.0, 2.0, 2.0);
+        let view_projection = view_projection(eye, cgmath::point3(0.0, 0.0, 0.0));
+        let rect = rect();
+        for point in [cgmath::point3(0.3f32, 0.1, -0.4), cgmath::point3(-1.0, 0.0, 0.5), cgmath::point3(0.0, 0.8, 0.0)] {
+            let clip = view_projection * point.to_homogeneous();
+            let pos = egui::pos2(
+                rect.left() + (clip.x / clip.w + 1.0) * 0.5 * rect.width(),
+                rect.top() + (1.0 - clip.y / clip.w) * 0.5 * rect.height(),
+            );
+            let (origin, direction) = unproject(view_projection, rect, pos).unwrap();
+            let to_point = point - origin;
+            let off_ray = to_point - direction * to_point.dot(direction);
+            assert!(off_ray.magnitude() < EPSILON, "{:?} is {} off the ray", point, off_ray.magnitude());
+        }
+    }
+
+    #[test]
+    fn empty_rect_has_no_ray() {
+        let rect = egui::Rect::from_min_size(egui::pos2(0.0, 0.0), egui::vec2(0.0, 0.0));
+        assert!(unproject(cgmath::Matrix4::from_scale(0.0), rect, rect.center()).is_none());
+    }
+
+    #[test]
+    fn ray_hits_the_near_face_of_a_box() {
+        let (min, max) = unit_box();
+        let t = ray_box(cgmath::point3(0.0, 0.0, 5.0), -cgmath::Vector3::unit_z(), min, max).unwrap();
+        assert!((t - 4.5).abs() < EPSILON);
+        assert_eq!(ray_box(cgmath::point3(0.0, 0.0, 0.0), cgmath::Vector3::unit_x(), min, max), Some(0.0));
+        assert!(ray_box(cgmath::point3(0.0, 2.0, 5.0), -cgmath::Vector3::unit_z(), min, max).is_none());
+        // pointing away from the box
+        assert!(ray_box(cgmath::point3(0.0, 0.0, 5.0), cgmath::Vector3::unit_z(), min, max).is_none());
+    }
+
+    #[test]
+    fn ray_hits_a_rotated_and_scaled_box() {
+        let (min, max) = unit_box();
+        let model_mat = cgmath::Matrix4::from_translation(cgmath::vec3(0.0, 0.0, -2.0))
+            * cgmath::Matrix4::from_angle_y(cgmath::Deg(45.0))
+            * cgmath::Matrix4::from_nonuniform_scale(2.0, 1.0, 2.0);
+        let t = ray_obb(cgmath::point3(0.0, 0.0, 5.0), -cgmath::Vector3::unit_z(), model_mat, min, max).unwrap();
+        // the corner of the rotated square points at the ray
+        assert!((t - (7.0 - 2.0f32.sqrt())).abs() < EPSILON);
+        // the scaled box is wider than the unit box
+        assert!(ray_obb(cgmath::point3(0.9, 0.0, 5.0), -cgmath::Vector3::unit_z(), model_mat, min, max).is_some());
+        assert!(ray_obb(cgmath::point3(1.5, 0.0, 5.0), -cgmath::Vector3::unit_z(), model_mat, min, max).is_none());
+    }
+
+    #[test]
+    fn nearest_hit_ignores_the_order_of_the_boxes() {
+        let (min, max) = unit_box();
+        let at = |z: f32| cgmath::Matrix4::from_translation(cgmath::vec3(0.0, 0.0, z));
+        let missed = cgmath::Matrix4::from_translation(cgmath::vec3(3.0, 0.0, 0.0));
+        let boxes = [(1, missed), (2, at(-3.0)), (3, at(0.0)), (4, at(-6.0))];
+        let origin = cgmath::point3(0.0, 0.0, 5.0);
+        let direction = -cgmath::Vector3::unit_z();
+        let (id, t) = nearest_hit(origin, direction, boxes, min, max).unwrap();
+        assert_eq!(id, 3);
+        assert!((t - 4.5).abs() < EPSILON);
+        assert!(nearest_hit(origin, direction, [(1, missed)], min, max).is_none());
+    }
+}
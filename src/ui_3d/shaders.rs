@@ -1,10 +1,47 @@
+/// The shadow lookup of the fragment shaders of lit meshes, spliced into them with `concat!`.
+macro_rules! shadow_glsl {
+    () => {
+        r#"
+        uniform mat4 light_space_mat;
+        uniform sampler2D shadow_map;
+        uniform bool shadows;
+
+        // 1.0 if the fragment is in the shadow of the first light and 0.0 if it is lit, averaged over
+        // the neighbouring texels of the shadow map to soften the edges
+        float shadow(vec3 fragPos)
+        {
+            if (!shadows) {
+                return 0.0;
+            }
+            vec4 lightSpace = light_space_mat * vec4(fragPos, 1.0);
+            vec3 coords = lightSpace.xyz / lightSpace.w * 0.5 + 0.5;
+            if (lightSpace.w <= 0.0 || coords.x < 0.0 || coords.x > 1.0 || coords.y < 0.0 || coords.y > 1.0 || coords.z > 1.0) {
+                return 0.0;
+            }
+            vec2 texel = 1.0 / vec2(textureSize(shadow_map, 0));
+            float sum = 0.0;
+            for (int x = -1; x <= 1; x++) {
+                for (int y = -1; y <= 1; y++) {
+                    float closest = texture(shadow_map, coords.xy + vec2(x, y) * texel).r;
+                    sum += coords.z - 0.00002 > closest ? 1.0 : 0.0;
+                }
+            }
+            return sum / 9.0;
+        }
+        "#
+    };
+}
+
 pub mod dominos {
     pub const VERTEX_SHADER: &str = r#"#version 330 core
         layout (location = 0) in vec3 pos_model_space;
         layout (location = 1) in vec3 aNormal;
         layout (location = 3) in mat4 model_mat;
-        layout (location = 7) in mat4 rot_mat;
+        layout (location = 7) in mat4 normal_mat;
         layout (location = 11) in float id;
+        layout (location = 12) in float selected;
+        layout (location = 13) in float highlighted;
+        layout (location = 14) in vec3 color;
 
         uniform mat4 view_mat;
         uniform mat4 perspective_mat;
@@ -12,56 +49,83 @@ pub mod dominos {
         out vec3 Normal;
         out vec3 FragPos;
         flat out float out_id;
+        flat out float out_selected;
+        flat out float out_highlighted;
+        flat out vec3 out_color;
 
         void main()
         {
-            Normal = vec3(rot_mat * vec4(aNormal, 1.0));
+            Normal = mat3(normal_mat) * aNormal;
             vec3 pos_world_space = pos_model_space;
             FragPos = vec3(model_mat * vec4(pos_world_space, 1.0));
             mat4 mvp = perspective_mat * view_mat * model_mat;
             gl_Position = mvp * vec4(pos_world_space, 1.0);
 
             out_id = id;
+            out_selected = selected;
+            out_highlighted = highlighted;
+            out_color = color;
         }
         "#;
-    pub const FRAGMENT_SHADER: &str =
+    pub const FRAGMENT_SHADER: &str = concat!(
         r#"#version 330 core
         out vec4 FragColor;
 
         in vec3 Normal;
         in vec3 FragPos;
         flat in float out_id;
-
-        uniform vec3 lightPos;
+        flat in float out_selected;
+        flat in float out_highlighted;
+        flat in vec3 out_color;
+
+        // must match `simulator::light::MAX_LIGHTS`
+        #define MAX_LIGHTS 8
+
+        uniform int light_count;
+        // the position of a point light (w = 1) or the direction a directional light shines in
+        // (w = 0)
+        uniform vec4 light_positions[MAX_LIGHTS];
+        // the colour of a light scaled by its intensity
+        uniform vec3 light_colors[MAX_LIGHTS];
         uniform vec3 camPos;
-        uniform float selected_id;
-
+        "#,
+        shadow_glsl!(),
+        r#"
         void main()
         {
-            vec3 lightColor = vec3(1.0, 1.0, 1.0);
-            vec3 objectColor = vec3(1.0, 0.0, 0.0); 
-            if (selected_id == out_id) {
+            vec3 objectColor = out_color;
+            if (out_selected > 0.5) {
                 objectColor = vec3(0.0, 0.0, 1.0);
+            } else if (out_highlighted > 0.5) {
+                objectColor = vec3(1.0, 0.8, 0.0);
             }
 
             float ambientStrength = 0.1;
-            vec3 ambient = ambientStrength * lightColor;
+            vec3 ambient = ambientStrength * vec3(1.0, 1.0, 1.0);
 
             vec3 norm = normalize(Normal);
-            vec3 lightDir = normalize(lightPos - FragPos);
-            float diff = max(dot(norm, lightDir), 0.0);
-            vec3 diffuse = diff * lightColor;
-
-            float specularStrength = 0.5;
             vec3 viewDir = normalize(camPos - FragPos);
-            vec3 reflectDir = reflect(-lightDir, norm);
-            float spec = pow(max(dot(viewDir, reflectDir), 0.0), 32);
-            vec3 specular = specularStrength * spec * lightColor;
+            float specularStrength = 0.5;
+            vec3 lit = vec3(0.0, 0.0, 0.0);
+            for (int i = 0; i < light_count; i++) {
+                vec4 light = light_positions[i];
+                vec3 lightDir = light.w > 0.5 ? normalize(light.xyz - FragPos) : normalize(-light.xyz);
+                float diff = max(dot(norm, lightDir), 0.0);
+                vec3 reflectDir = reflect(-lightDir, norm);
+                float spec = pow(max(dot(viewDir, reflectDir), 0.0), 32);
+                vec3 contribution = (diff + specularStrength * spec) * light_colors[i];
+                // only the first light casts shadows
+                if (i == 0) {
+                    contribution *= 1.0 - shadow(FragPos);
+                }
+                lit += contribution;
+            }
 
-            vec3 result = (diffuse + ambient + specular) * objectColor;
+            vec3 result = (ambient + lit) * objectColor;
             FragColor = vec4(result, 1.0);
         }
-        "#;
+        "#
+    );
 
     pub fn get_vertices(dimensions: cgmath::Vector3<f32>) -> [f32; 216] {
         [
@@ -129,9 +193,11 @@ pub mod light_source {
         r#"#version 330 core
         out vec4 FragColor;
 
+        uniform vec3 lightColor;
+
         void main()
         {
-            FragColor = vec4(1.0, 1.0, 1.0, 1.0);
+            FragColor = vec4(lightColor, 1.0);
         }
         "#;
 
@@ -200,39 +266,56 @@ pub mod ground_plane {
             gl_Position = mvp * vec4(pos_world_space, 1.0);
         }
         "#;
-    pub const FRAGMENT_SHADER: &str =
+    pub const FRAGMENT_SHADER: &str = concat!(
         r#"#version 330 core
         out vec4 FragColor;
 
         in vec3 Normal;
         in vec3 FragPos;
 
-        uniform vec3 lightPos;
-        uniform vec3 camPos;
+        // must match `simulator::light::MAX_LIGHTS`
+        #define MAX_LIGHTS 8
 
+        uniform int light_count;
+        // the position of a point light (w = 1) or the direction a directional light shines in
+        // (w = 0)
+        uniform vec4 light_positions[MAX_LIGHTS];
+        // the colour of a light scaled by its intensity
+        uniform vec3 light_colors[MAX_LIGHTS];
+        uniform vec3 camPos;
+        "#,
+        shadow_glsl!(),
+        r#"
         void main()
         {
-            vec3 lightColor = vec3(1.0, 1.0, 1.0);
             vec3 objectColor = vec3(0.0, 1.0, 1.0);
-            
+
             float ambientStrength = 0.1;
-            vec3 ambient = ambientStrength * lightColor;
+            vec3 ambient = ambientStrength * vec3(1.0, 1.0, 1.0);
 
             vec3 norm = normalize(Normal);
-            vec3 lightDir = normalize(lightPos - FragPos);
-            float diff = max(dot(norm, lightDir), 0.0);
-            vec3 diffuse = diff * lightColor;
-
-            float specularStrength = 0.5;
             vec3 viewDir = normalize(camPos - FragPos);
-            vec3 reflectDir = reflect(-lightDir, norm);
-            float spec = pow(max(dot(viewDir, reflectDir), 0.0), 32);
-            vec3 specular = specularStrength * spec * lightColor;
+            float specularStrength = 0.5;
+            vec3 lit = vec3(0.0, 0.0, 0.0);
+            for (int i = 0; i < light_count; i++) {
+                vec4 light = light_positions[i];
+                vec3 lightDir = light.w > 0.5 ? normalize(light.xyz - FragPos) : normalize(-light.xyz);
+                float diff = max(dot(norm, lightDir), 0.0);
+                vec3 reflectDir = reflect(-lightDir, norm);
+                float spec = pow(max(dot(viewDir, reflectDir), 0.0), 32);
+                vec3 contribution = (diff + specularStrength * spec) * light_colors[i];
+                // only the first light casts shadows
+                if (i == 0) {
+                    contribution *= 1.0 - shadow(FragPos);
+                }
+                lit += contribution;
+            }
 
-            vec3 result = (diffuse + ambient + specular) * objectColor;
+            vec3 result = (ambient + lit) * objectColor;
             FragColor = vec4(result, 1.0);
         }
-        "#;
+        "#
+    );
 
     pub const VERTICES: [f32; 36] = [
         -20f32,  0f32, -20f32,  0.0f32,  1.0f32,  0.0f32,
@@ -243,3 +326,135 @@ pub mod ground_plane {
         -20f32,  0f32, -20f32,  0.0f32,  1.0f32,  0.0f32
     ];
 }
+
+/// Renders only the depth of the instanced meshes as seen from the light, into the shadow map.
+pub mod shadow {
+    pub const VERTEX_SHADER: &str = r#"#version 330 core
+        layout (location = 0) in vec3 pos_model_space;
+        layout (location = 3) in mat4 model_mat;
+
+        uniform mat4 light_space_mat;
+
+        void main()
+        {
+            gl_Position = light_space_mat * model_mat * vec4(pos_model_space, 1.0);
+        }
+        "#;
+    pub const FRAGMENT_SHADER: &str =
+        r#"#version 330 core
+
+        void main()
+        {
+        }
+        "#;
+}
+
+/// Meshes of the obstacles of the terrain, drawn with the shaders of the dominos. Boxes use
+/// [`dominos::get_vertices`] with a size of 1.
+pub mod terrain {
+    /// The unit wedge, rising from y = 0 at z = -0.5 to y = 1 at z = 0.5.
+    pub const WEDGE_VERTICES: [f32; 144] = [
+        -0.5f32, 0.0f32, -0.5f32,  0.0f32, -1.0f32,  0.0f32,
+         0.5f32, 0.0f32, -0.5f32,  0.0f32, -1.0f32,  0.0f32,
+         0.5f32, 0.0f32,  0.5f32,  0.0f32, -1.0f32,  0.0f32,
+         0.5f32, 0.0f32,  0.5f32,  0.0f32, -1.0f32,  0.0f32,
+        -0.5f32, 0.0f32,  0.5f32,  0.0f32, -1.0f32,  0.0f32,
+        -0.5f32, 0.0f32, -0.5f32,  0.0f32, -1.0f32,  0.0f32,
+
+        -0.5f32, 0.0f32,  0.5f32,  0.0f32,  0.0f32,  1.0f32,
+         0.5f32, 0.0f32,  0.5f32,  0.0f32,  0.0f32,  1.0f32,
+         0.5f32, 1.0f32,  0.5f32,  0.0f32,  0.0f32,  1.0f32,
+         0.5f32, 1.0f32,  0.5f32,  0.0f32,  0.0f32,  1.0f32,
+        -0.5f32, 1.0f32,  0.5f32,  0.0f32,  0.0f32,  1.0f32,
+        -0.5f32, 0.0f32,  0.5f32,  0.0f32,  0.0f32,  1.0f32,
+
+        -0.5f32, 0.0f32, -0.5f32,  0.0f32,  0.70710677f32, -0.70710677f32,
+         0.5f32, 0.0f32, -0.5f32,  0.0f32,  0.70710677f32, -0.70710677f32,
+         0.5f32, 1.0f32,  0.5f32,  0.0f32,  0.70710677f32, -0.70710677f32,
+         0.5f32, 1.0f32,  0.5f32,  0.0f32,  0.70710677f32, -0.70710677f32,
+        -0.5f32, 1.0f32,  0.5f32,  0.0f32,  0.70710677f32, -0.70710677f32,
+        -0.5f32, 0.0f32, -0.5f32,  0.0f32,  0.70710677f32, -0.70710677f32,
+
+        -0.5f32, 0.0f32, -0.5f32, -1.0f32,  0.0f32,  0.0f32,
+        -0.5f32, 0.0f32,  0.5f32, -1.0f32,  0.0f32,  0.0f32,
+        -0.5f32, 1.0f32,  0.5f32, -1.0f32,  0.0f32,  0.0f32,
+
+         0.5f32, 0.0f32, -0.5f32,  1.0f32,  0.0f32,  0.0f32,
+         0.5f32, 0.0f32,  0.5f32,  1.0f32,  0.0f32,  0.0f32,
+         0.5f32, 1.0f32,  0.5f32,  1.0f32,  0.0f32,  0.0f32
+    ];
+}
+
+/// Meshes of the mechanical components, drawn with the shaders of the dominos. Boxes use
+/// [`dominos::get_vertices`] with a size of 1.
+pub mod components {
+    /// Number of rings from pole to pole and of segments around the vertical axis of the sphere.
+    const SPHERE_RINGS: usize = 12;
+    const SPHERE_SEGMENTS: usize = 24;
+
+    /// Triangles of the sphere with a radius of 1 around the origin, with the position and the
+    /// normal of every vertex.
+    pub fn sphere_vertices() -> Vec<f32> {
+        let point = |ring: usize, segment: usize| {
+            let (polar, azimuth) = (std::f32::consts::PI * ring as f32 / SPHERE_RINGS as f32, std::f32::consts::TAU * segment as f32 / SPHERE_SEGMENTS as f32);
+            [polar.sin() * azimuth.cos(), polar.cos(), polar.sin() * azimuth.sin()]
+        };
+        let mut vertices = Vec::with_capacity(SPHERE_RINGS * SPHERE_SEGMENTS * 6 * 6);
+        for ring in 0..SPHERE_RINGS {
+            for segment in 0..SPHERE_SEGMENTS {
+                let corners = [point(ring, segment), point(ring + 1, segment), point(ring + 1, segment + 1), point(ring, segment + 1)];
+                for i in [0, 1, 2, 2, 3, 0] {
+                    // on the unit sphere the normal is the position
+                    vertices.extend_from_slice(&corners[i]);
+                    vertices.extend_from_slice(&corners[i]);
+                }
+            }
+        }
+        vertices
+    }
+}
+
+pub mod gizmo {
+    pub const VERTEX_SHADER: &str = r#"#version 330 core
+        layout (location = 0) in vec3 pos_model_space;
+        layout (location = 1) in float handle;
+
+        uniform mat4 view_mat;
+        uniform mat4 perspective_mat;
+        uniform mat4 model_mat;
+
+        flat out float out_handle;
+
+        void main()
+        {
+            out_handle = handle;
+            mat4 mvp = perspective_mat * view_mat * model_mat;
+            gl_Position = mvp * vec4(pos_model_space, 1.0);
+        }
+        "#;
+    pub const FRAGMENT_SHADER: &str = 
+        r#"#version 330 core
+        out vec4 FragColor;
+        flat in float out_handle;
+
+        uniform float highlighted_handle;
+
+        void main()
+        {
+            vec3 color;
+            if (out_handle < 0.5) {
+                color = vec3(0.9, 0.2, 0.2); // x axis
+            } else if (out_handle < 1.5) {
+                color = vec3(0.2, 0.3, 0.9); // z axis
+            } else if (out_handle < 2.5) {
+                color = vec3(0.9, 0.9, 0.9); // ground plane
+            } else {
+                color = vec3(0.9, 0.8, 0.2); // rotation
+            }
+            if (abs(out_handle - highlighted_handle) < 0.5) {
+                color = mix(color, vec3(1.0, 1.0, 1.0), 0.6);
+            }
+            FragColor = vec4(color, 1.0);
+        }
+        "#;
+}
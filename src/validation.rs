@@ -0,0 +1,183 @@
+//! Checks of a layout for mistakes that break its domino chains, before it is simulated.
+//!
+//! The chains are followed along the [`KnockGraph`] of the layout, a chain that ends before an
+//! output port although there are more dominos ahead of it is reported.
+
+use std::fmt;
+
+use cgmath::InnerSpace;
+
+use crate::{knock::{self, KnockGraph}, simulator::{PortDirection, Simulator}};
+
+/// Height above (or depth below) the ground plane or the terrain from which on a domino counts as
+/// floating (or sunk), in meters.
+const FLOATING_HEIGHT: f32 = 0.001;
+/// Depth two dominos have to overlap by to be reported, so that dominos which only touch are not.
+const OVERLAP_TOLERANCE: f32 = 0.0005;
+/// Distance up to which the stone after the end of a chain is looked for, relative to the height
+/// of the last domino.
+const NEXT_STONE_DISTANCE_PER_HEIGHT: f32 = 2.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Problem {
+    /// The next domino of the chain is too far away to be hit.
+    GapTooLarge { next: u32, gap: f32 },
+    /// The domino intersects another one.
+    Overlap { other: u32 },
+    /// The domino falls to a side where it does not hit the next domino of the chain.
+    WrongDirection { next: u32 },
+    /// The domino starts a chain that no chain from an input port reaches. Only reported for
+    /// layouts with input ports, without them every chain is started by hand.
+    Unreachable,
+    /// The domino does not stand on the ground or the terrain.
+    Floating { height: f32 },
+    /// The base of the domino is below the surface of the ground or the terrain.
+    Sunk { depth: f32 },
+}
+
+/// A problem of a single domino.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Issue {
+    pub domino_id: u32,
+    pub problem: Problem,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let id = self.domino_id;
+        match self.problem {
+            Problem::GapTooLarge { next, gap } => write!(f, "domino {} is {:.3} m away from domino {}, too far to knock it over", id, gap, next),
+            Problem::Overlap { other } => write!(f, "domino {} intersects domino {}", id, other),
+            Problem::WrongDirection { next } => write!(f, "domino {} falls away from the next domino {}", id, next),
+            Problem::Unreachable => write!(f, "domino {} starts a chain that is not reached from any input port", id),
+            Problem::Floating { height } => write!(f, "domino {} floats {:.3} m above the ground", id, height),
+            Problem::Sunk { depth } => write!(f, "domino {} is sunk {:.3} m into the ground", id, depth),
+        }
+    }
+}
+
+/// Checks the layout and returns its issues. [`Problem::Unreachable`] chains are only looked for
+/// if the layout has an input port.
+pub fn validate(simulator: &Simulator) -> Vec<Issue> {
+    let dominos = &simulator.dominos;
+    let mut issues = vec![];
+
+    for d in dominos {
+        let height = d.position.y - simulator.ground_height(d.position.x, d.position.z);
+        if height > FLOATING_HEIGHT {
+            issues.push(Issue { domino_id: d.id, problem: Problem::Floating { height } });
+        } else if height < -FLOATING_HEIGHT {
+            issues.push(Issue { domino_id: d.id, problem: Problem::Sunk { depth: -height } });
+        }
+    }
+
+    let obbs: Vec<_> = dominos.iter().map(|d| d.obb()).collect();
+    for a in 0..dominos.len() {
+        for b in a + 1..dominos.len() {
+            if obbs[a].intersects(&obbs[b], OVERLAP_TOLERANCE) {
+                issues.push(Issue { domino_id: dominos[a].id, problem: Problem::Overlap { other: dominos[b].id } });
+            }
+        }
+    }
+
+    let graph = KnockGraph::new(simulator);
+    if simulator.ports.iter().any(|p| p.direction == PortDirection::Input) {
+        for start in &graph.unconnected_starts {
+            issues.push(Issue { domino_id: dominos[*start].id, problem: Problem::Unreachable });
+        }
+    }
+
+    let is_output = |id: u32| simulator.ports.iter().any(|p| p.direction == PortDirection::Output && p.domino_id == id);
+    for fall in graph.falls.iter().filter(|f| f.knocks.is_empty()) {
+        let a = fall.index;
+        if is_output(dominos[a].id) {
+            continue;
+        }
+        // the chain ends here, unless a domino further along it was missed
+        let reach = NEXT_STONE_DISTANCE_PER_HEIGHT * dominos[a].dimensions().y;
+        let offset = |b: usize| cgmath::vec2(dominos[b].position.x - dominos[a].position.x, dominos[b].position.z - dominos[a].position.z);
+        let next = (0..dominos.len())
+            .filter(|b| *b != a && offset(*b).dot(fall.arrival) > 0.0 && offset(*b).magnitude() <= reach)
+            .min_by(|b, c| offset(*b).magnitude().total_cmp(&offset(*c).magnitude()));
+        if let Some(b) = next {
+            let problem = match knock::gap_in_front(&dominos[a], fall.side, &dominos[b]) {
+                Some(gap) => Problem::GapTooLarge { next: dominos[b].id, gap },
+                None => Problem::WrongDirection { next: dominos[b].id },
+            };
+            issues.push(Issue { domino_id: dominos[a].id, problem });
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{path::DEFAULT_SPACING, simulator::{Domino, Port, DOMINO_SIZE}};
+
+    /// A line of `count` dominos along the z axis starting at (`x`, `z`), falling towards +z.
+    fn line(first_id: u32, x: f32, z: f32, count: u32) -> Vec<Domino> {
+        (0..count).map(|i| Domino::new(first_id + i, cgmath::point3(x, 0.0, z + i as f32 * DEFAULT_SPACING), 0.0)).collect()
+    }
+
+    fn simulator(dominos: Vec<Domino>, ports: &[(u32, PortDirection)]) -> Simulator {
+        let ports = ports.iter().enumerate().map(|(i, (id, direction))| Port { name: i.to_string(), domino_id: *id, direction: *direction }).collect();
+        Simulator { dominos, ports, ..Simulator::empty() }
+    }
+
+    #[test]
+    fn connected_line_has_no_issues() {
+        let s = simulator(line(0, 0.0, 0.0, 5), &[(0, PortDirection::Input), (4, PortDirection::Output)]);
+        assert_eq!(validate(&s), []);
+    }
+
+    #[test]
+    fn reports_a_gap_that_is_too_large() {
+        let mut dominos = line(0, 0.0, 0.0, 3);
+        dominos.push(Domino::new(3, cgmath::point3(0.0, 0.0, 2.0 * DEFAULT_SPACING + 0.2), 0.0));
+        let issues = validate(&simulator(dominos, &[(0, PortDirection::Input), (3, PortDirection::Output)]));
+        // the stone behind the gap is not reached either
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0], Issue { domino_id: 3, problem: Problem::Unreachable });
+        assert_eq!(issues[1].domino_id, 2);
+        match issues[1].problem {
+            Problem::GapTooLarge { next, gap } => {
+                assert_eq!(next, 3);
+                assert!((gap - (0.2 - DOMINO_SIZE.z)).abs() < 1e-4, "gap {}", gap);
+            }
+            ref p => panic!("unexpected problem {:?}", p),
+        }
+    }
+
+    #[test]
+    fn reports_chains_not_reached_from_an_input_port() {
+        let mut dominos = line(0, 0.0, 0.0, 3);
+        dominos.extend(line(3, 1.0, 0.0, 3));
+        let issues = validate(&simulator(dominos, &[(0, PortDirection::Input), (2, PortDirection::Output), (5, PortDirection::Output)]));
+        assert_eq!(issues, [Issue { domino_id: 3, problem: Problem::Unreachable }]);
+    }
+
+    #[test]
+    fn without_input_ports_no_chain_is_unreachable() {
+        let mut dominos = line(0, 0.0, 0.0, 3);
+        dominos.extend(line(3, 1.0, 0.0, 3));
+        assert_eq!(validate(&simulator(dominos.clone(), &[])), []);
+        // the other checks still apply
+        dominos[4].position.y = 0.01;
+        let issues = validate(&simulator(dominos, &[(5, PortDirection::Output)]));
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].domino_id, 4);
+        assert!(matches!(issues[0].problem, Problem::Floating { height } if (height - 0.01).abs() < 1e-6));
+    }
+
+    #[test]
+    fn reports_overlapping_and_sunk_dominos() {
+        let mut dominos = vec![Domino::new(0, cgmath::point3(0.0, 0.0, 0.0), 0.0), Domino::new(1, cgmath::point3(0.01, 0.0, 0.0), 0.0)];
+        dominos.push(Domino::new(2, cgmath::point3(1.0, -0.01, 0.0), 0.0));
+        let issues = validate(&simulator(dominos, &[]));
+        assert_eq!(issues.len(), 2);
+        assert!(matches!(issues[0].problem, Problem::Sunk { depth } if (depth - 0.01).abs() < 1e-6));
+        assert_eq!(issues[1], Issue { domino_id: 0, problem: Problem::Overlap { other: 1 } });
+    }
+}